@@ -0,0 +1,452 @@
+//! Synchronous client for use outside of an async runtime, available
+//! using the `blocking` feature
+
+#[cfg(feature = "compression")]
+use crate::UploadCompression;
+use crate::{
+    format::formats_support,
+    instrument::instrument_blocking,
+    request::{IDEMPOTENCY_KEY_HEADER, TRACEPARENT_HEADER},
+    throttle, ClientOptions, ConvertRequest, ConvertResponse, CreateError, DocumentFormat,
+    ErrorResponse, HttpVersion, RequestError, ServerCapabilities, StatusResponse, SupportedFormat,
+    VersionResponse,
+};
+use bytes::Bytes;
+use reqwest::{
+    blocking::{multipart::Form, Client, Response},
+    StatusCode,
+};
+use std::{
+    io::{self, Write},
+    sync::{Arc, OnceLock},
+};
+
+/// Blocking version of [OfficeConvertClient](crate::OfficeConvertClient)
+#[derive(Clone)]
+pub struct OfficeConvertBlockingClient {
+    /// HTTP client to connect to the server with
+    http: Client,
+    /// Host the office convert server is running on
+    host: Arc<str>,
+    /// Formats supported by the server, loaded on first use
+    supported_formats: Arc<OnceLock<Vec<SupportedFormat>>>,
+    /// Capabilities of the server, loaded on first use
+    capabilities: Arc<OnceLock<ServerCapabilities>>,
+    /// Compression applied to files before they are uploaded
+    #[cfg(feature = "compression")]
+    upload_compression: Option<UploadCompression>,
+    /// Maximum size in bytes of a converted PDF
+    max_response_size: Option<u64>,
+}
+
+/// Handles error responses from the server, converting them into
+/// a [RequestError::ErrorResponse] or [RequestError::Throttled]
+fn check_response(response: Response) -> Result<Response, RequestError> {
+    let status = response.status();
+
+    if status.is_client_error() || status.is_server_error() {
+        let retry_after = throttle::retry_after(response.headers());
+        let body = response.json::<ErrorResponse>();
+
+        return Err(RequestError::from_response(status, retry_after, body));
+    }
+
+    Ok(response)
+}
+
+/// Reads the body of a response, the download is aborted once the body is
+/// larger than the maximum size
+fn read_body(mut response: Response, max_size: Option<u64>) -> Result<Bytes, RequestError> {
+    let Some(max) = max_size else {
+        return response.bytes().map_err(RequestError::InvalidResponse);
+    };
+
+    // Responses declaring their size are rejected before downloading
+    let content_length = response.content_length().unwrap_or_default();
+    if content_length > max {
+        return Err(RequestError::ResponseTooLarge { max });
+    }
+
+    let mut body = LimitedBody {
+        body: Vec::with_capacity(content_length as usize),
+        max,
+        exceeded: false,
+    };
+
+    if let Err(err) = response.copy_to(&mut body) {
+        return Err(match body.exceeded {
+            true => RequestError::ResponseTooLarge { max },
+            false => RequestError::InvalidResponse(err),
+        });
+    }
+
+    Ok(Bytes::from(body.body))
+}
+
+/// Writer collecting a response body that fails once the body is larger
+/// than the maximum size
+struct LimitedBody {
+    body: Vec<u8>,
+    max: u64,
+    /// Whether the body was larger than the maximum size
+    exceeded: bool,
+}
+
+impl Write for LimitedBody {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if (self.body.len() + buf.len()) as u64 > self.max {
+            self.exceeded = true;
+            return Err(io::Error::other("response exceeds the maximum size"));
+        }
+
+        self.body.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl OfficeConvertBlockingClient {
+    /// Creates a new office convert client using the default options
+    ///
+    /// ## Arguments
+    /// * `host` - The host where the server is located
+    pub fn new<T>(host: T) -> Result<Self, CreateError>
+    where
+        T: Into<Arc<str>>,
+    {
+        Self::new_with_options(host, ClientOptions::default())
+    }
+
+    /// Creates a new office convert client using the provided options
+    ///
+    /// ## Arguments
+    /// * `host` - The host where the server is located
+    /// * `options` - The configuration options for the client
+    pub fn new_with_options<T>(host: T, options: ClientOptions) -> Result<Self, CreateError>
+    where
+        T: Into<Arc<str>>,
+    {
+        let mut builder = Client::builder()
+            .pool_idle_timeout(options.pool_idle_timeout)
+            .tcp_keepalive(options.tcp_keepalive);
+
+        if let Some(connect_timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+
+        // Blocking client only supports a timeout for the whole request
+        builder = builder.timeout(options.read_timeout);
+
+        if let Some(max_idle) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+
+        builder = match options.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+
+        let client = builder.build().map_err(CreateError::Builder)?;
+        let client = Self {
+            max_response_size: options.max_response_size,
+            ..Self::from_client(host, client)?
+        };
+
+        #[cfg(feature = "compression")]
+        let client = Self {
+            upload_compression: options.upload_compression,
+            ..client
+        };
+
+        Ok(client)
+    }
+
+    /// Create an office convert client from an existing [reqwest::blocking::Client]
+    /// if your setup is more advanced than the default configuration. Unix socket
+    /// hosts are not supported by the blocking client
+    ///
+    /// ## Arguments
+    /// * `host` - The host where the server is located
+    /// * `client` - The request HTTP client to use
+    pub fn from_client<T>(host: T, client: Client) -> Result<Self, CreateError>
+    where
+        T: Into<Arc<str>>,
+    {
+        let host: Arc<str> = host.into();
+
+        // Blocking requests can only be sent over TCP
+        if host.starts_with("unix://") {
+            return Err(CreateError::UnsupportedHost);
+        }
+
+        Ok(Self {
+            http: client,
+            host,
+            supported_formats: Default::default(),
+            capabilities: Default::default(),
+            #[cfg(feature = "compression")]
+            upload_compression: None,
+            max_response_size: None,
+        })
+    }
+
+    /// Obtains the current status of the converter server
+    pub fn get_status(&self) -> Result<StatusResponse, RequestError> {
+        instrument_blocking(&self.host, "status", || {
+            let route = format!("{}/status", self.host);
+            let response = self
+                .http
+                .get(route)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            check_response(response)?
+                .json()
+                .map_err(RequestError::InvalidResponse)
+        })
+    }
+
+    /// Obtains the LibreOffice version that the server is using
+    pub fn get_office_version(&self) -> Result<VersionResponse, RequestError> {
+        instrument_blocking(&self.host, "office_version", || {
+            let route = format!("{}/office-version", self.host);
+            let response = self
+                .http
+                .get(route)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            check_response(response)?
+                .json()
+                .map_err(RequestError::InvalidResponse)
+        })
+    }
+
+    /// Obtains the list of supported file formats from the server, will give back
+    /// an error if the version of LibreOffice does not support querying the
+    /// available file types
+    pub fn get_supported_formats(&self) -> Result<Vec<SupportedFormat>, RequestError> {
+        instrument_blocking(&self.host, "supported_formats", || {
+            let route = format!("{}/supported-formats", self.host);
+            let response = self
+                .http
+                .get(route)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            check_response(response)?
+                .json()
+                .map_err(RequestError::InvalidResponse)
+        })
+    }
+
+    /// Obtains the API version, endpoints, options and limits of the server, see
+    /// [OfficeConvertClient::get_capabilities](crate::OfficeConvertClient::get_capabilities)
+    pub fn get_capabilities(&self) -> Result<ServerCapabilities, RequestError> {
+        instrument_blocking(&self.host, "capabilities", || {
+            let route = format!("{}/capabilities", self.host);
+            let response = self
+                .http
+                .get(route)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            // Older servers don't have the endpoint
+            if response.status() == StatusCode::NOT_FOUND {
+                return Ok(ServerCapabilities::legacy());
+            }
+
+            check_response(response)?
+                .json()
+                .map_err(RequestError::InvalidResponse)
+        })
+    }
+
+    /// Provides the capabilities of the server, requested from the server on
+    /// first use and cached for the lifetime of the client
+    pub fn capabilities(&self) -> Result<&ServerCapabilities, RequestError> {
+        match self.capabilities.get() {
+            Some(value) => Ok(value),
+            None => {
+                let capabilities = self.get_capabilities()?;
+                Ok(self.capabilities.get_or_init(|| capabilities))
+            }
+        }
+    }
+
+    /// Checks if the server can convert the provided mime type or file extension,
+    /// see [OfficeConvertClient::supports](crate::OfficeConvertClient::supports)
+    ///
+    /// ## Arguments
+    /// * `mime_or_extension` - The mime type or file extension to check
+    pub fn supports(&self, mime_or_extension: &str) -> Result<bool, RequestError> {
+        let formats = match self.supported_formats.get() {
+            Some(value) => value,
+            None => {
+                let formats = self.get_supported_formats()?;
+                self.supported_formats.get_or_init(|| formats)
+            }
+        };
+
+        Ok(formats_support(formats, mime_or_extension))
+    }
+
+    /// Checks if the server can convert the provided format
+    ///
+    /// ## Arguments
+    /// * `format` - The format to check
+    pub fn supports_format(&self, format: DocumentFormat) -> Result<bool, RequestError> {
+        self.supports(format.mime())
+    }
+
+    /// Host the office convert server is running on
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Gets the current busy status of the convert server, the server
+    /// is busy when it has no workers available
+    pub fn is_busy(&self) -> Result<bool, RequestError> {
+        let status = self.get_status()?;
+        Ok(!status.is_available())
+    }
+
+    /// Pre-establishes a connection to the server so the next request doesn't
+    /// pay for connecting, see [crate::OfficeConvertClient::warm_up]
+    pub fn warm_up(&self) -> Result<(), RequestError> {
+        instrument_blocking(&self.host, "warm_up", || {
+            let route = format!("{}/status", self.host);
+            let response = self
+                .http
+                .get(route)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            // Body must be read for the connection to be returned to the pool
+            response.bytes().map_err(RequestError::InvalidResponse)?;
+
+            Ok(())
+        })
+    }
+
+    /// Tells the converter server to collect garbage
+    pub fn collect_garbage(&self) -> Result<(), RequestError> {
+        instrument_blocking(&self.host, "collect_garbage", || {
+            let route = format!("{}/collect-garbage", self.host);
+            let response = self
+                .http
+                .post(route)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            check_response(response)?;
+
+            Ok(())
+        })
+    }
+
+    /// Converts the provided office file format bytes into a
+    /// PDF returning the PDF file bytes
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    pub fn convert(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
+        self.convert_request(ConvertRequest::new(file))
+    }
+
+    /// Converts the provided office file format bytes into a PDF
+    /// returning the PDF file bytes, the file name and mime type are
+    /// sent to the server to pick how the file is imported
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `file_name` - The original name of the file (i.e "report.docx")
+    /// * `mime_type` - The mime type of the file
+    pub fn convert_named(
+        &self,
+        file: Vec<u8>,
+        file_name: &str,
+        mime_type: &str,
+    ) -> Result<Bytes, RequestError> {
+        self.convert_request(
+            ConvertRequest::new(file)
+                .file_name(file_name)
+                .mime_type(mime_type),
+        )
+    }
+
+    /// Converts the file from the provided request into a PDF
+    /// returning the PDF file bytes
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    pub fn convert_request(&self, request: ConvertRequest) -> Result<Bytes, RequestError> {
+        self.convert_response(request).map(|response| response.pdf)
+    }
+
+    /// Converts the file from the provided request into a PDF returning
+    /// the PDF along with the details the server provided about the
+    /// conversion (i.e the file name of the PDF)
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    pub fn convert_response(
+        &self,
+        request: ConvertRequest,
+    ) -> Result<ConvertResponse, RequestError> {
+        // Files are uploaded uncompressed to servers that don't accept the compression
+        #[cfg(feature = "compression")]
+        let request = match self.upload_compression {
+            Some(compression)
+                if self.capabilities().map_or(true, |capabilities| {
+                    capabilities.supports_upload_encoding(compression.content_encoding())
+                }) =>
+            {
+                request
+                    .compressed(compression)
+                    .map_err(RequestError::Compression)?
+            }
+            _ => request,
+        };
+
+        instrument_blocking(&self.host, "convert", || {
+            let route = format!("{}/convert", self.host);
+            let mut builder = self.http.post(route);
+
+            if let Some(idempotency_key) = &request.idempotency_key {
+                builder = builder.header(IDEMPOTENCY_KEY_HEADER, idempotency_key);
+            }
+
+            if let Some(traceparent) = &request.traceparent {
+                builder = builder.header(TRACEPARENT_HEADER, traceparent);
+            }
+
+            let priority = request.priority;
+            let output_filename = request.output_filename.clone();
+            let mut form = Form::new().part("file", request.into_blocking_file_part()?);
+
+            if let Some(priority) = priority {
+                form = form.text("priority", priority.as_str());
+            }
+
+            if let Some(output_filename) = output_filename {
+                form = form.text("output_filename", output_filename);
+            }
+
+            let response = builder
+                .multipart(form)
+                .send()
+                .map_err(RequestError::RequestFailed)?;
+
+            let response = check_response(response)?;
+            let headers = response.headers().clone();
+            let pdf = read_body(response, self.max_response_size)?;
+
+            Ok(ConvertResponse::from_headers(pdf, &headers))
+        })
+    }
+}
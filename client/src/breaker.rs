@@ -0,0 +1,128 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Options for the circuit breaker the load balancer keeps for each server
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerOptions {
+    /// Number of consecutive failures after which the breaker opens and the
+    /// server is skipped, zero disables the breaker
+    pub failure_threshold: u32,
+
+    /// Time the server is skipped for once the breaker opens
+    pub open_duration: Duration,
+
+    /// Maximum time the server is skipped for, the time doubles each time
+    /// the trial conversion after the breaker opened fails
+    pub max_open_duration: Duration,
+}
+
+impl Default for CircuitBreakerOptions {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(10),
+            max_open_duration: Duration::from_secs(300),
+        }
+    }
+}
+
+/// State of the circuit breaker for a server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Server is used as normal
+    #[default]
+    Closed,
+    /// Server failed too many times in a row and is skipped until the
+    /// open duration has passed
+    Open,
+    /// Open duration has passed, the next conversion is a trial that closes
+    /// the breaker when it succeeds or opens it again when it fails
+    HalfOpen,
+}
+
+/// Circuit breaker tracking the consecutive failures of a server
+#[derive(Default)]
+pub(crate) struct CircuitBreaker {
+    /// Current state of the breaker
+    state: CircuitState,
+    /// Number of failures since the last success
+    consecutive_failures: u32,
+    /// Time until which the server is skipped while open
+    open_until: Option<Instant>,
+    /// Number of trials that failed since the breaker first opened
+    failed_trials: u32,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    pub(crate) fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures
+    }
+
+    /// Time until which the server is skipped, [None] when not open
+    pub(crate) fn open_until(&self) -> Option<Instant> {
+        match self.state {
+            CircuitState::Open => self.open_until,
+            _ => None,
+        }
+    }
+
+    /// Allows a trial conversion once the open duration has passed,
+    /// provides the new state when it changed
+    pub(crate) fn half_open(&mut self) -> Option<CircuitState> {
+        if self.state != CircuitState::Open {
+            return None;
+        }
+
+        self.state = CircuitState::HalfOpen;
+        self.open_until = None;
+        Some(self.state)
+    }
+
+    /// Records a success closing the breaker, provides the new state
+    /// when it changed
+    pub(crate) fn record_success(&mut self) -> Option<CircuitState> {
+        self.consecutive_failures = 0;
+        self.failed_trials = 0;
+        self.open_until = None;
+
+        if self.state == CircuitState::Closed {
+            return None;
+        }
+
+        self.state = CircuitState::Closed;
+        Some(self.state)
+    }
+
+    /// Records a failure opening the breaker when a trial failed or the
+    /// threshold was reached, provides the new state when it changed
+    pub(crate) fn record_failure(
+        &mut self,
+        options: &CircuitBreakerOptions,
+        now: Instant,
+    ) -> Option<CircuitState> {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+
+        match self.state {
+            CircuitState::HalfOpen => self.failed_trials = self.failed_trials.saturating_add(1),
+            CircuitState::Closed
+                if options.failure_threshold > 0
+                    && self.consecutive_failures >= options.failure_threshold => {}
+            _ => return None,
+        }
+
+        // Open duration doubles with each failed trial
+        let multiplier = 1u32.checked_shl(self.failed_trials).unwrap_or(u32::MAX);
+        let duration = options
+            .open_duration
+            .saturating_mul(multiplier)
+            .min(options.max_open_duration);
+
+        self.state = CircuitState::Open;
+        self.open_until = Some(now + duration);
+        Some(self.state)
+    }
+}
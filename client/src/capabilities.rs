@@ -0,0 +1,99 @@
+use serde::Deserialize;
+
+/// Endpoints available on servers from before capabilities were reported
+const LEGACY_ENDPOINTS: &[&str] = &[
+    "/status",
+    "/office-version",
+    "/supported-formats",
+    "/collect-garbage",
+    "/convert",
+];
+
+/// Details about what a server supports, reported by the `/capabilities`
+/// endpoint. Servers from before the endpoint existed are described by
+/// [ServerCapabilities::legacy]
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ServerCapabilities {
+    /// Version of the HTTP API, zero for servers that don't report
+    /// their capabilities
+    pub api_version: u32,
+    /// Version of the server, [None] for servers that don't report
+    /// their capabilities
+    pub server_version: Option<String>,
+    /// Paths of the available endpoints (i.e "/convert" or "/jobs/{id}")
+    pub endpoints: Vec<String>,
+    /// Multipart fields accepted by /convert
+    pub convert_options: Vec<String>,
+    /// Content encodings uploaded files can be compressed with
+    pub upload_encodings: Vec<String>,
+    /// Formats /convert can respond with
+    pub response_formats: Vec<String>,
+    /// Names of the engines conversions can select
+    pub engines: Vec<String>,
+    /// Whether raw LibreOffice options are accepted
+    pub raw_options: bool,
+    /// Limits applied to requests
+    pub limits: CapabilityLimits,
+}
+
+/// Limits applied to requests by the server, [None] when there is no limit
+/// or the server doesn't report it
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CapabilityLimits {
+    /// Maximum size in bytes of an uploaded file
+    pub max_file_size: Option<u64>,
+    /// Maximum number of conversions waiting for LibreOffice
+    pub max_queued: Option<usize>,
+    /// Maximum number of pages in a converted PDF
+    pub max_output_pages: Option<u64>,
+    /// Maximum size in bytes of a converted PDF
+    pub max_output_bytes: Option<u64>,
+    /// Maximum number of pages rendered as images
+    pub max_render_pages: Option<u64>,
+    /// Maximum number of files within a batch archive
+    pub batch_max_entries: Option<usize>,
+    /// Maximum total decompressed size in bytes of a batch archive
+    pub batch_max_size: Option<u64>,
+}
+
+impl ServerCapabilities {
+    /// Capabilities assumed for servers that don't report their capabilities,
+    /// only the endpoints every server version provides are available
+    pub fn legacy() -> Self {
+        Self {
+            endpoints: LEGACY_ENDPOINTS
+                .iter()
+                .map(|value| value.to_string())
+                .collect(),
+            convert_options: vec!["file".to_string()],
+            response_formats: vec!["pdf".to_string()],
+            ..Default::default()
+        }
+    }
+
+    /// Whether the server didn't report its capabilities
+    pub fn is_legacy(&self) -> bool {
+        self.api_version == 0
+    }
+
+    /// Whether the server provides the endpoint with the provided path
+    /// (i.e "/ws" or "/jobs/{id}")
+    pub fn has_endpoint(&self, path: &str) -> bool {
+        self.endpoints.iter().any(|value| value == path)
+    }
+
+    /// Whether /convert accepts the multipart field with the provided name
+    pub fn supports_option(&self, name: &str) -> bool {
+        self.convert_options.iter().any(|value| value == name)
+    }
+
+    /// Whether uploaded files can be compressed with the provided content
+    /// encoding (i.e "gzip")
+    pub fn supports_upload_encoding(&self, encoding: &str) -> bool {
+        self.upload_encodings
+            .iter()
+            .any(|value| value.eq_ignore_ascii_case(encoding))
+    }
+}
@@ -0,0 +1,217 @@
+use crate::SupportedFormat;
+use std::{fmt::Display, str::FromStr};
+use thiserror::Error;
+
+/// Common document formats that can be checked against the formats
+/// supported by a server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DocumentFormat {
+    /// Word 97-2003 document (.doc)
+    Doc,
+    /// Word document (.docx)
+    Docx,
+    /// OpenDocument text (.odt)
+    Odt,
+    /// OpenDocument flat XML text (.fodt)
+    Fodt,
+    /// Rich text format (.rtf)
+    Rtf,
+    /// WordPerfect document (.wpd)
+    Wpd,
+    /// Plain text (.txt)
+    Txt,
+    /// HTML document (.html)
+    Html,
+    /// Excel 97-2003 workbook (.xls)
+    Xls,
+    /// Excel workbook (.xlsx)
+    Xlsx,
+    /// OpenDocument spreadsheet (.ods)
+    Ods,
+    /// OpenDocument flat XML spreadsheet (.fods)
+    Fods,
+    /// Comma separated values (.csv)
+    Csv,
+    /// PowerPoint 97-2003 presentation (.ppt)
+    Ppt,
+    /// PowerPoint presentation (.pptx)
+    Pptx,
+    /// OpenDocument presentation (.odp)
+    Odp,
+    /// OpenDocument flat XML presentation (.fodp)
+    Fodp,
+    /// OpenDocument drawing (.odg)
+    Odg,
+}
+
+impl DocumentFormat {
+    /// All the known formats
+    pub const ALL: &'static [DocumentFormat] = &[
+        DocumentFormat::Doc,
+        DocumentFormat::Docx,
+        DocumentFormat::Odt,
+        DocumentFormat::Fodt,
+        DocumentFormat::Rtf,
+        DocumentFormat::Wpd,
+        DocumentFormat::Txt,
+        DocumentFormat::Html,
+        DocumentFormat::Xls,
+        DocumentFormat::Xlsx,
+        DocumentFormat::Ods,
+        DocumentFormat::Fods,
+        DocumentFormat::Csv,
+        DocumentFormat::Ppt,
+        DocumentFormat::Pptx,
+        DocumentFormat::Odp,
+        DocumentFormat::Fodp,
+        DocumentFormat::Odg,
+    ];
+
+    /// Mime type of the format
+    pub fn mime(&self) -> &'static str {
+        match self {
+            DocumentFormat::Doc => "application/msword",
+            DocumentFormat::Docx => {
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document"
+            }
+            DocumentFormat::Odt => "application/vnd.oasis.opendocument.text",
+            DocumentFormat::Fodt => "application/vnd.oasis.opendocument.text-flat-xml",
+            DocumentFormat::Rtf => "application/rtf",
+            DocumentFormat::Wpd => "application/vnd.wordperfect",
+            DocumentFormat::Txt => "text/plain",
+            DocumentFormat::Html => "text/html",
+            DocumentFormat::Xls => "application/vnd.ms-excel",
+            DocumentFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            DocumentFormat::Ods => "application/vnd.oasis.opendocument.spreadsheet",
+            DocumentFormat::Fods => "application/vnd.oasis.opendocument.spreadsheet-flat-xml",
+            DocumentFormat::Csv => "text/csv",
+            DocumentFormat::Ppt => "application/vnd.ms-powerpoint",
+            DocumentFormat::Pptx => {
+                "application/vnd.openxmlformats-officedocument.presentationml.presentation"
+            }
+            DocumentFormat::Odp => "application/vnd.oasis.opendocument.presentation",
+            DocumentFormat::Fodp => "application/vnd.oasis.opendocument.presentation-flat-xml",
+            DocumentFormat::Odg => "application/vnd.oasis.opendocument.graphics",
+        }
+    }
+
+    /// File extension of the format without the leading dot
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DocumentFormat::Doc => "doc",
+            DocumentFormat::Docx => "docx",
+            DocumentFormat::Odt => "odt",
+            DocumentFormat::Fodt => "fodt",
+            DocumentFormat::Rtf => "rtf",
+            DocumentFormat::Wpd => "wpd",
+            DocumentFormat::Txt => "txt",
+            DocumentFormat::Html => "html",
+            DocumentFormat::Xls => "xls",
+            DocumentFormat::Xlsx => "xlsx",
+            DocumentFormat::Ods => "ods",
+            DocumentFormat::Fods => "fods",
+            DocumentFormat::Csv => "csv",
+            DocumentFormat::Ppt => "ppt",
+            DocumentFormat::Pptx => "pptx",
+            DocumentFormat::Odp => "odp",
+            DocumentFormat::Fodp => "fodp",
+            DocumentFormat::Odg => "odg",
+        }
+    }
+
+    /// Finds the format for a file extension, the extension is case
+    /// insensitive and may include the leading dot
+    ///
+    /// ## Arguments
+    /// * `extension` - The file extension
+    pub fn from_extension(extension: &str) -> Option<Self> {
+        let extension = extension.strip_prefix('.').unwrap_or(extension);
+
+        // Alternative extensions
+        if extension.eq_ignore_ascii_case("htm") {
+            return Some(DocumentFormat::Html);
+        }
+
+        Self::ALL
+            .iter()
+            .find(|format| format.extension().eq_ignore_ascii_case(extension))
+            .copied()
+    }
+
+    /// Finds the format for a mime type, any parameters (i.e "; charset=utf-8")
+    /// are ignored
+    ///
+    /// ## Arguments
+    /// * `mime` - The mime type
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        let mime = mime_essence(mime);
+
+        Self::ALL
+            .iter()
+            .find(|format| format.mime().eq_ignore_ascii_case(mime))
+            .copied()
+    }
+}
+
+impl Display for DocumentFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.extension())
+    }
+}
+
+/// Error for values that aren't a known format
+#[derive(Debug, Error)]
+#[error("unknown document format: {0}")]
+pub struct UnknownFormat(String);
+
+impl FromStr for DocumentFormat {
+    type Err = UnknownFormat;
+
+    /// Parses either a mime type or a file extension
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let format = if value.contains('/') {
+            Self::from_mime(value)
+        } else {
+            Self::from_extension(value)
+        };
+
+        format.ok_or_else(|| UnknownFormat(value.to_string()))
+    }
+}
+
+impl SupportedFormat {
+    /// Known format for the mime type of this format
+    pub fn document_format(&self) -> Option<DocumentFormat> {
+        DocumentFormat::from_mime(&self.mime)
+    }
+
+    /// Checks if this format has the provided mime type, parameters are ignored
+    ///
+    /// ## Arguments
+    /// * `mime` - The mime type to compare
+    pub fn matches_mime(&self, mime: &str) -> bool {
+        mime_essence(&self.mime).eq_ignore_ascii_case(mime_essence(mime))
+    }
+}
+
+/// Checks if the provided formats include a mime type or file extension,
+/// file extensions are checked using the mime type of their [DocumentFormat]
+pub(crate) fn formats_support(formats: &[SupportedFormat], mime_or_extension: &str) -> bool {
+    let mime = if mime_or_extension.contains('/') {
+        mime_or_extension
+    } else {
+        match DocumentFormat::from_extension(mime_or_extension) {
+            Some(format) => format.mime(),
+            None => return false,
+        }
+    };
+
+    formats.iter().any(|format| format.matches_mime(mime))
+}
+
+/// Removes any parameters from a mime type
+fn mime_essence(mime: &str) -> &str {
+    mime.split(';').next().unwrap_or_default().trim()
+}
@@ -0,0 +1,153 @@
+use crate::RequestError;
+use std::future::Future;
+#[cfg(any(feature = "tracing", feature = "metrics"))]
+use std::time::Duration;
+
+/// Name of the span requests are wrapped in
+#[cfg(feature = "tracing")]
+const SPAN_NAME: &str = "office_convert_request";
+
+/// Name of the counter incremented for each request
+#[cfg(feature = "metrics")]
+const REQUESTS_METRIC: &str = "office_convert_client_requests_total";
+
+/// Name of the histogram recording the request duration in seconds
+#[cfg(feature = "metrics")]
+const DURATION_METRIC: &str = "office_convert_client_request_duration_seconds";
+
+/// Instruments a request to the server at the provided host, the request is
+/// wrapped in a span when the "tracing" feature is enabled and recorded as
+/// metrics when the "metrics" feature is enabled
+///
+/// ## Arguments
+/// * `host` - Host of the server the request is made to
+/// * `operation` - Name of the operation (i.e "convert" or "status")
+/// * `request` - Future performing the request
+#[allow(unused_variables)]
+pub(crate) async fn instrument<T, F>(
+    host: &str,
+    operation: &'static str,
+    request: F,
+) -> Result<T, RequestError>
+where
+    F: Future<Output = Result<T, RequestError>>,
+{
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    return request.await;
+
+    #[cfg(any(feature = "tracing", feature = "metrics"))]
+    {
+        let started_at = std::time::Instant::now();
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(host, operation);
+
+        #[cfg(feature = "tracing")]
+        let result = tracing::Instrument::instrument(request, span.clone()).await;
+        #[cfg(not(feature = "tracing"))]
+        let result = request.await;
+
+        let duration = started_at.elapsed();
+
+        #[cfg(feature = "tracing")]
+        record_span(&span, duration, &result);
+        #[cfg(feature = "metrics")]
+        record_metrics(host, operation, duration, &result);
+
+        result
+    }
+}
+
+/// Instruments a blocking request to the server at the provided host,
+/// see [instrument]
+#[cfg(feature = "blocking")]
+#[allow(unused_variables)]
+pub(crate) fn instrument_blocking<T, F>(
+    host: &str,
+    operation: &'static str,
+    request: F,
+) -> Result<T, RequestError>
+where
+    F: FnOnce() -> Result<T, RequestError>,
+{
+    #[cfg(not(any(feature = "tracing", feature = "metrics")))]
+    return request();
+
+    #[cfg(any(feature = "tracing", feature = "metrics"))]
+    {
+        let started_at = std::time::Instant::now();
+
+        #[cfg(feature = "tracing")]
+        let span = request_span(host, operation);
+
+        #[cfg(feature = "tracing")]
+        let result = span.in_scope(request);
+        #[cfg(not(feature = "tracing"))]
+        let result = request();
+
+        let duration = started_at.elapsed();
+
+        #[cfg(feature = "tracing")]
+        record_span(&span, duration, &result);
+        #[cfg(feature = "metrics")]
+        record_metrics(host, operation, duration, &result);
+
+        result
+    }
+}
+
+/// Outcome of a request used to label spans and metrics
+#[cfg(any(feature = "tracing", feature = "metrics"))]
+fn outcome<T>(result: &Result<T, RequestError>) -> &'static str {
+    match result {
+        Ok(_) => "success",
+        Err(RequestError::ErrorResponse { .. }) => "error_response",
+        Err(RequestError::Throttled { .. }) => "throttled",
+        Err(RequestError::ServerConnectTimeout) => "timeout",
+        Err(_) => "failed",
+    }
+}
+
+/// Creates the span for a request, the duration and outcome are
+/// recorded once the request completes
+#[cfg(feature = "tracing")]
+fn request_span(host: &str, operation: &'static str) -> tracing::Span {
+    tracing::info_span!(
+        SPAN_NAME,
+        host,
+        operation,
+        duration_ms = tracing::field::Empty,
+        outcome = tracing::field::Empty,
+        error = tracing::field::Empty,
+    )
+}
+
+/// Records the duration and outcome of a completed request on its span
+#[cfg(feature = "tracing")]
+fn record_span<T>(span: &tracing::Span, duration: Duration, result: &Result<T, RequestError>) {
+    span.record("duration_ms", duration.as_millis() as u64);
+    span.record("outcome", outcome(result));
+
+    if let Err(err) = result {
+        span.record("error", tracing::field::display(err));
+    }
+}
+
+/// Records a completed request in the request counter and duration histogram
+/// labeled by the host, operation and outcome
+#[cfg(feature = "metrics")]
+fn record_metrics<T>(
+    host: &str,
+    operation: &'static str,
+    duration: Duration,
+    result: &Result<T, RequestError>,
+) {
+    let labels = [
+        ("host", host.to_string()),
+        ("operation", operation.to_string()),
+        ("outcome", outcome(result).to_string()),
+    ];
+
+    metrics::counter!(REQUESTS_METRIC, &labels).increment(1);
+    metrics::histogram!(DURATION_METRIC, &labels).record(duration.as_secs_f64());
+}
@@ -1,16 +1,60 @@
 use async_trait::async_trait;
-use bytes::Bytes;
-use reqwest::multipart::{Form, Part};
+use bytes::{Bytes, BytesMut};
+use instrument::instrument;
+use reqwest::{
+    multipart::{Form, Part},
+    Body, RequestBuilder, Response, StatusCode,
+};
 use serde::Deserialize;
-use std::{sync::Arc, time::Duration};
+use std::{path::Path, sync::Arc, time::Duration};
 use thiserror::Error;
-
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    sync::OnceCell,
+};
+use tokio_util::io::ReaderStream;
+
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod breaker;
+mod capabilities;
+mod format;
+mod instrument;
 pub mod load;
-
-pub use load::{LoadBalanceError, OfficeConvertLoadBalancer};
+pub mod metrics;
+#[cfg(feature = "test-util")]
+pub mod mock;
+mod request;
+mod response;
+mod routing;
+mod throttle;
+#[cfg(unix)]
+mod unix;
+#[cfg(feature = "websocket")]
+mod ws;
+
+pub use breaker::{CircuitBreakerOptions, CircuitState};
+pub use capabilities::{CapabilityLimits, ServerCapabilities};
+pub use format::{DocumentFormat, UnknownFormat};
+pub use load::{BackendFailure, LoadBalanceError, LoadBalancerOptions, OfficeConvertLoadBalancer};
+pub use metrics::{BackendMetricsSnapshot, BalancerObserver, LoadBalancerMetrics};
+#[cfg(feature = "compression")]
+pub use request::UploadCompression;
+pub use request::{ConvertRequest, Priority, UploadProgressFn};
+pub use response::ConvertResponse;
+pub use routing::{FirstFree, HintedRouting, Hints, RoutingPolicy, SizeClass};
+
+#[cfg(feature = "blocking")]
+pub use blocking::OfficeConvertBlockingClient;
+#[cfg(feature = "test-util")]
+pub use mock::{MockCall, MockConvertOffice, MockResponse};
 
 /// Trait implement by entities that can convert office files into
 /// PDF files.
+///
+/// Dropping the future returned by a conversion closes the connection to
+/// the server, the server cancels the conversion rather than finishing a
+/// conversion nobody will read
 #[async_trait]
 pub trait ConvertOffice {
     /// Converts the provided office file format bytes into a
@@ -19,6 +63,52 @@ pub trait ConvertOffice {
     /// ## Arguments
     /// * `file` - The file bytes to convert
     async fn convert(&self, file: Vec<u8>) -> Result<Bytes, RequestError>;
+
+    /// Converts the file from the provided request into a PDF
+    /// returning the PDF file bytes
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    async fn convert_request(&self, request: ConvertRequest) -> Result<Bytes, RequestError> {
+        self.convert(request.file).await
+    }
+
+    /// Converts the file from the provided request into a PDF returning
+    /// the PDF along with the details the server provided about the
+    /// conversion (i.e the file name of the PDF)
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    async fn convert_response(
+        &self,
+        request: ConvertRequest,
+    ) -> Result<ConvertResponse, RequestError> {
+        self.convert_request(request)
+            .await
+            .map(ConvertResponse::new)
+    }
+
+    /// Converts the provided office file format bytes into a PDF
+    /// returning the PDF file bytes, the file name and mime type are
+    /// sent to the server to pick how the file is imported
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `file_name` - The original name of the file (i.e "report.docx")
+    /// * `mime_type` - The mime type of the file
+    async fn convert_named(
+        &self,
+        file: Vec<u8>,
+        file_name: &str,
+        mime_type: &str,
+    ) -> Result<Bytes, RequestError> {
+        self.convert_request(
+            ConvertRequest::new(file)
+                .file_name(file_name)
+                .mime_type(mime_type),
+        )
+        .await
+    }
 }
 
 #[derive(Clone)]
@@ -27,6 +117,18 @@ pub struct OfficeConvertClient {
     http: reqwest::Client,
     /// Host the office convert server is running on
     host: Arc<str>,
+    /// Formats supported by the server, loaded on first use
+    supported_formats: Arc<OnceCell<Vec<SupportedFormat>>>,
+    /// Capabilities of the server, loaded on first use
+    capabilities: Arc<OnceCell<ServerCapabilities>>,
+    /// Client for sending requests when the host is a Unix socket
+    #[cfg(unix)]
+    unix: Option<unix::UnixClient>,
+    /// Compression applied to files before they are uploaded
+    #[cfg(feature = "compression")]
+    upload_compression: Option<UploadCompression>,
+    /// Maximum size in bytes of a converted PDF
+    max_response_size: Option<u64>,
 }
 
 /// Errors that can occur during setup
@@ -35,6 +137,10 @@ pub enum CreateError {
     /// Builder failed to create HTTP client
     #[error(transparent)]
     Builder(reqwest::Error),
+
+    /// Host is a Unix socket which is not supported by the client or platform
+    #[error("unix socket hosts are not supported")]
+    UnsupportedHost,
 }
 
 /// Errors that can occur during a request
@@ -55,22 +161,145 @@ pub enum RequestError {
     /// Error message from the convert server reply
     #[error("{reason}")]
     ErrorResponse {
+        /// HTTP status code of the response
+        status: StatusCode,
         reason: String,
+        /// Machine readable error code if provided by the server
+        code: Option<String>,
         backtrace: Option<String>,
     },
+
+    /// Server asked the client to slow down (429 or 503 response), the request
+    /// can be made again once the delay has passed
+    #[error("{reason}")]
+    Throttled {
+        /// HTTP status code of the response
+        status: StatusCode,
+        reason: String,
+        /// Machine readable error code if provided by the server
+        code: Option<String>,
+        /// Time the server asked the client to wait before making another
+        /// request, from the Retry-After or RateLimit headers when provided
+        retry_after: Option<Duration>,
+    },
+
+    /// Failed to write the converted PDF to the output
+    #[error("failed to write output: {0}")]
+    Output(std::io::Error),
+
+    /// Converted PDF is larger than [ClientOptions::max_response_size], the
+    /// download was aborted
+    #[error("response exceeds the maximum size of {max} bytes")]
+    ResponseTooLarge { max: u64 },
+
+    /// Error from the load balancer
+    #[error(transparent)]
+    LoadBalance(#[from] LoadBalanceError),
+
+    /// Failed to compress the file before uploading
+    #[cfg(feature = "compression")]
+    #[error("failed to compress file: {0}")]
+    Compression(std::io::Error),
+
+    /// Failed to request the server over its Unix socket
+    #[cfg(unix)]
+    #[error(transparent)]
+    UnixSocket(Box<hyper_util::client::legacy::Error>),
+
+    /// Failed to connect or communicate over the WebSocket
+    #[cfg(feature = "websocket")]
+    #[error(transparent)]
+    WebSocket(Box<tokio_tungstenite::tungstenite::Error>),
+
+    /// Message from the server over the WebSocket was unexpected or malformed
+    #[cfg(feature = "websocket")]
+    #[error("invalid websocket message: {0}")]
+    InvalidMessage(String),
+}
+
+impl RequestError {
+    /// Checks if the error is likely specific to the server that handled the
+    /// request (Connection failures, server errors, or the server crashing
+    /// mid-response) in which case the request could succeed on another server
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            RequestError::RequestFailed(_)
+            | RequestError::InvalidResponse(_)
+            | RequestError::ServerConnectTimeout => true,
+            RequestError::ErrorResponse { status, .. } => status.is_server_error(),
+            // Throttling is specific to the server that responded
+            RequestError::Throttled { .. } => true,
+            RequestError::LoadBalance(_)
+            | RequestError::Output(_)
+            // Every server would produce the same oversized PDF
+            | RequestError::ResponseTooLarge { .. } => false,
+            #[cfg(feature = "compression")]
+            RequestError::Compression(_) => false,
+            #[cfg(unix)]
+            RequestError::UnixSocket(_) => true,
+            #[cfg(feature = "websocket")]
+            RequestError::WebSocket(_) | RequestError::InvalidMessage(_) => true,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct StatusResponse {
+    /// Whether the server is busy
     pub is_busy: bool,
+    /// Number of workers available to start a conversion, not
+    /// provided by older servers
+    #[serde(default)]
+    pub available_workers: Option<usize>,
+    /// State of each worker, not provided by older servers
+    #[serde(default)]
+    pub workers: Vec<WorkerStatus>,
+}
+
+impl StatusResponse {
+    /// Whether the server has a worker available to start a conversion,
+    /// falls back to the busy state for older servers
+    pub fn is_available(&self) -> bool {
+        match self.available_workers {
+            Some(available_workers) => available_workers > 0,
+            None => !self.is_busy,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    /// Waiting for a conversion
+    Idle,
+    /// Converting a document
+    Converting,
+    /// Starting or restarting its office instance
+    Restarting,
 }
 
 #[derive(Debug, Deserialize)]
+pub struct WorkerStatus {
+    /// Index of the worker
+    pub id: usize,
+    /// Current state of the worker
+    pub state: WorkerState,
+    /// ID of the job being converted when converting
+    pub job_id: Option<u64>,
+    /// Time in milliseconds the worker has been in its current state
+    pub state_duration_ms: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct SupportedFormat {
     /// Name of the file format
     pub name: String,
     /// Mime type of the format
     pub mime: String,
+    /// File extension of the format, only reported for known formats by
+    /// newer servers
+    #[serde(default)]
+    pub extension: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -83,11 +312,100 @@ pub struct VersionResponse {
     pub build_id: String,
 }
 
+impl RequestError {
+    /// Creates the error for an error response from the server, throttling
+    /// responses become [RequestError::Throttled] even when the body isn't
+    /// an error from the server (i.e a proxy rate limiting requests)
+    pub(crate) fn from_response(
+        status: StatusCode,
+        retry_after: Option<Duration>,
+        body: Result<ErrorResponse, reqwest::Error>,
+    ) -> Self {
+        if throttle::is_throttled(status) {
+            let (reason, code) = match body {
+                Ok(body) => (body.reason, body.code),
+                Err(_) => (status.to_string(), None),
+            };
+
+            return RequestError::Throttled {
+                status,
+                reason,
+                code,
+                retry_after,
+            };
+        }
+
+        match body {
+            Ok(body) => RequestError::ErrorResponse {
+                status,
+                reason: body.reason,
+                code: body.code,
+                backtrace: body.backtrace,
+            },
+            Err(err) => RequestError::InvalidResponse(err),
+        }
+    }
+
+    /// Time the server asked the client to wait before making another
+    /// request, [None] for errors that aren't throttling or when the server
+    /// didn't provide a delay
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            RequestError::Throttled { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Reads the error from an error response from the server
+async fn error_response(response: Response) -> RequestError {
+    let status = response.status();
+    let retry_after = throttle::retry_after(response.headers());
+    let body = response.json::<ErrorResponse>().await;
+
+    RequestError::from_response(status, retry_after, body)
+}
+
+/// Reads the body of a response, the download is aborted once the body is
+/// larger than the maximum size
+async fn read_body(mut response: Response, max_size: Option<u64>) -> Result<Bytes, RequestError> {
+    let Some(max) = max_size else {
+        return response
+            .bytes()
+            .await
+            .map_err(RequestError::InvalidResponse);
+    };
+
+    // Responses declaring their size are rejected before downloading
+    let content_length = response.content_length().unwrap_or_default();
+    if content_length > max {
+        return Err(RequestError::ResponseTooLarge { max });
+    }
+
+    let mut body = BytesMut::with_capacity(content_length as usize);
+
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(RequestError::InvalidResponse)?
+    {
+        if (body.len() + chunk.len()) as u64 > max {
+            return Err(RequestError::ResponseTooLarge { max });
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(body.freeze())
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ErrorResponse {
     /// Server reason for the error
     reason: String,
+    /// Machine readable error code if available
+    code: Option<String>,
     /// Server backtrace if available
     backtrace: Option<String>,
 }
@@ -99,6 +417,30 @@ pub struct ClientOptions {
 
     /// Timeout when reading responses from the server
     pub read_timeout: Option<Duration>,
+
+    /// Maximum number of idle connections to keep open to the server,
+    /// [None] for no limit
+    pub pool_max_idle_per_host: Option<usize>,
+
+    /// Time an idle connection is kept open before it is closed, [None]
+    /// keeps idle connections open until the server closes them
+    pub pool_idle_timeout: Option<Duration>,
+
+    /// HTTP version used to communicate with the server
+    pub http_version: HttpVersion,
+
+    /// Interval to send TCP keepalive probes on open connections at, keeps
+    /// proxies from closing idle connections. [None] disables keepalive
+    pub tcp_keepalive: Option<Duration>,
+
+    /// Compression applied to files before they are uploaded, [None]
+    /// uploads files uncompressed
+    #[cfg(feature = "compression")]
+    pub upload_compression: Option<UploadCompression>,
+
+    /// Maximum size in bytes of a converted PDF, downloads of larger PDFs are
+    /// aborted with [RequestError::ResponseTooLarge]. [None] for no limit
+    pub max_response_size: Option<u64>,
 }
 
 impl Default for ClientOptions {
@@ -107,10 +449,30 @@ impl Default for ClientOptions {
             // Allow the connection to fail if not established in 700ms
             connect_timeout: Some(Duration::from_millis(700)),
             read_timeout: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            http_version: HttpVersion::default(),
+            tcp_keepalive: None,
+            #[cfg(feature = "compression")]
+            upload_compression: None,
+            max_response_size: None,
         }
     }
 }
 
+/// HTTP version used to communicate with the server
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HttpVersion {
+    /// HTTP/1.1, or HTTP/2 when negotiated with the server over TLS
+    #[default]
+    Auto,
+    /// Only use HTTP/1.1
+    Http1,
+    /// Only use HTTP/2 without negotiating it first (prior knowledge),
+    /// requires the server or any proxies to support HTTP/2
+    Http2,
+}
+
 impl OfficeConvertClient {
     /// Creates a new office convert client using the default options
     ///
@@ -132,18 +494,42 @@ impl OfficeConvertClient {
     where
         T: Into<Arc<str>>,
     {
-        let mut builder = reqwest::Client::builder();
+        let mut builder = reqwest::Client::builder()
+            .pool_idle_timeout(options.pool_idle_timeout)
+            .tcp_keepalive(options.tcp_keepalive);
 
         if let Some(connect_timeout) = options.connect_timeout {
             builder = builder.connect_timeout(connect_timeout);
         }
 
-        if let Some(connect_timeout) = options.read_timeout {
-            builder = builder.read_timeout(connect_timeout);
+        if let Some(read_timeout) = options.read_timeout {
+            builder = builder.read_timeout(read_timeout);
+        }
+
+        if let Some(max_idle) = options.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
         }
 
+        builder = match options.http_version {
+            HttpVersion::Auto => builder,
+            HttpVersion::Http1 => builder.http1_only(),
+            HttpVersion::Http2 => builder.http2_prior_knowledge(),
+        };
+
         let client = builder.build().map_err(CreateError::Builder)?;
-        Self::from_client(host, client)
+
+        let client = Self {
+            max_response_size: options.max_response_size,
+            ..Self::from_client(host, client)?
+        };
+
+        #[cfg(feature = "compression")]
+        let client = Self {
+            upload_compression: options.upload_compression,
+            ..client
+        };
+
+        Ok(client)
     }
 
     /// Create an office convert client from an existing [reqwest::Client] if
@@ -156,184 +542,411 @@ impl OfficeConvertClient {
     where
         T: Into<Arc<str>>,
     {
+        let host: Arc<str> = host.into();
+
+        #[cfg(unix)]
+        let unix = host
+            .strip_prefix(unix::UNIX_HOST_PREFIX)
+            .map(|path| unix::UnixClient::new(path.into()));
+
+        #[cfg(not(unix))]
+        if host.starts_with("unix://") {
+            return Err(CreateError::UnsupportedHost);
+        }
+
         Ok(Self {
             http: client,
-            host: host.into(),
+            host,
+            supported_formats: Default::default(),
+            capabilities: Default::default(),
+            #[cfg(unix)]
+            unix,
+            #[cfg(feature = "compression")]
+            upload_compression: None,
+            max_response_size: None,
         })
     }
 
+    /// Creates the URL for the provided route on the server
+    fn route(&self, route: &str) -> String {
+        #[cfg(unix)]
+        if self.unix.is_some() {
+            return format!("{}{route}", unix::UNIX_BASE_URL);
+        }
+
+        format!("{}{route}", self.host)
+    }
+
+    /// Sends the provided request to the server
+    async fn send(&self, request: RequestBuilder) -> Result<Response, RequestError> {
+        #[cfg(unix)]
+        if let Some(unix) = &self.unix {
+            let request = request.build().map_err(RequestError::RequestFailed)?;
+            return unix.execute(request).await;
+        }
+
+        request.send().await.map_err(RequestError::RequestFailed)
+    }
+
     /// Obtains the current status of the converter server
     pub async fn get_status(&self) -> Result<StatusResponse, RequestError> {
-        let route = format!("{}/status", self.host);
-        let response = self
-            .http
-            .get(route)
-            .send()
-            .await
-            .map_err(RequestError::RequestFailed)?;
+        instrument(&self.host, "status", async {
+            let route = self.route("/status");
+            let response = self.send(self.http.get(route)).await?;
 
-        let status = response.status();
+            let status = response.status();
 
-        // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            // Extract the response message
+            let response: StatusResponse = response
                 .json()
                 .await
                 .map_err(RequestError::InvalidResponse)?;
 
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
-
-        // Extract the response message
-        let response: StatusResponse = response
-            .json()
-            .await
-            .map_err(RequestError::InvalidResponse)?;
-
-        Ok(response)
+            Ok(response)
+        })
+        .await
     }
 
     /// Obtains the LibreOffice version that the server is using
     pub async fn get_office_version(&self) -> Result<VersionResponse, RequestError> {
-        let route = format!("{}/office-version", self.host);
-        let response = self
-            .http
-            .get(route)
-            .send()
-            .await
-            .map_err(RequestError::RequestFailed)?;
+        instrument(&self.host, "office_version", async {
+            let route = self.route("/office-version");
+            let response = self.send(self.http.get(route)).await?;
 
-        let status = response.status();
+            let status = response.status();
 
-        // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            // Extract the response message
+            let response: VersionResponse = response
                 .json()
                 .await
                 .map_err(RequestError::InvalidResponse)?;
 
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
-
-        // Extract the response message
-        let response: VersionResponse = response
-            .json()
-            .await
-            .map_err(RequestError::InvalidResponse)?;
-
-        Ok(response)
+            Ok(response)
+        })
+        .await
     }
 
     /// Obtains the list of supported file formats from the server, will give back
     /// an error if the version of LibreOffice does not support querying the
     /// available file types
     pub async fn get_supported_formats(&self) -> Result<Vec<SupportedFormat>, RequestError> {
-        let route = format!("{}/supported-formats", self.host);
-        let response = self
-            .http
-            .get(route)
-            .send()
-            .await
-            .map_err(RequestError::RequestFailed)?;
+        instrument(&self.host, "supported_formats", async {
+            let route = self.route("/supported-formats");
+            let response = self.send(self.http.get(route)).await?;
 
-        let status = response.status();
+            let status = response.status();
 
-        // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            // Extract the response message
+            let response: Vec<SupportedFormat> = response
                 .json()
                 .await
                 .map_err(RequestError::InvalidResponse)?;
 
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
+            Ok(response)
+        })
+        .await
+    }
+
+    /// Obtains the API version, endpoints, options and limits of the server.
+    /// Servers from before capabilities were reported respond with a 404 and
+    /// are described by [ServerCapabilities::legacy]
+    pub async fn get_capabilities(&self) -> Result<ServerCapabilities, RequestError> {
+        instrument(&self.host, "capabilities", async {
+            let route = self.route("/capabilities");
+            let response = self.send(self.http.get(route)).await?;
+
+            let status = response.status();
+
+            // Older servers don't have the endpoint
+            if status == StatusCode::NOT_FOUND {
+                return Ok(ServerCapabilities::legacy());
+            }
 
-        // Extract the response message
-        let response: Vec<SupportedFormat> = response
-            .json()
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            // Extract the response message
+            let response: ServerCapabilities = response
+                .json()
+                .await
+                .map_err(RequestError::InvalidResponse)?;
+
+            Ok(response)
+        })
+        .await
+    }
+
+    /// Provides the capabilities of the server, see [Self::get_capabilities].
+    /// The capabilities are requested from the server on first use and cached
+    /// for the lifetime of the client
+    pub async fn capabilities(&self) -> Result<&ServerCapabilities, RequestError> {
+        self.capabilities
+            .get_or_try_init(|| self.get_capabilities())
             .await
-            .map_err(RequestError::InvalidResponse)?;
+    }
+
+    /// Whether the server accepts files compressed with the provided compression,
+    /// assumed to be accepted when the capabilities of the server can't be loaded
+    #[cfg(feature = "compression")]
+    async fn accepts_compression(&self, compression: UploadCompression) -> bool {
+        match self.capabilities().await {
+            Ok(capabilities) => {
+                capabilities.supports_upload_encoding(compression.content_encoding())
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Checks if the server can convert the provided mime type or file extension
+    /// (i.e "application/msword", "docx" or ".docx"). File extensions are checked
+    /// using the mime type of their [DocumentFormat], unknown extensions are not
+    /// supported. The supported formats are requested from the server on first
+    /// use and cached for the lifetime of the client
+    ///
+    /// ## Arguments
+    /// * `mime_or_extension` - The mime type or file extension to check
+    pub async fn supports(&self, mime_or_extension: &str) -> Result<bool, RequestError> {
+        let formats = self
+            .supported_formats
+            .get_or_try_init(|| self.get_supported_formats())
+            .await?;
+
+        Ok(format::formats_support(formats, mime_or_extension))
+    }
+
+    /// Checks if the server can convert the provided format, see [Self::supports]
+    ///
+    /// ## Arguments
+    /// * `format` - The format to check
+    pub async fn supports_format(&self, format: DocumentFormat) -> Result<bool, RequestError> {
+        self.supports(format.mime()).await
+    }
 
-        Ok(response)
+    /// Host the office convert server is running on
+    pub fn host(&self) -> &str {
+        &self.host
     }
 
-    /// Gets the current busy status of the convert server
+    /// Gets the current busy status of the convert server, the server
+    /// is busy when it has no workers available
     pub async fn is_busy(&self) -> Result<bool, RequestError> {
         let status = self.get_status().await?;
-        Ok(status.is_busy)
+        Ok(!status.is_available())
+    }
+
+    /// Pre-establishes a connection to the server so the next request doesn't
+    /// pay for connecting, the connection is kept in the pool until it has been
+    /// idle for the pool idle timeout
+    pub async fn warm_up(&self) -> Result<(), RequestError> {
+        instrument(&self.host, "warm_up", async {
+            let route = self.route("/status");
+            let response = self.send(self.http.get(route)).await?;
+
+            // Body must be read for the connection to be returned to the pool
+            response
+                .bytes()
+                .await
+                .map_err(RequestError::InvalidResponse)?;
+
+            Ok(())
+        })
+        .await
     }
 
     /// Tells the converter server to collect garbage
     pub async fn collect_garbage(&self) -> Result<(), RequestError> {
-        let route = format!("{}/collect-garbage", self.host);
-        let response = self
-            .http
-            .post(route)
-            .send()
-            .await
-            .map_err(RequestError::RequestFailed)?;
+        instrument(&self.host, "collect_garbage", async {
+            let route = self.route("/collect-garbage");
+            let response = self.send(self.http.post(route)).await?;
 
-        let status = response.status();
+            let status = response.status();
 
-        // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    /// Converts the file read from the provided reader into a PDF writing the
+    /// PDF to the provided writer as it is received, the file and PDF are never
+    /// held in memory in full so gigabyte sized documents can be converted.
+    /// Provides the number of bytes written
+    ///
+    /// Upload compression is not applied to files read from a reader
+    ///
+    /// ## Arguments
+    /// * `file` - Reader to read the file to convert from
+    /// * `output` - Writer to write the converted PDF to
+    pub async fn convert_to_writer<R, W>(
+        &self,
+        file: R,
+        output: &mut W,
+    ) -> Result<u64, RequestError>
+    where
+        R: AsyncRead + Send + Sync + 'static,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        instrument(&self.host, "convert", async {
+            let route = self.route("/convert");
+            let part = Part::stream(Body::wrap_stream(ReaderStream::new(file)));
+            let form = Form::new().part("file", part);
+            let mut response = self.send(self.http.post(route).multipart(form)).await?;
+
+            let status = response.status();
+
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            let max_size = self.max_response_size.unwrap_or(u64::MAX);
+            if response
+                .content_length()
+                .is_some_and(|length| length > max_size)
+            {
+                return Err(RequestError::ResponseTooLarge { max: max_size });
+            }
+
+            let mut written: u64 = 0;
+
+            while let Some(chunk) = response
+                .chunk()
                 .await
-                .map_err(RequestError::InvalidResponse)?;
+                .map_err(RequestError::InvalidResponse)?
+            {
+                if written + chunk.len() as u64 > max_size {
+                    return Err(RequestError::ResponseTooLarge { max: max_size });
+                }
+
+                output
+                    .write_all(&chunk)
+                    .await
+                    .map_err(RequestError::Output)?;
+                written += chunk.len() as u64;
+            }
+
+            output.flush().await.map_err(RequestError::Output)?;
+
+            Ok(written)
+        })
+        .await
+    }
 
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
+    /// Converts the file read from the provided reader into a PDF writing the
+    /// PDF to the file at the output path as it is received, see
+    /// [OfficeConvertClient::convert_to_writer]. The output file is removed
+    /// when the conversion fails
+    ///
+    /// ## Arguments
+    /// * `file` - Reader to read the file to convert from
+    /// * `output` - Path to write the converted PDF to
+    pub async fn convert_to_file<R>(&self, file: R, output: &Path) -> Result<u64, RequestError>
+    where
+        R: AsyncRead + Send + Sync + 'static,
+    {
+        let mut output_file = tokio::fs::File::create(output)
+            .await
+            .map_err(RequestError::Output)?;
+
+        let result = self.convert_to_writer(file, &mut output_file).await;
+
+        if result.is_err() {
+            drop(output_file);
+            _ = tokio::fs::remove_file(output).await;
         }
 
-        Ok(())
+        result
     }
 }
 
 #[async_trait]
 impl ConvertOffice for OfficeConvertClient {
     async fn convert(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
-        let route = format!("{}/convert", self.host);
-        let form = Form::new().part("file", Part::bytes(file));
-        let response = self
-            .http
-            .post(route)
-            .multipart(form)
-            .send()
-            .await
-            .map_err(RequestError::RequestFailed)?;
-
-        let status = response.status();
-
-        // Handle error responses
-        if status.is_client_error() || status.is_server_error() {
-            let body: ErrorResponse = response
-                .json()
-                .await
-                .map_err(RequestError::InvalidResponse)?;
-
-            return Err(RequestError::ErrorResponse {
-                reason: body.reason,
-                backtrace: body.backtrace,
-            });
-        }
+        self.convert_request(ConvertRequest::new(file)).await
+    }
 
-        let response = response
-            .bytes()
+    async fn convert_request(&self, request: ConvertRequest) -> Result<Bytes, RequestError> {
+        self.convert_response(request)
             .await
-            .map_err(RequestError::InvalidResponse)?;
+            .map(|response| response.pdf)
+    }
 
-        Ok(response)
+    async fn convert_response(
+        &self,
+        request: ConvertRequest,
+    ) -> Result<ConvertResponse, RequestError> {
+        // Compression is CPU bound so is kept off the async runtime, files are
+        // uploaded uncompressed to servers that don't accept the compression
+        #[cfg(feature = "compression")]
+        let request = match self.upload_compression {
+            Some(compression) if self.accepts_compression(compression).await => {
+                tokio::task::spawn_blocking(move || request.compressed(compression))
+                    .await
+                    .map_err(|err| RequestError::Compression(std::io::Error::other(err)))?
+                    .map_err(RequestError::Compression)?
+            }
+            _ => request,
+        };
+
+        instrument(&self.host, "convert", async {
+            let route = self.route("/convert");
+            let mut builder = self.http.post(route);
+
+            if let Some(idempotency_key) = &request.idempotency_key {
+                builder = builder.header(request::IDEMPOTENCY_KEY_HEADER, idempotency_key);
+            }
+
+            if let Some(traceparent) = &request.traceparent {
+                builder = builder.header(request::TRACEPARENT_HEADER, traceparent);
+            }
+
+            let priority = request.priority;
+            let output_filename = request.output_filename.clone();
+            let mut form = Form::new().part("file", request.into_file_part()?);
+
+            if let Some(priority) = priority {
+                form = form.text("priority", priority.as_str());
+            }
+
+            if let Some(output_filename) = output_filename {
+                form = form.text("output_filename", output_filename);
+            }
+
+            let response = self.send(builder.multipart(form)).await?;
+
+            let status = response.status();
+
+            // Handle error responses
+            if status.is_client_error() || status.is_server_error() {
+                return Err(error_response(response).await);
+            }
+
+            let headers = response.headers().clone();
+            let pdf = read_body(response, self.max_response_size).await?;
+
+            Ok(ConvertResponse::from_headers(pdf, &headers))
+        })
+        .await
     }
 }
@@ -1,6 +1,13 @@
-use crate::{ConvertOffice, OfficeConvertClient, RequestError};
+use crate::{
+    breaker::{CircuitBreaker, CircuitBreakerOptions, CircuitState},
+    metrics::{BackendMetrics, BalancerObserver, LoadBalancerMetrics},
+    routing::{FirstFree, Hints, RoutingPolicy},
+    ConvertOffice, ConvertRequest, ConvertResponse, OfficeConvertClient, RequestError,
+};
 use async_trait::async_trait;
+use futures_util::{stream, StreamExt};
 use std::{
+    fmt::Debug,
     sync::{atomic::AtomicUsize, Arc},
     time::Duration,
 };
@@ -20,8 +27,45 @@ pub struct OfficeConvertLoadBalancer {
     inner: Arc<OfficeConvertLoadBalancerInner>,
 }
 
+/// Options for configuring the load balancer
+#[derive(Clone, Default)]
+pub struct LoadBalancerOptions {
+    /// Maximum number of times a conversion that failed with a retryable error
+    /// (Connection failure, server error) will be retried on a different server
+    /// before giving up, zero disables failover
+    pub max_failover_attempts: usize,
+
+    /// Generate an idempotency key for requests that don't provide one so that
+    /// servers can recognize repeated attempts of the same conversion
+    pub idempotency_keys: bool,
+
+    /// Observer to notify of load balancer events
+    pub observer: Option<Arc<dyn BalancerObserver>>,
+
+    /// Policy deciding which servers a conversion is tried on, defaults
+    /// to [FirstFree] which tries every server in order
+    pub routing: Option<Arc<dyn RoutingPolicy>>,
+
+    /// Circuit breaker skipping servers that fail repeatedly, see
+    /// [CircuitBreakerOptions]
+    pub circuit_breaker: CircuitBreakerOptions,
+}
+
+impl Debug for LoadBalancerOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LoadBalancerOptions")
+            .field("max_failover_attempts", &self.max_failover_attempts)
+            .field("idempotency_keys", &self.idempotency_keys)
+            .field("observer", &self.observer.is_some())
+            .field("routing", &self.routing.is_some())
+            .field("circuit_breaker", &self.circuit_breaker)
+            .finish()
+    }
+}
+
 impl OfficeConvertLoadBalancer {
     /// Creates a load balancer from the provided collection of clients
+    /// using the default options
     ///
     /// ## Arguments
     /// * `clients` - The clients to load balance amongst
@@ -29,20 +73,46 @@ impl OfficeConvertLoadBalancer {
     where
         I: IntoIterator<Item = OfficeConvertClient>,
     {
-        let clients = clients
+        Self::new_with_options(clients, LoadBalancerOptions::default())
+    }
+
+    /// Creates a load balancer from the provided collection of clients
+    /// using the provided options
+    ///
+    /// ## Arguments
+    /// * `clients` - The clients to load balance amongst
+    /// * `options` - The configuration options for the load balancer
+    pub fn new_with_options<I>(clients: I, options: LoadBalancerOptions) -> Self
+    where
+        I: IntoIterator<Item = OfficeConvertClient>,
+    {
+        let (clients, metrics): (Vec<_>, Vec<_>) = clients
             .into_iter()
             .map(|client| {
-                Mutex::new(LoadBalancedClient {
+                let metrics = BackendMetrics::new(client.host().to_string());
+                let client = LoadBalancedClient {
                     client,
                     busy_externally_at: None,
-                })
+                    throttled_until: None,
+                    breaker: CircuitBreaker::default(),
+                };
+
+                (client, metrics)
             })
-            .collect::<Vec<_>>();
+            .unzip();
+
+        let hosts = clients
+            .iter()
+            .map(|client| client.client.host().to_string())
+            .collect();
 
         let inner = OfficeConvertLoadBalancerInner {
-            clients,
+            clients: clients.into_iter().map(Mutex::new).collect(),
+            hosts,
+            metrics,
             free_notify: Notify::new(),
             active: AtomicUsize::new(0),
+            options,
         };
 
         Self {
@@ -50,6 +120,25 @@ impl OfficeConvertLoadBalancer {
         }
     }
 
+    /// Creates a snapshot of the current metrics for each server
+    pub fn metrics(&self) -> LoadBalancerMetrics {
+        LoadBalancerMetrics::from_backends(&self.inner.metrics)
+    }
+
+    /// Pre-establishes a connection to each server, see [OfficeConvertClient::warm_up].
+    /// Provides the outcome for each server in the same order as the clients
+    pub async fn warm_up(&self) -> Vec<Result<(), RequestError>> {
+        let mut results = Vec::with_capacity(self.inner.clients.len());
+
+        for client in self.inner.clients.iter() {
+            // Lock is only held long enough to copy the client
+            let client = client.lock().await.client.clone();
+            results.push(client.warm_up().await);
+        }
+
+        results
+    }
+
     /// Checks if all client connections are blocked externally, used
     /// to handle the case when to not wait on notifiers
     pub async fn is_externally_blocked(&self) -> bool {
@@ -76,11 +165,21 @@ struct OfficeConvertLoadBalancerInner {
     /// Available clients the load balancer can use
     clients: Vec<Mutex<LoadBalancedClient>>,
 
+    /// Hosts of the clients, provided to the routing policy
+    hosts: Vec<String>,
+
+    /// Metrics for each client, tracked separately so they can be
+    /// read while a client is in use
+    metrics: Vec<BackendMetrics>,
+
     /// Number of active in use clients
     active: AtomicUsize,
 
     /// Notifier for connections that are no longer busy
     free_notify: Notify,
+
+    /// Load balancer configuration
+    options: LoadBalancerOptions,
 }
 
 struct LoadBalancedClient {
@@ -89,17 +188,126 @@ struct LoadBalancedClient {
 
     /// Last time the server reported as busy externally
     busy_externally_at: Option<Instant>,
+
+    /// Time until which the server asked not to be sent conversions
+    throttled_until: Option<Instant>,
+
+    /// Circuit breaker for the server
+    breaker: CircuitBreaker,
+}
+
+impl OfficeConvertLoadBalancerInner {
+    /// Takes the client out of rotation until its next busy check, notifies
+    /// the observer when the client was previously in rotation
+    fn mark_busy_externally(&self, index: usize, client: &mut LoadBalancedClient, now: Instant) {
+        let was_busy = client.busy_externally_at.is_some();
+        client.busy_externally_at = Some(now);
+
+        if was_busy {
+            return;
+        }
+
+        self.metrics[index].set_busy_externally(Some(now.into_std()));
+
+        if let Some(observer) = &self.options.observer {
+            observer.on_evict(index, client.client.host());
+        }
+    }
+
+    /// Skips the client until the delay the server asked for has passed,
+    /// notifies the observer of the delay
+    fn mark_throttled(
+        &self,
+        index: usize,
+        client: &mut LoadBalancedClient,
+        retry_after: Option<Duration>,
+    ) {
+        let delay = retry_after.unwrap_or(DEFAULT_THROTTLE_DELAY);
+        client.throttled_until = Some(Instant::now() + delay);
+
+        debug!("server at {index} throttled conversions for {delay:?}");
+
+        if let Some(observer) = &self.options.observer {
+            observer.on_throttle(index, client.client.host(), delay);
+        }
+    }
+
+    /// Updates the circuit breaker metrics for the client, notifies the
+    /// observer when the breaker changed state
+    fn update_circuit(
+        &self,
+        index: usize,
+        client: &LoadBalancedClient,
+        changed: Option<CircuitState>,
+    ) {
+        let breaker = &client.breaker;
+        self.metrics[index].set_circuit(
+            breaker.state(),
+            breaker.consecutive_failures(),
+            breaker.open_until().map(Instant::into_std),
+        );
+
+        let Some(state) = changed else {
+            return;
+        };
+
+        debug!("circuit breaker for server at {index} is now {state:?}");
+
+        if let Some(observer) = &self.options.observer {
+            observer.on_circuit_change(index, client.client.host(), state);
+        }
+    }
+
+    /// Records a failure of the server with its circuit breaker
+    fn record_failure(&self, index: usize, client: &mut LoadBalancedClient) {
+        let changed = client
+            .breaker
+            .record_failure(&self.options.circuit_breaker, Instant::now());
+        self.update_circuit(index, client, changed);
+    }
+
+    /// Records a success of the server with its circuit breaker
+    fn record_success(&self, index: usize, client: &mut LoadBalancedClient) {
+        let changed = client.breaker.record_success();
+        self.update_circuit(index, client, changed);
+    }
+
+    /// Returns the client to rotation after passing a busy check
+    fn clear_busy_externally(&self, index: usize, client: &mut LoadBalancedClient) {
+        if client.busy_externally_at.take().is_some() {
+            self.metrics[index].set_busy_externally(None);
+        }
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum LoadBalanceError {
     #[error("no servers available for load balancing")]
     NoServers,
+
+    /// Conversion failed on every server it was attempted on
+    #[error("conversion failed on all attempted servers ({} attempts)", .0.len())]
+    AllBackendsFailed(Vec<BackendFailure>),
+}
+
+/// Failed conversion attempt on a specific server
+#[derive(Debug)]
+pub struct BackendFailure {
+    /// Index of the client within the load balancer
+    pub index: usize,
+    /// Host of the server that failed
+    pub host: String,
+    /// The error that occurred
+    pub cause: RequestError,
 }
 
 /// Time in-between external busy checks
 const RETRY_BUSY_CHECK_AFTER: Duration = Duration::from_secs(5);
 
+/// Time to skip a server that throttled a conversion without
+/// providing a delay
+const DEFAULT_THROTTLE_DELAY: Duration = Duration::from_secs(5);
+
 /// Time to wait before repeated attempts
 const RETRY_SINGLE_EXTERNAL: Duration = Duration::from_secs(1);
 
@@ -109,13 +317,138 @@ const NOTIFY_TIMEOUT: Duration = Duration::from_secs(120);
 #[async_trait]
 impl ConvertOffice for OfficeConvertLoadBalancer {
     async fn convert(&self, file: Vec<u8>) -> Result<bytes::Bytes, RequestError> {
+        self.convert_request(ConvertRequest::new(file)).await
+    }
+
+    async fn convert_request(&self, request: ConvertRequest) -> Result<bytes::Bytes, RequestError> {
+        self.convert_request_with_hints(request, Hints::default())
+            .await
+    }
+
+    async fn convert_response(
+        &self,
+        request: ConvertRequest,
+    ) -> Result<ConvertResponse, RequestError> {
+        self.convert_response_with_hints(request, Hints::default())
+            .await
+    }
+}
+
+impl OfficeConvertLoadBalancer {
+    /// Converts a batch of files spreading the conversions across the servers,
+    /// at most `max_parallel` conversions are made at the same time (at least
+    /// one). Provides the outcome for each file in the same order as the files,
+    /// a failed conversion doesn't stop the remaining files from converting
+    ///
+    /// ## Arguments
+    /// * `files` - The file bytes to convert
+    /// * `max_parallel` - Maximum number of conversions to make at the same time
+    pub async fn convert_many<I>(
+        &self,
+        files: I,
+        max_parallel: usize,
+    ) -> Vec<Result<bytes::Bytes, RequestError>>
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+    {
+        stream::iter(files)
+            .map(|file| self.convert(file))
+            .buffered(max_parallel.max(1))
+            .collect()
+            .await
+    }
+
+    /// Converts the provided file using the hints to decide which servers
+    /// the conversion is made on, see [RoutingPolicy]
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    /// * `hints` - Hints about the conversion
+    pub async fn convert_with_hints(
+        &self,
+        file: Vec<u8>,
+        hints: Hints,
+    ) -> Result<bytes::Bytes, RequestError> {
+        self.convert_request_with_hints(ConvertRequest::new(file), hints)
+            .await
+    }
+
+    /// Converts the file from the provided request using the hints to decide
+    /// which servers the conversion is made on, see [RoutingPolicy]
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    /// * `hints` - Hints about the conversion
+    pub async fn convert_request_with_hints(
+        &self,
+        request: ConvertRequest,
+        hints: Hints,
+    ) -> Result<bytes::Bytes, RequestError> {
+        self.convert_response_with_hints(request, hints)
+            .await
+            .map(|response| response.pdf)
+    }
+
+    /// Converts the file from the provided request using the hints to decide
+    /// which servers the conversion is made on, see [RoutingPolicy]. Provides
+    /// the PDF along with the details the server provided about the conversion
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    /// * `hints` - Hints about the conversion
+    pub async fn convert_response_with_hints(
+        &self,
+        request: ConvertRequest,
+        hints: Hints,
+    ) -> Result<ConvertResponse, RequestError> {
         let inner = &*self.inner;
 
-        let total_clients = inner.clients.len();
+        let mut order = match &inner.options.routing {
+            Some(routing) => routing.route(&hints, request.file.len(), &inner.hosts),
+            None => FirstFree.route(&hints, request.file.len(), &inner.hosts),
+        };
+
+        // Servers that don't exist or are repeated by the policy are ignored
+        let mut seen = vec![false; inner.clients.len()];
+        order.retain(|index| {
+            seen.get_mut(*index)
+                .is_some_and(|seen| !std::mem::replace(seen, true))
+        });
+
+        if order.is_empty() {
+            return Err(LoadBalanceError::NoServers.into());
+        }
+
+        let total_clients = order.len();
         let multiple_clients = total_clients > 1;
+        let max_failover_attempts = inner.options.max_failover_attempts;
+
+        let mut request = request;
+
+        // Every attempt shares the same key
+        if inner.options.idempotency_keys {
+            request.ensure_idempotency_key();
+        }
+
+        // Failed attempts on other servers when failing over
+        let mut failures: Vec<BackendFailure> = Vec::new();
+
+        // Number of attempts that failed or were throttled
+        let mut attempts = 0;
 
         loop {
-            for (index, client) in inner.clients.iter().enumerate() {
+            // Earliest time a throttled server or a server with an open circuit
+            // breaker accepts conversions again
+            let mut next_available_at: Option<Instant> = None;
+
+            for &index in &order {
+                let client = &inner.clients[index];
+
+                // Don't attempt the same server again when failing over
+                if failures.iter().any(|failure| failure.index == index) {
+                    continue;
+                }
+
                 let mut client = match client.try_lock() {
                     Ok(value) => value,
                     // Server is already in use
@@ -126,6 +459,32 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
 
                 let now = Instant::now();
 
+                // Don't route to the server until the delay it asked for has passed
+                if let Some(throttled_until) = client.throttled_until {
+                    if now < throttled_until {
+                        next_available_at = Some(
+                            next_available_at
+                                .map_or(throttled_until, |value| value.min(throttled_until)),
+                        );
+                        continue;
+                    }
+
+                    client.throttled_until = None;
+                }
+
+                // Skip servers that failed repeatedly until their breaker allows a trial
+                if let Some(open_until) = client.breaker.open_until() {
+                    if now < open_until {
+                        next_available_at = Some(
+                            next_available_at.map_or(open_until, |value| value.min(open_until)),
+                        );
+                        continue;
+                    }
+
+                    let changed = client.breaker.half_open();
+                    inner.update_circuit(index, client, changed);
+                }
+
                 if let Some(busy_externally_at) = client.busy_externally_at {
                     let since_check = now.duration_since(busy_externally_at);
 
@@ -136,10 +495,12 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
                 }
 
                 // Check if the server is busy externally (Busy outside of our control)
-                let externally_busy = match client.client.is_busy().await {
-                    Ok(value) => value,
+                let externally_busy = match client.client.get_status().await {
+                    // Busy when the server has no available workers
+                    Ok(status) => !status.is_available(),
                     Err(err) => {
                         error!("failed to perform server busy check at {index}: {err}");
+                        inner.record_failure(index, client);
 
                         // Mark erroneous servers as busy
                         true
@@ -150,21 +511,47 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
                 if externally_busy {
                     debug!("server at {index} is busy externally");
 
-                    client.busy_externally_at = Some(now);
+                    inner.mark_busy_externally(index, client, now);
                     continue;
                 }
 
                 // Clear external busy state
-                client.busy_externally_at = None;
+                inner.clear_busy_externally(index, client);
 
                 debug!("obtained available server {index} for convert");
 
+                let metrics = &inner.metrics[index];
+                metrics.start();
+
+                if let Some(observer) = &inner.options.observer {
+                    observer.on_acquire(index, client.client.host());
+                }
+
                 // Increase active counter
                 inner
                     .active
                     .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
 
-                let response = client.client.convert(file).await;
+                // Whether another attempt can be made if this one fails, throttled
+                // servers can be attempted again once their delay has passed
+                let can_retry = attempts < max_failover_attempts;
+                let can_failover = can_retry && failures.len() + 1 < total_clients;
+
+                // Keep a copy of the request when it may need to be sent again
+                let payload = if can_retry {
+                    request.clone()
+                } else {
+                    std::mem::take(&mut request)
+                };
+
+                let started_at = Instant::now();
+                let response = client.client.convert_response(payload).await;
+
+                metrics.finish(response.is_ok(), started_at.elapsed());
+
+                if let (Err(cause), Some(observer)) = (&response, &inner.options.observer) {
+                    observer.on_failure(index, client.client.host(), cause);
+                }
 
                 // Notify waiters that this server is now free
                 inner.free_notify.notify_waiters();
@@ -174,7 +561,47 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
                     .active
                     .fetch_sub(1, std::sync::atomic::Ordering::SeqCst);
 
-                return response;
+                match &response {
+                    Err(RequestError::Throttled { retry_after, .. }) => {
+                        inner.mark_throttled(index, client, *retry_after)
+                    }
+                    Err(cause) if cause.is_retryable() => inner.record_failure(index, client),
+                    // Server handled the request even when it rejected the file
+                    _ => inner.record_success(index, client),
+                }
+
+                let cause = match response {
+                    Err(cause) if max_failover_attempts > 0 && cause.is_retryable() => cause,
+                    response => return response,
+                };
+
+                attempts += 1;
+
+                // Throttling isn't a failure of the server, the conversion is routed
+                // again skipping the server until its delay has passed
+                if let RequestError::Throttled { .. } = cause {
+                    if !can_retry {
+                        return Err(cause);
+                    }
+
+                    debug!("convert throttled on server {index}, routing again");
+                    continue;
+                }
+
+                error!("convert failed on server {index}, failing over: {cause}");
+
+                // Mark the server as busy so its checked before being used again
+                inner.mark_busy_externally(index, client, Instant::now());
+
+                failures.push(BackendFailure {
+                    index,
+                    host: client.client.host().to_string(),
+                    cause,
+                });
+
+                if !can_failover {
+                    return Err(LoadBalanceError::AllBackendsFailed(failures).into());
+                }
             }
 
             let active_counter = inner.active.load(std::sync::atomic::Ordering::SeqCst);
@@ -193,9 +620,15 @@ impl ConvertOffice for OfficeConvertLoadBalancer {
 
             debug!("no available servers, waiting until one is available");
 
-            // All servers are in use, wait for the free notifier, this has a timeout
-            // incase a complication occurs
-            _ = timeout(NOTIFY_TIMEOUT, inner.free_notify.notified()).await;
+            // All servers are in use, wait for the free notifier, a throttled server
+            // or a server with an open circuit breaker to accept conversions again,
+            // this has a timeout incase a complication occurs
+            let wait = next_available_at.map_or(NOTIFY_TIMEOUT, |expires_at| {
+                expires_at
+                    .saturating_duration_since(Instant::now())
+                    .min(NOTIFY_TIMEOUT)
+            });
+            _ = timeout(wait, inner.free_notify.notified()).await;
         }
     }
 }
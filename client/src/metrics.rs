@@ -0,0 +1,236 @@
+use crate::{breaker::CircuitState, RequestError};
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Observer notified of load balancer events, used to export the state
+/// of the load balancer to an external metrics stack. Callbacks are
+/// invoked inline so should not block
+pub trait BalancerObserver: Send + Sync {
+    /// Server was acquired to perform a conversion
+    ///
+    /// ## Arguments
+    /// * `index` - Index of the client within the load balancer
+    /// * `host` - Host of the server
+    fn on_acquire(&self, index: usize, host: &str) {
+        _ = (index, host);
+    }
+
+    /// Conversion failed on a server
+    ///
+    /// ## Arguments
+    /// * `index` - Index of the client within the load balancer
+    /// * `host` - Host of the server
+    /// * `cause` - The error that occurred
+    fn on_failure(&self, index: usize, host: &str, cause: &RequestError) {
+        _ = (index, host, cause);
+    }
+
+    /// Server was taken out of rotation, either because it reported as busy
+    /// externally, failed its status check, or failed a conversion when
+    /// failing over. The server is checked again before being used
+    ///
+    /// ## Arguments
+    /// * `index` - Index of the client within the load balancer
+    /// * `host` - Host of the server
+    fn on_evict(&self, index: usize, host: &str) {
+        _ = (index, host);
+    }
+
+    /// Server throttled a conversion (429 or 503 response), the server is
+    /// skipped until the delay has passed
+    ///
+    /// ## Arguments
+    /// * `index` - Index of the client within the load balancer
+    /// * `host` - Host of the server
+    /// * `delay` - Time the server is skipped for, from the Retry-After or
+    ///   RateLimit headers when provided
+    fn on_throttle(&self, index: usize, host: &str, delay: Duration) {
+        _ = (index, host, delay);
+    }
+
+    /// Circuit breaker for a server changed state, see [CircuitState]
+    ///
+    /// ## Arguments
+    /// * `index` - Index of the client within the load balancer
+    /// * `host` - Host of the server
+    /// * `state` - The new state of the breaker
+    fn on_circuit_change(&self, index: usize, host: &str, state: CircuitState) {
+        _ = (index, host, state);
+    }
+}
+
+/// Metrics tracked for a server within the load balancer
+pub(crate) struct BackendMetrics {
+    /// Host of the server
+    host: String,
+    /// Number of conversions currently running
+    in_flight: AtomicUsize,
+    /// Number of successful conversions
+    conversions: AtomicU64,
+    /// Number of failed conversions
+    failures: AtomicU64,
+    /// Total time in milliseconds spent on successful conversions
+    total_latency_ms: AtomicU64,
+    /// When the server was taken out of rotation for being busy externally
+    busy_externally_since: Mutex<Option<Instant>>,
+    /// State of the circuit breaker for the server
+    circuit: Mutex<CircuitMetrics>,
+}
+
+/// Circuit breaker state tracked for a server
+#[derive(Default)]
+struct CircuitMetrics {
+    /// Current state of the breaker
+    state: CircuitState,
+    /// Number of failures since the last success
+    consecutive_failures: u32,
+    /// Time until which the server is skipped while open
+    open_until: Option<Instant>,
+}
+
+impl BackendMetrics {
+    pub(crate) fn new(host: String) -> Self {
+        Self {
+            host,
+            in_flight: AtomicUsize::new(0),
+            conversions: AtomicU64::new(0),
+            failures: AtomicU64::new(0),
+            total_latency_ms: AtomicU64::new(0),
+            busy_externally_since: Mutex::new(None),
+            circuit: Mutex::default(),
+        }
+    }
+
+    /// Marks the start of a conversion
+    pub(crate) fn start(&self) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Marks the end of a conversion
+    pub(crate) fn finish(&self, success: bool, latency: Duration) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        if success {
+            self.conversions.fetch_add(1, Ordering::SeqCst);
+            self.total_latency_ms
+                .fetch_add(latency.as_millis() as u64, Ordering::SeqCst);
+        } else {
+            self.failures.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    /// Sets whether the server is busy externally
+    pub(crate) fn set_busy_externally(&self, busy_since: Option<Instant>) {
+        if let Ok(mut value) = self.busy_externally_since.lock() {
+            *value = busy_since;
+        }
+    }
+
+    /// Sets the state of the circuit breaker for the server
+    pub(crate) fn set_circuit(
+        &self,
+        state: CircuitState,
+        consecutive_failures: u32,
+        open_until: Option<Instant>,
+    ) {
+        if let Ok(mut value) = self.circuit.lock() {
+            *value = CircuitMetrics {
+                state,
+                consecutive_failures,
+                open_until,
+            };
+        }
+    }
+
+    /// Creates a snapshot of the current metrics
+    fn snapshot(&self, index: usize) -> BackendMetricsSnapshot {
+        let conversions = self.conversions.load(Ordering::SeqCst);
+        let total_latency_ms = self.total_latency_ms.load(Ordering::SeqCst);
+
+        let average_latency = match conversions {
+            0 => None,
+            conversions => Some(Duration::from_millis(total_latency_ms / conversions)),
+        };
+
+        let busy_externally_for = self
+            .busy_externally_since
+            .lock()
+            .ok()
+            .and_then(|value| *value)
+            .map(|busy_since| busy_since.elapsed());
+
+        let (circuit, consecutive_failures, circuit_open_for) = self
+            .circuit
+            .lock()
+            .map(|value| {
+                let open_for = value
+                    .open_until
+                    .map(|open_until| open_until.saturating_duration_since(Instant::now()));
+                (value.state, value.consecutive_failures, open_for)
+            })
+            .unwrap_or_default();
+
+        BackendMetricsSnapshot {
+            index,
+            host: self.host.clone(),
+            in_flight: self.in_flight.load(Ordering::SeqCst),
+            conversions,
+            failures: self.failures.load(Ordering::SeqCst),
+            average_latency,
+            busy_externally_for,
+            circuit,
+            consecutive_failures,
+            circuit_open_for,
+        }
+    }
+}
+
+/// Snapshot of the load balancer metrics
+#[derive(Debug, Clone)]
+pub struct LoadBalancerMetrics {
+    /// Metrics for each server, in the same order as the clients
+    pub backends: Vec<BackendMetricsSnapshot>,
+}
+
+impl LoadBalancerMetrics {
+    pub(crate) fn from_backends(backends: &[BackendMetrics]) -> Self {
+        Self {
+            backends: backends
+                .iter()
+                .enumerate()
+                .map(|(index, backend)| backend.snapshot(index))
+                .collect(),
+        }
+    }
+}
+
+/// Snapshot of the metrics for a server
+#[derive(Debug, Clone)]
+pub struct BackendMetricsSnapshot {
+    /// Index of the client within the load balancer
+    pub index: usize,
+    /// Host of the server
+    pub host: String,
+    /// Number of conversions currently running on the server
+    pub in_flight: usize,
+    /// Number of successful conversions
+    pub conversions: u64,
+    /// Number of failed conversions
+    pub failures: u64,
+    /// Average time taken by successful conversions
+    pub average_latency: Option<Duration>,
+    /// Time the server has been out of rotation for being busy externally
+    pub busy_externally_for: Option<Duration>,
+    /// State of the circuit breaker for the server
+    pub circuit: CircuitState,
+    /// Number of conversions that failed in a row since the last success
+    pub consecutive_failures: u32,
+    /// Time remaining until the server is tried again while the circuit
+    /// breaker is open
+    pub circuit_open_for: Option<Duration>,
+}
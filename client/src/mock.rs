@@ -0,0 +1,219 @@
+use crate::{ConvertOffice, ConvertRequest, Priority, RequestError};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// Minimal single page PDF returned by the mock when no PDF is provided
+pub const MOCK_PDF: &[u8] = b"%PDF-1.4
+1 0 obj << /Type /Catalog /Pages 2 0 R >> endobj
+2 0 obj << /Type /Pages /Kids [3 0 R] /Count 1 >> endobj
+3 0 obj << /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] >> endobj
+trailer << /Root 1 0 R >>
+%%EOF
+";
+
+/// Test double for [ConvertOffice] that converts without a server, conversions
+/// are recorded and respond with canned PDFs or scripted errors so conversion
+/// flows can be tested without running the server and LibreOffice
+///
+/// Scripted responses are used in the order they were pushed, once they run
+/// out every conversion responds with the default PDF. Clones of the mock share
+/// the same responses and recorded calls
+#[derive(Clone, Default)]
+pub struct MockConvertOffice {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    /// PDF returned when there are no scripted responses, [MOCK_PDF] when not set
+    default_pdf: Option<Bytes>,
+    /// Delay before every conversion responds
+    latency: Option<Duration>,
+    /// Scripted responses used before the default PDF
+    responses: VecDeque<MockResponse>,
+    /// Conversions made using the mock
+    calls: Vec<MockCall>,
+}
+
+/// Scripted response for a single conversion
+#[derive(Debug)]
+pub struct MockResponse {
+    /// Result of the conversion
+    result: Result<Bytes, RequestError>,
+    /// Delay before responding, replaces the latency of the mock
+    latency: Option<Duration>,
+}
+
+impl MockResponse {
+    /// Creates a response providing the PDF
+    ///
+    /// ## Arguments
+    /// * `pdf` - The PDF bytes to respond with
+    pub fn pdf<B>(pdf: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        Self {
+            result: Ok(pdf.into()),
+            latency: None,
+        }
+    }
+
+    /// Creates a response failing with the error
+    ///
+    /// ## Arguments
+    /// * `error` - The error to fail with
+    pub fn error(error: RequestError) -> Self {
+        Self {
+            result: Err(error),
+            latency: None,
+        }
+    }
+
+    /// Sets the delay before responding
+    ///
+    /// ## Arguments
+    /// * `latency` - Time to wait before responding
+    pub fn latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+}
+
+/// Conversion made using the mock
+#[derive(Debug, Clone)]
+pub struct MockCall {
+    /// The file bytes provided for conversion
+    pub file: Vec<u8>,
+    /// Idempotency key of the request
+    pub idempotency_key: Option<String>,
+    /// Priority of the request
+    pub priority: Option<Priority>,
+    /// Original name of the file
+    pub file_name: Option<String>,
+    /// Mime type of the file
+    pub mime_type: Option<String>,
+    /// File name requested for the converted file
+    pub output_filename: Option<String>,
+}
+
+impl MockConvertOffice {
+    /// Creates a mock responding to every conversion with [MOCK_PDF]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the PDF provided when there are no scripted responses
+    ///
+    /// ## Arguments
+    /// * `pdf` - The PDF bytes to respond with
+    pub fn with_pdf<B>(self, pdf: B) -> Self
+    where
+        B: Into<Bytes>,
+    {
+        self.lock().default_pdf = Some(pdf.into());
+        self
+    }
+
+    /// Sets the delay before every conversion responds
+    ///
+    /// ## Arguments
+    /// * `latency` - Time to wait before responding
+    pub fn with_latency(self, latency: Duration) -> Self {
+        self.lock().latency = Some(latency);
+        self
+    }
+
+    /// Adds a scripted response used by the next conversion without one
+    ///
+    /// ## Arguments
+    /// * `response` - The response to add
+    pub fn push(&self, response: MockResponse) -> &Self {
+        self.lock().responses.push_back(response);
+        self
+    }
+
+    /// Adds a scripted response providing the PDF
+    ///
+    /// ## Arguments
+    /// * `pdf` - The PDF bytes to respond with
+    pub fn push_pdf<B>(&self, pdf: B) -> &Self
+    where
+        B: Into<Bytes>,
+    {
+        self.push(MockResponse::pdf(pdf))
+    }
+
+    /// Adds a scripted response failing with the error
+    ///
+    /// ## Arguments
+    /// * `error` - The error to fail with
+    pub fn push_error(&self, error: RequestError) -> &Self {
+        self.push(MockResponse::error(error))
+    }
+
+    /// Provides the conversions made using the mock in the order they were made
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.lock().calls.clone()
+    }
+
+    /// Number of conversions made using the mock
+    pub fn call_count(&self) -> usize {
+        self.lock().calls.len()
+    }
+
+    /// Clears the recorded conversions
+    pub fn clear_calls(&self) {
+        self.lock().calls.clear();
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MockState> {
+        // State is only modified in ways that can't panic
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+#[async_trait]
+impl ConvertOffice for MockConvertOffice {
+    async fn convert(&self, file: Vec<u8>) -> Result<Bytes, RequestError> {
+        self.convert_request(ConvertRequest::new(file)).await
+    }
+
+    async fn convert_request(&self, request: ConvertRequest) -> Result<Bytes, RequestError> {
+        let (result, latency) = {
+            let state = &mut *self.lock();
+
+            state.calls.push(MockCall {
+                file: request.file,
+                idempotency_key: request.idempotency_key,
+                priority: request.priority,
+                file_name: request.file_name,
+                mime_type: request.mime_type,
+                output_filename: request.output_filename,
+            });
+
+            match state.responses.pop_front() {
+                Some(response) => (response.result, response.latency.or(state.latency)),
+                None => {
+                    let pdf = state
+                        .default_pdf
+                        .clone()
+                        .unwrap_or_else(|| Bytes::from_static(MOCK_PDF));
+
+                    (Ok(pdf), state.latency)
+                }
+            }
+        };
+
+        if let Some(latency) = latency {
+            tokio::time::sleep(latency).await;
+        }
+
+        result
+    }
+}
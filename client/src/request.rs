@@ -0,0 +1,388 @@
+use crate::RequestError;
+use bytes::Bytes;
+use futures_util::{stream, StreamExt};
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, CONTENT_ENCODING},
+    multipart::Part,
+    Body,
+};
+use std::{fmt::Debug, sync::Arc};
+
+/// Size of the chunks the file is uploaded in when tracking progress
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Header the idempotency key is provided to the server in
+pub(crate) const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Header the W3C trace context is provided to the server in
+pub(crate) const TRACEPARENT_HEADER: &str = "traceparent";
+
+/// Callback invoked with the number of bytes uploaded and the total
+/// number of bytes to upload
+pub type UploadProgressFn = Arc<dyn Fn(u64, u64) + Send + Sync>;
+
+/// Request to convert a file, allows providing additional options
+/// alongside the file
+#[derive(Clone, Default)]
+pub struct ConvertRequest {
+    /// The file bytes to convert
+    pub(crate) file: Vec<u8>,
+    /// Optional callback for tracking upload progress
+    pub(crate) upload_progress: Option<UploadProgressFn>,
+    /// Optional key identifying repeated attempts of the same request
+    pub(crate) idempotency_key: Option<String>,
+    /// Optional priority of the conversion on the server
+    pub(crate) priority: Option<Priority>,
+    /// Encoding the file was compressed with before uploading
+    pub(crate) content_encoding: Option<&'static str>,
+    /// Optional W3C trace context the server continues the trace from
+    pub(crate) traceparent: Option<String>,
+    /// Optional original name of the file (i.e "report.docx")
+    pub(crate) file_name: Option<String>,
+    /// Optional mime type of the file
+    pub(crate) mime_type: Option<String>,
+    /// Optional file name for the converted file (i.e "report.pdf")
+    pub(crate) output_filename: Option<String>,
+}
+
+/// Compression applied to files before they are uploaded to the server,
+/// the server decompresses the file before converting it
+#[cfg(feature = "compression")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UploadCompression {
+    /// gzip compression (Default compression level)
+    Gzip,
+    /// zstd compression (Default compression level)
+    Zstd,
+}
+
+#[cfg(feature = "compression")]
+impl UploadCompression {
+    /// Content encoding the server is told the file was compressed with
+    pub(crate) fn content_encoding(&self) -> &'static str {
+        match self {
+            UploadCompression::Gzip => "gzip",
+            UploadCompression::Zstd => "zstd",
+        }
+    }
+
+    /// Compresses the provided bytes
+    fn compress(&self, bytes: &[u8]) -> std::io::Result<Vec<u8>> {
+        use std::io::Write;
+
+        match self {
+            UploadCompression::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                encoder.finish()
+            }
+            UploadCompression::Zstd => zstd::encode_all(bytes, zstd::DEFAULT_COMPRESSION_LEVEL),
+        }
+    }
+}
+
+/// Priority of a conversion on the server, when the server is busy waiting
+/// conversions with a higher priority are converted first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    High,
+    Normal,
+    Low,
+}
+
+impl Priority {
+    /// Value of the priority field sent to the server
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Priority::High => "high",
+            Priority::Normal => "normal",
+            Priority::Low => "low",
+        }
+    }
+}
+
+impl Debug for ConvertRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConvertRequest")
+            .field("file", &self.file.len())
+            .field("upload_progress", &self.upload_progress.is_some())
+            .field("idempotency_key", &self.idempotency_key)
+            .field("priority", &self.priority)
+            .field("content_encoding", &self.content_encoding)
+            .field("traceparent", &self.traceparent)
+            .field("file_name", &self.file_name)
+            .field("mime_type", &self.mime_type)
+            .field("output_filename", &self.output_filename)
+            .finish()
+    }
+}
+
+impl ConvertRequest {
+    /// Creates a new request to convert the provided file bytes
+    ///
+    /// ## Arguments
+    /// * `file` - The file bytes to convert
+    pub fn new(file: Vec<u8>) -> Self {
+        Self {
+            file,
+            upload_progress: None,
+            idempotency_key: None,
+            priority: None,
+            content_encoding: None,
+            traceparent: None,
+            file_name: None,
+            mime_type: None,
+            output_filename: None,
+        }
+    }
+
+    /// Sets the original name of the file, the server uses the file name to
+    /// pick how the file is imported (i.e emails and file type detection)
+    ///
+    /// ## Arguments
+    /// * `file_name` - The name of the file (i.e "report.docx")
+    pub fn file_name<N>(mut self, file_name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.file_name = Some(file_name.into());
+        self
+    }
+
+    /// Sets the mime type of the file, the server uses the mime type to pick
+    /// how the file is imported when the file name isn't enough
+    ///
+    /// ## Arguments
+    /// * `mime_type` - The mime type of the file (i.e "application/vnd.ms-excel")
+    pub fn mime_type<M>(mut self, mime_type: M) -> Self
+    where
+        M: Into<String>,
+    {
+        self.mime_type = Some(mime_type.into());
+        self
+    }
+
+    /// Sets the file name of the converted file, the server provides the name in
+    /// the Content-Disposition header of the response (see [ConvertResponse::file_name]).
+    /// Defaults to the file name with a ".pdf" extension
+    ///
+    /// ## Arguments
+    /// * `output_filename` - The name of the converted file (i.e "report.pdf")
+    ///
+    /// [ConvertResponse::file_name]: crate::ConvertResponse::file_name
+    pub fn output_filename<N>(mut self, output_filename: N) -> Self
+    where
+        N: Into<String>,
+    {
+        self.output_filename = Some(output_filename.into());
+        self
+    }
+
+    /// Sets a callback to invoke as the file is uploaded to the server,
+    /// the callback is provided the number of bytes uploaded and the
+    /// total number of bytes
+    ///
+    /// ## Arguments
+    /// * `callback` - The progress callback
+    pub fn on_upload_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(u64, u64) + Send + Sync + 'static,
+    {
+        self.upload_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets the idempotency key for the request, the server responds to repeated
+    /// requests with the same key using the result of the first request instead
+    /// of converting the file again
+    ///
+    /// ## Arguments
+    /// * `key` - The idempotency key, 1 to 255 visible ASCII characters
+    pub fn idempotency_key<K>(mut self, key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Sets the priority of the conversion on the server, servers with tenants
+    /// configured don't allow raising the priority above the tenant priority
+    ///
+    /// ## Arguments
+    /// * `priority` - The priority of the conversion
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// Sets the W3C trace context of the request, servers exporting traces
+    /// continue the trace so the conversion appears within the trace of the
+    /// caller. The load balancer sends the same trace context to every server
+    /// it tries
+    ///
+    /// ## Arguments
+    /// * `traceparent` - The "traceparent" header value (i.e "00-{trace id}-{span id}-01")
+    pub fn traceparent<T>(mut self, traceparent: T) -> Self
+    where
+        T: Into<String>,
+    {
+        self.traceparent = Some(traceparent.into());
+        self
+    }
+
+    /// Sets a randomly generated idempotency key for the request if
+    /// the request doesn't already have a key
+    pub(crate) fn ensure_idempotency_key(&mut self) {
+        if self.idempotency_key.is_some() {
+            return;
+        }
+
+        let key = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(|value| value as char)
+            .collect::<String>();
+
+        self.idempotency_key = Some(key);
+    }
+
+    /// Compresses the file before it is uploaded, upload progress is reported
+    /// using the size of the compressed file
+    #[cfg(feature = "compression")]
+    pub(crate) fn compressed(mut self, compression: UploadCompression) -> std::io::Result<Self> {
+        // Files are only compressed once when the request is repeated
+        if self.content_encoding.is_some() {
+            return Ok(self);
+        }
+
+        self.file = compression.compress(&self.file)?;
+        self.content_encoding = Some(compression.content_encoding());
+        Ok(self)
+    }
+
+    /// Headers for the file part, includes the encoding the file was compressed with
+    fn file_part_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let Some(content_encoding) = self.content_encoding {
+            headers.insert(CONTENT_ENCODING, HeaderValue::from_static(content_encoding));
+        }
+
+        headers
+    }
+
+    /// Creates the multipart form part for the file, when tracking progress
+    /// the file is streamed in chunks reporting progress as each is sent
+    pub(crate) fn into_file_part(self) -> Result<Part, RequestError> {
+        let headers = self.file_part_headers();
+        let (file_name, mime_type) = (self.file_name, self.mime_type);
+
+        let part = match self.upload_progress {
+            Some(callback) => Self::progress_part(self.file, callback),
+            None => Part::bytes(self.file),
+        };
+
+        let mut part = part.headers(headers);
+
+        if let Some(file_name) = file_name {
+            part = part.file_name(file_name);
+        }
+
+        match mime_type {
+            Some(mime_type) => part
+                .mime_str(&mime_type)
+                .map_err(RequestError::RequestFailed),
+            None => Ok(part),
+        }
+    }
+
+    /// Creates a part streaming the file in chunks reporting progress to
+    /// the callback as each chunk is sent
+    fn progress_part(file: Vec<u8>, callback: UploadProgressFn) -> Part {
+        let file = Bytes::from(file);
+        let total = file.len() as u64;
+
+        let chunks = (0..file.len())
+            .step_by(UPLOAD_CHUNK_SIZE)
+            .map(move |start| file.slice(start..(start + UPLOAD_CHUNK_SIZE).min(file.len())));
+
+        let mut uploaded: u64 = 0;
+        let body = stream::iter(chunks).map(move |chunk| {
+            uploaded += chunk.len() as u64;
+            callback(uploaded, total);
+            Ok::<_, std::io::Error>(chunk)
+        });
+
+        Part::stream_with_length(Body::wrap_stream(body), total)
+    }
+
+    /// Creates the blocking multipart form part for the file, when tracking
+    /// progress the file is read in chunks reporting progress as each is sent
+    #[cfg(feature = "blocking")]
+    pub(crate) fn into_blocking_file_part(
+        self,
+    ) -> Result<reqwest::blocking::multipart::Part, RequestError> {
+        use reqwest::blocking::multipart::Part;
+
+        let headers = self.file_part_headers();
+
+        let part = match self.upload_progress {
+            Some(callback) => {
+                let total = self.file.len() as u64;
+                let reader = ProgressReader {
+                    inner: std::io::Cursor::new(self.file),
+                    uploaded: 0,
+                    total,
+                    callback,
+                };
+
+                Part::reader_with_length(reader, total)
+            }
+            None => Part::bytes(self.file),
+        };
+
+        let mut part = part.headers(headers);
+
+        if let Some(file_name) = self.file_name {
+            part = part.file_name(file_name);
+        }
+
+        match self.mime_type {
+            Some(mime_type) => part
+                .mime_str(&mime_type)
+                .map_err(RequestError::RequestFailed),
+            None => Ok(part),
+        }
+    }
+}
+
+/// Reader reporting progress as the file is read for uploading
+#[cfg(feature = "blocking")]
+struct ProgressReader {
+    /// Reader for the file bytes
+    inner: std::io::Cursor<Vec<u8>>,
+    /// Number of bytes read so far
+    uploaded: u64,
+    /// Total number of bytes
+    total: u64,
+    /// Callback to report progress to
+    callback: UploadProgressFn,
+}
+
+#[cfg(feature = "blocking")]
+impl std::io::Read for ProgressReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let limit = buf.len().min(UPLOAD_CHUNK_SIZE);
+        let count = self.inner.read(&mut buf[..limit])?;
+
+        if count > 0 {
+            self.uploaded += count as u64;
+            (self.callback)(self.uploaded, self.total);
+        }
+
+        Ok(count)
+    }
+}
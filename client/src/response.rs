@@ -0,0 +1,129 @@
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, CONTENT_DISPOSITION, ETAG};
+
+/// Header listing warnings about repairs and substituted fonts from a conversion
+const CONVERSION_WARNINGS_HEADER: &str = "x-conversion-warnings";
+
+/// Header containing the number of attempts made converting the file
+const CONVERSION_ATTEMPTS_HEADER: &str = "x-conversion-attempts";
+
+/// Header containing the time in milliseconds the conversion waited for office
+const QUEUE_WAIT_HEADER: &str = "x-queue-wait-ms";
+
+/// Header containing the ID of the retained result of a conversion
+const RESULT_ID_HEADER: &str = "x-result-id";
+
+/// Converted PDF along with the details the server provided about
+/// the conversion
+#[derive(Debug, Clone, Default)]
+pub struct ConvertResponse {
+    /// The PDF file bytes
+    pub pdf: Bytes,
+    /// File name of the PDF from the Content-Disposition header, the requested
+    /// output filename or the name of the uploaded file with a ".pdf" extension
+    pub file_name: Option<String>,
+    /// Warnings about repairs and substituted fonts from the conversion
+    pub warnings: Vec<String>,
+    /// Number of attempts the server made converting the file, only provided
+    /// when the server retries failed conversions
+    pub attempts: Option<u32>,
+    /// Time in milliseconds the conversion waited for office on the server
+    /// before it started, not provided for emails and PDFs passed through
+    pub queue_wait_ms: Option<u64>,
+    /// Entity tag of the conversion, can be used to check whether the same
+    /// conversion was already made
+    pub etag: Option<String>,
+    /// ID of the result when the server retains results
+    pub result_id: Option<String>,
+}
+
+impl ConvertResponse {
+    /// Creates a response containing only the PDF
+    pub fn new(pdf: Bytes) -> Self {
+        Self {
+            pdf,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a response from the PDF and the headers of the server response
+    pub(crate) fn from_headers(pdf: Bytes, headers: &HeaderMap) -> Self {
+        let header = |name| headers.get(name).and_then(|value| value.to_str().ok());
+
+        Self {
+            pdf,
+            file_name: header(CONTENT_DISPOSITION.as_str()).and_then(disposition_file_name),
+            warnings: header(CONVERSION_WARNINGS_HEADER)
+                .and_then(|value| serde_json::from_str(value).ok())
+                .unwrap_or_default(),
+            attempts: header(CONVERSION_ATTEMPTS_HEADER).and_then(|value| value.parse().ok()),
+            queue_wait_ms: header(QUEUE_WAIT_HEADER).and_then(|value| value.parse().ok()),
+            etag: header(ETAG.as_str()).map(str::to_string),
+            result_id: header(RESULT_ID_HEADER).map(str::to_string),
+        }
+    }
+}
+
+/// Provides the file name from a Content-Disposition header, the extended
+/// "filename*" parameter is preferred over "filename" (RFC 6266)
+fn disposition_file_name(value: &str) -> Option<String> {
+    let mut file_name = None;
+
+    for parameter in value.split(';').skip(1) {
+        let Some((name, value)) = parameter.split_once('=') else {
+            continue;
+        };
+
+        match name.trim().to_ascii_lowercase().as_str() {
+            "filename*" => {
+                if let Some(value) = extended_value(value.trim()) {
+                    return Some(value);
+                }
+            }
+            "filename" => {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+
+                file_name = Some(value.to_string());
+            }
+            _ => {}
+        }
+    }
+
+    file_name
+}
+
+/// Decodes an extended parameter value (i.e "UTF-8''report%20%C3%A9.pdf"),
+/// only UTF-8 values are supported (RFC 8187)
+fn extended_value(value: &str) -> Option<String> {
+    let (charset, value) = value.split_once('\'')?;
+    let (_language, value) = value.split_once('\'')?;
+
+    if !charset.eq_ignore_ascii_case("utf-8") {
+        return None;
+    }
+
+    percent_decode(value)
+}
+
+/// Decodes a percent encoded UTF-8 value
+fn percent_decode(value: &str) -> Option<String> {
+    let mut output = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(byte) = bytes.next() {
+        if byte != b'%' {
+            output.push(byte);
+            continue;
+        }
+
+        let high = (bytes.next()? as char).to_digit(16)?;
+        let low = (bytes.next()? as char).to_digit(16)?;
+        output.push((high * 16 + low) as u8);
+    }
+
+    String::from_utf8(output).ok()
+}
@@ -0,0 +1,146 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+};
+
+/// Hints about a conversion used by the load balancer [RoutingPolicy]
+/// to decide which servers to try the conversion on
+#[derive(Debug, Clone, Default)]
+pub struct Hints {
+    /// Key identifying related conversions (i.e the tenant the conversion
+    /// is for), conversions with the same key are kept on the same server
+    /// when it is available
+    pub affinity_key: Option<String>,
+
+    /// Size class of the file, when not provided the routing policy may
+    /// pick the size class based on the size of the file
+    pub size_class: Option<SizeClass>,
+}
+
+impl Hints {
+    /// Creates hints with the provided affinity key
+    ///
+    /// ## Arguments
+    /// * `key` - Key identifying related conversions
+    pub fn affinity<K>(key: K) -> Self
+    where
+        K: Into<String>,
+    {
+        Self {
+            affinity_key: Some(key.into()),
+            size_class: None,
+        }
+    }
+
+    /// Sets the size class of the file
+    ///
+    /// ## Arguments
+    /// * `size_class` - The size class of the file
+    pub fn size_class(mut self, size_class: SizeClass) -> Self {
+        self.size_class = Some(size_class);
+        self
+    }
+}
+
+/// Size class of a file being converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeClass {
+    /// File can be converted by any server
+    Normal,
+    /// File should be converted by the servers designated for large files
+    Large,
+}
+
+/// Policy deciding which servers the load balancer tries a conversion on
+/// and in which order. Servers that are in use or busy are skipped so the
+/// conversion is made on the first available server in the order
+pub trait RoutingPolicy: Send + Sync {
+    /// Provides the indexes of the servers to try the conversion on in the
+    /// order they should be tried, servers that aren't included are not used
+    /// for the conversion
+    ///
+    /// ## Arguments
+    /// * `hints` - Hints provided with the conversion
+    /// * `file_size` - Size of the file being converted in bytes
+    /// * `hosts` - Hosts of the servers within the load balancer
+    fn route(&self, hints: &Hints, file_size: usize, hosts: &[String]) -> Vec<usize>;
+}
+
+/// Default routing policy, tries every server in the order the clients
+/// were provided to the load balancer ignoring any hints
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FirstFree;
+
+impl RoutingPolicy for FirstFree {
+    fn route(&self, _hints: &Hints, _file_size: usize, hosts: &[String]) -> Vec<usize> {
+        (0..hosts.len()).collect()
+    }
+}
+
+/// Routing policy using the conversion hints, large files are routed to the
+/// servers designated for large files and other files prefer the remaining
+/// servers to keep the large servers free. Conversions with an affinity key
+/// try the same server first so related conversions stay on one server
+#[derive(Debug, Clone, Default)]
+pub struct HintedRouting {
+    /// Hosts of the servers designated for large files, large files use
+    /// any server when none of the hosts are in the load balancer
+    pub large_hosts: Vec<String>,
+
+    /// Files of at least this many bytes are treated as large when the
+    /// hints don't provide a size class
+    pub large_file_size: Option<usize>,
+}
+
+impl HintedRouting {
+    /// Picks the size class for the conversion
+    fn size_class(&self, hints: &Hints, file_size: usize) -> SizeClass {
+        if let Some(size_class) = hints.size_class {
+            return size_class;
+        }
+
+        match self.large_file_size {
+            Some(large_file_size) if file_size >= large_file_size => SizeClass::Large,
+            _ => SizeClass::Normal,
+        }
+    }
+}
+
+impl RoutingPolicy for HintedRouting {
+    fn route(&self, hints: &Hints, file_size: usize, hosts: &[String]) -> Vec<usize> {
+        let (large, normal): (Vec<usize>, Vec<usize>) =
+            (0..hosts.len()).partition(|index| self.large_hosts.contains(&hosts[*index]));
+
+        let mut order = match self.size_class(hints, file_size) {
+            SizeClass::Large if !large.is_empty() => large,
+            // Large servers are only used once the other servers are in use
+            _ => normal.into_iter().chain(large).collect(),
+        };
+
+        if let Some(key) = &hints.affinity_key {
+            prefer_affinity(&mut order, key, hosts);
+        }
+
+        order
+    }
+}
+
+/// Moves the server preferred for the affinity key to the front of the order,
+/// the server is picked using rendezvous hashing so that adding or removing a
+/// server only moves the keys of that server
+fn prefer_affinity(order: &mut [usize], key: &str, hosts: &[String]) {
+    let preferred = order
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, index)| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hosts[**index].hash(&mut hasher);
+            hasher.finish()
+        })
+        .map(|(position, _)| position);
+
+    if let Some(position) = preferred {
+        order[..=position].rotate_right(1);
+    }
+}
@@ -0,0 +1,74 @@
+use reqwest::{header::HeaderMap, StatusCode};
+use std::time::{Duration, SystemTime};
+
+/// Header with the time to wait before making another request
+const RETRY_AFTER_HEADER: &str = "retry-after";
+
+/// Header with the number of seconds until the rate limit resets
+/// (IETF RateLimit header fields draft, separate header form)
+const RATE_LIMIT_RESET_HEADER: &str = "ratelimit-reset";
+
+/// Header with the rate limit policy items, the `t` parameter is the number
+/// of seconds until the limit resets (IETF RateLimit header fields draft)
+const RATE_LIMIT_HEADER: &str = "ratelimit";
+
+/// Whether the status is a server asking the client to slow down
+/// (429 Too Many Requests or 503 Service Unavailable)
+pub(crate) fn is_throttled(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE
+}
+
+/// Finds how long the server asked the client to wait before making another
+/// request, the Retry-After header takes priority over the RateLimit headers
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+    };
+
+    if let Some(value) = header(RETRY_AFTER_HEADER).and_then(parse_retry_after) {
+        return Some(value);
+    }
+
+    if let Some(value) = header(RATE_LIMIT_RESET_HEADER).and_then(parse_seconds) {
+        return Some(value);
+    }
+
+    header(RATE_LIMIT_HEADER).and_then(parse_rate_limit)
+}
+
+/// Parses a Retry-After value, either a number of seconds or a HTTP date
+fn parse_retry_after(value: &str) -> Option<Duration> {
+    if let Some(value) = parse_seconds(value) {
+        return Some(value);
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+
+    // Dates in the past mean the request can be made immediately
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
+
+/// Parses the reset time from the items of a RateLimit header
+/// (i.e `"default";r=0;t=30`), the longest reset time is used when
+/// multiple policies are provided
+fn parse_rate_limit(value: &str) -> Option<Duration> {
+    value
+        .split(',')
+        .flat_map(|item| item.split(';').skip(1))
+        .filter_map(|parameter| {
+            let (key, value) = parameter.split_once('=')?;
+            (key.trim() == "t").then(|| parse_seconds(value))?
+        })
+        .max()
+}
+
+/// Parses a non-negative number of seconds
+fn parse_seconds(value: &str) -> Option<Duration> {
+    value.trim().parse::<u64>().ok().map(Duration::from_secs)
+}
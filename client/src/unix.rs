@@ -0,0 +1,136 @@
+//! Transport for connecting to servers listening on a Unix domain socket,
+//! reqwest only supports TCP so requests are sent using hyper directly
+
+use crate::RequestError;
+use http_body_util::BodyDataStream;
+use hyper::{rt, Uri};
+use hyper_util::{
+    client::legacy::{
+        connect::{Connected, Connection},
+        Client,
+    },
+    rt::{TokioExecutor, TokioIo},
+};
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio::net::UnixStream;
+
+/// Prefix for hosts that refer to a Unix domain socket
+pub(crate) const UNIX_HOST_PREFIX: &str = "unix://";
+
+/// Base URL used for requests sent over a Unix socket, the host is
+/// ignored as the connection is always made to the socket
+pub(crate) const UNIX_BASE_URL: &str = "http://localhost";
+
+/// HTTP client that sends requests over a Unix domain socket
+#[derive(Clone)]
+pub(crate) struct UnixClient {
+    /// Path to the socket, used for WebSocket connections
+    #[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+    path: Arc<Path>,
+    /// Underlying HTTP client
+    client: Client<UnixConnector, reqwest::Body>,
+}
+
+impl UnixClient {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        let path: Arc<Path> = Arc::from(path);
+        let client =
+            Client::builder(TokioExecutor::new()).build(UnixConnector { path: path.clone() });
+
+        Self { path, client }
+    }
+
+    /// Path to the socket
+    #[cfg(feature = "websocket")]
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Sends the provided request over the socket
+    pub(crate) async fn execute(
+        &self,
+        request: reqwest::Request,
+    ) -> Result<reqwest::Response, RequestError> {
+        let request: http::Request<reqwest::Body> =
+            request.try_into().map_err(RequestError::RequestFailed)?;
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|err| RequestError::UnixSocket(Box::new(err)))?;
+
+        let response = response.map(|body| reqwest::Body::wrap_stream(BodyDataStream::new(body)));
+
+        Ok(reqwest::Response::from(response))
+    }
+}
+
+/// Connector that connects to the socket regardless of the request URI
+#[derive(Clone)]
+struct UnixConnector {
+    /// Path to the socket
+    path: Arc<Path>,
+}
+
+impl tower_service::Service<Uri> for UnixConnector {
+    type Response = UnixConnection;
+    type Error = std::io::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+
+        Box::pin(async move {
+            let stream = UnixStream::connect(&*path).await?;
+            Ok(UnixConnection(TokioIo::new(stream)))
+        })
+    }
+}
+
+/// Connection to the socket
+struct UnixConnection(TokioIo<UnixStream>);
+
+impl Connection for UnixConnection {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl rt::Read for UnixConnection {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: rt::ReadBufCursor<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl rt::Write for UnixConnection {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
@@ -0,0 +1,267 @@
+use crate::{
+    instrument::instrument, ConvertOffice, ConvertRequest, ErrorResponse, OfficeConvertClient,
+    RequestError,
+};
+use bytes::{Bytes, BytesMut};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+};
+use tokio_rustls::{
+    rustls::{pki_types::ServerName, ClientConfig, RootCertStore},
+    TlsConnector,
+};
+use tokio_tungstenite::{
+    client_async,
+    tungstenite::{client::IntoClientRequest, Message},
+    WebSocketStream,
+};
+
+/// Size of the chunks the file is uploaded in
+const UPLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Messages sent to the server as text frames
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Starts a conversion, followed by the file bytes as binary frames
+    Start { size: u64 },
+}
+
+/// Messages sent by the server as text frames
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// Bytes of the file received by the server so far
+    UploadProgress { received: u64, total: u64 },
+    /// File was received and is being converted
+    Converting,
+    /// Conversion completed, followed by the converted file as binary frames
+    Result { size: u64 },
+    /// All the converted file bytes have been sent
+    Complete,
+    /// Conversion failed
+    Error {
+        status: u16,
+        reason: String,
+        code: Option<String>,
+    },
+}
+
+impl OfficeConvertClient {
+    /// Converts the file from the provided request into a PDF over the
+    /// WebSocket endpoint returning the PDF file bytes. The upload progress
+    /// callback is invoked with the number of bytes the server has received.
+    ///
+    /// Servers without the WebSocket endpoint convert the file using
+    /// [ConvertOffice::convert_request] instead
+    ///
+    /// ## Arguments
+    /// * `request` - The request containing the file and options
+    pub async fn convert_ws(&self, request: ConvertRequest) -> Result<Bytes, RequestError> {
+        if self
+            .capabilities()
+            .await
+            .is_ok_and(|capabilities| !capabilities.has_endpoint("/ws"))
+        {
+            return self.convert_request(request).await;
+        }
+
+        instrument(&self.host, "convert_ws", async {
+            #[cfg(unix)]
+            if let Some(unix) = &self.unix {
+                let stream = tokio::net::UnixStream::connect(unix.path())
+                    .await
+                    .map_err(|err| RequestError::WebSocket(Box::new(err.into())))?;
+
+                let (socket, _) = client_async("ws://localhost/ws", stream)
+                    .await
+                    .map_err(|err| RequestError::WebSocket(Box::new(err)))?;
+
+                return convert_socket(socket, request, self.max_response_size).await;
+            }
+
+            let route = match self.host.strip_prefix("https://") {
+                Some(host) => format!("wss://{host}/ws"),
+                None => match self.host.strip_prefix("http://") {
+                    Some(host) => format!("ws://{host}/ws"),
+                    None => format!("ws://{}/ws", self.host),
+                },
+            };
+
+            let ws_request = route
+                .into_client_request()
+                .map_err(|err| RequestError::WebSocket(Box::new(err)))?;
+
+            let uri = ws_request.uri();
+            let secure = uri.scheme_str() == Some("wss");
+            let host = uri.host().unwrap_or_default().to_string();
+            let port = uri.port_u16().unwrap_or(if secure { 443 } else { 80 });
+
+            let stream = TcpStream::connect((host.as_str(), port))
+                .await
+                .map_err(|err| RequestError::WebSocket(Box::new(err.into())))?;
+
+            if !secure {
+                let (socket, _) = client_async(ws_request, stream)
+                    .await
+                    .map_err(|err| RequestError::WebSocket(Box::new(err)))?;
+
+                return convert_socket(socket, request, self.max_response_size).await;
+            }
+
+            let mut roots = RootCertStore::empty();
+            roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+            let config = ClientConfig::builder()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+            let server_name = ServerName::try_from(host).map_err(|err| {
+                RequestError::WebSocket(Box::new(std::io::Error::other(err).into()))
+            })?;
+
+            let stream = TlsConnector::from(Arc::new(config))
+                .connect(server_name, stream)
+                .await
+                .map_err(|err| RequestError::WebSocket(Box::new(err.into())))?;
+
+            let (socket, _) = client_async(ws_request, stream)
+                .await
+                .map_err(|err| RequestError::WebSocket(Box::new(err)))?;
+
+            convert_socket(socket, request, self.max_response_size).await
+        })
+        .await
+    }
+}
+
+/// Performs the conversion protocol over the connected socket, the file is
+/// uploaded while the server messages are received so that progress messages
+/// don't fill up the connection buffer
+async fn convert_socket<S>(
+    socket: WebSocketStream<S>,
+    request: ConvertRequest,
+    max_size: Option<u64>,
+) -> Result<Bytes, RequestError>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let (mut sink, mut stream) = socket.split();
+
+    let start = ClientMessage::Start {
+        size: request.file.len() as u64,
+    };
+    let start = serde_json::to_string(&start)
+        .map_err(|err| RequestError::InvalidMessage(err.to_string()))?;
+
+    // Failures while uploading are left to the receiving side to report, the
+    // server may have sent an error message before closing the connection
+    let upload = async {
+        if sink.send(Message::Text(start)).await.is_err() {
+            return;
+        }
+
+        for chunk in request.file.chunks(UPLOAD_CHUNK_SIZE) {
+            if sink.send(Message::Binary(chunk.to_vec())).await.is_err() {
+                return;
+            }
+        }
+    };
+
+    let max_size = max_size.unwrap_or(u64::MAX);
+
+    let receive = async {
+        let mut output: Option<BytesMut> = None;
+
+        loop {
+            let message = match stream.next().await {
+                Some(Ok(message)) => message,
+                Some(Err(err)) => return Err(RequestError::WebSocket(Box::new(err))),
+                None => {
+                    return Err(RequestError::InvalidMessage(
+                        "connection closed before conversion completed".to_string(),
+                    ))
+                }
+            };
+
+            let message = match message {
+                Message::Text(message) => message,
+                Message::Binary(chunk) => match output.as_mut() {
+                    Some(output) => {
+                        if (output.len() + chunk.len()) as u64 > max_size {
+                            return Err(RequestError::ResponseTooLarge { max: max_size });
+                        }
+
+                        output.extend_from_slice(&chunk);
+                        continue;
+                    }
+                    None => {
+                        return Err(RequestError::InvalidMessage(
+                            "received file chunk before result".to_string(),
+                        ))
+                    }
+                },
+                Message::Close(_) => {
+                    return Err(RequestError::InvalidMessage(
+                        "connection closed before conversion completed".to_string(),
+                    ))
+                }
+                _ => continue,
+            };
+
+            let message: ServerMessage = serde_json::from_str(&message)
+                .map_err(|err| RequestError::InvalidMessage(err.to_string()))?;
+
+            match message {
+                ServerMessage::UploadProgress { received, total } => {
+                    if let Some(callback) = request.upload_progress.as_ref() {
+                        callback(received, total);
+                    }
+                }
+                ServerMessage::Converting => {}
+                ServerMessage::Result { size } => {
+                    if size > max_size {
+                        return Err(RequestError::ResponseTooLarge { max: max_size });
+                    }
+
+                    output = Some(BytesMut::with_capacity(size as usize));
+                }
+                ServerMessage::Complete => {
+                    return output.map(BytesMut::freeze).ok_or_else(|| {
+                        RequestError::InvalidMessage("completed without a result".to_string())
+                    });
+                }
+                ServerMessage::Error {
+                    status,
+                    reason,
+                    code,
+                } => {
+                    let status =
+                        StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+                    // Messages don't carry headers so throttling has no delay
+                    return Err(RequestError::from_response(
+                        status,
+                        None,
+                        Ok(ErrorResponse {
+                            reason,
+                            code,
+                            backtrace: None,
+                        }),
+                    ));
+                }
+            }
+        }
+    };
+
+    let (_, result) = tokio::join!(upload, receive);
+
+    _ = sink.close().await;
+
+    result
+}
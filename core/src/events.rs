@@ -0,0 +1,90 @@
+use crate::{warnings::parse_error_callback, ConversionWarning};
+use libreofficekit::CallbackType;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+/// Command office reports the modified status of the document with
+const MODIFIED_STATUS_COMMAND: &str = ".uno:ModifiedStatus";
+
+/// Sender for the events office reports while processing a document
+pub type OfficeEventSender = mpsc::UnboundedSender<OfficeEvent>;
+
+/// Event office reported through its callback while loading or exporting
+/// a document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OfficeEvent {
+    /// Office started a long running operation (i.e loading or exporting),
+    /// provides the text office describes the operation with
+    ProgressStart { text: Option<String> },
+    /// Progress of the current operation as a percentage
+    Progress { percent: u8 },
+    /// Office finished the current operation
+    ProgressFinish,
+    /// Problem office recovered from, the converted PDF may be missing content
+    Warning { warning: ConversionWarning },
+    /// Office requested the password to open the document
+    PasswordRequested,
+    /// Office requested the password to modify the document
+    PasswordToModifyRequested,
+    /// Modified status of the document changed, documents are modified when
+    /// office updates them while loading (i.e recalculating formulas)
+    ModifiedStatus { modified: bool },
+}
+
+/// State change payload sent in newer office versions
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StateChangedPayload {
+    command_name: String,
+    state: String,
+}
+
+/// Classifies a callback from office into an event, provides [None] for
+/// callbacks that aren't relevant to converting documents (i.e tile
+/// invalidation) or have a malformed payload
+pub(crate) fn classify_callback(ty: CallbackType, payload: &[u8]) -> Option<OfficeEvent> {
+    let text = || {
+        std::str::from_utf8(payload)
+            .ok()
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    match ty {
+        CallbackType::StatusIndicatorStart => Some(OfficeEvent::ProgressStart {
+            text: text().map(str::to_string),
+        }),
+        CallbackType::StatusIndicatorSetValue => {
+            let percent = text()?.parse::<u32>().ok()?.min(100) as u8;
+            Some(OfficeEvent::Progress { percent })
+        }
+        CallbackType::StatusIndicatorFinish => Some(OfficeEvent::ProgressFinish),
+        CallbackType::Error => {
+            parse_error_callback(payload).map(|warning| OfficeEvent::Warning { warning })
+        }
+        CallbackType::DocumentPassword => Some(OfficeEvent::PasswordRequested),
+        CallbackType::DocumentPasswordModify => Some(OfficeEvent::PasswordToModifyRequested),
+        CallbackType::StateChanged => parse_modified_status(text()?),
+        _ => None,
+    }
+}
+
+/// Parses the modified status from a state change, either in the form
+/// ".uno:ModifiedStatus=true" or as a JSON object
+fn parse_modified_status(payload: &str) -> Option<OfficeEvent> {
+    let (command, state) = match serde_json::from_str::<StateChangedPayload>(payload) {
+        Ok(value) => (value.command_name, value.state),
+        Err(_) => {
+            let (command, state) = payload.split_once('=')?;
+            (command.to_string(), state.to_string())
+        }
+    };
+
+    if command != MODIFIED_STATUS_COMMAND {
+        return None;
+    }
+
+    let modified = state.trim().parse::<bool>().ok()?;
+    Some(OfficeEvent::ModifiedStatus { modified })
+}
@@ -0,0 +1,480 @@
+use crate::locale::{is_valid_locale, is_valid_timezone};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// PDF version / standard to export as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PdfVersion {
+    #[serde(rename = "pdf-1.5")]
+    Pdf15,
+    #[serde(rename = "pdf-1.6")]
+    Pdf16,
+    #[serde(rename = "pdf-1.7")]
+    Pdf17,
+    #[serde(rename = "pdfa-1b")]
+    PdfA1b,
+    #[serde(rename = "pdfa-2b")]
+    PdfA2b,
+    #[serde(rename = "pdfa-3b")]
+    PdfA3b,
+}
+
+impl PdfVersion {
+    /// Value for the "SelectPdfVersion" property of the PDF export filter
+    fn filter_value(&self) -> i32 {
+        match self {
+            PdfVersion::Pdf15 => 15,
+            PdfVersion::Pdf16 => 16,
+            PdfVersion::Pdf17 => 17,
+            PdfVersion::PdfA1b => 1,
+            PdfVersion::PdfA2b => 2,
+            PdfVersion::PdfA3b => 3,
+        }
+    }
+}
+
+/// Options for the LibreOffice PDF export filter
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PdfExportOptions {
+    /// PDF version / standard to export as
+    pub pdf_version: Option<PdfVersion>,
+    /// Maximum resolution in DPI to reduce images to
+    pub image_resolution: Option<u32>,
+    /// JPEG compression quality for images (1-100)
+    pub jpeg_quality: Option<u8>,
+    /// Range of pages to export (i.e "1-5" or "1,3,5-7")
+    pub page_range: Option<String>,
+    /// Digitally sign the exported PDF using the configured signing certificate
+    pub sign: Option<bool>,
+    /// Password required to open the exported PDF
+    pub user_password: Option<String>,
+    /// Password required to change the permissions of the exported PDF, the
+    /// permissions can only be restricted when an owner password is set
+    pub owner_password: Option<String>,
+    /// Disallow printing the exported PDF
+    pub no_print: Option<bool>,
+    /// Disallow copying content from the exported PDF
+    pub no_copy: Option<bool>,
+    /// Disallow modifying the exported PDF
+    pub no_modify: Option<bool>,
+    /// Export a tagged PDF describing the structure of the document (headings,
+    /// lists, tables and reading order) for screen readers
+    pub tagged: Option<bool>,
+    /// Export a PDF conforming to PDF/UA (Universal Accessibility), PDF/UA
+    /// PDFs are always tagged
+    pub pdf_ua: Option<bool>,
+    /// Remove document metadata (author, title and dates), comments, speaker
+    /// notes, attached files and scripts from the exported PDF. Macros are
+    /// never run while loading documents that are sanitized
+    pub sanitize: Option<bool>,
+    /// Options only used when exporting presentations
+    pub impress: Option<ImpressExportOptions>,
+    /// Options only used when exporting text documents
+    pub writer: Option<WriterExportOptions>,
+    /// Filter options string passed to the PDF export filter as-is, used in
+    /// place of the options created from the other fields. Allows using
+    /// export filter features that aren't wrapped by these options
+    pub raw_filter_options: Option<String>,
+    /// Options appended to the options used when loading the document, these
+    /// can override the default document security options
+    pub raw_load_options: Option<String>,
+    /// Locale to load the document with as a BCP 47 language tag (i.e "de-DE"),
+    /// dates, numbers and currencies using the default locale of the document
+    /// are formatted for this locale
+    pub locale: Option<String>,
+    /// Time zone to calculate the current date and time in (i.e "Europe/Berlin"),
+    /// used by date fields and functions such as NOW() and TODAY()
+    pub timezone: Option<String>,
+    /// Password to modify for documents protected with one, documents are
+    /// loaded read-only when the password is not provided or incorrect
+    pub modify_password: Option<String>,
+}
+
+/// Options for the PDF export filter that only apply to presentations
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ImpressExportOptions {
+    /// Export the notes page of each slide (containing the speaker notes)
+    /// after the slides
+    pub notes_pages: Option<bool>,
+    /// Export slides that are hidden in the presentation
+    pub hidden_slides: Option<bool>,
+    /// Range of slides to export (i.e "1-5" or "1,3,5-7")
+    pub slide_range: Option<String>,
+}
+
+/// Options for the PDF export filter that only apply to text documents
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WriterExportOptions {
+    /// How comments in the document are exported
+    pub comments: Option<CommentsMode>,
+    /// How form fields in the document are exported
+    pub form_fields: Option<FormFieldsMode>,
+}
+
+/// How comments are included in the exported PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentsMode {
+    /// Comments are stripped from the PDF
+    None,
+    /// Comments are exported as PDF annotations
+    Annotations,
+    /// Comments are printed in the page margin
+    Margin,
+}
+
+/// How form fields are included in the exported PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FormFieldsMode {
+    /// Form fields are exported as an interactive PDF form
+    Interactive,
+    /// Form fields are flattened into the page content
+    Flatten,
+}
+
+impl PdfExportOptions {
+    /// Checks the options are within their allowed ranges
+    pub fn validate(&self) -> Result<(), String> {
+        if self.image_resolution == Some(0) {
+            return Err("image_resolution must be greater than zero".to_string());
+        }
+
+        if self
+            .jpeg_quality
+            .is_some_and(|quality| !(1..=100).contains(&quality))
+        {
+            return Err("jpeg_quality must be between 1 and 100".to_string());
+        }
+
+        if self.user_password.as_deref() == Some("") {
+            return Err("user_password must not be empty".to_string());
+        }
+
+        if self.owner_password.as_deref() == Some("") {
+            return Err("owner_password must not be empty".to_string());
+        }
+
+        let restricted = [self.no_print, self.no_copy, self.no_modify]
+            .into_iter()
+            .any(|value| value == Some(true));
+
+        if restricted && self.owner_password.is_none() {
+            return Err("no_print, no_copy and no_modify require an owner_password".to_string());
+        }
+
+        let pdf_a = matches!(
+            self.pdf_version,
+            Some(PdfVersion::PdfA1b | PdfVersion::PdfA2b | PdfVersion::PdfA3b)
+        );
+
+        // PDF/A does not allow encryption
+        if pdf_a && self.is_encrypted() {
+            return Err("passwords cannot be used with PDF/A versions".to_string());
+        }
+
+        if self.pdf_ua == Some(true) && self.tagged == Some(false) {
+            return Err("pdf_ua requires a tagged pdf".to_string());
+        }
+
+        if self.sanitize == Some(true) {
+            self.validate_sanitize()?;
+        }
+
+        if self
+            .page_range
+            .as_deref()
+            .is_some_and(|value| !is_valid_range(value))
+        {
+            return Err("page_range must only contain page numbers, '-' and ','".to_string());
+        }
+
+        if self.raw_filter_options.is_some() && self.has_filter_options() {
+            return Err(
+                "raw_filter_options cannot be combined with other export options".to_string(),
+            );
+        }
+
+        if self
+            .locale
+            .as_deref()
+            .is_some_and(|value| !is_valid_locale(value))
+        {
+            return Err("locale must be a language tag (i.e \"de-DE\")".to_string());
+        }
+
+        if self
+            .timezone
+            .as_deref()
+            .is_some_and(|value| !is_valid_timezone(value))
+        {
+            return Err(
+                "timezone must be the name of an installed time zone (i.e \"Europe/Berlin\")"
+                    .to_string(),
+            );
+        }
+
+        if let Some(slide_range) = self
+            .impress
+            .as_ref()
+            .and_then(|impress| impress.slide_range.as_deref())
+        {
+            if !is_valid_range(slide_range) {
+                return Err("slide_range must only contain slide numbers, '-' and ','".to_string());
+            }
+
+            // Both ranges are provided to the export filter as the page range
+            if self.page_range.is_some() {
+                return Err("page_range and slide_range cannot be used together".to_string());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the options can be used with a sanitized PDF, the PDF is
+    /// modified after exporting which isn't possible for signed or encrypted
+    /// PDFs and the metadata is required by PDF/A and PDF/UA
+    fn validate_sanitize(&self) -> Result<(), String> {
+        if self.sign == Some(true) {
+            return Err("signed pdfs cannot be sanitized".to_string());
+        }
+
+        if self.is_encrypted() {
+            return Err("passwords cannot be used with sanitize".to_string());
+        }
+
+        let pdf_a = matches!(
+            self.pdf_version,
+            Some(PdfVersion::PdfA1b | PdfVersion::PdfA2b | PdfVersion::PdfA3b)
+        );
+
+        if pdf_a || self.pdf_ua == Some(true) {
+            return Err("PDF/A and PDF/UA require metadata removed by sanitize".to_string());
+        }
+
+        let comments = self
+            .writer
+            .as_ref()
+            .and_then(|writer| writer.comments)
+            .is_some_and(|comments| comments != CommentsMode::None);
+
+        if comments {
+            return Err("comments cannot be exported with sanitize".to_string());
+        }
+
+        let notes_pages = self
+            .impress
+            .as_ref()
+            .and_then(|impress| impress.notes_pages)
+            .unwrap_or_default();
+
+        if notes_pages {
+            return Err("notes pages cannot be exported with sanitize".to_string());
+        }
+
+        // Raw filter options replace the options excluding hidden content
+        if self.raw_filter_options.is_some() {
+            return Err("raw_filter_options cannot be used with sanitize".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Whether the exported PDF will be encrypted using a password
+    pub fn is_encrypted(&self) -> bool {
+        self.user_password.is_some() || self.owner_password.is_some()
+    }
+
+    /// Whether any of the options creating the export filter options are set
+    fn has_filter_options(&self) -> bool {
+        let typed = Self {
+            sign: None,
+            sanitize: None,
+            raw_filter_options: None,
+            raw_load_options: None,
+            locale: None,
+            timezone: None,
+            modify_password: None,
+            ..self.clone()
+        };
+
+        typed != Self::default()
+    }
+
+    /// Creates the JSON filter options string for the PDF export filter,
+    /// provides [None] when no options are set
+    pub fn filter_options(&self) -> Option<String> {
+        if let Some(raw_filter_options) = &self.raw_filter_options {
+            return Some(raw_filter_options.clone());
+        }
+
+        let mut options = Map::new();
+
+        if let Some(pdf_version) = self.pdf_version {
+            options.insert(
+                "SelectPdfVersion".to_string(),
+                filter_property("long", pdf_version.filter_value()),
+            );
+        }
+
+        if let Some(image_resolution) = self.image_resolution {
+            options.insert(
+                "ReduceImageResolution".to_string(),
+                filter_property("boolean", true),
+            );
+            options.insert(
+                "MaxImageResolution".to_string(),
+                filter_property("long", image_resolution),
+            );
+        }
+
+        if let Some(jpeg_quality) = self.jpeg_quality {
+            options.insert(
+                "UseLosslessCompression".to_string(),
+                filter_property("boolean", false),
+            );
+            options.insert("Quality".to_string(), filter_property("long", jpeg_quality));
+        }
+
+        if let Some(page_range) = &self.page_range {
+            options.insert(
+                "PageRange".to_string(),
+                filter_property("string", page_range),
+            );
+        }
+
+        // PDF/UA requires the structure tags
+        let tagged = match self.pdf_ua {
+            Some(true) => Some(true),
+            _ => self.tagged,
+        };
+
+        if let Some(tagged) = tagged {
+            options.insert(
+                "UseTaggedPDF".to_string(),
+                filter_property("boolean", tagged),
+            );
+        }
+
+        if let Some(pdf_ua) = self.pdf_ua {
+            options.insert(
+                "PDFUACompliance".to_string(),
+                filter_property("boolean", pdf_ua),
+            );
+        }
+
+        if let Some(impress) = &self.impress {
+            if let Some(notes_pages) = impress.notes_pages {
+                options.insert(
+                    "ExportNotesPages".to_string(),
+                    filter_property("boolean", notes_pages),
+                );
+            }
+
+            if let Some(hidden_slides) = impress.hidden_slides {
+                options.insert(
+                    "ExportHiddenSlides".to_string(),
+                    filter_property("boolean", hidden_slides),
+                );
+            }
+
+            if let Some(slide_range) = &impress.slide_range {
+                options.insert(
+                    "PageRange".to_string(),
+                    filter_property("string", slide_range),
+                );
+            }
+        }
+
+        if let Some(writer) = &self.writer {
+            if let Some(comments) = writer.comments {
+                options.insert(
+                    "ExportNotes".to_string(),
+                    filter_property("boolean", comments == CommentsMode::Annotations),
+                );
+                options.insert(
+                    "ExportNotesInMargin".to_string(),
+                    filter_property("boolean", comments == CommentsMode::Margin),
+                );
+            }
+
+            if let Some(form_fields) = writer.form_fields {
+                options.insert(
+                    "ExportFormFields".to_string(),
+                    filter_property("boolean", form_fields == FormFieldsMode::Interactive),
+                );
+            }
+        }
+
+        // Sanitized PDFs exclude comments, speaker notes and the source document
+        if self.sanitize == Some(true) {
+            for name in [
+                "ExportNotes",
+                "ExportNotesInMargin",
+                "ExportNotesPages",
+                "IsAddStream",
+            ] {
+                options.insert(name.to_string(), filter_property("boolean", false));
+            }
+        }
+
+        if let Some(user_password) = &self.user_password {
+            options.insert("EncryptFile".to_string(), filter_property("boolean", true));
+            options.insert(
+                "DocumentOpenPassword".to_string(),
+                filter_property("string", user_password),
+            );
+        }
+
+        if let Some(owner_password) = &self.owner_password {
+            let allowed = |value: Option<bool>| value != Some(true);
+
+            options.insert(
+                "RestrictPermissions".to_string(),
+                filter_property("boolean", true),
+            );
+            options.insert(
+                "PermissionPassword".to_string(),
+                filter_property("string", owner_password),
+            );
+            // 0 = Not permitted, 2 = High resolution printing
+            options.insert(
+                "Printing".to_string(),
+                filter_property("long", if allowed(self.no_print) { 2 } else { 0 }),
+            );
+            // 0 = Not permitted, 4 = Any changes except extracting pages
+            options.insert(
+                "Changes".to_string(),
+                filter_property("long", if allowed(self.no_modify) { 4 } else { 0 }),
+            );
+            options.insert(
+                "EnableCopyingOfContent".to_string(),
+                filter_property("boolean", allowed(self.no_copy)),
+            );
+        }
+
+        if options.is_empty() {
+            return None;
+        }
+
+        Some(Value::Object(options).to_string())
+    }
+}
+
+/// Checks a page or slide range only contains numbers, '-' and ','
+fn is_valid_range(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|value| value.is_ascii_digit() || matches!(value, '-' | ','))
+}
+
+/// Creates a typed filter property value, LibreOffice expects
+/// the value in its string form
+fn filter_property<V: ToString>(ty: &str, value: V) -> Value {
+    json!({ "type": ty, "value": value.to_string() })
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    process::Command,
+};
+use tracing::{debug, warn};
+
+/// Name of the generated fontconfig file within the temp directory
+#[cfg(all(unix, not(target_os = "macos")))]
+const FONTCONFIG_FILE_NAME: &str = "office-convert-fonts.conf";
+
+/// Default fontconfig configuration file
+#[cfg(all(unix, not(target_os = "macos")))]
+const DEFAULT_FONTCONFIG_FILE: &str = "/etc/fonts/fonts.conf";
+
+/// Registers additional font directories for LibreOffice to use, must be
+/// called before office is created.
+///
+/// LibreOffice finds fonts using fontconfig so a fontconfig file including
+/// the existing configuration and the additional directories is written to
+/// the temp directory and provided through the FONTCONFIG_FILE variable
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn configure_font_dirs(font_dirs: &[PathBuf], temp_dir: &Path) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    if font_dirs.is_empty() {
+        return Ok(());
+    }
+
+    let config_path = temp_dir.join(FONTCONFIG_FILE_NAME);
+
+    // Include any configuration the environment already provided
+    let base_config = std::env::var_os("FONTCONFIG_FILE")
+        .map(PathBuf::from)
+        // Don't include the generated file if office was already started
+        .filter(|path| path != &config_path)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FONTCONFIG_FILE));
+
+    let mut config = String::from(
+        "<?xml version=\"1.0\"?>\n<!DOCTYPE fontconfig SYSTEM \"fonts.dtd\">\n<fontconfig>\n",
+    );
+    config.push_str(&format!(
+        "  <include ignore_missing=\"yes\">{}</include>\n",
+        escape_xml(&base_config.to_string_lossy())
+    ));
+
+    for font_dir in font_dirs {
+        if !font_dir.is_dir() {
+            warn!(path = %font_dir.display(), "font directory does not exist");
+        }
+
+        config.push_str(&format!(
+            "  <dir>{}</dir>\n",
+            escape_xml(&font_dir.to_string_lossy())
+        ));
+    }
+
+    config.push_str("</fontconfig>\n");
+
+    std::fs::write(&config_path, config).context("failed to write fontconfig file")?;
+
+    debug!(path = %config_path.display(), "registered font directories");
+
+    std::env::set_var("FONTCONFIG_FILE", config_path);
+
+    Ok(())
+}
+
+/// Font directories rely on fontconfig which LibreOffice does not use on
+/// Windows and macOS
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn configure_font_dirs(font_dirs: &[PathBuf], _temp_dir: &Path) -> anyhow::Result<()> {
+    if !font_dirs.is_empty() {
+        warn!("font directories are only supported on platforms using fontconfig");
+    }
+
+    Ok(())
+}
+
+/// Lists the font families available to LibreOffice using fontconfig, family
+/// names are lowercase. Provides [None] when the fonts cannot be listed (i.e
+/// fontconfig is not installed)
+pub fn installed_font_families() -> Option<HashSet<String>> {
+    let families = font_families()?
+        .into_iter()
+        .map(|family| family.to_lowercase())
+        .collect();
+
+    Some(families)
+}
+
+/// Lists the names of the font families available to LibreOffice using
+/// fontconfig as they are named by the fonts. Provides [None] when the fonts
+/// cannot be listed (i.e fontconfig is not installed)
+pub fn font_families() -> Option<BTreeSet<String>> {
+    let output = match Command::new("fc-list").arg("--format=%{family}\n").output() {
+        Ok(value) => value,
+        Err(err) => {
+            debug!(%err, "failed to list installed fonts");
+            return None;
+        }
+    };
+
+    if !output.status.success() {
+        debug!(status = %output.status, "failed to list installed fonts");
+        return None;
+    }
+
+    let output = String::from_utf8_lossy(&output.stdout);
+
+    // Each line contains the comma separated names of a family (i.e localized names)
+    let families = output
+        .lines()
+        .flat_map(|line| line.split(','))
+        .map(|family| family.trim().to_string())
+        .filter(|family| !family.is_empty())
+        .collect();
+
+    Some(families)
+}
+
+/// Escapes a value for use as XML text
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
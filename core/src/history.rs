@@ -0,0 +1,118 @@
+use crate::Priority;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Number of recent conversions kept in the history
+const HISTORY_CAPACITY: usize = 50;
+
+/// Number of recent queue waits the percentiles are calculated from
+const QUEUE_WAIT_CAPACITY: usize = 1000;
+
+/// Details about a conversion made by the converter
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionRecord {
+    /// Unix timestamp in milliseconds of when the conversion was requested
+    pub requested_at: u64,
+    /// Priority the conversion waited for the runner with
+    pub priority: Priority,
+    /// Time in milliseconds spent waiting for the runner
+    pub wait_ms: u64,
+    /// Time in milliseconds spent converting once the runner was available
+    pub convert_ms: u64,
+    /// Size of the input file in bytes
+    pub input_size: usize,
+    /// Size of the converted PDF in bytes, [None] when the conversion failed
+    pub output_size: Option<usize>,
+    /// Error message when the conversion failed
+    pub error: Option<String>,
+}
+
+/// Most recent conversions made by the converter, older conversions are
+/// dropped once the history is full
+#[derive(Default)]
+pub(crate) struct ConversionHistory(Mutex<VecDeque<ConversionRecord>>);
+
+impl ConversionHistory {
+    /// Adds a conversion to the history
+    pub(crate) fn push(&self, record: ConversionRecord) {
+        let history = &mut *self.0.lock();
+
+        if history.len() == HISTORY_CAPACITY {
+            history.pop_front();
+        }
+
+        history.push_back(record);
+    }
+
+    /// Provides the conversions in the history, most recent first
+    pub(crate) fn recent(&self) -> Vec<ConversionRecord> {
+        self.0.lock().iter().rev().cloned().collect()
+    }
+}
+
+/// Percentiles of the time recent requests spent waiting for the runner
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct QueueWaitStats {
+    /// Number of recent requests the percentiles are calculated from
+    pub samples: usize,
+    /// Median time in milliseconds spent waiting for the runner
+    pub p50_ms: u64,
+    /// 95th percentile time in milliseconds spent waiting for the runner
+    pub p95_ms: u64,
+}
+
+/// Time in milliseconds the most recent requests spent waiting for the
+/// runner, older waits are dropped once full
+#[derive(Default)]
+pub(crate) struct QueueWaits(Mutex<VecDeque<u64>>);
+
+impl QueueWaits {
+    /// Adds the time a request spent waiting
+    pub(crate) fn push(&self, wait_ms: u64) {
+        let waits = &mut *self.0.lock();
+
+        if waits.len() == QUEUE_WAIT_CAPACITY {
+            waits.pop_front();
+        }
+
+        waits.push_back(wait_ms);
+    }
+
+    /// Calculates the percentiles of the recent waits
+    pub(crate) fn stats(&self) -> QueueWaitStats {
+        let mut waits: Vec<u64> = self.0.lock().iter().copied().collect();
+        waits.sort_unstable();
+
+        QueueWaitStats {
+            samples: waits.len(),
+            p50_ms: percentile(&waits, 50),
+            p95_ms: percentile(&waits, 95),
+        }
+    }
+}
+
+/// Nearest-rank percentile of sorted values, zero when there are no values
+fn percentile(sorted: &[u64], percentile: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+
+    let rank = (sorted.len() * percentile).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Converts a time into a unix timestamp in milliseconds
+pub(crate) fn unix_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(duration_ms)
+        .unwrap_or_default()
+}
+
+/// Converts a duration into whole milliseconds
+pub(crate) fn duration_ms(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
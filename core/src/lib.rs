@@ -0,0 +1,761 @@
+//! Conversion engine for converting office file formats into PDFs using
+//! LibreOffice, used by the office-convert-server and usable directly by
+//! applications that run alongside a LibreOffice install
+
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+use libreofficekit::{DocumentType, FilterTypes, OfficeVersionInfo};
+use runner::{office_runner, OfficeMsg};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info_span, warn, Instrument, Span};
+
+mod events;
+mod export;
+mod fonts;
+mod history;
+mod limits;
+mod locale;
+mod priority;
+mod process;
+mod profile;
+mod render;
+mod retry;
+mod runner;
+mod sandbox;
+mod sanitize;
+mod temp;
+mod usage;
+mod validate;
+mod warnings;
+mod watchdog;
+mod worker;
+
+pub use events::{OfficeEvent, OfficeEventSender};
+pub use export::{
+    CommentsMode, FormFieldsMode, ImpressExportOptions, PdfExportOptions, PdfVersion,
+    WriterExportOptions,
+};
+pub use fonts::{font_families, installed_font_families};
+pub use history::{ConversionRecord, QueueWaitStats};
+pub use limits::{page_count, OutputLimits};
+pub use priority::{Priority, PriorityAging};
+pub use process::{run_conversion_process, ConversionProcess};
+pub use profile::DocumentSecurity;
+pub use render::{ImageFormat, RenderOptions, MAX_RENDER_DPI};
+pub use retry::RetryPolicy;
+pub use sandbox::{InvalidSandboxUser, SandboxOptions, SandboxUser};
+use sanitize::sanitize_conversion;
+pub use sanitize::sanitize_pdf;
+pub use temp::{memory_temp_dir, reap_temp_files, spawn_temp_reaper, TempFile};
+pub use usage::{ResourceUsage, ResourceUsageStats, UsageHistogram};
+pub use validate::Validation;
+pub use warnings::{Conversion, ConversionWarning};
+pub use worker::{WorkerState, WorkerStatus};
+
+use history::{duration_ms, unix_ms, ConversionHistory, QueueWaits};
+use priority::{FairShare, PriorityQueue, PrioritySlot, SizeClass};
+use process::ProcessRunner;
+use usage::UsageRecorder;
+use watchdog::{spawn_watchdog, JobOutput, Watchdog};
+use worker::WorkerTracker;
+
+/// ID of the job currently being converted, zero when idle
+static CURRENT_JOB: AtomicU64 = AtomicU64::new(0);
+
+/// Sets the ID of the job currently being converted
+fn set_current_job(job_id: Option<u64>) {
+    CURRENT_JOB.store(job_id.unwrap_or_default(), Ordering::SeqCst);
+}
+
+/// Gets the ID of the job currently being converted, used to tag
+/// output written by LibreOffice with the job that produced it
+pub fn current_job() -> Option<u64> {
+    match CURRENT_JOB.load(Ordering::SeqCst) {
+        0 => None,
+        value => Some(value),
+    }
+}
+
+/// Sets environment variables for office (i.e "SAL_USE_VCLPLUGIN" or locale
+/// variables), headless rendering can differ between systems. Variables the
+/// runner sets for the fonts and profile take precedence.
+///
+/// Changing the environment isn't thread safe so this must be called before
+/// any other threads are started (i.e before the tokio runtime is created)
+pub fn set_office_environment(environment: &BTreeMap<String, String>) {
+    for (name, value) in environment {
+        std::env::set_var(name, value);
+    }
+}
+
+/// Errors that can occur while converting a document
+#[derive(Debug, Error)]
+pub enum ConvertError {
+    /// Temp directory does not have enough free space for the conversion
+    #[error("insufficient disk space for conversion (required {required} bytes, available {available} bytes)")]
+    InsufficientDisk { required: u64, available: u64 },
+
+    /// Signing was requested but no signing certificate is configured
+    #[error("pdf signing is unavailable, no signing certificate is configured")]
+    SigningUnavailable,
+
+    /// Time zone was requested but conversions aren't made using a
+    /// conversion process
+    #[error("time zones are unavailable, conversions are not made using a conversion process")]
+    TimezoneUnavailable,
+
+    /// Office took longer than the maximum duration to complete a single step
+    /// of the conversion
+    #[error("conversion timed out after {seconds} seconds")]
+    Timeout { seconds: u64 },
+
+    /// Converted PDF is larger than the maximum output size
+    #[error("converted pdf is {size} bytes which exceeds the maximum of {max} bytes")]
+    OutputTooLarge { size: u64, max: u64 },
+
+    /// Converted PDF has more pages than the maximum page count
+    #[error("converted pdf has {pages} pages which exceeds the maximum of {max} pages")]
+    TooManyPages { pages: u64, max: u64 },
+
+    /// Office failed to convert the document
+    #[error(transparent)]
+    Office(#[from] anyhow::Error),
+}
+
+/// Handle for converting documents using an office runner, the runner owns
+/// the LibreOffice instance on a dedicated thread and processes one
+/// conversion at a time. Waiting conversions are processed in order of
+/// their [Priority], taking turns between the sources given a fair share
+/// (see [Converter::with_fair_share])
+#[derive(Clone)]
+pub struct Converter {
+    /// Sender for messages to the runner
+    tx: mpsc::Sender<OfficeMsg>,
+    /// Queue of conversions waiting for the runner
+    queue: Arc<PriorityQueue>,
+    /// Priority of conversions made through this handle
+    priority: Priority,
+    /// Share of the runner conversions made through this handle take turns with
+    share: Option<FairShare>,
+    /// Most recent conversions made using the runner
+    history: Arc<ConversionHistory>,
+    /// Time the most recent requests spent waiting for the runner
+    queue_waits: Arc<QueueWaits>,
+    /// Resources used by the conversions made using the runner
+    usage: Arc<UsageRecorder>,
+    /// State of the runner
+    worker: Arc<WorkerTracker>,
+    /// Whether a signing certificate is configured
+    signing: bool,
+    /// Limits on the size of converted PDFs
+    output_limits: OutputLimits,
+    /// Policy for retrying conversions that fail
+    retry: RetryPolicy,
+    /// Files smaller than this size in bytes are converted before waiting larger files
+    small_file_threshold: Option<u64>,
+    /// Runner starting a new process for each conversion, conversions are made
+    /// using the office runner when not set
+    process: Option<Arc<ProcessRunner>>,
+    /// Sender for the events office reports while converting through this handle
+    events: Option<OfficeEventSender>,
+}
+
+impl Converter {
+    /// Starts a new office runner on its own thread providing the details
+    /// about the office install and a converter to access it
+    pub async fn start(options: ConverterOptions) -> anyhow::Result<(OfficeDetails, Converter)> {
+        let (tx, rx) = mpsc::channel(1);
+        let signing = options.signing.is_some();
+        let output_limits = options.output_limits;
+        let retry = options.retry;
+        let small_file_threshold = options.small_file_threshold;
+        let queue = Arc::new(PriorityQueue::new(options.priority_aging));
+        let process = options
+            .conversion_process
+            .clone()
+            .map(|process| Arc::new(ProcessRunner::new(process, &options)));
+
+        let (startup_tx, startup_rx) = oneshot::channel();
+        let worker = Arc::new(WorkerTracker::new());
+        let watchdog = Arc::new(Watchdog::new(options.max_convert_duration));
+
+        spawn_watchdog(watchdog.clone());
+
+        std::thread::spawn({
+            let worker = worker.clone();
+
+            move || {
+                let mut startup_tx = Some(startup_tx);
+
+                if let Err(cause) = office_runner(options, rx, &worker, &watchdog, &mut startup_tx)
+                {
+                    error!(%cause, "failed to start office runner");
+
+                    // Send the error to the startup channel if its still available
+                    if let Some(startup_tx) = startup_tx.take() {
+                        _ = startup_tx.send(Err(cause));
+                    }
+                }
+            }
+        });
+
+        // Wait for a successful startup
+        let office_details = startup_rx.await.context("startup channel unavailable")??;
+
+        Ok((
+            office_details,
+            Converter {
+                tx,
+                queue,
+                priority: Priority::default(),
+                share: None,
+                history: Arc::default(),
+                queue_waits: Arc::default(),
+                usage: Arc::default(),
+                worker,
+                signing,
+                output_limits,
+                retry,
+                small_file_threshold,
+                process,
+                events: None,
+            },
+        ))
+    }
+
+    /// Creates a handle to the same runner whose conversions wait for the
+    /// runner with the provided priority
+    pub fn with_priority(&self, priority: Priority) -> Converter {
+        Converter {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    /// Priority of conversions made through this handle
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Creates a handle to the same runner that reports the events office
+    /// provides while converting (i.e progress and warnings) to the provided
+    /// sender. Events are not reported for conversions made using a
+    /// conversion process
+    pub fn with_events(&self, events: OfficeEventSender) -> Converter {
+        Converter {
+            events: Some(events),
+            ..self.clone()
+        }
+    }
+
+    /// Creates a handle to the same runner whose conversions take turns with
+    /// conversions from other sources (i.e tenants) identified by the provided
+    /// key. Waiting conversions with the same priority are given the runner in
+    /// proportion to the weight of their source
+    pub fn with_fair_share(&self, key: &str, weight: u32) -> Converter {
+        Converter {
+            share: Some(FairShare {
+                key: Arc::from(key),
+                weight,
+            }),
+            ..self.clone()
+        }
+    }
+
+    /// Sends the provided file bytes to the office runner for conversion
+    /// and waits for the converted PDF bytes
+    pub async fn convert(&self, bytes: Bytes) -> Result<Bytes, ConvertError> {
+        self.convert_with_options(bytes, PdfExportOptions::default())
+            .await
+    }
+
+    /// Sends the provided file bytes to the office runner for conversion
+    /// using the provided export options and waits for the converted PDF bytes,
+    /// fails when the converted PDF exceeds the output limits
+    pub async fn convert_with_options(
+        &self,
+        bytes: Bytes,
+        options: PdfExportOptions,
+    ) -> Result<Bytes, ConvertError> {
+        self.convert_with_warnings(bytes, options)
+            .await
+            .map(|conversion| conversion.pdf)
+    }
+
+    /// Converts the provided file bytes using the provided export options the
+    /// same as [Converter::convert_with_options] also providing any warnings
+    /// office reported about problems it recovered from while converting and
+    /// the number of attempts made when retrying failed conversions
+    pub async fn convert_with_warnings(
+        &self,
+        bytes: Bytes,
+        options: PdfExportOptions,
+    ) -> Result<Conversion, ConvertError> {
+        if options.sign == Some(true) && !self.signing {
+            return Err(ConvertError::SigningUnavailable);
+        }
+
+        // Time zone is set for the conversion process when starting it, changing
+        // the time zone of this process while it's running isn't safe
+        if options.timezone.is_some() && self.process.is_none() {
+            return Err(ConvertError::TimezoneUnavailable);
+        }
+
+        let requested_at = SystemTime::now();
+        let input_size = bytes.len();
+        let sanitize = options.sanitize == Some(true);
+        let wait_started_at = Instant::now();
+
+        // Runner is held until the conversion completes
+        let slot = self.acquire(input_size).await;
+        let wait = wait_started_at.elapsed();
+
+        let convert_started_at = Instant::now();
+        let result = self.send_convert_with_retry(bytes, options).await;
+        let convert = convert_started_at.elapsed();
+        drop(slot);
+
+        // Metadata, attachments and scripts are removed after exporting
+        let result = match result {
+            Ok(conversion) if sanitize => sanitize_conversion(conversion).await,
+            result => result,
+        };
+
+        let result = match result {
+            Ok(conversion) => self
+                .output_limits
+                .check(&conversion.pdf)
+                .await
+                .map(|_| conversion),
+            Err(err) => Err(err),
+        };
+
+        let result = result.map(|conversion| Conversion {
+            queue_wait_ms: duration_ms(wait),
+            ..conversion
+        });
+
+        if let Ok(conversion) = &result {
+            self.usage.record(&conversion.usage);
+        }
+
+        self.history.push(ConversionRecord {
+            requested_at: unix_ms(requested_at),
+            priority: self.priority,
+            wait_ms: duration_ms(wait),
+            convert_ms: duration_ms(convert),
+            input_size,
+            output_size: result.as_ref().ok().map(|conversion| conversion.pdf.len()),
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+
+        result
+    }
+
+    /// Waits until the runner is available for this handle to process a
+    /// file of the provided size, the time spent waiting is recorded
+    async fn acquire(&self, size: usize) -> PrioritySlot {
+        let size_class = match self.small_file_threshold {
+            Some(threshold) if (size as u64) < threshold => SizeClass::Small,
+            _ => SizeClass::Large,
+        };
+
+        let started_at = Instant::now();
+        let slot = self
+            .queue
+            .acquire(self.priority, size_class, self.share.as_ref())
+            .instrument(info_span!("queue_wait", priority = ?self.priority, ?size_class))
+            .await;
+
+        self.queue_waits.push(duration_ms(started_at.elapsed()));
+        slot
+    }
+
+    /// Sends the conversion to the office runner attempting it again when it
+    /// fails and the retry policy allows, the runner must be held by the caller
+    /// so retries and restarts happen before any waiting conversions
+    async fn send_convert_with_retry(
+        &self,
+        bytes: Bytes,
+        options: PdfExportOptions,
+    ) -> Result<Conversion, ConvertError> {
+        let mut attempts = 1;
+
+        loop {
+            match self.send_convert(bytes.clone(), options.clone()).await {
+                Ok(conversion) => {
+                    return Ok(Conversion {
+                        attempts,
+                        ..conversion
+                    })
+                }
+                Err(err) if self.retry.should_retry(attempts, &err) => {
+                    warn!(%err, attempts, "conversion failed, retrying");
+
+                    if self.retry.restart {
+                        if let Err(cause) = self.restart().await {
+                            error!(%cause, "failed to restart office before retrying");
+                        }
+                    }
+
+                    attempts += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Sends the conversion to the office runner and waits for the converted
+    /// PDF bytes, the runner must be held by the caller
+    async fn send_convert(
+        &self,
+        bytes: Bytes,
+        options: PdfExportOptions,
+    ) -> Result<Conversion, ConvertError> {
+        if let Some(process) = &self.process {
+            return process.convert(&self.worker, bytes, options).await;
+        }
+
+        let (tx, rx) = oneshot::channel();
+
+        self.tx
+            .send(OfficeMsg::Convert {
+                bytes,
+                options: Box::new(options),
+                events: self.events.clone(),
+                span: Span::current(),
+                tx,
+            })
+            .await
+            .context("failed to send convert request")?;
+
+        match rx.await.context("failed to get convert response")?? {
+            JobOutput::Pdf(conversion) => Ok(conversion),
+            output => Err(anyhow!("unexpected convert output: {output:?}").into()),
+        }
+    }
+
+    /// Sends the provided file bytes to the office runner to detect the kind
+    /// of document office loads the file as using its type detection, the file
+    /// is loaded but not exported. Provides [None] when office could not load
+    /// the file
+    pub async fn detect(&self, bytes: Bytes) -> Result<Option<DocumentKind>, ConvertError> {
+        let _slot = self.acquire(bytes.len()).await;
+
+        if let Some(process) = &self.process {
+            return process.detect(&self.worker, bytes).await;
+        }
+
+        let (tx, rx) = oneshot::channel();
+
+        self.tx
+            .send(OfficeMsg::Detect {
+                bytes,
+                span: Span::current(),
+                tx,
+            })
+            .await
+            .context("failed to send detect request")?;
+
+        match rx.await.context("failed to get detect response")?? {
+            JobOutput::DocumentKind(value) => Ok(value),
+            output => Err(anyhow!("unexpected detect output: {output:?}").into()),
+        }
+    }
+
+    /// Sends the provided file bytes to the office runner to check whether
+    /// office can load the file, the file is loaded but not exported. Files
+    /// office can't load provide a [Validation] with the reason rather than
+    /// an error
+    pub async fn validate(&self, bytes: Bytes) -> Result<Validation, ConvertError> {
+        let _slot = self.acquire(bytes.len()).await;
+
+        if let Some(process) = &self.process {
+            return process.validate(&self.worker, bytes).await;
+        }
+
+        let (tx, rx) = oneshot::channel();
+
+        self.tx
+            .send(OfficeMsg::Validate {
+                bytes,
+                span: Span::current(),
+                tx,
+            })
+            .await
+            .context("failed to send validate request")?;
+
+        match rx.await.context("failed to get validate response")?? {
+            JobOutput::Validation(value) => Ok(value),
+            output => Err(anyhow!("unexpected validate output: {output:?}").into()),
+        }
+    }
+
+    /// Sends the provided file bytes to the office runner to render each page
+    /// of the document as an image using the provided options, provides the
+    /// images in page order. Fails when the document has more pages than the
+    /// output page limit or the images are larger than the output size limit
+    pub async fn render_pages(
+        &self,
+        bytes: Bytes,
+        options: RenderOptions,
+    ) -> Result<Vec<Bytes>, ConvertError> {
+        let pages = {
+            let _slot = self.acquire(bytes.len()).await;
+            self.send_render(bytes, options).await?
+        };
+
+        if let Some(max) = self.output_limits.max_bytes {
+            let size: u64 = pages.iter().map(|page| page.len() as u64).sum();
+            if size > max {
+                return Err(ConvertError::OutputTooLarge { size, max });
+            }
+        }
+
+        Ok(pages)
+    }
+
+    /// Sends the rendering to the office runner and waits for the rendered
+    /// images, the runner must be held by the caller
+    async fn send_render(
+        &self,
+        bytes: Bytes,
+        options: RenderOptions,
+    ) -> Result<Vec<Bytes>, ConvertError> {
+        if let Some(process) = &self.process {
+            return process.render(&self.worker, bytes, options).await;
+        }
+
+        let (tx, rx) = oneshot::channel();
+
+        self.tx
+            .send(OfficeMsg::Render {
+                bytes,
+                options,
+                span: Span::current(),
+                tx,
+            })
+            .await
+            .context("failed to send render request")?;
+
+        match rx.await.context("failed to get render response")?? {
+            JobOutput::Pages(pages) => Ok(pages),
+            output => Err(anyhow!("unexpected render output: {output:?}").into()),
+        }
+    }
+
+    /// Checks a PDF produced from converted documents (i.e multiple conversions
+    /// merged together) against the output limits
+    pub async fn check_output_limits(&self, pdf: &Bytes) -> Result<(), ConvertError> {
+        self.output_limits.check(pdf).await
+    }
+
+    /// Requests the office runner tear down and recreate its office instance,
+    /// waits until the restart has completed. The restart is processed after
+    /// any conversion that is currently in progress
+    pub async fn restart(&self) -> anyhow::Result<()> {
+        let (tx, rx) = oneshot::channel();
+
+        self.tx
+            .send(OfficeMsg::Restart { tx })
+            .await
+            .context("failed to send restart request")?;
+
+        rx.await.context("failed to get restart response")?
+    }
+
+    /// Gets the current state of the runner, doesn't wait on the runner
+    /// so is safe to call while a conversion is in progress
+    pub fn status(&self) -> WorkerStatus {
+        self.worker.status()
+    }
+
+    /// Number of conversions waiting for the runner, doesn't include the
+    /// conversion currently in progress
+    pub fn waiting(&self) -> usize {
+        self.queue.waiting()
+    }
+
+    /// Provides the most recent conversions made by the converter (and any
+    /// handles to the same runner), most recent first
+    pub fn recent_conversions(&self) -> Vec<ConversionRecord> {
+        self.history.recent()
+    }
+
+    /// Provides the percentiles of the time the most recent requests made by
+    /// the converter (and any handles to the same runner) spent waiting for
+    /// the runner
+    pub fn queue_wait_stats(&self) -> QueueWaitStats {
+        self.queue_waits.stats()
+    }
+
+    /// Provides histograms of the resources (CPU time, peak memory and temp
+    /// disk) used by the successful conversions made by the converter (and
+    /// any handles to the same runner)
+    pub fn resource_usage(&self) -> ResourceUsageStats {
+        self.usage.snapshot()
+    }
+
+    /// Checks if the runner is currently busy converting a document
+    /// or restarting
+    pub fn is_busy(&self) -> bool {
+        self.status().state != WorkerState::Idle
+    }
+
+    /// Tells office to clean up and trim its memory usage, processed
+    /// after the conversion currently in progress
+    pub async fn collect_garbage(&self) -> anyhow::Result<()> {
+        self.tx
+            .send(OfficeMsg::CollectGarbage)
+            .await
+            .context("failed to send collect garbage request")
+    }
+}
+
+/// Options for creating a converter
+#[derive(Debug, Clone)]
+pub struct ConverterOptions {
+    /// Path to the office installation
+    pub office_path: PathBuf,
+    /// Directory to write temporary input and output files
+    pub temp_dir: PathBuf,
+    /// Memory backed directory (i.e "/dev/shm") to write the input and output
+    /// files of a conversion to in place of the temp directory. Office can only
+    /// load and save documents using files, this avoids writing them to disk.
+    /// The temp directory is used when the directory doesn't have space for
+    /// the conversion
+    pub memory_dir: Option<PathBuf>,
+    /// Free disk space in bytes to keep available in the temp directory
+    pub min_free_disk: u64,
+    /// Whether to overwrite temporary files before deleting them
+    pub secure_delete: bool,
+    /// Additional directories to load fonts from
+    pub font_dirs: Vec<PathBuf>,
+    /// Certificate used to sign PDFs when requested by the export options
+    pub signing: Option<SigningCertificate>,
+    /// Maximum time office can spend in a single call (loading, exporting or
+    /// signing) before the conversion fails with [ConvertError::Timeout], office
+    /// is restarted once the call returns. The process exits when the call has
+    /// not returned after twice the duration as office cannot be restarted
+    /// while a call is stuck
+    pub max_convert_duration: Option<Duration>,
+    /// Limits on the size of converted PDFs, conversions exceeding them fail
+    /// with [ConvertError::OutputTooLarge] or [ConvertError::TooManyPages]
+    pub output_limits: OutputLimits,
+    /// Restrictions applied to office, see [SandboxOptions]
+    pub sandbox: SandboxOptions,
+    /// Whether documents can run macros and update external links while
+    /// loading, both are disabled by default
+    pub document_security: DocumentSecurity,
+    /// Policy for retrying conversions that fail inside office, failed
+    /// conversions are not retried by default
+    pub retry: RetryPolicy,
+    /// Size in bytes below which files are considered small, waiting
+    /// conversions of small files are processed before waiting conversions
+    /// of larger files with the same priority. Waiting conversions are
+    /// processed in order regardless of size when not set
+    pub small_file_threshold: Option<u64>,
+    /// Policy for raising the priority of conversions that have waited a
+    /// long time so low priority conversions aren't held back forever by
+    /// higher priority conversions. Priorities are not raised when not set
+    pub priority_aging: Option<PriorityAging>,
+    /// Draw embedded objects (i.e equations and embedded spreadsheets) using
+    /// the replacement image stored with them instead of converting them into
+    /// office objects, objects office can't convert otherwise render as
+    /// placeholders
+    pub prefer_replacement_images: bool,
+    /// Fonts to use in place of fonts that aren't installed keyed by the name
+    /// of the missing font (i.e "Calibri" to "Carlito"), applied by office
+    /// while loading documents
+    pub font_substitutions: BTreeMap<String, String>,
+    /// Command starting a new process for each conversion, see [ConversionProcess].
+    /// Office is still started in this process to provide the [OfficeDetails].
+    /// Conversions are made using the office instance in this process when not set
+    pub conversion_process: Option<ConversionProcess>,
+    /// Return memory to the system after every job for hosts with little
+    /// memory, office caches are trimmed and the freed memory of the process
+    /// is released at the cost of slower conversions
+    pub low_memory: bool,
+}
+
+/// Certificate and private key used to digitally sign exported PDFs
+#[derive(Clone)]
+pub struct SigningCertificate {
+    /// DER encoded X.509 certificate
+    pub certificate: Vec<u8>,
+    /// DER encoded PKCS#8 private key for the certificate
+    pub private_key: Vec<u8>,
+}
+
+impl std::fmt::Debug for SigningCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Private key is intentionally omitted
+        f.debug_struct("SigningCertificate")
+            .field("certificate", &self.certificate.len())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Kind of document office loaded a file as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DocumentKind {
+    /// Text document (Writer)
+    Text,
+    /// Spreadsheet (Calc)
+    Spreadsheet,
+    /// Presentation (Impress)
+    Presentation,
+    /// Drawing (Draw), includes images and PDFs
+    Drawing,
+    /// Other kind of document (i.e formulas)
+    Other,
+}
+
+impl From<DocumentType> for DocumentKind {
+    fn from(value: DocumentType) -> Self {
+        match value {
+            DocumentType::Text => DocumentKind::Text,
+            DocumentType::Spreadsheet => DocumentKind::Spreadsheet,
+            DocumentType::Presentation => DocumentKind::Presentation,
+            DocumentType::Drawing => DocumentKind::Drawing,
+            DocumentType::Other(_) => DocumentKind::Other,
+        }
+    }
+}
+
+/// Details about the office installation used by a converter
+#[derive(Debug)]
+pub struct OfficeDetails {
+    /// File types supported by the install, not available on older versions
+    pub filter_types: Option<FilterTypes>,
+    /// Version of the install, not available on older versions
+    pub version: Option<OfficeVersionInfo>,
+}
+
+impl OfficeDetails {
+    /// Checks if office is able to convert the file at the provided path based
+    /// on the mime type of its file extension. When the filter types are not
+    /// available all files are treated as supported
+    pub fn is_supported_path(&self, path: &Path) -> bool {
+        let filter_types = match &self.filter_types {
+            Some(value) => value,
+            None => return true,
+        };
+
+        mime_guess::from_path(path)
+            .iter()
+            .any(|mime| filter_types.is_mime_supported(mime.essence_str()))
+    }
+}
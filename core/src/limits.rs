@@ -0,0 +1,53 @@
+use crate::ConvertError;
+use anyhow::Context;
+use bytes::Bytes;
+use lopdf::Document;
+
+/// Limits on the size of converted PDFs, protects consumers of the server
+/// from unexpectedly large outputs (i.e a small spreadsheet that expands
+/// to thousands of pages)
+#[derive(Debug, Default, Clone, Copy)]
+pub struct OutputLimits {
+    /// Maximum number of pages in a converted PDF
+    pub max_pages: Option<u64>,
+    /// Maximum size of a converted PDF in bytes
+    pub max_bytes: Option<u64>,
+    /// Maximum number of pages rendered as images, each page is rendered
+    /// using a separate export of the document
+    pub max_render_pages: Option<u64>,
+}
+
+impl OutputLimits {
+    /// Checks the converted PDF against the limits, the page count is only
+    /// read when a page limit is configured
+    pub(crate) async fn check(&self, pdf: &Bytes) -> Result<(), ConvertError> {
+        if let Some(max) = self.max_bytes {
+            let size = pdf.len() as u64;
+            if size > max {
+                return Err(ConvertError::OutputTooLarge { size, max });
+            }
+        }
+
+        if let Some(max) = self.max_pages {
+            // Parsing large documents is expensive so its moved off the async runtime
+            let pages = tokio::task::spawn_blocking({
+                let pdf = pdf.clone();
+                move || page_count(&pdf)
+            })
+            .await
+            .context("failed to count pages")??;
+
+            if pages > max {
+                return Err(ConvertError::TooManyPages { pages, max });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts the number of pages in the provided PDF document
+pub fn page_count(bytes: &[u8]) -> anyhow::Result<u64> {
+    let document = Document::load_mem(bytes).context("failed to load pdf")?;
+    Ok(document.get_pages().len() as u64)
+}
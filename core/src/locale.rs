@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+/// Environment variable the time zone is read from
+pub(crate) const TZ_VARIABLE: &str = "TZ";
+
+/// Directory time zone files are read from when "TZDIR" isn't set
+const DEFAULT_ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// Checks the locale is a BCP 47 language tag made of a 2-3 letter language
+/// and optional subtags (i.e "de", "de-DE" or "sr-Latn-RS")
+pub(crate) fn is_valid_locale(value: &str) -> bool {
+    let mut parts = value.split('-');
+
+    let language_valid = parts.next().is_some_and(|language| {
+        (2..=3).contains(&language.len()) && language.chars().all(|c| c.is_ascii_alphabetic())
+    });
+
+    language_valid
+        && parts.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// Checks the time zone is the name of a time zone installed on the system
+/// (i.e "Europe/Berlin" or "UTC")
+pub(crate) fn is_valid_timezone(value: &str) -> bool {
+    let name_valid = !value.is_empty()
+        && !value.starts_with('/')
+        && value.split('/').all(|part| part != ".." && part != ".")
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '/' | '_' | '-' | '+'));
+
+    name_valid && zoneinfo_dir().join(value).is_file()
+}
+
+/// Directory containing the time zone files
+fn zoneinfo_dir() -> PathBuf {
+    std::env::var_os("TZDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_ZONEINFO_DIR))
+}
+
+/// Locale of the system (i.e "en-US") from the "LC_ALL" or "LANG" environment
+/// variables, documents loaded without a locale are loaded using this locale
+/// once another locale has been used
+pub(crate) fn system_locale() -> String {
+    ["LC_ALL", "LANG"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        // Strip the encoding and modifier (i.e "en_US.UTF-8@euro")
+        .filter_map(|value| value.split(['.', '@']).next().map(str::to_string))
+        .map(|value| value.replace('_', "-"))
+        .find(|value| is_valid_locale(value))
+        .unwrap_or_else(|| "en-US".to_string())
+}
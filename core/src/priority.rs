@@ -0,0 +1,321 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use tracing::debug;
+
+/// Priority of a conversion, when office is busy the waiting conversion
+/// with the highest priority is processed next. Conversions with the same
+/// priority are processed in the order they were requested
+///
+/// Variants are declared from lowest to highest priority
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Processed after any waiting normal or high priority conversions
+    Low,
+    /// Default priority
+    #[default]
+    Normal,
+    /// Processed before any waiting normal or low priority conversions
+    High,
+}
+
+impl Priority {
+    /// Raises the priority by the provided number of levels without going
+    /// above the maximum, priorities already above the maximum are kept
+    fn raise(self, levels: u128, max: Priority) -> Priority {
+        let mut priority = self;
+
+        for _ in 0..levels {
+            if priority >= max {
+                break;
+            }
+
+            priority = match priority {
+                Priority::Low => Priority::Normal,
+                Priority::Normal | Priority::High => Priority::High,
+            };
+        }
+
+        priority
+    }
+}
+
+/// Policy for raising the priority of waiting conversions, conversions that
+/// have waited a long time are eventually processed even while conversions
+/// with a higher priority keep arriving
+#[derive(Debug, Clone, Copy)]
+pub struct PriorityAging {
+    /// Time a conversion waits before its priority is raised by one level,
+    /// the priority is raised again each time it waits this long
+    pub interval: Duration,
+    /// Highest priority waiting conversions can be raised to
+    pub max: Priority,
+}
+
+/// Size class of a conversion, waiting conversions of small files are
+/// processed before waiting conversions of large files with the same
+/// priority so small files don't wait behind several large files
+///
+/// Variants are declared from lowest to highest precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum SizeClass {
+    /// File at or above the small file threshold
+    Large,
+    /// File below the small file threshold
+    Small,
+}
+
+/// Virtual time a conversion with a weight of one is given by the fair
+/// queue, conversions with a higher weight are given a fraction of this
+const SHARE_SCALE: u64 = 1_000_000;
+
+/// Share of the runner for conversions from the same source (i.e a tenant),
+/// waiting conversions with the same priority and size class take turns
+/// between sources in proportion to their weights so a source with many
+/// waiting conversions doesn't hold up the others
+#[derive(Debug, Clone)]
+pub(crate) struct FairShare {
+    /// Key identifying the source of the conversions
+    pub(crate) key: Arc<str>,
+    /// Weight of the source, a source with a weight of two is given the
+    /// runner twice as often as a source with a weight of one
+    pub(crate) weight: u32,
+}
+
+/// Queue deciding which conversion is sent to the office runner next, only
+/// one conversion holds the runner at a time
+pub(crate) struct PriorityQueue {
+    state: Mutex<QueueState>,
+    /// Policy for raising the priority of waiting conversions, priorities
+    /// are not raised when not set
+    aging: Option<PriorityAging>,
+}
+
+#[derive(Default)]
+struct QueueState {
+    /// Whether a conversion currently holds the runner
+    busy: bool,
+    /// Sequence number for the next waiter, keeps waiters with the
+    /// same priority in the order they arrived
+    next_seq: u64,
+    /// Conversions waiting for the runner
+    waiters: BinaryHeap<Waiter>,
+    /// Virtual start time of the last conversion given the runner
+    virtual_time: u64,
+    /// Virtual finish time of the last waiting conversion from each source
+    finish_times: HashMap<Arc<str>, u64>,
+}
+
+/// Conversion waiting for the runner
+struct Waiter {
+    /// Priority the waiter is ordered by, the requested priority raised
+    /// by the time it has waited when priority aging is enabled
+    priority: Priority,
+    /// Priority the waiter was queued with
+    requested: Priority,
+    /// Time the waiter was queued
+    queued_at: Instant,
+    size_class: SizeClass,
+    /// Virtual time the waiter starts at, conversions are given the
+    /// runner in order of their finish time
+    start: u64,
+    finish: u64,
+    seq: u64,
+    /// Sender notified when the waiter is given the runner
+    tx: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Higher priorities come first then small files then earlier finish
+    /// times then earlier sequence numbers
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| self.size_class.cmp(&other.size_class))
+            .then_with(|| other.finish.cmp(&self.finish))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl PriorityQueue {
+    /// Creates a queue raising the priority of waiting conversions using
+    /// the provided aging policy
+    pub(crate) fn new(aging: Option<PriorityAging>) -> Self {
+        Self {
+            state: Mutex::default(),
+            aging,
+        }
+    }
+
+    /// Waits until the runner is given to the conversion, the runner is
+    /// held until the returned slot is dropped. Conversions without a share
+    /// are treated as the only conversion from their source
+    pub(crate) async fn acquire(
+        self: &Arc<Self>,
+        priority: Priority,
+        size_class: SizeClass,
+        share: Option<&FairShare>,
+    ) -> PrioritySlot {
+        let rx = {
+            let state = &mut *self.state.lock();
+
+            if !state.busy {
+                state.busy = true;
+                return PrioritySlot(self.clone());
+            }
+
+            let (tx, rx) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+
+            // Sources with waiting conversions start after their last waiting
+            // conversion, each conversion advances the source by its share
+            let (start, finish) = match share {
+                Some(share) => {
+                    let start = state
+                        .finish_times
+                        .get(&share.key)
+                        .map_or(state.virtual_time, |finish| {
+                            (*finish).max(state.virtual_time)
+                        });
+                    let finish = start + SHARE_SCALE / u64::from(share.weight.max(1));
+                    state.finish_times.insert(share.key.clone(), finish);
+                    (start, finish)
+                }
+                None => (state.virtual_time, state.virtual_time + SHARE_SCALE),
+            };
+
+            state.waiters.push(Waiter {
+                priority,
+                requested: priority,
+                queued_at: Instant::now(),
+                size_class,
+                start,
+                finish,
+                seq,
+                tx,
+            });
+            rx
+        };
+
+        let mut waiting = WaitingSlot {
+            queue: self.clone(),
+            rx,
+        };
+
+        // Waiters are only removed from the queue to be given the runner
+        _ = (&mut waiting.rx).await;
+
+        PrioritySlot(self.clone())
+    }
+
+    /// Number of conversions waiting for the runner
+    pub(crate) fn waiting(&self) -> usize {
+        // Waiters that stopped waiting remain until the runner is released
+        self.state
+            .lock()
+            .waiters
+            .iter()
+            .filter(|waiter| !waiter.tx.is_closed())
+            .count()
+    }
+
+    /// Gives the runner to the next waiting conversion
+    fn release(&self) {
+        let state = &mut *self.state.lock();
+
+        if let Some(aging) = self.aging {
+            state.age_waiters(aging);
+        }
+
+        while let Some(waiter) = state.waiters.pop() {
+            // Waiters that stopped waiting (i.e request was cancelled) are skipped
+            if waiter.tx.send(()).is_ok() {
+                state.virtual_time = state.virtual_time.max(waiter.start);
+
+                // Sources without waiting conversions start again from the virtual time
+                let virtual_time = state.virtual_time;
+                state
+                    .finish_times
+                    .retain(|_, finish| *finish > virtual_time);
+                return;
+            }
+        }
+
+        state.busy = false;
+    }
+}
+
+impl QueueState {
+    /// Raises the priority of the waiters by the time they have waited
+    fn age_waiters(&mut self, aging: PriorityAging) {
+        let now = Instant::now();
+        let interval = aging.interval.as_nanos().max(1);
+        let mut waiters = std::mem::take(&mut self.waiters).into_vec();
+
+        for waiter in &mut waiters {
+            let levels = now.duration_since(waiter.queued_at).as_nanos() / interval;
+            let priority = waiter.requested.raise(levels, aging.max);
+
+            if priority != waiter.priority {
+                debug!(
+                    requested = ?waiter.requested,
+                    ?priority,
+                    "raised priority of waiting conversion"
+                );
+                waiter.priority = priority;
+            }
+        }
+
+        self.waiters = BinaryHeap::from(waiters);
+    }
+}
+
+/// Slot holding the office runner, gives the runner to the next
+/// waiting conversion when dropped
+pub(crate) struct PrioritySlot(Arc<PriorityQueue>);
+
+impl Drop for PrioritySlot {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Conversion waiting in the queue, handles the waiter being given the
+/// runner after it has stopped waiting
+struct WaitingSlot {
+    queue: Arc<PriorityQueue>,
+    rx: oneshot::Receiver<()>,
+}
+
+impl Drop for WaitingSlot {
+    fn drop(&mut self) {
+        self.rx.close();
+
+        // Runner was given to the waiter but never taken
+        if self.rx.try_recv().is_ok() {
+            self.queue.release();
+        }
+    }
+}
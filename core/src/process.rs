@@ -0,0 +1,395 @@
+use crate::{
+    locale::TZ_VARIABLE,
+    temp::TempFile,
+    worker::{WorkerState, WorkerTracker},
+    Conversion, ConversionWarning, ConvertError, Converter, ConverterOptions, DocumentKind,
+    PdfExportOptions, RenderOptions, ResourceUsage, RetryPolicy, Validation,
+};
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::process::Command;
+use tracing::{debug, info_span, Instrument};
+
+/// Environment variable the conversion process reads the path to its
+/// request file from
+const REQUEST_VARIABLE: &str = "OFFICE_CONVERT_PROCESS_REQUEST";
+
+/// Command starting a short-lived process to run a single conversion in, so
+/// that a document crashing or corrupting office only affects its own
+/// process. The process must call [run_conversion_process] with the same
+/// converter options
+#[derive(Debug, Clone)]
+pub struct ConversionProcess {
+    /// Program to run (i.e the current executable)
+    pub program: PathBuf,
+    /// Arguments to run the program with
+    pub args: Vec<OsString>,
+}
+
+/// Work for the conversion process to perform
+#[derive(Serialize, Deserialize)]
+enum ProcessTask {
+    /// Convert the input to a PDF written to the output
+    Convert(Box<PdfExportOptions>),
+    /// Detect the kind of document office loads the input as
+    Detect,
+    /// Check whether office can load the input
+    Validate,
+    /// Render each page of the input as an image, the images are written
+    /// to the output one after another
+    Render(RenderOptions),
+}
+
+/// Request written for the conversion process
+#[derive(Serialize, Deserialize)]
+struct ProcessRequest {
+    /// Path to the file to convert
+    input: PathBuf,
+    /// Path to write the converted PDF to
+    output: PathBuf,
+    /// Path to write the [ProcessResponse] to
+    response: PathBuf,
+    /// Work to perform
+    task: ProcessTask,
+}
+
+/// Outcome written by the conversion process
+#[derive(Serialize, Deserialize)]
+enum ProcessResponse {
+    /// Input was converted and the PDF was written to the output
+    Converted {
+        warnings: Vec<ConversionWarning>,
+        read_only: bool,
+        usage: ResourceUsage,
+    },
+    /// Kind of document the input was loaded as
+    Detected(Option<DocumentKind>),
+    /// Outcome of loading the input
+    Validated(Validation),
+    /// Pages were rendered and written to the output, provides the size
+    /// in bytes of the image of each page
+    Rendered { image_sizes: Vec<usize> },
+    /// Conversion failed
+    Failed(ProcessError),
+}
+
+/// [ConvertError] reported by the conversion process
+#[derive(Serialize, Deserialize)]
+enum ProcessError {
+    InsufficientDisk { required: u64, available: u64 },
+    SigningUnavailable,
+    Timeout { seconds: u64 },
+    TooManyPages { pages: u64, max: u64 },
+    Office(String),
+}
+
+impl From<ConvertError> for ProcessError {
+    fn from(value: ConvertError) -> Self {
+        match value {
+            ConvertError::InsufficientDisk {
+                required,
+                available,
+            } => ProcessError::InsufficientDisk {
+                required,
+                available,
+            },
+            ConvertError::SigningUnavailable => ProcessError::SigningUnavailable,
+            ConvertError::Timeout { seconds } => ProcessError::Timeout { seconds },
+            ConvertError::TooManyPages { pages, max } => ProcessError::TooManyPages { pages, max },
+            err => ProcessError::Office(format!("{:#}", anyhow::Error::from(err))),
+        }
+    }
+}
+
+impl From<ProcessError> for ConvertError {
+    fn from(value: ProcessError) -> Self {
+        match value {
+            ProcessError::InsufficientDisk {
+                required,
+                available,
+            } => ConvertError::InsufficientDisk {
+                required,
+                available,
+            },
+            ProcessError::SigningUnavailable => ConvertError::SigningUnavailable,
+            ProcessError::Timeout { seconds } => ConvertError::Timeout { seconds },
+            ProcessError::TooManyPages { pages, max } => ConvertError::TooManyPages { pages, max },
+            ProcessError::Office(message) => ConvertError::Office(anyhow!(message)),
+        }
+    }
+}
+
+/// Runs conversions in a new process for each conversion
+pub(crate) struct ProcessRunner {
+    /// Command starting the process
+    process: ConversionProcess,
+    /// Directory to write the files exchanged with the process
+    temp_dir: PathBuf,
+    /// Whether to overwrite the exchanged files before deleting them
+    secure_delete: bool,
+}
+
+impl ProcessRunner {
+    pub(crate) fn new(process: ConversionProcess, options: &ConverterOptions) -> Self {
+        Self {
+            process,
+            temp_dir: options.temp_dir.clone(),
+            secure_delete: options.secure_delete,
+        }
+    }
+
+    /// Converts the file in a new conversion process
+    pub(crate) async fn convert(
+        &self,
+        worker: &WorkerTracker,
+        bytes: Bytes,
+        options: PdfExportOptions,
+    ) -> Result<Conversion, ConvertError> {
+        let task = ProcessTask::Convert(Box::new(options));
+
+        match self.run(worker, bytes, task).await? {
+            (
+                ProcessResponse::Converted {
+                    warnings,
+                    read_only,
+                    usage,
+                },
+                Some(pdf),
+            ) => Ok(Conversion {
+                pdf,
+                warnings,
+                attempts: 1,
+                read_only,
+                usage,
+                queue_wait_ms: 0,
+            }),
+            _ => Err(anyhow!("unexpected conversion process response").into()),
+        }
+    }
+
+    /// Detects the kind of document in a new conversion process
+    pub(crate) async fn detect(
+        &self,
+        worker: &WorkerTracker,
+        bytes: Bytes,
+    ) -> Result<Option<DocumentKind>, ConvertError> {
+        match self.run(worker, bytes, ProcessTask::Detect).await? {
+            (ProcessResponse::Detected(kind), _) => Ok(kind),
+            _ => Err(anyhow!("unexpected conversion process response").into()),
+        }
+    }
+
+    /// Checks whether office can load the file in a new conversion process
+    pub(crate) async fn validate(
+        &self,
+        worker: &WorkerTracker,
+        bytes: Bytes,
+    ) -> Result<Validation, ConvertError> {
+        match self.run(worker, bytes, ProcessTask::Validate).await? {
+            (ProcessResponse::Validated(validation), _) => Ok(validation),
+            _ => Err(anyhow!("unexpected conversion process response").into()),
+        }
+    }
+
+    /// Renders the pages of the file in a new conversion process
+    pub(crate) async fn render(
+        &self,
+        worker: &WorkerTracker,
+        bytes: Bytes,
+        options: RenderOptions,
+    ) -> Result<Vec<Bytes>, ConvertError> {
+        match self
+            .run(worker, bytes, ProcessTask::Render(options))
+            .await?
+        {
+            (ProcessResponse::Rendered { image_sizes }, Some(mut output)) => {
+                if image_sizes.iter().sum::<usize>() != output.len() {
+                    return Err(anyhow!("conversion process output has the wrong size").into());
+                }
+
+                Ok(image_sizes
+                    .into_iter()
+                    .map(|size| output.split_to(size))
+                    .collect())
+            }
+            _ => Err(anyhow!("unexpected conversion process response").into()),
+        }
+    }
+
+    /// Runs the task in a new conversion process, provides the response
+    /// of the process and the converted PDF
+    async fn run(
+        &self,
+        worker: &WorkerTracker,
+        bytes: Bytes,
+        task: ProcessTask,
+    ) -> Result<(ProcessResponse, Option<Bytes>), ConvertError> {
+        let files = [
+            TempFile::new(&self.temp_dir, "process_input"),
+            TempFile::new(&self.temp_dir, "process_output"),
+            TempFile::new(&self.temp_dir, "process_response"),
+            TempFile::new(&self.temp_dir, "process_request"),
+        ];
+
+        worker.set(WorkerState::Converting, None);
+
+        let result = self
+            .run_with_files(&files, bytes, task)
+            .instrument(info_span!("conversion_process"))
+            .await;
+
+        worker.set(WorkerState::Idle, None);
+
+        let secure = self.secure_delete;
+        _ = tokio::task::spawn_blocking(move || {
+            for file in files {
+                file.remove(secure);
+            }
+        })
+        .await;
+
+        result
+    }
+
+    /// Exchanges the task with the conversion process through the files
+    /// (input, output, response and request)
+    async fn run_with_files(
+        &self,
+        [input, output, response, request]: &[TempFile; 4],
+        bytes: Bytes,
+        task: ProcessTask,
+    ) -> Result<(ProcessResponse, Option<Bytes>), ConvertError> {
+        let timezone = match &task {
+            ProcessTask::Convert(options) => options.timezone.clone(),
+            _ => None,
+        };
+
+        let request_bytes = serde_json::to_vec(&ProcessRequest {
+            input: input.path.clone(),
+            output: output.path.clone(),
+            response: response.path.clone(),
+            task,
+        })
+        .context("failed to serialize conversion process request")?;
+
+        tokio::fs::write(&input.path, bytes)
+            .await
+            .context("failed to write conversion process input")?;
+        tokio::fs::write(&request.path, request_bytes)
+            .await
+            .context("failed to write conversion process request")?;
+
+        let mut command = Command::new(&self.process.program);
+        command
+            .args(&self.process.args)
+            .env(REQUEST_VARIABLE, &request.path)
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+
+        // Office calculates the current date and time (i.e date fields and NOW())
+        // using the time zone of its process
+        if let Some(timezone) = timezone {
+            command.env(TZ_VARIABLE, timezone);
+        }
+
+        let status = command
+            .status()
+            .await
+            .context("failed to start conversion process")?;
+
+        debug!(%status, "conversion process exited");
+
+        // Process crashing (i.e a segfault) leaves no response
+        let response: ProcessResponse = match tokio::fs::read(&response.path).await {
+            Ok(value) => serde_json::from_slice(&value)
+                .context("failed to parse conversion process response")?,
+            Err(_) => {
+                return Err(
+                    anyhow!("conversion process exited without a response ({status})").into(),
+                )
+            }
+        };
+
+        match response {
+            ProcessResponse::Failed(err) => Err(err.into()),
+            ProcessResponse::Converted { .. } | ProcessResponse::Rendered { .. } => {
+                let pdf = tokio::fs::read(&output.path)
+                    .await
+                    .context("failed to read conversion process output")?;
+                Ok((response, Some(Bytes::from(pdf))))
+            }
+            response => Ok((response, None)),
+        }
+    }
+}
+
+/// Runs the single conversion requested by the process that started this
+/// process, the entry point of the [ConversionProcess] command. Office is
+/// started using the provided options
+pub async fn run_conversion_process(options: ConverterOptions) -> anyhow::Result<()> {
+    let request_path =
+        std::env::var_os(REQUEST_VARIABLE).context("missing conversion process request")?;
+    let request = std::fs::read(Path::new(&request_path))
+        .context("failed to read conversion process request")?;
+    let request: ProcessRequest =
+        serde_json::from_slice(&request).context("failed to parse conversion process request")?;
+
+    let options = ConverterOptions {
+        conversion_process: None,
+        // Retries are made by the process that started this process
+        retry: RetryPolicy::default(),
+        ..options
+    };
+
+    let (_, converter) = Converter::start(options).await?;
+
+    let input = std::fs::read(&request.input).context("failed to read conversion input")?;
+    let input = Bytes::from(input);
+
+    let response = match request.task {
+        ProcessTask::Convert(options) => match converter.send_convert(input, *options).await {
+            Ok(conversion) => {
+                std::fs::write(&request.output, &conversion.pdf)
+                    .context("failed to write conversion output")?;
+
+                ProcessResponse::Converted {
+                    warnings: conversion.warnings,
+                    read_only: conversion.read_only,
+                    usage: conversion.usage,
+                }
+            }
+            Err(err) => ProcessResponse::Failed(err.into()),
+        },
+        ProcessTask::Render(options) => match converter.send_render(input, options).await {
+            Ok(images) => {
+                std::fs::write(&request.output, images.concat())
+                    .context("failed to write conversion output")?;
+
+                ProcessResponse::Rendered {
+                    image_sizes: images.iter().map(Bytes::len).collect(),
+                }
+            }
+            Err(err) => ProcessResponse::Failed(err.into()),
+        },
+        ProcessTask::Detect => match converter.detect(input).await {
+            Ok(kind) => ProcessResponse::Detected(kind),
+            Err(err) => ProcessResponse::Failed(err.into()),
+        },
+        ProcessTask::Validate => match converter.validate(input).await {
+            Ok(validation) => ProcessResponse::Validated(validation),
+            Err(err) => ProcessResponse::Failed(err.into()),
+        },
+    };
+
+    let response = serde_json::to_vec(&response).context("failed to serialize response")?;
+    std::fs::write(&request.response, response)
+        .context("failed to write conversion process response")?;
+
+    Ok(())
+}
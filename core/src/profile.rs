@@ -0,0 +1,336 @@
+use crate::ConverterOptions;
+use std::path::PathBuf;
+
+/// Name of the office user profile directory within the temp directory
+#[cfg(all(unix, not(target_os = "macos")))]
+const PROFILE_DIR_NAME: &str = "office-convert-profile";
+
+/// Path of the configuration file within the profile directory, office reads
+/// its user profile from "$XDG_CONFIG_HOME/libreoffice/4/user"
+#[cfg(all(unix, not(target_os = "macos")))]
+const REGISTRY_FILE_PATH: &str = "libreoffice/4/user/registrymodifications.xcu";
+
+/// Configuration items disabling the conversion of embedded Microsoft objects
+/// into office objects while loading, the objects are kept as OLE objects and
+/// drawn using the replacement image stored with them
+#[cfg(all(unix, not(target_os = "macos")))]
+const REPLACEMENT_IMAGE_ITEMS: &[(&str, &str, &str)] = &[
+    (
+        "/org.openoffice.Office.Common/Filter/Microsoft/Import",
+        "MathTypeToMath",
+        "false",
+    ),
+    (
+        "/org.openoffice.Office.Common/Filter/Microsoft/Import",
+        "WinWordToWriter",
+        "false",
+    ),
+    (
+        "/org.openoffice.Office.Common/Filter/Microsoft/Import",
+        "ExcelToCalc",
+        "false",
+    ),
+    (
+        "/org.openoffice.Office.Common/Filter/Microsoft/Import",
+        "PowerPointToImpress",
+        "false",
+    ),
+];
+
+/// Settings restricting what documents can do while they are loaded, by
+/// default macros are never executed and external links are never updated
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DocumentSecurity {
+    /// Allow macros embedded in documents to run according to the office
+    /// macro security level instead of never running them
+    pub allow_macros: bool,
+    /// Allow office to resolve external links (linked files, images and DDE
+    /// references) using its defaults instead of never updating them
+    pub allow_external_links: bool,
+}
+
+impl DocumentSecurity {
+    /// Options provided when loading documents
+    pub(crate) fn load_options(&self) -> &'static str {
+        if self.allow_macros {
+            "InteractionHandler=0,Batch=1,EnableMacrosExecution=true"
+        } else {
+            "InteractionHandler=0,Batch=1,EnableMacrosExecution=false,MacroSecurityLevel=3"
+        }
+    }
+
+    /// Configuration items written to the user profile
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn registry_items(&self) -> Vec<(&'static str, &'static str, &'static str)> {
+        let mut items = Vec::new();
+
+        if !self.allow_macros {
+            items.extend([
+                (
+                    "/org.openoffice.Office.Common/Security/Scripting",
+                    "DisableMacrosExecution",
+                    "true",
+                ),
+                (
+                    "/org.openoffice.Office.Common/Security/Scripting",
+                    "MacroSecurityLevel",
+                    "3",
+                ),
+            ]);
+        }
+
+        if !self.allow_external_links {
+            items.extend([
+                (
+                    "/org.openoffice.Office.Common/Security/Scripting",
+                    "BlockUntrustedRefererLinks",
+                    "true",
+                ),
+                // Writer: 0 = Never, 1 = On request, 2 = Always
+                ("/org.openoffice.Office.Writer/Content/Update", "Link", "0"),
+                // Calc: 0 = Always, 1 = Never, 2 = On request
+                ("/org.openoffice.Office.Calc/Content/Update", "Link", "1"),
+            ]);
+        }
+
+        items
+    }
+}
+
+/// Creates the office user profile within the temp directory and writes the
+/// document security settings, the embedded object settings and the font
+/// replacement table into its configuration, must be called before office
+/// is created.
+///
+/// The profile is dedicated to the server so the configuration is replaced
+/// on each start
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn configure_profile(options: &ConverterOptions) -> anyhow::Result<Option<PathBuf>> {
+    use anyhow::Context;
+    use tracing::debug;
+
+    let security = &options.document_security;
+    let replacement_images = options.prefer_replacement_images;
+
+    let profile_dir = options.temp_dir.join(PROFILE_DIR_NAME);
+    let registry_path = profile_dir.join(REGISTRY_FILE_PATH);
+
+    if let Some(parent) = registry_path.parent() {
+        std::fs::create_dir_all(parent).context("failed to create profile directory")?;
+    }
+
+    let registry = registry_xml(security, replacement_images, &options.font_substitutions);
+
+    std::fs::write(&registry_path, registry).context("failed to write profile configuration")?;
+
+    debug!(
+        path = %profile_dir.display(),
+        ?security,
+        replacement_images,
+        font_substitutions = options.font_substitutions.len(),
+        "configured office profile"
+    );
+
+    // Office finds the user profile relative to the config directory
+    std::env::set_var("XDG_CONFIG_HOME", &profile_dir);
+
+    Ok(Some(profile_dir))
+}
+
+/// Creates the contents of the profile configuration file from the document
+/// security settings, the embedded object settings and the font replacement
+/// table
+#[cfg(all(unix, not(target_os = "macos")))]
+fn registry_xml(
+    security: &DocumentSecurity,
+    replacement_images: bool,
+    font_substitutions: &std::collections::BTreeMap<String, String>,
+) -> String {
+    use crate::fonts::escape_xml;
+
+    let mut registry = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <oor:items xmlns:oor=\"http://openoffice.org/2001/registry\" \
+        xmlns:xs=\"http://www.w3.org/2001/XMLSchema\" \
+        xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\">\n",
+    );
+
+    let mut items = security.registry_items();
+    if replacement_images {
+        items.extend_from_slice(REPLACEMENT_IMAGE_ITEMS);
+    }
+
+    for (path, name, value) in items {
+        registry.push_str(&format!(
+            "<item oor:path=\"{path}\"><prop oor:name=\"{name}\" oor:op=\"fuse\"><value>{value}</value></prop></item>\n"
+        ));
+    }
+
+    // Replacement table fonts are only replaced when they aren't installed
+    if !font_substitutions.is_empty() {
+        registry.push_str(
+            "<item oor:path=\"/org.openoffice.Office.Common/Font/Substitution\">\
+            <prop oor:name=\"Replacement\" oor:op=\"fuse\"><value>true</value></prop></item>\n",
+        );
+    }
+
+    for (index, (font, substitute)) in font_substitutions.iter().enumerate() {
+        registry.push_str(&format!(
+            "<item oor:path=\"/org.openoffice.Office.Common/Font/Substitution/FontPairs\">\
+            <node oor:name=\"_{index}\" oor:op=\"replace\">\
+            <prop oor:name=\"Always\" oor:op=\"fuse\"><value>false</value></prop>\
+            <prop oor:name=\"OnScreenOnly\" oor:op=\"fuse\"><value>false</value></prop>\
+            <prop oor:name=\"ReplaceFont\" oor:op=\"fuse\"><value>{}</value></prop>\
+            <prop oor:name=\"SubstituteFont\" oor:op=\"fuse\"><value>{}</value></prop>\
+            </node></item>\n",
+            escape_xml(font),
+            escape_xml(substitute)
+        ));
+    }
+
+    registry.push_str("</oor:items>\n");
+    registry
+}
+
+/// Office only finds its user profile through XDG_CONFIG_HOME on platforms
+/// using XDG directories, the default profile is used elsewhere and only the
+/// macro settings provided when loading documents apply
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub(crate) fn configure_profile(options: &ConverterOptions) -> anyhow::Result<Option<PathBuf>> {
+    if !options.document_security.allow_external_links {
+        tracing::warn!("external links can only be disabled on platforms using XDG directories");
+    }
+
+    if !options.font_substitutions.is_empty() {
+        tracing::warn!("font substitutions can only be applied on platforms using XDG directories");
+    }
+
+    if options.prefer_replacement_images {
+        tracing::warn!(
+            "replacement images can only be preferred on platforms using XDG directories"
+        );
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DocumentSecurity;
+
+    /// Every combination of the document security settings
+    const SECURITY_LEVELS: [DocumentSecurity; 4] = [
+        DocumentSecurity {
+            allow_macros: false,
+            allow_external_links: false,
+        },
+        DocumentSecurity {
+            allow_macros: true,
+            allow_external_links: false,
+        },
+        DocumentSecurity {
+            allow_macros: false,
+            allow_external_links: true,
+        },
+        DocumentSecurity {
+            allow_macros: true,
+            allow_external_links: true,
+        },
+    ];
+
+    #[test]
+    fn load_options_disable_macros_by_default() {
+        assert_eq!(
+            DocumentSecurity::default().load_options(),
+            "InteractionHandler=0,Batch=1,EnableMacrosExecution=false,MacroSecurityLevel=3"
+        );
+    }
+
+    #[test]
+    fn load_options_follow_macro_setting() {
+        for security in SECURITY_LEVELS {
+            let expected = match security.allow_macros {
+                true => "InteractionHandler=0,Batch=1,EnableMacrosExecution=true",
+                false => {
+                    "InteractionHandler=0,Batch=1,EnableMacrosExecution=false,MacroSecurityLevel=3"
+                }
+            };
+
+            assert_eq!(security.load_options(), expected, "{security:?}");
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    mod registry {
+        use super::{super::*, SECURITY_LEVELS};
+        use std::collections::BTreeMap;
+
+        const SCRIPTING: &str = "/org.openoffice.Office.Common/Security/Scripting";
+
+        const MACRO_ITEMS: [(&str, &str, &str); 2] = [
+            (SCRIPTING, "DisableMacrosExecution", "true"),
+            (SCRIPTING, "MacroSecurityLevel", "3"),
+        ];
+
+        const EXTERNAL_LINK_ITEMS: [(&str, &str, &str); 3] = [
+            (SCRIPTING, "BlockUntrustedRefererLinks", "true"),
+            ("/org.openoffice.Office.Writer/Content/Update", "Link", "0"),
+            ("/org.openoffice.Office.Calc/Content/Update", "Link", "1"),
+        ];
+
+        #[test]
+        fn registry_items_for_each_security_level() {
+            for security in SECURITY_LEVELS {
+                let mut expected = Vec::new();
+                if !security.allow_macros {
+                    expected.extend(MACRO_ITEMS);
+                }
+                if !security.allow_external_links {
+                    expected.extend(EXTERNAL_LINK_ITEMS);
+                }
+
+                assert_eq!(security.registry_items(), expected, "{security:?}");
+            }
+        }
+
+        #[test]
+        fn registry_xml_contains_security_items() {
+            for security in SECURITY_LEVELS {
+                let xml = registry_xml(&security, false, &BTreeMap::new());
+
+                assert!(xml.starts_with("<?xml"));
+                assert!(xml.ends_with("</oor:items>\n"));
+
+                let items = MACRO_ITEMS
+                    .iter()
+                    .map(|item| (item, !security.allow_macros))
+                    .chain(
+                        EXTERNAL_LINK_ITEMS
+                            .iter()
+                            .map(|item| (item, !security.allow_external_links)),
+                    );
+
+                for ((path, name, value), written) in items {
+                    let item = format!(
+                        "<item oor:path=\"{path}\"><prop oor:name=\"{name}\" oor:op=\"fuse\"><value>{value}</value></prop></item>\n"
+                    );
+                    assert_eq!(xml.contains(&item), written, "{security:?} {name}");
+                }
+
+                assert!(!xml.contains("Filter/Microsoft/Import"));
+                assert!(!xml.contains("Font/Substitution"));
+            }
+        }
+
+        #[test]
+        fn registry_xml_allowing_everything_has_no_items() {
+            let security = DocumentSecurity {
+                allow_macros: true,
+                allow_external_links: true,
+            };
+            let xml = registry_xml(&security, false, &BTreeMap::new());
+
+            assert!(!xml.contains("<item"));
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Context};
+use lopdf::{Dictionary, Document, Object};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Maximum resolution pages can be rendered at in DPI
+pub const MAX_RENDER_DPI: u32 = 600;
+
+/// Quality of rendered JPEG images (1-100)
+const JPEG_QUALITY: u32 = 90;
+
+/// Size of a US letter page in points, used for pages without a size
+const DEFAULT_PAGE_SIZE: (f32, f32) = (612.0, 792.0);
+
+/// Image format pages are rendered as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[default]
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    /// Format provided to office when saving, office picks the image
+    /// export filter for the kind of document (i.e "writer_png_Export")
+    pub(crate) fn office_format(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    /// File extension for the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+        }
+    }
+
+    /// Mime type for the format
+    pub fn mime(&self) -> &'static str {
+        match self {
+            ImageFormat::Png => "image/png",
+            ImageFormat::Jpeg => "image/jpeg",
+        }
+    }
+}
+
+/// Options for rendering each page of a document as an image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderOptions {
+    /// Image format to render the pages as
+    pub format: ImageFormat,
+    /// Resolution to render the pages at in DPI
+    pub dpi: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            format: ImageFormat::default(),
+            dpi: 96,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Checks the options are valid, provides a message describing the
+    /// first invalid option
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_RENDER_DPI).contains(&self.dpi) {
+            return Err(format!("dpi must be between 1 and {MAX_RENDER_DPI}"));
+        }
+
+        Ok(())
+    }
+
+    /// Filter options string for exporting the page with the provided
+    /// number (starting at 1) and size in points as an image
+    pub(crate) fn filter_options(&self, page: usize, (width, height): (f32, f32)) -> String {
+        let pixels = |points: f32| (points / 72.0 * self.dpi as f32).round().max(1.0) as u32;
+
+        let mut options = Map::new();
+        options.insert(
+            "PageRange".to_string(),
+            json!({ "type": "string", "value": page.to_string() }),
+        );
+        options.insert(
+            "PixelWidth".to_string(),
+            json!({ "type": "long", "value": pixels(width).to_string() }),
+        );
+        options.insert(
+            "PixelHeight".to_string(),
+            json!({ "type": "long", "value": pixels(height).to_string() }),
+        );
+
+        if self.format == ImageFormat::Jpeg {
+            options.insert(
+                "Quality".to_string(),
+                json!({ "type": "long", "value": JPEG_QUALITY.to_string() }),
+            );
+        }
+
+        Value::Object(options).to_string()
+    }
+}
+
+/// Size of each page of the provided PDF in points, rotated pages
+/// provide their size as displayed
+pub(crate) fn page_sizes(pdf: &[u8]) -> anyhow::Result<Vec<(f32, f32)>> {
+    let document = Document::load_mem(pdf).context("failed to load pdf")?;
+
+    document
+        .get_pages()
+        .into_values()
+        .map(|page_id| {
+            let page = document
+                .get_dictionary(page_id)
+                .context("pdf missing page object")?;
+            Ok(page_size(&document, page))
+        })
+        .collect()
+}
+
+/// Size of a page in points from its media box, the media box and rotation
+/// can be inherited from the parent page tree nodes
+fn page_size(document: &Document, page: &Dictionary) -> (f32, f32) {
+    let (width, height) = inherited(document, page, b"MediaBox")
+        .and_then(|value| media_box_size(document, value).ok())
+        .unwrap_or(DEFAULT_PAGE_SIZE);
+
+    let rotate = inherited(document, page, b"Rotate")
+        .and_then(|value| value.as_i64().ok())
+        .unwrap_or_default();
+
+    if rotate.rem_euclid(180) == 90 {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+/// Finds a value on the page or the nearest parent page tree node
+fn inherited<'a>(document: &'a Document, page: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut node = page;
+
+    // Depth is limited so a cyclic page tree can't loop forever
+    for _ in 0..32 {
+        if let Ok(value) = node.get(key) {
+            return Some(value);
+        }
+
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = document.get_dictionary(parent).ok()?;
+    }
+
+    None
+}
+
+/// Width and height of a media box rectangle
+fn media_box_size(document: &Document, value: &Object) -> anyhow::Result<(f32, f32)> {
+    let value = match value {
+        Object::Reference(id) => document.get_object(*id)?,
+        value => value,
+    };
+
+    let values = value
+        .as_array()?
+        .iter()
+        .map(Object::as_float)
+        .collect::<Result<Vec<f32>, _>>()?;
+
+    let [left, bottom, right, top] = values[..] else {
+        return Err(anyhow!("invalid media box"));
+    };
+
+    Ok(((right - left).abs(), (top - bottom).abs()))
+}
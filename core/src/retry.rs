@@ -0,0 +1,24 @@
+use crate::ConvertError;
+
+/// Policy for retrying conversions that fail inside office, some documents
+/// fail to convert nondeterministically (i.e import races in office) and
+/// succeed when converted again
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RetryPolicy {
+    /// Number of times a failed conversion is attempted again before the
+    /// error is returned, conversions are not retried when zero
+    pub retries: u32,
+    /// Whether to restart office before each retry so the conversion is
+    /// attempted on a fresh office instance
+    pub restart: bool,
+}
+
+impl RetryPolicy {
+    /// Checks if a conversion that failed with the provided error after
+    /// the provided number of attempts should be attempted again. Only
+    /// failures from office are retried, failures caused by the document
+    /// or the limits (i.e timeouts and output limits) fail the same way again
+    pub(crate) fn should_retry(&self, attempts: u32, error: &ConvertError) -> bool {
+        matches!(error, ConvertError::Office(_)) && attempts <= self.retries
+    }
+}
@@ -0,0 +1,840 @@
+use crate::{
+    events::{classify_callback, OfficeEvent, OfficeEventSender},
+    export::PdfExportOptions,
+    fonts::configure_font_dirs,
+    locale::system_locale,
+    profile::{configure_profile, DocumentSecurity},
+    render::{page_sizes, RenderOptions},
+    sandbox::apply_sandbox,
+    set_current_job,
+    temp::JobTempFiles,
+    usage::UsageMeter,
+    watchdog::{ConvertStage, JobOutput, JobResponseTx, Watchdog},
+    worker::{WorkerState, WorkerTracker},
+    Conversion, ConversionWarning, ConvertError, ConverterOptions, DocumentKind, OfficeDetails,
+    Validation,
+};
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+use libreofficekit::{DocUrl, Document, Office, OfficeError, OfficeOptionalFeatures};
+use parking_lot::Mutex;
+use std::{ffi::CStr, path::Path, rc::Rc};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info, info_span, warn, Span};
+
+/// Target passed to office when trimming its memory after every job in low
+/// memory mode, values of 1000 and above drop as much as office can
+const LOW_MEMORY_TRIM_TARGET: i32 = 2000;
+
+/// Messages the office runner can process
+pub(crate) enum OfficeMsg {
+    /// Message to convert a file
+    Convert {
+        /// The file bytes to convert
+        bytes: Bytes,
+
+        /// Options for the PDF export
+        options: Box<PdfExportOptions>,
+
+        /// Sender for the events office reports while converting
+        events: Option<OfficeEventSender>,
+
+        /// Span the conversion was requested in, spans for the steps
+        /// of the conversion are created within this span
+        span: Span,
+
+        /// The return channel for sending back the result
+        tx: JobResponseTx,
+    },
+
+    /// Message to detect the kind of document office loads a file as
+    Detect {
+        /// The file bytes to detect
+        bytes: Bytes,
+
+        /// Span the detection was requested in
+        span: Span,
+
+        /// The return channel for sending back the result
+        tx: JobResponseTx,
+    },
+
+    /// Message to check whether office can load a file
+    Validate {
+        /// The file bytes to validate
+        bytes: Bytes,
+
+        /// Span the validation was requested in
+        span: Span,
+
+        /// The return channel for sending back the result
+        tx: JobResponseTx,
+    },
+
+    /// Message to render each page of a file as an image
+    Render {
+        /// The file bytes to render
+        bytes: Bytes,
+
+        /// Options for the rendered images
+        options: RenderOptions,
+
+        /// Span the rendering was requested in
+        span: Span,
+
+        /// The return channel for sending back the result
+        tx: JobResponseTx,
+    },
+
+    /// Tells office to clean up and trim its memory usage
+    CollectGarbage,
+
+    /// Tells the runner to tear down and recreate the office instance
+    Restart {
+        /// The return channel for sending back the restart outcome
+        tx: oneshot::Sender<anyhow::Result<()>>,
+    },
+}
+
+#[derive(Debug, Default)]
+struct RunnerState {
+    password_requested: bool,
+    /// URL of the document currently being loaded
+    input_url: Option<DocUrl>,
+    /// Password to modify to try when office requests one
+    modify_password: Option<String>,
+    /// Whether the current document was loaded read-only
+    read_only: bool,
+    /// Warnings reported by office during the current conversion
+    warnings: Vec<ConversionWarning>,
+    /// Sender for the events office reports during the current conversion
+    events: Option<OfficeEventSender>,
+}
+
+/// Work the runner performs for a job
+enum JobTask {
+    /// Convert the document to a PDF
+    Convert(Box<PdfExportOptions>),
+    /// Detect the kind of document office loads the file as
+    Detect,
+    /// Check whether office can load the file
+    Validate,
+    /// Render each page of the document as an image
+    Render(RenderOptions),
+}
+
+/// Reason the runner stopped processing messages with an office instance
+enum StopReason {
+    /// Restart was requested, provides the channel for the restart outcome
+    Restart(oneshot::Sender<anyhow::Result<()>>),
+    /// Conversion exceeded the maximum duration, office is restarted as
+    /// its state is unknown
+    TimedOut,
+    /// Message channel was closed
+    Closed,
+}
+
+/// Main event loop for an office runner
+pub(crate) fn office_runner(
+    options: ConverterOptions,
+    mut rx: mpsc::Receiver<OfficeMsg>,
+    worker: &WorkerTracker,
+    watchdog: &Watchdog,
+    startup_tx: &mut Option<oneshot::Sender<anyhow::Result<OfficeDetails>>>,
+) -> anyhow::Result<()> {
+    let runner_state = Rc::new(Mutex::new(RunnerState::default()));
+
+    // ID to use for the next conversion job
+    let mut next_job_id: u64 = 1;
+
+    // Channel to report the outcome of a requested restart
+    let mut restart_tx: Option<oneshot::Sender<anyhow::Result<()>>> = None;
+
+    // Fonts must be registered before office is created, the variables set for
+    // the fonts and profile replace any set using set_office_environment
+    configure_font_dirs(&options.font_dirs, &options.temp_dir)?;
+
+    // Profile must be configured before office is created
+    let profile_dir = configure_profile(&options)?;
+
+    // Restrictions must be applied before office is created so that the
+    // threads office creates inherit them
+    apply_sandbox(&options, profile_dir.as_deref()).context("failed to apply office sandbox")?;
+
+    loop {
+        // Create office instance
+        let office = match create_office(&options, &runner_state) {
+            Ok(value) => value,
+            Err(cause) => {
+                if let Some(restart_tx) = restart_tx.take() {
+                    _ = restart_tx.send(Err(anyhow!("failed to restart office: {cause:#}")));
+                }
+
+                return Err(cause);
+            }
+        };
+
+        // Report successful startup
+        if let Some(startup_tx) = startup_tx.take() {
+            // Load supported filters and office version details
+            let filter_types = office.get_filter_types().ok();
+            let version = office.get_version_info().ok();
+
+            _ = startup_tx.send(Ok(OfficeDetails {
+                filter_types,
+                version,
+            }));
+        }
+
+        // Report successful restart
+        if let Some(restart_tx) = restart_tx.take() {
+            info!("office restarted");
+            _ = restart_tx.send(Ok(()));
+        }
+
+        worker.set(WorkerState::Idle, None);
+
+        match process_messages(
+            &office,
+            &options,
+            &mut rx,
+            worker,
+            watchdog,
+            &runner_state,
+            &mut next_job_id,
+        ) {
+            StopReason::Restart(tx) => {
+                info!("restarting office");
+                restart_tx = Some(tx);
+            }
+            StopReason::TimedOut => {
+                warn!("restarting office after a conversion timed out");
+            }
+            StopReason::Closed => return Ok(()),
+        }
+
+        worker.set(WorkerState::Restarting, None);
+
+        // Existing instance must be dropped before a new one can be created
+        drop(office);
+    }
+}
+
+/// Creates and configures a new office instance
+fn create_office(
+    options: &ConverterOptions,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> anyhow::Result<Office> {
+    let office = Office::new(&options.office_path).context("failed to create office instance")?;
+
+    // Allow prompting for passwords
+    office
+        .set_optional_features(
+            OfficeOptionalFeatures::DOCUMENT_PASSWORD
+                | OfficeOptionalFeatures::DOCUMENT_PASSWORD_TO_MODIFY,
+        )
+        .context("failed to set optional features")?;
+
+    office
+        .register_callback({
+            let runner_state = runner_state.clone();
+
+            move |office, ty, payload| {
+                let payload = match payload.is_null() {
+                    true => &[][..],
+                    false => unsafe { CStr::from_ptr(payload) }.to_bytes(),
+                };
+
+                let Some(event) = classify_callback(ty, payload) else {
+                    debug!(?ty, "callback invoked");
+                    return;
+                };
+
+                debug!(?event, "office event");
+
+                let state = &mut *runner_state.lock();
+
+                match &event {
+                    OfficeEvent::PasswordRequested => {
+                        state.password_requested = true;
+
+                        // Provide now password
+                        if let Some(input_url) = &state.input_url {
+                            if let Err(cause) = office.set_document_password(input_url, None) {
+                                error!(?cause, "failed to set document password");
+                            }
+                        }
+                    }
+
+                    // Provided password is only tried once, the document is loaded
+                    // read-only when no password is provided or it is incorrect
+                    OfficeEvent::PasswordToModifyRequested => {
+                        let password = state.modify_password.take();
+                        state.read_only = password.is_none();
+
+                        if let Some(input_url) = &state.input_url {
+                            if let Err(cause) =
+                                office.set_document_password(input_url, password.as_deref())
+                            {
+                                error!(?cause, "failed to set document password to modify");
+                            }
+                        }
+                    }
+
+                    // Problems office recovered from while loading or exporting
+                    OfficeEvent::Warning { warning } if !state.warnings.contains(warning) => {
+                        state.warnings.push(warning.clone());
+                    }
+
+                    _ => {}
+                }
+
+                // Events are provided to the conversion that requested them
+                if let Some(events) = &state.events {
+                    _ = events.send(event);
+                }
+            }
+        })
+        .context("failed to register office callback")?;
+
+    Ok(office)
+}
+
+/// Processes messages using the provided office instance until the channel
+/// is closed, a restart is requested, or a conversion times out
+fn process_messages(
+    office: &Office,
+    options: &ConverterOptions,
+    rx: &mut mpsc::Receiver<OfficeMsg>,
+    worker: &WorkerTracker,
+    watchdog: &Watchdog,
+    runner_state: &Rc<Mutex<RunnerState>>,
+    next_job_id: &mut u64,
+) -> StopReason {
+    // Whether a document has been loaded with a locale
+    let mut locale_changed = false;
+
+    // Get next message
+    while let Some(msg) = rx.blocking_recv() {
+        let (input, mut task, span, output) = match msg {
+            OfficeMsg::Convert {
+                bytes,
+                options,
+                events,
+                span,
+                tx,
+            } => {
+                runner_state.lock().events = events;
+                (bytes, JobTask::Convert(options), span, tx)
+            }
+            // Detection only loads the document
+            OfficeMsg::Detect { bytes, span, tx } => (bytes, JobTask::Detect, span, tx),
+            OfficeMsg::Validate { bytes, span, tx } => (bytes, JobTask::Validate, span, tx),
+            OfficeMsg::Render {
+                bytes,
+                options,
+                span,
+                tx,
+            } => (bytes, JobTask::Render(options), span, tx),
+
+            OfficeMsg::CollectGarbage => {
+                if let Err(cause) = office.trim_memory(2000) {
+                    error!(%cause, "failed to collect garbage")
+                }
+                continue;
+            }
+            OfficeMsg::Restart { tx } => return StopReason::Restart(tx),
+        };
+
+        // Requests cancelled before the runner received them are skipped
+        if output.is_closed() {
+            debug!("request was cancelled, skipping job");
+            runner_state.lock().events = None;
+            continue;
+        }
+
+        // The locale a document is loaded with becomes the locale of office, documents
+        // without a locale are loaded with the system locale once another was used
+        if let JobTask::Convert(export_options) = &mut task {
+            match &export_options.locale {
+                Some(_) => locale_changed = true,
+                None if locale_changed => export_options.locale = Some(system_locale()),
+                None => {}
+            }
+        }
+
+        let job_id = *next_job_id;
+        *next_job_id += 1;
+
+        // Tag logs and captured office output with the job
+        let _span = info_span!(parent: &span, "job", job_id).entered();
+        set_current_job(Some(job_id));
+        worker.set(WorkerState::Converting, Some(job_id));
+        watchdog.start(job_id, input.len(), output);
+
+        // Resources used are measured from before the input is written
+        let meter = UsageMeter::start();
+
+        // Pick a directory with enough space to convert the document
+        let result = job_temp_dir(options, input.len() as u64)
+            // Convert document
+            .and_then(|job_dir| {
+                // Create unique temporary files for the job
+                let temp_files = JobTempFiles::new(job_dir)?;
+                runner_state.lock().input_url = Some(temp_files.input_url.clone());
+                watchdog.set_input_path(temp_files.input_path.clone());
+
+                let result = match &task {
+                    JobTask::Convert(export_options) => convert_document(
+                        office,
+                        &temp_files,
+                        input,
+                        export_options,
+                        options,
+                        watchdog,
+                        runner_state,
+                    )
+                    .map(|pdf| {
+                        let state = &mut *runner_state.lock();
+
+                        JobOutput::Pdf(Conversion {
+                            pdf,
+                            warnings: std::mem::take(&mut state.warnings),
+                            attempts: 1,
+                            read_only: state.read_only,
+                            usage: meter.finish(temp_files.disk_usage()),
+                            queue_wait_ms: 0,
+                        })
+                    })
+                    .map_err(ConvertError::from),
+                    JobTask::Detect => {
+                        detect_document(office, &temp_files, input, options, watchdog, runner_state)
+                            .map(JobOutput::DocumentKind)
+                            .map_err(ConvertError::from)
+                    }
+                    JobTask::Validate => validate_document(
+                        office,
+                        &temp_files,
+                        input,
+                        options,
+                        watchdog,
+                        runner_state,
+                    )
+                    .map(JobOutput::Validation)
+                    .map_err(ConvertError::from),
+                    JobTask::Render(render_options) => render_document(
+                        office,
+                        &temp_files,
+                        input,
+                        render_options,
+                        options,
+                        watchdog,
+                        runner_state,
+                    )
+                    .map(JobOutput::Pages),
+                };
+
+                // Remove the temporary files regardless of the outcome
+                info_span!("temp_cleanup").in_scope(|| temp_files.cleanup(options.secure_delete));
+
+                result
+            });
+
+        set_current_job(None);
+
+        // Reset runner state
+        *runner_state.lock() = RunnerState::default();
+
+        // Send response, the watchdog has already responded when timed out
+        match watchdog.finish() {
+            Some(output) => _ = output.send(result),
+            None => return StopReason::TimedOut,
+        }
+
+        worker.set(WorkerState::Idle, None);
+
+        if options.low_memory {
+            release_memory(office);
+        }
+    }
+
+    StopReason::Closed
+}
+
+/// Returns the memory freed by a finished job to the system, office drops its
+/// caches and the freed memory of the process held by the allocator is released
+fn release_memory(office: &Office) {
+    if let Err(cause) = office.trim_memory(LOW_MEMORY_TRIM_TARGET) {
+        debug!(%cause, "failed to trim office memory");
+    }
+
+    // Office allocates using the C allocator even when the server uses another allocator
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    // SAFETY: malloc_trim only releases memory that is no longer allocated
+    unsafe {
+        libc::malloc_trim(0);
+    }
+}
+
+/// Picks the directory to write the files for a conversion to, the memory
+/// directory is preferred when it has space for the conversion
+fn job_temp_dir(options: &ConverterOptions, input_size: u64) -> Result<&Path, ConvertError> {
+    if let Some(memory_dir) = &options.memory_dir {
+        if check_disk_space(memory_dir, input_size, options.min_free_disk).is_ok() {
+            return Ok(memory_dir);
+        }
+
+        debug!("insufficient space in memory directory, using temp directory");
+    }
+
+    check_disk_space(&options.temp_dir, input_size, options.min_free_disk)?;
+    Ok(&options.temp_dir)
+}
+
+/// Checks the temp directory has enough free space to convert a document of
+/// the provided size while keeping the reserved amount of space free
+fn check_disk_space(temp_dir: &Path, input_size: u64, reserve: u64) -> Result<(), ConvertError> {
+    let available = match fs2::available_space(temp_dir) {
+        Ok(value) => value,
+        Err(cause) => {
+            warn!(%cause, "failed to check available disk space");
+            return Ok(());
+        }
+    };
+
+    // Space for the input file and the output file (assumed to be no
+    // larger than the input) along with the reserved space
+    let required = input_size.saturating_mul(2).saturating_add(reserve);
+
+    if available < required {
+        return Err(ConvertError::InsufficientDisk {
+            required,
+            available,
+        });
+    }
+
+    Ok(())
+}
+
+/// Writes the provided document bytes to the temp input file and loads
+/// the document using the locale and raw load options from the export
+/// options, raw load options are appended to the default options
+fn load_document(
+    office: &Office,
+    temp_files: &JobTempFiles,
+    input: Bytes,
+    export_options: Option<&PdfExportOptions>,
+    options: &ConverterOptions,
+    watchdog: &Watchdog,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> anyhow::Result<Document> {
+    // Write to temp file
+    info_span!("temp_write", bytes = input.len())
+        .in_scope(|| std::fs::write(&temp_files.input_path, input))
+        .context("failed to write temp input")?;
+
+    // Macros are never run while loading documents that are sanitized
+    let sanitize = export_options.is_some_and(|value| value.sanitize == Some(true));
+    let document_security = DocumentSecurity {
+        allow_macros: options.document_security.allow_macros && !sanitize,
+        ..options.document_security
+    };
+
+    let mut load_options = document_security.load_options().to_string();
+
+    if let Some(locale) = export_options.and_then(|value| value.locale.as_deref()) {
+        load_options.push_str(",Language=");
+        load_options.push_str(locale);
+    }
+
+    // Later options take priority so raw options can override the defaults
+    if let Some(raw_load_options) =
+        export_options.and_then(|value| value.raw_load_options.as_deref())
+    {
+        load_options.push(',');
+        load_options.push_str(raw_load_options);
+    }
+
+    // Raw options can't turn macros back on for sanitized documents, the
+    // security options are repeated after them so they take priority
+    if sanitize {
+        load_options.push(',');
+        load_options.push_str(document_security.load_options());
+    }
+
+    runner_state.lock().modify_password =
+        export_options.and_then(|value| value.modify_password.clone());
+
+    // Load document
+    watchdog.stage(ConvertStage::Load)?;
+    let doc = info_span!("office_load")
+        .in_scope(|| office.document_load_with_options(&temp_files.input_url, &load_options));
+    let doc = match doc {
+        Ok(value) => value,
+        Err(err) => match err {
+            OfficeError::OfficeError(err) => {
+                error!(%err, "failed to load document");
+
+                let _state = &*runner_state.lock();
+
+                // File was encrypted with a password
+                if err.contains("Unsupported URL") {
+                    return Err(anyhow!("file is encrypted"));
+                }
+
+                // File is malformed or corrupted
+                if err.contains("loadComponentFromURL returned an empty reference") {
+                    return Err(anyhow!("file is corrupted"));
+                }
+
+                return Err(OfficeError::OfficeError(err).into());
+            }
+            err => return Err(err.into()),
+        },
+    };
+
+    debug!("document loaded");
+
+    Ok(doc)
+}
+
+/// Loads the provided document bytes to detect the kind of document office
+/// loads the file as, provides [None] when the file could not be loaded
+fn detect_document(
+    office: &Office,
+    temp_files: &JobTempFiles,
+    input: Bytes,
+    options: &ConverterOptions,
+    watchdog: &Watchdog,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> anyhow::Result<Option<DocumentKind>> {
+    let mut doc = match load_document(
+        office,
+        temp_files,
+        input,
+        None,
+        options,
+        watchdog,
+        runner_state,
+    ) {
+        Ok(value) => value,
+        Err(cause) => {
+            debug!(%cause, "document could not be loaded for detection");
+            return Ok(None);
+        }
+    };
+
+    let kind = doc
+        .get_document_type()
+        .context("failed to get document type")?;
+
+    // Free the loaded document before trimming memory
+    drop(doc);
+    _ = office.trim_memory(1000);
+
+    Ok(Some(DocumentKind::from(kind)))
+}
+
+/// Loads the provided document bytes to check whether office can load the
+/// file, failing to load the document is reported in the [Validation]
+fn validate_document(
+    office: &Office,
+    temp_files: &JobTempFiles,
+    input: Bytes,
+    options: &ConverterOptions,
+    watchdog: &Watchdog,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> anyhow::Result<Validation> {
+    let result = load_document(
+        office,
+        temp_files,
+        input,
+        None,
+        options,
+        watchdog,
+        runner_state,
+    );
+
+    let warnings = std::mem::take(&mut runner_state.lock().warnings);
+
+    let mut doc = match result {
+        Ok(value) => value,
+        Err(cause) => {
+            debug!(%cause, "document could not be loaded for validation");
+
+            return Ok(Validation {
+                document_type: None,
+                error: Some(format!("{cause:#}")),
+                warnings,
+                read_only: false,
+            });
+        }
+    };
+
+    let kind = doc
+        .get_document_type()
+        .context("failed to get document type")?;
+
+    // Free the loaded document before trimming memory
+    drop(doc);
+    _ = office.trim_memory(1000);
+
+    Ok(Validation {
+        document_type: Some(DocumentKind::from(kind)),
+        error: None,
+        warnings,
+        read_only: runner_state.lock().read_only,
+    })
+}
+
+/// Converts the provided document bytes into PDF format returning
+/// the converted bytes
+fn convert_document(
+    office: &Office,
+    temp_files: &JobTempFiles,
+    input: Bytes,
+    export_options: &PdfExportOptions,
+    options: &ConverterOptions,
+    watchdog: &Watchdog,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> anyhow::Result<Bytes> {
+    let mut doc = load_document(
+        office,
+        temp_files,
+        input,
+        Some(export_options),
+        options,
+        watchdog,
+        runner_state,
+    )?;
+
+    // Convert document
+    watchdog.stage(ConvertStage::Export)?;
+    let filter_options = export_options.filter_options();
+    let result = info_span!("office_save")
+        .in_scope(|| doc.save_as(&temp_files.output_url, "pdf", filter_options.as_deref()))?;
+
+    // Attempt to free up some memory
+    _ = office.trim_memory(1000);
+
+    if !result {
+        return Err(anyhow!("failed to convert file"));
+    }
+
+    // Sign the exported file in place
+    if export_options.sign == Some(true) {
+        let signing = options
+            .signing
+            .as_ref()
+            .context("no signing certificate is configured")?;
+
+        watchdog.stage(ConvertStage::Sign)?;
+        let signed = info_span!("office_sign")
+            .in_scope(|| {
+                office.sign_document(
+                    &temp_files.output_url,
+                    &signing.certificate,
+                    &signing.private_key,
+                )
+            })
+            .context("failed to sign pdf")?;
+
+        if !signed {
+            return Err(anyhow!("failed to sign pdf"));
+        }
+
+        debug!("document signed");
+    }
+
+    // Conversion may have timed out during the final call
+    watchdog.stage(ConvertStage::Read)?;
+
+    // Read document context
+    let bytes = info_span!("temp_read")
+        .in_scope(|| std::fs::read(&temp_files.output_path))
+        .context("failed to read temp out file")?;
+
+    Ok(Bytes::from(bytes))
+}
+
+/// Renders each page of the provided document bytes as an image using the
+/// image export filter of the kind of document, the document is exported
+/// as a PDF first to find the number of pages and their sizes
+fn render_document(
+    office: &Office,
+    temp_files: &JobTempFiles,
+    input: Bytes,
+    render_options: &RenderOptions,
+    options: &ConverterOptions,
+    watchdog: &Watchdog,
+    runner_state: &Rc<Mutex<RunnerState>>,
+) -> Result<Vec<Bytes>, ConvertError> {
+    let mut doc = load_document(
+        office,
+        temp_files,
+        input,
+        None,
+        options,
+        watchdog,
+        runner_state,
+    )?;
+
+    watchdog.stage(ConvertStage::Export)?;
+    let result = info_span!("office_save")
+        .in_scope(|| doc.save_as(&temp_files.output_url, "pdf", None))
+        .map_err(anyhow::Error::from)?;
+
+    if !result {
+        return Err(anyhow!("failed to convert file").into());
+    }
+
+    watchdog.stage(ConvertStage::Read)?;
+    let pdf = std::fs::read(&temp_files.output_path).context("failed to read temp out file")?;
+    let sizes = page_sizes(&pdf)?;
+
+    // Pages are checked before rendering as each page is a separate export
+    let limits = options.output_limits;
+    let max = [limits.max_pages, limits.max_render_pages]
+        .into_iter()
+        .flatten()
+        .min();
+
+    if let Some(max) = max {
+        let pages = sizes.len() as u64;
+        if pages > max {
+            return Err(ConvertError::TooManyPages { pages, max });
+        }
+    }
+
+    let mut images = Vec::with_capacity(sizes.len());
+
+    for (index, size) in sizes.into_iter().enumerate() {
+        let page = index + 1;
+        let filter_options = render_options.filter_options(page, size);
+
+        watchdog.stage(ConvertStage::Export)?;
+        let result = info_span!("office_render", page)
+            .in_scope(|| {
+                doc.save_as(
+                    &temp_files.output_url,
+                    render_options.format.office_format(),
+                    Some(&filter_options),
+                )
+            })
+            .map_err(anyhow::Error::from)?;
+
+        if !result {
+            return Err(anyhow!("failed to render page {page}").into());
+        }
+
+        watchdog.stage(ConvertStage::Read)?;
+        let image =
+            std::fs::read(&temp_files.output_path).context("failed to read rendered page")?;
+        images.push(Bytes::from(image));
+    }
+
+    // Attempt to free up some memory
+    _ = office.trim_memory(1000);
+
+    Ok(images)
+}
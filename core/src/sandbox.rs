@@ -0,0 +1,252 @@
+use crate::ConverterOptions;
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use thiserror::Error;
+
+/// Restrictions applied to the office runner thread before office is created,
+/// threads created by office inherit the restrictions.
+///
+/// Office is fed untrusted documents so the restrictions limit what office and
+/// the documents it loads can do through the kernel (files, network and
+/// privileges). Office runs inside the server process so code running in
+/// office still shares memory with the rest of the server
+#[derive(Debug, Default, Clone)]
+pub struct SandboxOptions {
+    /// User and group to run office as, requires permission to change user
+    /// (i.e started as root or with CAP_SETUID and CAP_SETGID). The temp
+    /// directory must be writable by the user
+    pub user: Option<SandboxUser>,
+    /// Restricts office to reading the office install, font directories and
+    /// system directories (/usr, /lib, /etc, ...) and writing to the temp
+    /// directory using landlock
+    pub restrict_filesystem: bool,
+    /// Additional paths office can read when the filesystem is restricted
+    pub read_paths: Vec<PathBuf>,
+    /// Blocks office from opening network sockets, prevents documents from
+    /// loading remote resources
+    pub block_network: bool,
+}
+
+impl SandboxOptions {
+    /// Checks if any of the restrictions are enabled
+    pub fn is_enabled(&self) -> bool {
+        self.user.is_some() || self.restrict_filesystem || self.block_network
+    }
+}
+
+/// User and group office is run as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SandboxUser {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Error for sandbox users that aren't in the "uid" or "uid:gid" format
+#[derive(Debug, Error)]
+#[error("invalid sandbox user \"{0}\", expected a uid or uid:gid")]
+pub struct InvalidSandboxUser(String);
+
+impl FromStr for SandboxUser {
+    type Err = InvalidSandboxUser;
+
+    /// Parses a "uid" or "uid:gid" pair, the gid is the same as the uid
+    /// when not provided
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidSandboxUser(value.to_string());
+
+        let (uid, gid) = match value.split_once(':') {
+            Some((uid, gid)) => (uid, gid),
+            None => (value, value),
+        };
+
+        Ok(SandboxUser {
+            uid: uid.trim().parse().map_err(|_| invalid())?,
+            gid: gid.trim().parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// System directories office needs to read libraries, configuration and
+/// fonts from when the filesystem is restricted
+#[cfg(target_os = "linux")]
+const SYSTEM_READ_PATHS: &[&str] = &[
+    "/usr", "/lib", "/lib64", "/bin", "/etc", "/opt", "/proc", "/sys",
+];
+
+/// Devices office needs to read when the filesystem is restricted
+#[cfg(target_os = "linux")]
+const DEVICE_READ_PATHS: &[&str] = &["/dev/urandom"];
+
+/// Devices office needs to write when the filesystem is restricted, the
+/// memory directory (/dev/shm) is only writable when it is used
+#[cfg(target_os = "linux")]
+const DEVICE_WRITE_PATHS: &[&str] = &["/dev/null"];
+
+/// Applies the sandbox restrictions to the current thread, must be called
+/// from the runner thread before office is created. The office user profile
+/// is given to the sandbox user
+#[cfg(target_os = "linux")]
+pub(crate) fn apply_sandbox(
+    options: &ConverterOptions,
+    profile_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use tracing::info;
+
+    let sandbox = &options.sandbox;
+    if !sandbox.is_enabled() {
+        return Ok(());
+    }
+
+    if let Some(user) = sandbox.user {
+        if let Some(profile_dir) = profile_dir {
+            chown_recursive(profile_dir, user).context("failed to change owner of profile")?;
+        }
+
+        switch_user(user)?;
+    }
+
+    if sandbox.restrict_filesystem {
+        restrict_filesystem(options)?;
+    }
+
+    if sandbox.block_network {
+        block_network()?;
+    }
+
+    info!(
+        user = ?sandbox.user,
+        restrict_filesystem = sandbox.restrict_filesystem,
+        block_network = sandbox.block_network,
+        "office sandbox enabled"
+    );
+
+    Ok(())
+}
+
+/// Sandboxing relies on Linux specific features (landlock and seccomp)
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn apply_sandbox(
+    options: &ConverterOptions,
+    _profile_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    if options.sandbox.is_enabled() {
+        anyhow::bail!("office sandboxing is only supported on Linux");
+    }
+
+    Ok(())
+}
+
+/// Changes the owner of the path and everything within it
+#[cfg(target_os = "linux")]
+fn chown_recursive(path: &Path, user: SandboxUser) -> std::io::Result<()> {
+    std::os::unix::fs::chown(path, Some(user.uid), Some(user.gid))?;
+
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            chown_recursive(&entry?.path(), user)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Changes the user and group of the current thread, the raw syscalls are used
+/// as the libc wrappers change the credentials of every thread in the process
+#[cfg(target_os = "linux")]
+fn switch_user(user: SandboxUser) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    fn check(result: libc::c_long) -> std::io::Result<()> {
+        if result != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    // Supplementary groups must be cleared before the group is changed and
+    // the group must be changed while the thread still has permission
+    check(unsafe { libc::syscall(libc::SYS_setgroups, 0, std::ptr::null::<libc::gid_t>()) })
+        .context("failed to clear supplementary groups")?;
+    check(unsafe { libc::syscall(libc::SYS_setresgid, user.gid, user.gid, user.gid) })
+        .context("failed to change group")?;
+    check(unsafe { libc::syscall(libc::SYS_setresuid, user.uid, user.uid, user.uid) })
+        .context("failed to change user")?;
+
+    Ok(())
+}
+
+/// Restricts the filesystem access of the current thread using landlock
+#[cfg(target_os = "linux")]
+fn restrict_filesystem(options: &ConverterOptions) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use landlock::{
+        path_beneath_rules, Access, AccessFs, Ruleset, RulesetAttr, RulesetCreatedAttr,
+        RulesetStatus, ABI,
+    };
+    use tracing::warn;
+
+    let abi = ABI::V3;
+
+    let read_paths = SYSTEM_READ_PATHS
+        .iter()
+        .chain(DEVICE_READ_PATHS)
+        .map(PathBuf::from)
+        .chain([options.office_path.clone()])
+        .chain(options.font_dirs.iter().cloned())
+        .chain(options.sandbox.read_paths.iter().cloned());
+
+    let write_paths = [options.temp_dir.clone(), std::env::temp_dir()]
+        .into_iter()
+        .chain(DEVICE_WRITE_PATHS.iter().map(PathBuf::from))
+        .chain(options.memory_dir.clone());
+
+    let status = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(read_paths, AccessFs::from_read(abi)))?
+        .add_rules(path_beneath_rules(write_paths, AccessFs::from_all(abi)))?
+        .restrict_self()
+        .context("failed to restrict filesystem access")?;
+
+    if status.ruleset == RulesetStatus::NotEnforced {
+        warn!("filesystem restrictions are not enforced, landlock is not supported by the kernel");
+    }
+
+    Ok(())
+}
+
+/// Blocks the current thread from creating sockets other than unix domain
+/// sockets using seccomp, socket creation fails with a permission error
+#[cfg(target_os = "linux")]
+fn block_network() -> anyhow::Result<()> {
+    use anyhow::Context;
+    use seccompiler::{
+        apply_filter, BpfProgram, SeccompAction, SeccompCmpArgLen, SeccompCmpOp, SeccompCondition,
+        SeccompFilter, SeccompRule,
+    };
+
+    let not_unix = SeccompCondition::new(
+        0,
+        SeccompCmpArgLen::Dword,
+        SeccompCmpOp::Ne,
+        libc::AF_UNIX as u64,
+    )?;
+
+    let filter = SeccompFilter::new(
+        [(libc::SYS_socket, vec![SeccompRule::new(vec![not_unix])?])]
+            .into_iter()
+            .collect(),
+        SeccompAction::Allow,
+        SeccompAction::Errno(libc::EACCES as u32),
+        std::env::consts::ARCH.try_into()?,
+    )?;
+
+    let program: BpfProgram = filter.try_into()?;
+    apply_filter(&program).context("failed to apply network filter")?;
+
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use crate::{Conversion, ConvertError};
+use anyhow::Context;
+use bytes::Bytes;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+
+/// Keys removed from every dictionary, these hold metadata (XMP metadata and
+/// application data), attached files and actions that run when the PDF is
+/// opened or interacted with
+const REMOVED_KEYS: &[&[u8]] = &[
+    b"Metadata",
+    b"PieceInfo",
+    b"AA",
+    b"AF",
+    b"EF",
+    b"EmbeddedFiles",
+    b"JavaScript",
+];
+
+/// Subtypes of annotations that are removed, these carry comments,
+/// attached files or media
+const REMOVED_ANNOTATIONS: &[&[u8]] = &[
+    b"Text",
+    b"Popup",
+    b"FreeText",
+    b"FileAttachment",
+    b"Sound",
+    b"Movie",
+    b"Screen",
+    b"RichMedia",
+];
+
+/// Types of actions that are removed, these run scripts or other programs
+const REMOVED_ACTIONS: &[&[u8]] = &[b"JavaScript", b"Launch", b"ImportData", b"Rendition"];
+
+/// Sanitizes the PDF of the conversion, see [sanitize_pdf]
+pub(crate) async fn sanitize_conversion(
+    conversion: Conversion,
+) -> Result<Conversion, ConvertError> {
+    // Parsing large documents is expensive so its moved off the async runtime
+    let pdf = tokio::task::spawn_blocking({
+        let pdf = conversion.pdf.clone();
+        move || sanitize_pdf(&pdf)
+    })
+    .await
+    .context("failed to sanitize pdf")?
+    .context("failed to sanitize pdf")?;
+
+    Ok(Conversion {
+        pdf: Bytes::from(pdf),
+        ..conversion
+    })
+}
+
+/// Removes the document information (author, title and dates), metadata,
+/// comments, attached files and scripts from the provided PDF. Page content,
+/// links and form fields are kept
+pub fn sanitize_pdf(bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut document = Document::load_mem(bytes).context("failed to load pdf")?;
+
+    // Document information is only referenced by the trailer and removed
+    // with the other unreferenced objects
+    document.trailer.remove(b"Info");
+
+    let removed: HashSet<ObjectId> = document
+        .objects
+        .iter()
+        .filter(|(_, object)| object.as_dict().is_ok_and(is_removed))
+        .map(|(id, _)| *id)
+        .collect();
+
+    for object in document.objects.values_mut() {
+        let dictionary = match object {
+            Object::Dictionary(value) => value,
+            Object::Stream(value) => &mut value.dict,
+            _ => continue,
+        };
+
+        sanitize_dictionary(dictionary, &removed);
+    }
+
+    document.prune_objects();
+
+    let mut output = Vec::new();
+    document
+        .save_to(&mut output)
+        .context("failed to write pdf")?;
+
+    Ok(output)
+}
+
+/// Removes the metadata keys, removed annotations and actions referenced by
+/// the dictionary, `removed` contains the IDs of the annotations and actions
+/// stored as their own objects
+fn sanitize_dictionary(dictionary: &mut Dictionary, removed: &HashSet<ObjectId>) {
+    for key in REMOVED_KEYS {
+        dictionary.remove(key);
+    }
+
+    let is_removed_object = |object: &Object| match object {
+        Object::Reference(id) => removed.contains(id),
+        Object::Dictionary(value) => is_removed(value),
+        _ => false,
+    };
+
+    for key in [b"A".as_slice(), b"OpenAction"] {
+        if dictionary.get(key).is_ok_and(is_removed_object) {
+            dictionary.remove(key);
+        }
+    }
+
+    if let Ok(Object::Array(annotations)) = dictionary.get_mut(b"Annots") {
+        annotations.retain(|annotation| !is_removed_object(annotation));
+    }
+}
+
+/// Whether the dictionary is an annotation or action that is removed
+fn is_removed(dictionary: &Dictionary) -> bool {
+    let name = |key: &[u8]| dictionary.get(key).and_then(Object::as_name).ok();
+
+    if let Some(subtype) = name(b"Subtype") {
+        if REMOVED_ANNOTATIONS.contains(&subtype) {
+            return true;
+        }
+    }
+
+    // Actions are identified by their action type
+    name(b"S").is_some_and(|action| REMOVED_ACTIONS.contains(&action))
+}
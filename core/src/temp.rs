@@ -0,0 +1,216 @@
+use anyhow::Context;
+use libreofficekit::DocUrl;
+use rand::{distributions::Alphanumeric, Rng};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tracing::{debug, warn};
+
+/// Prefix used by all temporary files created by the server
+const TEMP_FILE_PREFIX: &str = "lo_native_";
+
+/// Time between runs of the temp file reaper
+const REAP_INTERVAL: Duration = Duration::from_secs(60 * 5);
+
+/// Temporary input and output files used for a single conversion
+pub struct JobTempFiles {
+    /// Path to the input file
+    pub input_path: PathBuf,
+    /// Path to the output file
+    pub output_path: PathBuf,
+    /// Office URL for the input file
+    pub input_url: DocUrl,
+    /// Office URL for the output file
+    pub output_url: DocUrl,
+}
+
+/// Generates a random ID for naming temporary files
+fn random_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(|value| value as char)
+        .collect::<String>()
+}
+
+impl JobTempFiles {
+    /// Creates a new set of uniquely named temporary file paths
+    /// within the provided directory
+    pub fn new(temp_dir: &Path) -> anyhow::Result<Self> {
+        // Generate random ID for the path name
+        let random_id = random_id();
+
+        // Create input and output paths
+        let input_path = temp_dir.join(format!("{TEMP_FILE_PREFIX}input_{random_id}"));
+        let output_path = temp_dir.join(format!("{TEMP_FILE_PREFIX}output_{random_id}.pdf"));
+
+        // Create office type safe paths
+        let input_url = DocUrl::from_absolute_path(
+            input_path
+                .to_str()
+                .context("failed to create temp in path")?,
+        )
+        .context("failed to create input url")?;
+        let output_url = DocUrl::from_absolute_path(
+            output_path
+                .to_str()
+                .context("failed to create temp out path")?,
+        )
+        .context("failed to create output url")?;
+
+        Ok(Self {
+            input_path,
+            output_path,
+            input_url,
+            output_url,
+        })
+    }
+
+    /// Total size in bytes of the input and output files that exist
+    pub fn disk_usage(&self) -> u64 {
+        [&self.input_path, &self.output_path]
+            .into_iter()
+            .filter_map(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+
+    /// Removes the input and output files (if they exist)
+    pub fn cleanup(&self, secure: bool) {
+        for path in [&self.input_path, &self.output_path] {
+            if let Err(cause) = remove_file(path, secure) {
+                warn!(%cause, path = %path.display(), "failed to remove temp file");
+            }
+        }
+    }
+}
+
+/// Uniquely named temporary file for processing outside of office, uses
+/// the same prefix as the conversion files so that files left behind by
+/// a crash are removed by the reaper
+pub struct TempFile {
+    /// Path to the file
+    pub path: PathBuf,
+}
+
+impl TempFile {
+    /// Creates a new uniquely named temporary file path within the
+    /// provided directory, the name is used to identify the file
+    pub fn new(temp_dir: &Path, name: &str) -> Self {
+        let random_id = random_id();
+        let path = temp_dir.join(format!("{TEMP_FILE_PREFIX}{name}_{random_id}"));
+        Self { path }
+    }
+
+    /// Removes the file (if it exists)
+    pub fn remove(&self, secure: bool) {
+        if let Err(cause) = remove_file(&self.path, secure) {
+            warn!(%cause, path = %self.path.display(), "failed to remove temp file");
+        }
+    }
+}
+
+/// Removes the file at the provided path, when `secure` is enabled the file
+/// contents are overwritten with zeros before the file is removed
+fn remove_file(path: &Path, secure: bool) -> std::io::Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+
+    if secure {
+        overwrite_file(path)?;
+    }
+
+    std::fs::remove_file(path)
+}
+
+/// Overwrites the entire contents of the file with zeros
+fn overwrite_file(path: &Path) -> std::io::Result<()> {
+    const CHUNK_SIZE: u64 = 64 * 1024;
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    let mut remaining = file.metadata()?.len();
+    let zeros = [0u8; CHUNK_SIZE as usize];
+
+    while remaining > 0 {
+        let length = remaining.min(CHUNK_SIZE);
+        file.write_all(&zeros[..length as usize])?;
+        remaining -= length;
+    }
+
+    file.sync_all()
+}
+
+/// Removes temporary files within the temp directory that are older than
+/// `max_age`, these are files left behind by a crash during a conversion
+pub fn reap_temp_files(temp_dir: &Path, max_age: Duration, secure: bool) {
+    let entries = match std::fs::read_dir(temp_dir) {
+        Ok(value) => value,
+        Err(cause) => {
+            warn!(%cause, "failed to read temp directory");
+            return;
+        }
+    };
+
+    let now = SystemTime::now();
+
+    for entry in entries.flatten() {
+        if !entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(TEMP_FILE_PREFIX)
+        {
+            continue;
+        }
+
+        let modified = match entry.metadata().and_then(|metadata| metadata.modified()) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        let age = now.duration_since(modified).unwrap_or_default();
+        if age < max_age {
+            continue;
+        }
+
+        let path = entry.path();
+        debug!(path = %path.display(), "removing orphaned temp file");
+
+        if let Err(cause) = remove_file(&path, secure) {
+            warn!(%cause, path = %path.display(), "failed to remove orphaned temp file");
+        }
+    }
+}
+
+/// Finds a memory backed directory that conversion files can be written to,
+/// provides [None] when the system doesn't have one
+#[cfg(target_os = "linux")]
+pub fn memory_temp_dir() -> Option<PathBuf> {
+    let path = PathBuf::from("/dev/shm");
+    let metadata = std::fs::metadata(&path).ok()?;
+
+    (metadata.is_dir() && !metadata.permissions().readonly()).then_some(path)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn memory_temp_dir() -> Option<PathBuf> {
+    None
+}
+
+/// Spawns a background task that periodically removes orphaned temp files
+pub fn spawn_temp_reaper(temp_dir: PathBuf, max_age: Duration, secure: bool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(REAP_INTERVAL);
+
+        loop {
+            interval.tick().await;
+
+            let temp_dir = temp_dir.clone();
+            _ = tokio::task::spawn_blocking(move || reap_temp_files(&temp_dir, max_age, secure))
+                .await;
+        }
+    });
+}
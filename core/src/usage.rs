@@ -0,0 +1,233 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Upper bounds of the CPU time histogram buckets in seconds
+const CPU_SECONDS_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// Upper bounds of the peak memory histogram buckets in bytes (8MiB to 4GiB)
+const MEMORY_BYTES_BUCKETS: &[f64] = &[
+    8_388_608.0,
+    16_777_216.0,
+    33_554_432.0,
+    67_108_864.0,
+    134_217_728.0,
+    268_435_456.0,
+    536_870_912.0,
+    1_073_741_824.0,
+    2_147_483_648.0,
+    4_294_967_296.0,
+];
+
+/// Upper bounds of the temp disk histogram buckets in bytes (64KiB to 1GiB)
+const DISK_BYTES_BUCKETS: &[f64] = &[
+    65_536.0,
+    262_144.0,
+    1_048_576.0,
+    4_194_304.0,
+    16_777_216.0,
+    67_108_864.0,
+    268_435_456.0,
+    1_073_741_824.0,
+];
+
+/// Resources used by office converting a single document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    /// CPU time in milliseconds (user and system) used by the runner thread
+    /// while converting, [None] when not supported by the platform
+    pub cpu_ms: Option<u64>,
+    /// Increase in bytes of the peak resident memory of office while
+    /// converting over the memory used before the conversion, [None] when
+    /// not supported by the platform or the peak can't be reset (i.e when
+    /// office is sandboxed)
+    pub peak_rss_delta: Option<u64>,
+    /// Size in bytes of the temp files written for the conversion (the
+    /// input file and the converted PDF)
+    pub temp_disk_bytes: u64,
+}
+
+/// Measures the resources used by the runner from when the meter was
+/// started, must be used on the runner thread
+pub(crate) struct UsageMeter {
+    /// CPU time used by the thread when the meter was started
+    cpu: Option<Duration>,
+    /// Resident memory of the process when the meter was started
+    rss: Option<u64>,
+}
+
+impl UsageMeter {
+    /// Starts measuring, the peak resident memory of the process is
+    /// reset so the peak of the conversion can be measured
+    pub(crate) fn start() -> Self {
+        Self {
+            cpu: thread_cpu_time(),
+            rss: reset_peak_rss(),
+        }
+    }
+
+    /// Provides the resources used since the meter was started along with
+    /// the provided size of the temp files
+    pub(crate) fn finish(&self, temp_disk_bytes: u64) -> ResourceUsage {
+        let cpu_ms = self
+            .cpu
+            .zip(thread_cpu_time())
+            .map(|(start, end)| end.saturating_sub(start).as_millis() as u64);
+
+        let peak_rss_delta = self
+            .rss
+            .zip(peak_rss())
+            .map(|(start, peak)| peak.saturating_sub(start));
+
+        ResourceUsage {
+            cpu_ms,
+            peak_rss_delta,
+            temp_disk_bytes,
+        }
+    }
+}
+
+/// CPU time (user and system) used by the current thread
+#[cfg(target_os = "linux")]
+fn thread_cpu_time() -> Option<Duration> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+
+    // SAFETY: getrusage only writes to the provided rusage
+    if unsafe { libc::getrusage(libc::RUSAGE_THREAD, usage.as_mut_ptr()) } != 0 {
+        return None;
+    }
+
+    // SAFETY: getrusage initialized the rusage when it succeeded
+    let usage = unsafe { usage.assume_init() };
+    let time = |value: libc::timeval| {
+        Duration::from_secs(value.tv_sec as u64) + Duration::from_micros(value.tv_usec as u64)
+    };
+
+    Some(time(usage.ru_utime) + time(usage.ru_stime))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_cpu_time() -> Option<Duration> {
+    None
+}
+
+/// Resets the peak resident memory of the process to its current resident
+/// memory, provides the current resident memory in bytes
+#[cfg(target_os = "linux")]
+fn reset_peak_rss() -> Option<u64> {
+    // Writing "5" resets the peak resident memory (VmHWM)
+    std::fs::write("/proc/self/clear_refs", "5").ok()?;
+    process_status_bytes("VmRSS")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reset_peak_rss() -> Option<u64> {
+    None
+}
+
+/// Peak resident memory of the process in bytes since it was last reset
+#[cfg(target_os = "linux")]
+fn peak_rss() -> Option<u64> {
+    process_status_bytes("VmHWM")
+}
+
+#[cfg(not(target_os = "linux"))]
+fn peak_rss() -> Option<u64> {
+    None
+}
+
+/// Reads a memory field (i.e "VmRSS:    1024 kB") from the status of
+/// the process in bytes
+#[cfg(target_os = "linux")]
+fn process_status_bytes(field: &str) -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let value = status.lines().find_map(|line| {
+        line.strip_prefix(field)?
+            .strip_prefix(':')?
+            .trim()
+            .strip_suffix("kB")
+    })?;
+
+    value.trim().parse::<u64>().ok().map(|value| value * 1024)
+}
+
+/// Histogram of the values observed for a resource
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageHistogram {
+    /// Upper bound of each bucket along with the number of observed values
+    /// less than or equal to the bound
+    pub buckets: Vec<(f64, u64)>,
+    /// Sum of the observed values
+    pub sum: f64,
+    /// Number of observed values
+    pub count: u64,
+}
+
+impl UsageHistogram {
+    fn new(bounds: &[f64]) -> Self {
+        Self {
+            buckets: bounds.iter().map(|bound| (*bound, 0)).collect(),
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        for (bound, count) in &mut self.buckets {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+/// Histograms of the resources used by the conversions made by a converter
+#[derive(Debug, Clone, Serialize)]
+pub struct ResourceUsageStats {
+    /// CPU time in seconds used by each conversion
+    pub cpu_seconds: UsageHistogram,
+    /// Increase in peak resident memory in bytes of each conversion
+    pub peak_rss_bytes: UsageHistogram,
+    /// Temp disk used in bytes by each conversion
+    pub temp_disk_bytes: UsageHistogram,
+}
+
+impl Default for ResourceUsageStats {
+    fn default() -> Self {
+        Self {
+            cpu_seconds: UsageHistogram::new(CPU_SECONDS_BUCKETS),
+            peak_rss_bytes: UsageHistogram::new(MEMORY_BYTES_BUCKETS),
+            temp_disk_bytes: UsageHistogram::new(DISK_BYTES_BUCKETS),
+        }
+    }
+}
+
+/// Records the resources used by successful conversions
+#[derive(Default)]
+pub(crate) struct UsageRecorder(Mutex<ResourceUsageStats>);
+
+impl UsageRecorder {
+    /// Adds the resources used by a conversion to the histograms, resources
+    /// that weren't measured are skipped
+    pub(crate) fn record(&self, usage: &ResourceUsage) {
+        let stats = &mut *self.0.lock();
+
+        if let Some(cpu_ms) = usage.cpu_ms {
+            stats.cpu_seconds.observe(cpu_ms as f64 / 1000.0);
+        }
+
+        if let Some(peak_rss_delta) = usage.peak_rss_delta {
+            stats.peak_rss_bytes.observe(peak_rss_delta as f64);
+        }
+
+        stats.temp_disk_bytes.observe(usage.temp_disk_bytes as f64);
+    }
+
+    /// Provides a snapshot of the histograms
+    pub(crate) fn snapshot(&self) -> ResourceUsageStats {
+        self.0.lock().clone()
+    }
+}
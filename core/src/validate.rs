@@ -0,0 +1,24 @@
+use crate::{ConversionWarning, DocumentKind};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of loading a document without exporting it
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Validation {
+    /// Kind of document office loaded the file as, [None] when office
+    /// could not load the file
+    pub document_type: Option<DocumentKind>,
+    /// Reason office could not load the file (i.e "file is encrypted")
+    pub error: Option<String>,
+    /// Warnings reported by office while loading the document
+    pub warnings: Vec<ConversionWarning>,
+    /// Whether the document was loaded read-only as it is protected
+    /// with a password to modify
+    pub read_only: bool,
+}
+
+impl Validation {
+    /// Whether office was able to load the file
+    pub fn is_loadable(&self) -> bool {
+        self.document_type.is_some()
+    }
+}
@@ -0,0 +1,78 @@
+use crate::ResourceUsage;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Problem reported by office while loading or exporting a document that
+/// didn't stop the conversion, the converted PDF may be missing content
+/// (i.e unsupported features or content that was truncated)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConversionWarning {
+    /// Operation office was performing (i.e "load" or "save")
+    pub operation: Option<String>,
+    /// Kind of problem reported by office (i.e "io" or "filter")
+    pub kind: Option<String>,
+    /// Office error code for the problem
+    pub code: Option<String>,
+    /// Message from office describing the problem
+    pub message: String,
+}
+
+impl Display for ConversionWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Converted PDF along with the warnings reported while converting
+#[derive(Debug, Clone)]
+pub struct Conversion {
+    /// The converted PDF bytes
+    pub pdf: Bytes,
+    /// Warnings reported by office while converting
+    pub warnings: Vec<ConversionWarning>,
+    /// Number of attempts made to convert the document, more than one when
+    /// failed attempts were retried
+    pub attempts: u32,
+    /// Whether the document was protected with a password to modify and was
+    /// loaded read-only as the password was not provided or incorrect
+    pub read_only: bool,
+    /// Resources used by office converting the document, measured for the
+    /// last attempt
+    pub usage: ResourceUsage,
+    /// Time in milliseconds the conversion spent waiting for the runner
+    pub queue_wait_ms: u64,
+}
+
+/// Payload of the office error callback
+#[derive(Deserialize)]
+struct ErrorPayload {
+    classification: Option<String>,
+    cmd: Option<String>,
+    kind: Option<String>,
+    code: Option<String>,
+    message: Option<String>,
+}
+
+/// Parses the payload of an office error callback, provides [None] when
+/// the payload is malformed or doesn't have a message
+pub(crate) fn parse_error_callback(payload: &[u8]) -> Option<ConversionWarning> {
+    let payload: ErrorPayload = serde_json::from_slice(payload).ok()?;
+    let message = payload.message.filter(|value| !value.trim().is_empty())?;
+
+    // Errors that fail the conversion are reported by the conversion, the
+    // remaining errors are problems office recovered from
+    if !matches!(
+        payload.classification.as_deref(),
+        None | Some("warning" | "error")
+    ) {
+        return None;
+    }
+
+    Some(ConversionWarning {
+        operation: payload.cmd,
+        kind: payload.kind,
+        code: payload.code,
+        message: message.trim().to_string(),
+    })
+}
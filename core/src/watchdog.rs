@@ -0,0 +1,210 @@
+use crate::{Conversion, ConvertError, DocumentKind, Validation};
+use anyhow::anyhow;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::oneshot;
+use tracing::{debug, error};
+
+/// Longest time between checks of the active conversion
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Output of a job processed by the runner
+#[derive(Debug)]
+pub(crate) enum JobOutput {
+    /// Converted PDF from a conversion along with the warnings reported by office
+    Pdf(Conversion),
+    /// Kind of document office loaded the file as, [None] when office
+    /// could not load the file
+    DocumentKind(Option<DocumentKind>),
+    /// Image of each page of a document
+    Pages(Vec<Bytes>),
+    /// Outcome of loading a document without exporting it
+    Validation(Validation),
+}
+
+/// Channel for sending back the result of a job
+pub(crate) type JobResponseTx = oneshot::Sender<Result<JobOutput, ConvertError>>;
+
+/// Stage of a conversion, each stage is a single call into office
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConvertStage {
+    /// Writing the input file before loading
+    Prepare,
+    /// Loading the document (document_load)
+    Load,
+    /// Exporting the document as a PDF (save_as)
+    Export,
+    /// Signing the exported PDF (sign_document)
+    Sign,
+    /// Reading the exported PDF
+    Read,
+}
+
+impl Display for ConvertStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ConvertStage::Prepare => "prepare",
+            ConvertStage::Load => "document_load",
+            ConvertStage::Export => "save_as",
+            ConvertStage::Sign => "sign_document",
+            ConvertStage::Read => "read_output",
+        })
+    }
+}
+
+/// Conversion currently being processed by the runner
+struct ActiveConversion {
+    /// ID of the job
+    job_id: u64,
+    /// Size of the input file in bytes
+    input_size: usize,
+    /// Path to the temporary input file
+    input_path: Option<PathBuf>,
+    /// Current stage of the conversion
+    stage: ConvertStage,
+    /// When the current stage started
+    stage_started_at: Instant,
+    /// Channel for the response, taken by the watchdog when the
+    /// conversion times out
+    tx: Option<JobResponseTx>,
+}
+
+/// Watches how long the runner has been inside a single office call, office
+/// calls can't be interrupted so when a call exceeds the maximum duration the
+/// waiting request is answered with a timeout error and the office instance
+/// is restarted once the call returns
+pub(crate) struct Watchdog {
+    /// Conversion currently being processed
+    active: Mutex<Option<ActiveConversion>>,
+    /// Maximum time a single office call can take
+    max_duration: Option<Duration>,
+}
+
+impl Watchdog {
+    pub(crate) fn new(max_duration: Option<Duration>) -> Self {
+        Self {
+            active: Mutex::new(None),
+            max_duration,
+        }
+    }
+
+    /// Starts watching a new conversion, takes ownership of the response
+    /// channel so the watchdog can respond if the conversion times out
+    pub(crate) fn start(&self, job_id: u64, input_size: usize, tx: JobResponseTx) {
+        *self.active.lock() = Some(ActiveConversion {
+            job_id,
+            input_size,
+            input_path: None,
+            stage: ConvertStage::Prepare,
+            stage_started_at: Instant::now(),
+            tx: Some(tx),
+        });
+    }
+
+    /// Sets the path of the temporary input file, included in diagnostics
+    pub(crate) fn set_input_path(&self, input_path: PathBuf) {
+        if let Some(active) = &mut *self.active.lock() {
+            active.input_path = Some(input_path);
+        }
+    }
+
+    /// Moves the active conversion into a new stage, restarting the timer.
+    /// Fails when the conversion already timed out or the request was
+    /// cancelled (nothing is waiting for the response) so that the remaining
+    /// stages are skipped
+    pub(crate) fn stage(&self, stage: ConvertStage) -> anyhow::Result<()> {
+        if let Some(active) = &mut *self.active.lock() {
+            match &active.tx {
+                None => return Err(anyhow!("conversion timed out")),
+                Some(tx) if tx.is_closed() => {
+                    debug!(job_id = active.job_id, %stage, "request was cancelled, skipping remaining stages");
+                    return Err(anyhow!("conversion cancelled"));
+                }
+                Some(_) => {}
+            }
+
+            active.stage = stage;
+            active.stage_started_at = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Stops watching the active conversion, provides the response channel
+    /// or [None] when the conversion already timed out
+    pub(crate) fn finish(&self) -> Option<JobResponseTx> {
+        self.active.lock().take().and_then(|active| active.tx)
+    }
+
+    /// Checks the active conversion, responding with a timeout error when
+    /// the current stage has exceeded the maximum duration. Office is stuck
+    /// when the call still hasn't returned after twice the maximum duration,
+    /// the instance can't be restarted while the call is running so the
+    /// process exits for its supervisor to restart it
+    fn check(&self, max_duration: Duration) {
+        let active = &mut *self.active.lock();
+        let active = match active {
+            Some(value) => value,
+            None => return,
+        };
+
+        let elapsed = active.stage_started_at.elapsed();
+        if elapsed < max_duration {
+            return;
+        }
+
+        if let Some(tx) = active.tx.take() {
+            error!(
+                job_id = active.job_id,
+                stage = %active.stage,
+                elapsed_ms = elapsed.as_millis() as u64,
+                input_size = active.input_size,
+                input_path = ?active.input_path,
+                "conversion exceeded the maximum duration, office will be restarted once the call returns"
+            );
+
+            _ = tx.send(Err(ConvertError::Timeout {
+                seconds: max_duration.as_secs(),
+            }));
+            return;
+        }
+
+        if elapsed >= max_duration.saturating_mul(2) {
+            error!(
+                job_id = active.job_id,
+                stage = %active.stage,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "office is not responding, exiting so the server can be restarted"
+            );
+
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Spawns the thread checking the active conversion, stops once the
+/// runner has stopped
+pub(crate) fn spawn_watchdog(watchdog: Arc<Watchdog>) {
+    let max_duration = match watchdog.max_duration {
+        Some(value) => value,
+        None => return,
+    };
+
+    let interval = (max_duration / 4).clamp(Duration::from_millis(10), MAX_CHECK_INTERVAL);
+
+    std::thread::spawn(move || {
+        // Runner holds the only other reference
+        while Arc::strong_count(&watchdog) > 1 {
+            std::thread::sleep(interval);
+            watchdog.check(max_duration);
+        }
+
+        debug!("office runner stopped, stopping watchdog");
+    });
+}
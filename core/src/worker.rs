@@ -0,0 +1,52 @@
+use parking_lot::Mutex;
+use std::time::Instant;
+
+/// State of an office runner
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkerState {
+    /// Waiting for a conversion
+    Idle,
+    /// Converting a document
+    Converting,
+    /// Starting or restarting its office instance
+    Restarting,
+}
+
+/// Snapshot of the state of an office runner
+#[derive(Debug, Clone)]
+pub struct WorkerStatus {
+    /// Current state of the runner
+    pub state: WorkerState,
+    /// ID of the job being converted when converting
+    pub job_id: Option<u64>,
+    /// When the runner entered its current state
+    pub since: Instant,
+}
+
+/// Shared state of an office runner, updated by the runner thread and
+/// readable without waiting on the runner
+pub(crate) struct WorkerTracker(Mutex<WorkerStatus>);
+
+impl WorkerTracker {
+    pub(crate) fn new() -> Self {
+        Self(Mutex::new(WorkerStatus {
+            state: WorkerState::Restarting,
+            job_id: None,
+            since: Instant::now(),
+        }))
+    }
+
+    /// Moves the runner into a new state
+    pub(crate) fn set(&self, state: WorkerState, job_id: Option<u64>) {
+        *self.0.lock() = WorkerStatus {
+            state,
+            job_id,
+            since: Instant::now(),
+        };
+    }
+
+    /// Gets the current state of the runner
+    pub(crate) fn status(&self) -> WorkerStatus {
+        self.0.lock().clone()
+    }
+}
@@ -0,0 +1,301 @@
+use crate::{
+    base_path::BasePath,
+    error::{DynHttpError, HttpError},
+    queue::QueueLimit,
+    results::{ResultStats, ResultStore},
+};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{Html, Response},
+    Extension, Json,
+};
+use office_convert_core::{ConversionRecord, Converter};
+use serde::Serialize;
+use std::{
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// Maximum time to wait for the office runner to restart, the runner
+/// must finish any in progress conversion before it can restart
+const RESTART_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Bearer token required to access the admin endpoints
+#[derive(Clone)]
+pub struct AdminToken(Arc<str>);
+
+impl AdminToken {
+    pub fn new(token: String) -> Self {
+        Self(Arc::from(token))
+    }
+
+    /// Checks if the provided token matches in constant time (For the length
+    /// of the provided token) to prevent timing attacks
+    fn matches(&self, token: &str) -> bool {
+        let expected = self.0.as_bytes();
+        let token = token.as_bytes();
+
+        if expected.len() != token.len() {
+            return false;
+        }
+
+        expected
+            .iter()
+            .zip(token)
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+    }
+}
+
+/// Range of IP addresses in CIDR notation (i.e "10.0.0.0/8" or "fd00::/8"),
+/// a single address is a range containing only that address
+#[derive(Debug, Clone, Copy)]
+pub struct IpRange {
+    /// First address of the range
+    address: IpAddr,
+    /// Number of leading bits addresses in the range share
+    prefix: u32,
+}
+
+impl IpRange {
+    /// Checks if the address is within the range, IPv4 addresses mapped
+    /// to IPv6 addresses are compared as IPv4 addresses
+    fn contains(&self, address: IpAddr) -> bool {
+        match (self.address, address.to_canonical()) {
+            (IpAddr::V4(range), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix).unwrap_or(0);
+                u32::from(range) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(range), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix).unwrap_or(0);
+                u128::from(range) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for IpRange {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+
+        let address: IpAddr = address
+            .parse()
+            .map_err(|_| format!("\"{address}\" is not an IP address"))?;
+        let max_prefix = if address.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse()
+                .ok()
+                .filter(|prefix| *prefix <= max_prefix)
+                .ok_or_else(|| format!("prefix length must be between 0 and {max_prefix}"))?,
+            None => max_prefix,
+        };
+
+        Ok(Self {
+            address: address.to_canonical(),
+            prefix,
+        })
+    }
+}
+
+/// IP addresses allowed to access the admin endpoints
+#[derive(Clone)]
+pub struct AdminAllowlist(Arc<[IpRange]>);
+
+impl AdminAllowlist {
+    pub fn new(ranges: Vec<IpRange>) -> Self {
+        Self(Arc::from(ranges))
+    }
+}
+
+/// Errors that can occur when using the admin endpoints
+#[derive(Debug, Error)]
+pub enum AdminError {
+    /// Request did not include a bearer token
+    #[error("missing admin token")]
+    MissingToken,
+
+    /// Request included the wrong bearer token
+    #[error("invalid admin token")]
+    InvalidToken,
+
+    /// Request came from an address that isn't in the allowlist
+    #[error("address is not allowed to access this endpoint")]
+    AddressNotAllowed,
+
+    /// Office runner didn't restart within the timeout
+    #[error("timed out waiting for office to restart, the runner may be stuck on a conversion")]
+    RestartTimeout,
+
+    /// Config couldn't be reloaded, the previous config remains in use
+    #[error("failed to reload config: {0}")]
+    ReloadFailed(String),
+}
+
+impl HttpError for AdminError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AdminError::MissingToken | AdminError::InvalidToken => StatusCode::UNAUTHORIZED,
+            AdminError::AddressNotAllowed => StatusCode::FORBIDDEN,
+            AdminError::RestartTimeout => StatusCode::SERVICE_UNAVAILABLE,
+            AdminError::ReloadFailed(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            AdminError::AddressNotAllowed => Some("ADDRESS_NOT_ALLOWED"),
+            AdminError::ReloadFailed(_) => Some("RELOAD_FAILED"),
+            _ => None,
+        }
+    }
+}
+
+/// Middleware only allowing requests from addresses in the allowlist, requests
+/// over a Unix domain socket don't have an address and are allowed as access to
+/// the socket is controlled by its file permissions
+pub async fn restrict_to_allowlist(
+    State(allowlist): State<AdminAllowlist>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    if let Some(ConnectInfo(address)) = request.extensions().get::<ConnectInfo<SocketAddr>>() {
+        let address = address.ip();
+
+        if !allowlist.0.iter().any(|range| range.contains(address)) {
+            warn!(%address, "blocked admin request from address outside the allowlist");
+            return Err(AdminError::AddressNotAllowed.into());
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Middleware requiring requests provide the admin token as a bearer token
+/// in the authorization header
+pub async fn require_admin(
+    State(admin_token): State<AdminToken>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or(AdminError::MissingToken)?;
+
+    if !admin_token.matches(token) {
+        return Err(AdminError::InvalidToken.into());
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// POST /admin/restart-office
+///
+/// Tears down and recreates the office instance
+pub async fn restart_office(
+    Extension(office): Extension<Converter>,
+) -> Result<StatusCode, DynHttpError> {
+    info!("office restart requested");
+
+    tokio::time::timeout(RESTART_TIMEOUT, office.restart())
+        .await
+        .map_err(|_| AdminError::RestartTimeout)??;
+
+    Ok(StatusCode::OK)
+}
+
+/// Operator dashboard page, the page requests the admin token and uses
+/// it to load the stats from the admin endpoints
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
+/// Placeholder in the dashboard replaced with the base path the routes are served under
+const DASHBOARD_BASE_PATH: &str = "__BASE_PATH__";
+
+/// GET /admin
+///
+/// Serves the operator dashboard, the page doesn't contain any data so
+/// doesn't require the admin token
+pub async fn dashboard(Extension(base_path): Extension<BasePath>) -> Html<String> {
+    Html(DASHBOARD_HTML.replace(DASHBOARD_BASE_PATH, base_path.as_str()))
+}
+
+#[derive(Serialize)]
+pub struct StatsResponse {
+    /// Conversion queue depth
+    queue: QueueStats,
+    /// Resident memory used by the server process (including LibreOffice) in
+    /// bytes, [None] when it could not be determined
+    memory_bytes: Option<u64>,
+    /// Most recent conversions, most recent first
+    recent_conversions: Vec<ConversionRecord>,
+    /// Retained conversion results and how often they were downloaded again
+    results: ResultStats,
+}
+
+#[derive(Serialize)]
+struct QueueStats {
+    /// Number of conversion requests running or waiting
+    in_flight: usize,
+    /// Number of conversions waiting for LibreOffice
+    waiting: usize,
+    /// Maximum number of conversions that can be waiting, [None] when unlimited
+    max_queued: Option<usize>,
+}
+
+/// GET /admin/stats
+///
+/// Reports the queue depth, memory usage, recent conversions and retained results
+pub async fn stats(
+    Extension(office): Extension<Converter>,
+    Extension(queue_limit): Extension<Arc<QueueLimit>>,
+    Extension(results): Extension<Arc<ResultStore>>,
+) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        queue: QueueStats {
+            in_flight: queue_limit.in_flight(),
+            waiting: office.waiting(),
+            max_queued: queue_limit.max_queued(),
+        },
+        memory_bytes: resident_memory(),
+        recent_conversions: office.recent_conversions(),
+        results: results.stats(),
+    })
+}
+
+/// Reads the resident memory of the process in bytes
+#[cfg(target_os = "linux")]
+fn resident_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+
+    let kilobytes: u64 = line
+        .trim_start_matches("VmRSS:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn resident_memory() -> Option<u64> {
+    None
+}
@@ -0,0 +1,40 @@
+//! Global allocator selection, the system allocator is used unless the
+//! `jemalloc` or `mimalloc` feature is enabled
+
+#[cfg(all(feature = "jemalloc", feature = "mimalloc"))]
+compile_error!("the \"jemalloc\" and \"mimalloc\" features cannot be enabled together");
+
+#[cfg(feature = "jemalloc")]
+#[global_allocator]
+static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
+
+/// Pointer to the jemalloc options string
+#[cfg(feature = "jemalloc")]
+#[repr(transparent)]
+pub struct MallocConf(*const std::ffi::c_char);
+
+// SAFETY: Points to a static string that is never modified
+#[cfg(feature = "jemalloc")]
+unsafe impl Sync for MallocConf {}
+
+/// Options jemalloc reads when it starts. Freed memory is returned to the
+/// system after a second by a background thread rather than being kept for
+/// reuse, uploads and converted files are large short lived buffers that
+/// would otherwise keep the memory of the process high between conversions
+#[cfg(feature = "jemalloc")]
+#[export_name = "_rjem_malloc_conf"]
+pub static MALLOC_CONF: MallocConf =
+    MallocConf(c"background_thread:true,dirty_decay_ms:1000,muzzy_decay_ms:0".as_ptr());
+
+#[cfg(feature = "mimalloc")]
+#[global_allocator]
+static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
+
+/// Name of the global allocator
+pub const ALLOCATOR: &str = if cfg!(feature = "jemalloc") {
+    "jemalloc"
+} else if cfg!(feature = "mimalloc") {
+    "mimalloc"
+} else {
+    "system"
+};
@@ -0,0 +1,157 @@
+//! Helpers for reading and rewriting the XML parts of ZIP based documents
+//! (Office Open XML and OpenDocument), parts are read with a size limit so
+//! a small compressed document can't expand into an unbounded amount of memory
+
+use crate::error::HttpError;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+use thiserror::Error;
+use zip::{
+    read::ZipFile, result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive,
+    ZipWriter,
+};
+
+/// Signature at the start of a ZIP archive (Local file header)
+pub const ZIP_SIGNATURE: &[u8] = b"PK\x03\x04";
+
+/// Maximum decompressed size of a single document part that will be read
+pub const MAX_PART_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Errors that can occur while reading or rewriting the parts of a document
+#[derive(Debug, Error)]
+pub enum ArchiveError {
+    /// Part decompresses to more than [MAX_PART_SIZE]
+    #[error(
+        "document part \"{0}\" is larger than the {} MiB limit",
+        MAX_PART_SIZE / 1024 / 1024
+    )]
+    PartTooLarge(String),
+
+    /// Failed to read or rewrite the document archive
+    #[error(transparent)]
+    Zip(#[from] ZipError),
+
+    /// Failed to read or write the document archive
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl HttpError for ArchiveError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ArchiveError::PartTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ArchiveError::Zip(_) | ArchiveError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ArchiveError::PartTooLarge(_) => Some("DOCUMENT_PART_TOO_LARGE"),
+            _ => None,
+        }
+    }
+}
+
+/// Opens the archive of a ZIP based document, provides [None] when the input
+/// isn't a ZIP archive or the archive can't be read
+pub fn open_archive(input: &[u8]) -> Option<ZipArchive<Cursor<&[u8]>>> {
+    if !input.starts_with(ZIP_SIGNATURE) {
+        return None;
+    }
+
+    ZipArchive::new(Cursor::new(input)).ok()
+}
+
+/// Reads a part of the archive as a string
+pub fn read_part<R: Read + std::io::Seek>(
+    archive: &mut ZipArchive<R>,
+    name: &str,
+) -> Result<String, ArchiveError> {
+    read_entry(archive.by_name(name)?)
+}
+
+/// Reads the contents of an archive entry as a string, the declared size is
+/// checked before reading and the read is limited as the declared size can't
+/// be trusted
+fn read_entry(entry: ZipFile<'_>) -> Result<String, ArchiveError> {
+    let name = entry.name().to_string();
+    if entry.size() > MAX_PART_SIZE {
+        return Err(ArchiveError::PartTooLarge(name));
+    }
+
+    let mut value = String::new();
+    entry.take(MAX_PART_SIZE + 1).read_to_string(&mut value)?;
+
+    if value.len() as u64 > MAX_PART_SIZE {
+        return Err(ArchiveError::PartTooLarge(name));
+    }
+
+    Ok(value)
+}
+
+/// Rewrites the archive passing the XML of each part matching the provided
+/// predicate through the rewrite function, other parts are copied as-is
+/// without being decompressed
+pub fn rewrite_parts<M, F>(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    matches: M,
+    mut rewrite: F,
+) -> Result<Bytes, ArchiveError>
+where
+    M: Fn(&str) -> bool,
+    F: FnMut(&str) -> String,
+{
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+
+        if !matches(entry.name()) {
+            writer.raw_copy_file(entry)?;
+            continue;
+        }
+
+        drop(entry);
+
+        let entry = archive.by_index(index)?;
+        let name = entry.name().to_string();
+        let xml = rewrite(&read_entry(entry)?);
+
+        writer.start_file(name, options)?;
+        writer.write_all(xml.as_bytes())?;
+    }
+
+    let cursor = writer.finish()?;
+    Ok(Bytes::from(cursor.into_inner()))
+}
+
+/// Rewrites the archive replacing the contents of the provided parts
+pub fn replace_parts(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    mut parts: HashMap<String, String>,
+) -> Result<Bytes, ArchiveError> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+
+        match parts.remove(entry.name()) {
+            Some(xml) => {
+                let name = entry.name().to_string();
+                writer.start_file(name, options)?;
+                writer.write_all(xml.as_bytes())?;
+            }
+            // Other parts are copied as-is
+            None => writer.raw_copy_file(entry)?,
+        }
+    }
+
+    let cursor = writer.finish()?;
+    Ok(Bytes::from(cursor.into_inner()))
+}
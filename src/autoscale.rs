@@ -0,0 +1,154 @@
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+/// Smallest body limit picked when auto scaling
+const MIN_BODY_LIMIT: u64 = 32 * 1024 * 1024;
+
+/// Largest body limit picked when auto scaling, same as the default limit
+const MAX_BODY_LIMIT: u64 = 1024 * 1024 * 1024;
+
+/// Memory assumed to be used by each waiting conversion when picking the
+/// queue depth, covers the buffered upload and the converted PDF
+const QUEUED_CONVERSION_MEMORY: u64 = 128 * 1024 * 1024;
+
+/// Number of waiting conversions allowed for each CPU when auto scaling
+const QUEUED_PER_CPU: usize = 4;
+
+/// Maximum number of conversions waiting for office in low memory mode, each
+/// waiting conversion holds its upload in memory
+pub const LOW_MEMORY_MAX_QUEUED: usize = 1;
+
+/// CPU and memory available to the server, limited by the cgroup of the
+/// process when running in a container
+#[derive(Debug, Clone, Copy)]
+pub struct SystemResources {
+    /// Number of CPUs available, includes cgroup CPU quotas
+    pub cpus: usize,
+    /// Memory available in bytes, [None] when it could not be determined
+    pub memory: Option<u64>,
+}
+
+impl SystemResources {
+    /// Detects the resources available to the process
+    pub fn detect() -> Self {
+        // Standard library accounts for cgroup quotas and CPU affinity
+        let cpus = std::thread::available_parallelism()
+            .map(|value| value.get())
+            .unwrap_or(1);
+
+        let memory = match (cgroup_memory_limit(), total_memory()) {
+            (Some(limit), Some(total)) => Some(limit.min(total)),
+            (limit, total) => limit.or(total),
+        };
+
+        Self { cpus, memory }
+    }
+}
+
+/// Defaults picked from the available resources, explicitly provided
+/// arguments take priority over these
+#[derive(Debug, Clone, Copy)]
+pub struct AutoScale {
+    /// Resources the defaults were picked for
+    pub resources: SystemResources,
+    /// Number of threads for the async runtime
+    pub worker_threads: usize,
+    /// Maximum number of conversions that can be waiting for office
+    pub max_queued: usize,
+    /// Maximum size of a request body in bytes
+    pub max_body_size: u64,
+}
+
+impl AutoScale {
+    /// Picks defaults for the provided resources. Uploads are buffered in memory
+    /// and office needs several times the size of a document to load it, so the
+    /// body limit is a quarter of the memory and the queue is limited to the number
+    /// of conversions that fit in half of the memory
+    pub fn from_resources(resources: SystemResources) -> Self {
+        let max_queued = resources.cpus.saturating_mul(QUEUED_PER_CPU);
+
+        let (max_queued, max_body_size) = match resources.memory {
+            Some(memory) => {
+                let fits_memory = (memory / 2 / QUEUED_CONVERSION_MEMORY) as usize;
+                let max_body_size = (memory / 4).clamp(MIN_BODY_LIMIT, MAX_BODY_LIMIT);
+
+                (max_queued.min(fits_memory).max(1), max_body_size)
+            }
+            None => (max_queued, MAX_BODY_LIMIT),
+        };
+
+        Self {
+            resources,
+            worker_threads: resources.cpus,
+            max_queued,
+            max_body_size,
+        }
+    }
+}
+
+/// Reads the memory limit of the cgroup the process belongs to,
+/// provides [None] when there is no limit
+#[cfg(target_os = "linux")]
+fn cgroup_memory_limit() -> Option<u64> {
+    let cgroups = std::fs::read_to_string("/proc/self/cgroup").ok()?;
+
+    // Hybrid setups list both cgroup v1 controllers and the cgroup v2 hierarchy
+    cgroups.lines().find_map(|line| {
+        let mut parts = line.splitn(3, ':');
+        let (_, controllers, path) = (parts.next()?, parts.next()?, parts.next()?);
+        let path = path.trim_start_matches('/');
+
+        // cgroup v2 has a single unified hierarchy
+        if controllers.is_empty() {
+            let root = Path::new("/sys/fs/cgroup");
+            return read_limit(&root.join(path).join("memory.max"))
+                .or_else(|| read_limit(&root.join("memory.max")));
+        }
+
+        if controllers.split(',').any(|value| value == "memory") {
+            let root = Path::new("/sys/fs/cgroup/memory");
+            return read_limit(&root.join(path).join("memory.limit_in_bytes"))
+                .or_else(|| read_limit(&root.join("memory.limit_in_bytes")));
+        }
+
+        None
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cgroup_memory_limit() -> Option<u64> {
+    None
+}
+
+/// Reads a cgroup memory limit file, cgroup v2 uses "max" for no limit and
+/// cgroup v1 uses a value close to the maximum
+#[cfg(target_os = "linux")]
+fn read_limit(path: &Path) -> Option<u64> {
+    let value = std::fs::read_to_string(path).ok()?;
+    let value: u64 = value.trim().parse().ok()?;
+
+    // Unlimited cgroup v1 limits are rounded down to the page size
+    (value < u64::MAX / 2).then_some(value)
+}
+
+/// Reads the total memory of the system
+#[cfg(target_os = "linux")]
+fn total_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+
+    let kilobytes: u64 = line
+        .trim_start_matches("MemTotal:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+
+    Some(kilobytes * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn total_memory() -> Option<u64> {
+    None
+}
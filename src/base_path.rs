@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+/// Path prefix the routes are served under when running behind a reverse
+/// proxy that routes by path (i.e "/convert-api"), empty when the routes
+/// are served from the root
+#[derive(Debug, Clone, Default)]
+pub struct BasePath(Arc<str>);
+
+impl BasePath {
+    /// Parses the base path, the path must start with "/" and only contain
+    /// unreserved URL characters. Trailing slashes are removed so "/" serves
+    /// the routes from the root
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        if !value.starts_with('/') {
+            anyhow::bail!("base path must start with \"/\"");
+        }
+
+        let valid = value
+            .bytes()
+            .all(|value| value.is_ascii_alphanumeric() || b"/-._~".contains(&value));
+        if !valid {
+            anyhow::bail!(
+                "base path must only contain letters, numbers, '/', '-', '.', '_' and '~'"
+            );
+        }
+
+        if value.contains("//") {
+            anyhow::bail!("base path must not contain empty segments");
+        }
+
+        Ok(Self(Arc::from(value.trim_end_matches('/'))))
+    }
+
+    /// Whether the routes are served from the root
+    pub fn is_root(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Base path without a trailing slash, empty when served from the root
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Creates the URL path for a route (i.e "/download/token") including
+    /// the base path
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.0)
+    }
+}
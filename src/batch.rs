@@ -0,0 +1,288 @@
+use crate::{
+    compression::decompress_file,
+    config::SharedConfig,
+    error::{DynHttpError, HttpError},
+    failures::FailureInput,
+    tenant::{Tenant, Tenants},
+    ws::MaxFileSize,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    http::{header, HeaderValue, Response, StatusCode},
+    Extension,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use office_convert_core::{ConvertError, Converter, OfficeDetails, PdfExportOptions};
+use std::{
+    collections::HashSet,
+    io::{Cursor, Read, Write},
+    path::{Component, Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tracing::debug;
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Limits applied when extracting batch archives to protect
+/// against zip bombs
+#[derive(Debug, Clone, Copy)]
+pub struct BatchLimits {
+    /// Maximum number of entries allowed within the archive
+    pub max_entries: usize,
+    /// Maximum total decompressed size of all the entries in bytes
+    pub max_total_size: u64,
+}
+
+/// Errors that can occur while processing a batch archive
+#[derive(Debug, Error)]
+pub enum BatchError {
+    /// Uploaded file could not be read as a ZIP archive
+    #[error("archive is not a valid zip file: {0}")]
+    InvalidArchive(#[from] ZipError),
+
+    /// Failed to read the contents of an entry
+    #[error("failed to read archive entry: {0}")]
+    ReadEntry(#[from] std::io::Error),
+
+    /// Archive contained more entries than allowed
+    #[error("archive contains too many entries (maximum {0})")]
+    TooManyEntries(usize),
+
+    /// Archive decompressed to more bytes than allowed
+    #[error("archive exceeds the maximum decompressed size of {0} bytes")]
+    TooLarge(u64),
+
+    /// Archive did not contain any files that could be converted
+    #[error("archive contains no supported documents")]
+    NoDocuments,
+
+    /// One of the documents in the archive failed to convert
+    #[error("failed to convert \"{path}\": {cause}")]
+    ConvertEntry { path: String, cause: ConvertError },
+}
+
+impl HttpError for BatchError {
+    fn status(&self) -> StatusCode {
+        match self {
+            BatchError::ConvertEntry { cause, .. } => cause.status(),
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            BatchError::ConvertEntry { cause, .. } => cause.code(),
+            _ => None,
+        }
+    }
+}
+
+/// Document extracted from a batch archive
+pub struct ArchiveEntry {
+    /// Path of the document within the archive
+    pub path: PathBuf,
+    /// Decompressed document bytes
+    pub bytes: Bytes,
+}
+
+/// Request to convert a ZIP archive of files
+#[derive(TryFromMultipart)]
+pub struct ConvertBatchRequest {
+    /// The ZIP archive to convert
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+}
+
+/// POST /convert-batch
+///
+/// Converts every supported document within the provided ZIP archive to PDF
+/// format responding with a ZIP archive of the PDF files
+#[allow(clippy::too_many_arguments)]
+pub async fn convert_batch(
+    Extension(office): Extension<Converter>,
+    Extension(details): Extension<Arc<OfficeDetails>>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(limits): Extension<BatchLimits>,
+    Extension(tenants): Extension<Arc<Tenants>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    Extension(failure_input): Extension<FailureInput>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    TypedMultipart(ConvertBatchRequest { file }): TypedMultipart<ConvertBatchRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    let file = decompress_file(file, max_file_size).await?;
+    failure_input.set_file(&file);
+    let config = config.current();
+
+    // Extract the documents from the archive
+    let entries =
+        tokio::task::spawn_blocking(move || extract_archive(file.contents, limits, &details))
+            .await
+            .context("failed to extract archive")??;
+
+    // Convert each of the documents
+    let mut converted = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let input_size = entry.bytes.len();
+        let mut options = PdfExportOptions::default();
+        config.apply_load_options(
+            &mut options,
+            &entry.bytes,
+            entry.path.file_name().and_then(|value| value.to_str()),
+            None,
+        );
+
+        let bytes = office
+            .convert_with_options(entry.bytes, options)
+            .await
+            .map_err(|cause| BatchError::ConvertEntry {
+                path: entry.path.display().to_string(),
+                cause,
+            })?;
+
+        if let Some(Extension(tenant)) = &tenant {
+            tenants.record(&tenant.name, input_size, &bytes);
+        }
+
+        converted.push((entry.path, bytes));
+    }
+
+    // Create the output archive
+    let archive = tokio::task::spawn_blocking(move || create_archive(converted))
+        .await
+        .context("failed to create archive")?
+        .context("failed to create archive")?;
+
+    // Build the response
+    let response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/zip"),
+        )
+        .body(Body::from(archive))
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// Extracts the supported documents from the provided ZIP archive bytes
+pub fn extract_archive(
+    bytes: Bytes,
+    limits: BatchLimits,
+    details: &OfficeDetails,
+) -> Result<Vec<ArchiveEntry>, BatchError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+
+    if archive.len() > limits.max_entries {
+        return Err(BatchError::TooManyEntries(limits.max_entries));
+    }
+
+    let mut entries = Vec::new();
+    let mut total_size: u64 = 0;
+
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+
+        if entry.is_dir() {
+            continue;
+        }
+
+        // Skip entries that would escape the archive root
+        let path = match entry.enclosed_name() {
+            Some(value) => value,
+            None => {
+                debug!(name = entry.name(), "skipping unsafe archive entry");
+                continue;
+            }
+        };
+
+        if is_ignored_entry(&path) || !details.is_supported_path(&path) {
+            debug!(path = %path.display(), "skipping unsupported archive entry");
+            continue;
+        }
+
+        // The declared entry size can be forged so reading is capped to the
+        // remaining allowance (+1 to detect when its been exceeded)
+        let remaining = limits.max_total_size - total_size;
+        let mut contents = Vec::new();
+        (&mut entry)
+            .take(remaining + 1)
+            .read_to_end(&mut contents)?;
+
+        total_size += contents.len() as u64;
+        if total_size > limits.max_total_size {
+            return Err(BatchError::TooLarge(limits.max_total_size));
+        }
+
+        entries.push(ArchiveEntry {
+            path,
+            bytes: Bytes::from(contents),
+        });
+    }
+
+    if entries.is_empty() {
+        return Err(BatchError::NoDocuments);
+    }
+
+    Ok(entries)
+}
+
+/// Checks if the entry is an archive metadata file that should be skipped
+/// (i.e macOS resource forks and hidden files)
+fn is_ignored_entry(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(value) => {
+            let value = value.to_string_lossy();
+            value.starts_with('.') || value == "__MACOSX"
+        }
+        _ => false,
+    })
+}
+
+/// Creates a ZIP archive from the converted documents, documents keep their
+/// original directory structure with the extension replaced by ".pdf"
+pub fn create_archive(entries: Vec<(PathBuf, Bytes)>) -> Result<Vec<u8>, ZipError> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let mut used_names = HashSet::new();
+
+    for (path, bytes) in entries {
+        let mut name = archive_name(&path.with_extension("pdf"));
+
+        // Documents that only differ by extension keep their original
+        // extension to prevent a name collision (i.e report.doc and report.docx)
+        if used_names.contains(&name) {
+            let original = archive_name(&path);
+            name = format!("{original}.pdf");
+
+            // Names can still collide with other documents (i.e duplicate
+            // entries), these are numbered until the name is unique
+            let mut number = 2;
+            while used_names.contains(&name) {
+                name = format!("{original} ({number}).pdf");
+                number += 1;
+            }
+        }
+
+        writer.start_file(name.as_str(), options)?;
+        writer.write_all(&bytes)?;
+        used_names.insert(name);
+    }
+
+    let cursor = writer.finish()?;
+    Ok(cursor.into_inner())
+}
+
+/// Creates a ZIP entry name from the provided path, ZIP archives always
+/// use "/" as the path separator
+fn archive_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|component| match component {
+            Component::Normal(value) => Some(value.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
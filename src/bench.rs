@@ -0,0 +1,203 @@
+use anyhow::Context;
+use office_convert_client::{ConvertOffice, OfficeConvertClient};
+use reqwest::header::{HeaderMap, HeaderValue};
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+
+/// Header the API key is provided to tenant protected servers in
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Arguments for the bench subcommand
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// Host of the server to benchmark (i.e http://localhost:8080 or unix:///run/office-convert.sock)
+    #[arg(long)]
+    target: String,
+
+    /// File to convert for each request
+    #[arg(long)]
+    file: PathBuf,
+
+    /// Number of conversions to send at once, defaults to 4
+    #[arg(long)]
+    concurrency: Option<usize>,
+
+    /// Total number of conversions to send, defaults to 100
+    #[arg(long)]
+    requests: Option<usize>,
+
+    /// API key to send when the server has tenants configured
+    #[arg(long)]
+    api_key: Option<String>,
+}
+
+/// Outcome of a single conversion
+struct Sample {
+    /// Time from sending the request to receiving the full response
+    latency: Duration,
+    /// Size of the converted file, error message when the conversion failed
+    result: Result<usize, String>,
+}
+
+/// Benchmarks a running server by converting the same file repeatedly with
+/// a fixed number of conversions in flight, reporting the throughput and
+/// latency percentiles
+pub async fn run(args: BenchArgs) -> anyhow::Result<()> {
+    let concurrency = args.concurrency.unwrap_or(4).max(1);
+    let requests = args.requests.unwrap_or(100);
+
+    let file = std::fs::read(&args.file).context("failed to read bench file")?;
+
+    let mut headers = HeaderMap::new();
+    if let Some(api_key) = &args.api_key {
+        headers.insert(
+            API_KEY_HEADER,
+            HeaderValue::from_str(api_key).context("invalid api key")?,
+        );
+    }
+
+    let http = reqwest::Client::builder()
+        .default_headers(headers)
+        .build()
+        .context("failed to create http client")?;
+    let client = OfficeConvertClient::from_client(args.target.as_str(), http)
+        .context("failed to create client")?;
+
+    let status = client
+        .get_status()
+        .await
+        .context("failed to reach target server")?;
+
+    // Older servers don't report their workers
+    let workers = match status.workers.len() {
+        0 => "unknown".to_string(),
+        count => count.to_string(),
+    };
+
+    println!(
+        "benchmarking {} with {} ({} bytes): {requests} requests, {concurrency} concurrent, {workers} server workers",
+        args.target,
+        args.file.display(),
+        file.len(),
+    );
+
+    let file = Arc::new(file);
+    let next = Arc::new(AtomicUsize::new(0));
+    let mut tasks = JoinSet::new();
+
+    let started_at = Instant::now();
+
+    for _ in 0..concurrency.min(requests) {
+        let client = client.clone();
+        let file = file.clone();
+        let next = next.clone();
+
+        tasks.spawn(async move {
+            let mut samples = Vec::new();
+
+            while next.fetch_add(1, Ordering::SeqCst) < requests {
+                let request_started_at = Instant::now();
+                let result = client
+                    .convert(file.as_ref().clone())
+                    .await
+                    .map(|bytes| bytes.len())
+                    .map_err(|err| err.to_string());
+
+                samples.push(Sample {
+                    latency: request_started_at.elapsed(),
+                    result,
+                });
+            }
+
+            samples
+        });
+    }
+
+    let mut samples = Vec::with_capacity(requests);
+    while let Some(result) = tasks.join_next().await {
+        samples.extend(result.context("bench task panicked")?);
+    }
+
+    report(&samples, started_at.elapsed());
+
+    Ok(())
+}
+
+/// Prints the results of the benchmark
+fn report(samples: &[Sample], elapsed: Duration) {
+    let mut latencies: Vec<Duration> = samples.iter().map(|sample| sample.latency).collect();
+    latencies.sort();
+
+    let succeeded = samples
+        .iter()
+        .filter(|sample| sample.result.is_ok())
+        .count();
+    let output_bytes: usize = samples
+        .iter()
+        .filter_map(|sample| sample.result.as_ref().ok())
+        .sum();
+
+    // Group failures by their message so repeated errors are only shown once
+    let mut failures: BTreeMap<&str, usize> = BTreeMap::new();
+    for sample in samples {
+        if let Err(err) = &sample.result {
+            *failures.entry(err.as_str()).or_default() += 1;
+        }
+    }
+
+    println!();
+    println!("duration:    {:.2}s", elapsed.as_secs_f64());
+    println!(
+        "requests:    {} ({succeeded} succeeded, {} failed)",
+        samples.len(),
+        samples.len() - succeeded
+    );
+    println!(
+        "throughput:  {:.2} conversions/s",
+        succeeded as f64 / elapsed.as_secs_f64()
+    );
+    println!("output:      {output_bytes} bytes");
+
+    if let (Some(min), Some(max)) = (latencies.first(), latencies.last()) {
+        let mean = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+
+        println!();
+        println!("latency min: {}", format_duration(*min));
+        println!("latency avg: {}", format_duration(mean));
+        for percentile in [50, 90, 95, 99] {
+            println!(
+                "latency p{percentile}: {}",
+                format_duration(percentile_of(&latencies, percentile))
+            );
+        }
+        println!("latency max: {}", format_duration(*max));
+    }
+
+    if !failures.is_empty() {
+        println!();
+        println!("failures:");
+        for (err, count) in failures {
+            println!("  {count}x {err}");
+        }
+    }
+}
+
+/// Gets the value at the percentile (0-100) of the sorted latencies using
+/// the nearest rank method
+fn percentile_of(sorted: &[Duration], percentile: u32) -> Duration {
+    let rank = ((percentile as f64 / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Formats a duration in milliseconds
+fn format_duration(duration: Duration) -> String {
+    format!("{:.1}ms", duration.as_secs_f64() * 1000.0)
+}
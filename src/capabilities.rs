@@ -0,0 +1,168 @@
+use axum::{
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Version of the HTTP API, increased when endpoints, options or responses
+/// change in a way clients need to know about
+pub const API_VERSION: u32 = 1;
+
+/// Endpoints available on every server
+const ENDPOINTS: &[&str] = &[
+    "/status",
+    "/status/errors",
+    "/office-version",
+    "/supported-formats",
+    "/capabilities",
+    "/ready",
+    "/fonts",
+    "/metrics",
+    "/collect-garbage",
+    "/download/{token}",
+    "/convert",
+    "/convert-batch",
+    "/render",
+    "/render-pages",
+    "/detect",
+    "/validate",
+    "/extract-text",
+    "/jobs",
+    "/jobs/{id}",
+    "/jobs/{id}/result",
+    "/jobs/{id}/preview",
+    "/jobs/{id}/download-url",
+    "/uploads",
+    "/uploads/{id}",
+    "/uploads/{id}/convert",
+    "/ws",
+    "/selftest",
+    "/results/{id}",
+];
+
+/// Endpoints only available when an admin token is configured
+const ADMIN_ENDPOINTS: &[&str] = &[
+    "/admin",
+    "/admin/stats",
+    "/admin/usage",
+    "/admin/restart-office",
+    "/admin/reload",
+];
+
+/// Multipart fields accepted by /convert
+const CONVERT_OPTIONS: &[&str] = &[
+    "file",
+    "page_size",
+    "orientation",
+    "margin",
+    "include_attachments",
+    "preset",
+    "linearize",
+    "sign",
+    "user_password",
+    "owner_password",
+    "no_print",
+    "no_copy",
+    "no_modify",
+    "tagged",
+    "pdf_ua",
+    "sanitize",
+    "repair",
+    "response",
+    "impress_options",
+    "writer_options",
+    "priority",
+    "filter_options",
+    "load_options",
+    "locale",
+    "timezone",
+    "modify_password",
+    "pdf_passthrough",
+    "output_filename",
+    "fit_to_width",
+];
+
+/// Content encodings uploaded files can be compressed with
+const UPLOAD_ENCODINGS: &[&str] = &["gzip", "zstd"];
+
+/// Formats /convert can respond with
+const RESPONSE_FORMATS: &[&str] = &["pdf", "multipart"];
+
+/// Details about what the server supports, allows clients to use newer
+/// features only when the server they are talking to supports them
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+    /// Version of the HTTP API
+    pub api_version: u32,
+    /// Version of the server
+    pub server_version: &'static str,
+    /// Paths of the available endpoints
+    pub endpoints: Vec<&'static str>,
+    /// Multipart fields accepted by /convert
+    pub convert_options: &'static [&'static str],
+    /// Content encodings uploaded files can be compressed with
+    pub upload_encodings: &'static [&'static str],
+    /// Formats /convert can respond with
+    pub response_formats: &'static [&'static str],
+    /// Names of the engines conversions can select
+    pub engines: Vec<String>,
+    /// Whether raw LibreOffice options are accepted
+    pub raw_options: bool,
+    /// Limits applied to requests
+    pub limits: CapabilityLimits,
+}
+
+/// Limits applied to requests, [None] when there is no limit
+#[derive(Debug, Serialize)]
+pub struct CapabilityLimits {
+    /// Maximum size in bytes of an uploaded file
+    pub max_file_size: u64,
+    /// Maximum number of conversions waiting for LibreOffice
+    pub max_queued: Option<usize>,
+    /// Maximum number of pages in a converted PDF
+    pub max_output_pages: Option<u64>,
+    /// Maximum size in bytes of a converted PDF
+    pub max_output_bytes: Option<u64>,
+    /// Maximum number of pages rendered as images
+    pub max_render_pages: u64,
+    /// Maximum number of files within a batch archive
+    pub batch_max_entries: usize,
+    /// Maximum total decompressed size in bytes of a batch archive
+    pub batch_max_size: u64,
+}
+
+impl Capabilities {
+    /// Creates the capabilities of the server, admin endpoints are only
+    /// listed when they are available
+    pub fn new(
+        admin: bool,
+        engines: Vec<String>,
+        raw_options: bool,
+        limits: CapabilityLimits,
+    ) -> Arc<Self> {
+        let mut endpoints = ENDPOINTS.to_vec();
+        if admin {
+            endpoints.extend_from_slice(ADMIN_ENDPOINTS);
+        }
+
+        Arc::new(Self {
+            api_version: API_VERSION,
+            server_version: env!("CARGO_PKG_VERSION"),
+            endpoints,
+            convert_options: CONVERT_OPTIONS,
+            upload_encodings: UPLOAD_ENCODINGS,
+            response_formats: RESPONSE_FORMATS,
+            engines,
+            raw_options,
+            limits,
+        })
+    }
+}
+
+/// GET /capabilities
+///
+/// Reports the API version, endpoints, options and limits of the server
+pub async fn capabilities(Extension(capabilities): Extension<Arc<Capabilities>>) -> Response {
+    Json(&*capabilities).into_response()
+}
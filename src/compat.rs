@@ -0,0 +1,297 @@
+use crate::{
+    archive::{open_archive, read_part, replace_parts},
+    detect::{detect_archive, known_extension},
+    page_style::PageStyleError,
+    xml::{find_tag, get_attribute, remove_attribute, rewrite_tags, Attributes},
+};
+use bytes::Bytes;
+use std::collections::HashMap;
+
+/// Prefixes of formula functions LibreOffice may not be able to calculate.
+/// Excel 365 prefixes functions added after Excel 2007 (dynamic array functions
+/// like FILTER, SORT and UNIQUE along with XLOOKUP and LET) with "_xlfn." and
+/// "_xlws.", Google Sheets exports functions Excel doesn't have (GOOGLEFINANCE,
+/// IMPORTRANGE, QUERY) wrapped in "__xludf.DUMMYFUNCTION"
+const UNSUPPORTED_FUNCTION_PREFIXES: &[&str] = &["_xlfn.", "_xlws.", "__xludf."];
+
+/// Rewrites parts of documents exported by Google Docs and Office 365 that
+/// LibreOffice is known to import incorrectly, provides a warning describing
+/// the changes when the document was changed. Files that aren't affected are
+/// left unchanged
+///
+/// Formulas of Excel workbooks using functions LibreOffice may not support are
+/// replaced with the value they were last calculated to, the value is stored
+/// alongside the formula so the cells print as they did in Excel or Google
+/// Sheets instead of printing an error when LibreOffice recalculates them
+pub fn apply_compat_fixes(input: Bytes) -> Result<(Bytes, Option<String>), PageStyleError> {
+    // Archives that can't be read are left for office to handle
+    let mut archive = match open_archive(&input) {
+        Some(value) => value,
+        None => return Ok((input, None)),
+    };
+
+    if detect_archive(&mut archive).and_then(known_extension) != Some("xlsx") {
+        return Ok((input, None));
+    }
+
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.starts_with("xl/worksheets/") && name.ends_with(".xml"))
+        .map(str::to_string)
+        .collect();
+
+    let mut parts = HashMap::new();
+    let mut replaced = 0;
+
+    for name in names {
+        let Ok(xml) = read_part(&mut archive, &name) else {
+            continue;
+        };
+
+        let (xml, count) = replace_unsupported_formulas(&xml);
+        if count > 0 {
+            replaced += count;
+            parts.insert(name, xml);
+        }
+    }
+
+    if parts.is_empty() {
+        return Ok((input, None));
+    }
+
+    let output = replace_parts(&mut archive, parts)?;
+    let warning = format!(
+        "{replaced} {} using functions LibreOffice may not support {} replaced with {} last calculated value",
+        if replaced == 1 { "formula" } else { "formulas" },
+        if replaced == 1 { "was" } else { "were" },
+        if replaced == 1 { "its" } else { "their" },
+    );
+
+    Ok((output, Some(warning)))
+}
+
+/// Removes the formulas of the worksheet cells using functions LibreOffice may
+/// not support, cells keep their stored value. Provides the rewritten worksheet
+/// along with the number of formulas removed
+fn replace_unsupported_formulas(xml: &str) -> (String, usize) {
+    let mut output = String::with_capacity(xml.len());
+    let mut position = 0;
+    let mut replaced = 0;
+
+    while let Some(cell) = find_tag(xml, "c", position) {
+        if cell.self_closing {
+            output.push_str(&xml[position..cell.end]);
+            position = cell.end;
+            continue;
+        }
+
+        let Some(close) = xml[cell.end..].find("</c>").map(|value| cell.end + value) else {
+            break;
+        };
+
+        let contents = &xml[cell.end..close];
+        let Some(contents) = remove_unsupported_formula(contents) else {
+            output.push_str(&xml[position..close]);
+            position = close;
+            continue;
+        };
+
+        // Dynamic array cells reference metadata describing the spilled formula
+        let start_tag = rewrite_tags(&xml[cell.start..cell.end], "c", |attributes| {
+            remove_attribute(attributes, "cm");
+        });
+
+        output.push_str(&xml[position..cell.start]);
+        output.push_str(&start_tag);
+        output.push_str(&contents);
+        position = close;
+        replaced += 1;
+    }
+
+    output.push_str(&xml[position..]);
+    (output, replaced)
+}
+
+/// Removes the formula from the contents of a cell when it uses a function
+/// LibreOffice may not support and the cell has a stored value
+fn remove_unsupported_formula(contents: &str) -> Option<String> {
+    let formula = find_tag(contents, "f", 0)?;
+
+    // Cells without a stored value would be left empty
+    find_tag(contents, "v", 0)?;
+
+    // Other cells sharing the formula reference the cell it is stored in
+    if is_shared_formula(&formula.attributes) {
+        return None;
+    }
+
+    let (text, end) = match formula.self_closing {
+        true => ("", formula.end),
+        false => {
+            let close = formula.end + contents[formula.end..].find("</f>")?;
+            (&contents[formula.end..close], close + "</f>".len())
+        }
+    };
+
+    let unsupported = UNSUPPORTED_FUNCTION_PREFIXES
+        .iter()
+        .any(|prefix| text.contains(prefix));
+    if !unsupported {
+        return None;
+    }
+
+    Some(format!(
+        "{}{}",
+        &contents[..formula.start],
+        &contents[end..]
+    ))
+}
+
+/// Whether the formula is shared with other cells
+fn is_shared_formula(attributes: &Attributes) -> bool {
+    get_attribute(attributes, "t") == Some("shared")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_compat_fixes, replace_unsupported_formulas};
+    use bytes::Bytes;
+    use std::io::{Cursor, Read, Write};
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+    /// Styles part that isn't affected by the compat fixes
+    const STYLES: &str = "<styleSheet><fonts count=\"1\"><font/></fonts></styleSheet>";
+
+    /// Creates a ZIP archive from the provided parts, parts are stored
+    /// uncompressed so copying them can be checked
+    fn archive(parts: &[(&str, &str)]) -> Bytes {
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+
+        for (name, contents) in parts {
+            writer.start_file(*name, options).unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+
+        Bytes::from(writer.finish().unwrap().into_inner())
+    }
+
+    /// Creates an Excel workbook with a single worksheet
+    fn workbook(sheet: &str) -> Bytes {
+        archive(&[
+            ("xl/workbook.xml", "<workbook/>"),
+            ("xl/worksheets/sheet1.xml", sheet),
+            ("xl/styles.xml", STYLES),
+        ])
+    }
+
+    /// Reads a part of the archive along with its compression method
+    fn read_part(bytes: &[u8], name: &str) -> (String, CompressionMethod) {
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut entry = archive.by_name(name).unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        (contents, entry.compression())
+    }
+
+    #[test]
+    fn replaces_office_365_formulas() {
+        let sheet = "<sheetData><row r=\"1\">\
+            <c r=\"A1\" cm=\"1\"><f t=\"array\" ref=\"A1:A3\">_xlfn._xlws.SORT(B1:B3)</f><v>1</v></c>\
+            <c r=\"B1\"><f>_xlfn.XLOOKUP(C1,D1:D3,E1:E3)</f><v>2</v></c>\
+            </row></sheetData>";
+
+        let (output, warning) = apply_compat_fixes(workbook(sheet)).unwrap();
+
+        assert_eq!(
+            read_part(&output, "xl/worksheets/sheet1.xml").0,
+            "<sheetData><row r=\"1\">\
+            <c r=\"A1\"><v>1</v></c>\
+            <c r=\"B1\"><v>2</v></c>\
+            </row></sheetData>"
+        );
+        assert_eq!(
+            warning.as_deref(),
+            Some("2 formulas using functions LibreOffice may not support were replaced with their last calculated value")
+        );
+    }
+
+    #[test]
+    fn replaces_google_sheets_formulas() {
+        let sheet = "<sheetData><row r=\"1\">\
+            <c r=\"A1\" t=\"str\"><f>IFERROR(__xludf.DUMMYFUNCTION(\"GOOGLEFINANCE(\"\"GOOG\"\")\"),\"123.45\")</f><v>123.45</v></c>\
+            </row></sheetData>";
+
+        let (output, warning) = apply_compat_fixes(workbook(sheet)).unwrap();
+
+        assert_eq!(
+            read_part(&output, "xl/worksheets/sheet1.xml").0,
+            "<sheetData><row r=\"1\">\
+            <c r=\"A1\" t=\"str\"><v>123.45</v></c>\
+            </row></sheetData>"
+        );
+        assert_eq!(
+            warning.as_deref(),
+            Some("1 formula using functions LibreOffice may not support was replaced with its last calculated value")
+        );
+    }
+
+    #[test]
+    fn copies_unrelated_parts() {
+        let sheet = "<sheetData><row r=\"1\"><c r=\"A1\"><f>_xlfn.UNIQUE(B1:B3)</f><v>1</v></c></row></sheetData>";
+
+        let (output, _) = apply_compat_fixes(workbook(sheet)).unwrap();
+
+        // Unrelated parts keep their contents and compression
+        assert_eq!(
+            read_part(&output, "xl/styles.xml"),
+            (STYLES.to_string(), CompressionMethod::Stored)
+        );
+        assert_eq!(
+            read_part(&output, "xl/workbook.xml"),
+            ("<workbook/>".to_string(), CompressionMethod::Stored)
+        );
+    }
+
+    #[test]
+    fn leaves_supported_workbooks_unchanged() {
+        let input = workbook(
+            "<sheetData><row r=\"1\"><c r=\"A1\"><f>SUM(B1:B3)</f><v>6</v></c></row></sheetData>",
+        );
+
+        let (output, warning) = apply_compat_fixes(input.clone()).unwrap();
+
+        assert_eq!(output, input);
+        assert_eq!(warning, None);
+    }
+
+    #[test]
+    fn leaves_other_documents_unchanged() {
+        let input = archive(&[
+            ("word/document.xml", "<w:document/>"),
+            (
+                "xl/worksheets/sheet1.xml",
+                "<c><f>_xlfn.SORT(A1:A3)</f><v>1</v></c>",
+            ),
+        ]);
+
+        let (output, warning) = apply_compat_fixes(input.clone()).unwrap();
+
+        assert_eq!(output, input);
+        assert_eq!(warning, None);
+    }
+
+    #[test]
+    fn keeps_formulas_without_stored_values() {
+        let xml = "<c r=\"A1\"><f>_xlfn.SORT(B1:B3)</f></c>";
+        assert_eq!(replace_unsupported_formulas(xml), (xml.to_string(), 0));
+    }
+
+    #[test]
+    fn keeps_shared_formulas() {
+        let xml =
+            "<c r=\"A1\"><f t=\"shared\" ref=\"A1:A3\" si=\"0\">_xlfn.SORT(B1:B3)</f><v>1</v></c>\
+            <c r=\"A2\"><f t=\"shared\" si=\"0\"/><v>2</v></c>";
+        assert_eq!(replace_unsupported_formulas(xml), (xml.to_string(), 0));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::error::{DynHttpError, HttpError};
+use anyhow::Context;
+use axum::http::{header, Extensions, HeaderMap, StatusCode, Version};
+use axum_typed_multipart::FieldData;
+use bytes::Bytes;
+use std::io::Read;
+use thiserror::Error;
+use tower_http::compression::{
+    predicate::{NotForContentType, Predicate},
+    CompressionLayer, DefaultPredicate,
+};
+
+/// Creates the layer compressing responses using the encodings the client
+/// accepts (gzip or zstd), ZIP archives are already compressed so are skipped.
+/// Responses supporting range requests are skipped as ranges refer to the
+/// uncompressed bytes
+pub fn response_compression() -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new().compress_when(
+        DefaultPredicate::new()
+            .and(NotForContentType::new("application/zip"))
+            .and(
+                |_: StatusCode, _: Version, headers: &HeaderMap, _: &Extensions| {
+                    !headers.contains_key(header::ACCEPT_RANGES)
+                },
+            ),
+    )
+}
+
+/// Errors that can occur while decompressing an uploaded file
+#[derive(Debug, Error)]
+pub enum DecompressError {
+    /// File part was compressed using an unsupported encoding
+    #[error("unsupported content encoding \"{0}\" for file, expected gzip or zstd")]
+    UnsupportedEncoding(String),
+
+    /// File could not be decompressed
+    #[error("file could not be decompressed: {0}")]
+    InvalidData(std::io::Error),
+
+    /// Decompressed file is larger than the maximum file size
+    #[error("decompressed file exceeds the maximum size of {0} bytes")]
+    TooLarge(u64),
+}
+
+impl HttpError for DecompressError {
+    fn status(&self) -> StatusCode {
+        match self {
+            DecompressError::UnsupportedEncoding(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            DecompressError::InvalidData(_) => StatusCode::BAD_REQUEST,
+            DecompressError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            DecompressError::UnsupportedEncoding(_) => Some("UNSUPPORTED_CONTENT_ENCODING"),
+            DecompressError::InvalidData(_) => Some("INVALID_COMPRESSED_FILE"),
+            DecompressError::TooLarge(_) => Some("FILE_TOO_LARGE"),
+        }
+    }
+}
+
+/// Encodings a file part can be compressed with
+#[derive(Debug, Clone, Copy)]
+enum ContentEncoding {
+    Gzip,
+    Zstd,
+}
+
+/// Decompresses a file part that was uploaded with a "Content-Encoding" header,
+/// files without the header are provided as-is. The decompressed file is limited
+/// to the maximum file size
+pub async fn decompress_file(
+    mut file: FieldData<Bytes>,
+    max_size: u64,
+) -> Result<FieldData<Bytes>, DynHttpError> {
+    let encoding = match file.metadata.headers.remove(header::CONTENT_ENCODING) {
+        Some(value) => value,
+        None => return Ok(file),
+    };
+
+    let encoding = match encoding.to_str().map(str::trim) {
+        Ok(value) if value.eq_ignore_ascii_case("identity") => return Ok(file),
+        Ok(value) if value.eq_ignore_ascii_case("gzip") => ContentEncoding::Gzip,
+        Ok(value) if value.eq_ignore_ascii_case("zstd") => ContentEncoding::Zstd,
+        value => {
+            let value = value.unwrap_or_default().to_string();
+            return Err(DecompressError::UnsupportedEncoding(value).into());
+        }
+    };
+
+    let contents = file.contents;
+    file.contents = tokio::task::spawn_blocking(move || decompress(encoding, &contents, max_size))
+        .await
+        .context("failed to decompress file")??;
+
+    Ok(file)
+}
+
+/// Decompresses the bytes, fails when the output exceeds the maximum size
+fn decompress(
+    encoding: ContentEncoding,
+    input: &[u8],
+    max_size: u64,
+) -> Result<Bytes, DecompressError> {
+    let reader: Box<dyn Read> = match encoding {
+        ContentEncoding::Gzip => Box::new(flate2::read::MultiGzDecoder::new(input)),
+        ContentEncoding::Zstd => {
+            Box::new(zstd::Decoder::new(input).map_err(DecompressError::InvalidData)?)
+        }
+    };
+
+    // Reading one more byte than allowed detects oversized output without
+    // decompressing the whole file
+    let mut output = Vec::new();
+    reader
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut output)
+        .map_err(DecompressError::InvalidData)?;
+
+    if output.len() as u64 > max_size {
+        return Err(DecompressError::TooLarge(max_size));
+    }
+
+    Ok(Bytes::from(output))
+}
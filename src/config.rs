@@ -0,0 +1,281 @@
+use crate::{
+    detect::detect_input_format,
+    engine::{validate_engine_name, EngineConfig, DEFAULT_ENGINE},
+    error::HttpError,
+    pipeline::PipelineConfig,
+    tenant::TenantConfig,
+};
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use office_convert_core::PdfExportOptions;
+use parking_lot::RwLock;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tracing_subscriber::EnvFilter;
+
+/// Server configuration loaded from a TOML config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Named sets of export options clients can select
+    #[serde(default)]
+    pub presets: HashMap<String, PdfExportOptions>,
+
+    /// Tenants keyed by name, API keys are required to convert
+    /// files when any tenants are configured
+    #[serde(default)]
+    pub tenants: HashMap<String, TenantConfig>,
+
+    /// Additional office installs keyed by name, requests and tenants
+    /// can select which engine converts their files
+    #[serde(default)]
+    pub engines: HashMap<String, EngineConfig>,
+
+    /// File to persist tenant usage to, usage is only kept in memory
+    /// when not provided
+    pub usage_file: Option<PathBuf>,
+
+    /// Options used when loading documents keyed by the mime type or file
+    /// extension of the format they apply to, "*" applies to all formats
+    #[serde(default)]
+    pub load_options: HashMap<String, String>,
+
+    /// Logging filter (i.e "info" or "info,office_convert_server=debug"),
+    /// the RUST_LOG environment variable is used when not provided
+    pub log_level: Option<String>,
+
+    /// Processing stages run on every conversion
+    #[serde(default)]
+    pub pipeline: PipelineConfig,
+
+    /// Environment variables set for office before it starts (i.e
+    /// "SAL_USE_VCLPLUGIN"), engines use these along with their own
+    #[serde(default)]
+    pub office_env: BTreeMap<String, String>,
+}
+
+/// Server config that can be replaced while the server is running, requests
+/// use the config that was current when they started
+#[derive(Clone)]
+pub struct SharedConfig(Arc<RwLock<Arc<Config>>>);
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(config))))
+    }
+
+    /// Provides the current config
+    pub fn current(&self) -> Arc<Config> {
+        self.0.read().clone()
+    }
+
+    /// Replaces the current config
+    pub fn replace(&self, config: Config) {
+        *self.0.write() = Arc::new(config);
+    }
+}
+
+/// Key of the load options applied to all formats
+const ALL_FORMATS: &str = "*";
+
+/// Errors that can occur when selecting a preset
+#[derive(Debug, Error)]
+pub enum PresetError {
+    /// Requested preset is not defined in the config
+    #[error("unknown preset \"{0}\"")]
+    Unknown(String),
+}
+
+impl HttpError for PresetError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("UNKNOWN_PRESET")
+    }
+}
+
+/// Export options provided with a request are invalid
+#[derive(Debug, Error)]
+#[error("invalid export options: {0}")]
+pub struct InvalidExportOptions(pub String);
+
+impl HttpError for InvalidExportOptions {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("INVALID_EXPORT_OPTIONS")
+    }
+}
+
+/// Whether requests can provide raw filter and load options that are
+/// passed to office as-is
+#[derive(Debug, Clone, Copy)]
+pub struct AllowRawOptions(pub bool);
+
+/// Raw options were provided with a request when they aren't allowed
+#[derive(Debug, Error)]
+#[error("raw filter_options and load_options are disabled on this server")]
+pub struct RawOptionsDisabled;
+
+impl HttpError for RawOptionsDisabled {
+    fn status(&self) -> StatusCode {
+        StatusCode::FORBIDDEN
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("RAW_OPTIONS_DISABLED")
+    }
+}
+
+impl Config {
+    /// Loads and validates the config file at the provided path
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path).context("failed to read config file")?;
+        let mut config: Config =
+            toml::from_str(&contents).context("failed to parse config file")?;
+
+        for (name, preset) in &config.presets {
+            preset
+                .validate()
+                .map_err(|err| anyhow!("invalid preset \"{name}\": {err}"))?;
+        }
+
+        for (format, options) in &config.load_options {
+            if format.is_empty() || options.is_empty() {
+                anyhow::bail!("load options for \"{format}\" must not be empty");
+            }
+        }
+
+        // Formats are matched ignoring case
+        config.load_options = std::mem::take(&mut config.load_options)
+            .into_iter()
+            .map(|(format, options)| (format.to_ascii_lowercase(), options))
+            .collect();
+
+        if let Some(log_level) = &config.log_level {
+            EnvFilter::try_new(log_level).context("invalid log level")?;
+        }
+
+        validate_office_env(&config.office_env).context("invalid office_env")?;
+
+        for (name, engine) in &config.engines {
+            validate_engine_name(name)
+                .map_err(|err| anyhow!("invalid engine \"{name}\": {err}"))?;
+            validate_office_env(&engine.office_env)
+                .with_context(|| format!("invalid office_env for engine \"{name}\""))?;
+        }
+
+        for (name, tenant) in &config.tenants {
+            if let Some(engine) = &tenant.engine {
+                if engine != DEFAULT_ENGINE && !config.engines.contains_key(engine) {
+                    anyhow::bail!("tenant \"{name}\" uses unknown engine \"{engine}\"");
+                }
+            }
+        }
+
+        config
+            .pipeline
+            .validate()
+            .context("invalid pipeline config")?;
+
+        Ok(config)
+    }
+
+    /// Environment variables for the office of the provided engine, variables
+    /// of the engine replace the variables for all engines with the same name
+    pub fn office_env(&self, engine: Option<&str>) -> BTreeMap<String, String> {
+        let mut env = self.office_env.clone();
+
+        if let Some(engine) = engine.and_then(|name| self.engines.get(name)) {
+            env.extend(engine.office_env.clone());
+        }
+
+        env
+    }
+
+    /// Adds the load options configured for the format of the file to the export
+    /// options. Options for all formats come first followed by the options for
+    /// the format and then the existing options (from the request or preset), as
+    /// later options take priority the most specific options are used
+    pub fn apply_load_options(
+        &self,
+        options: &mut PdfExportOptions,
+        bytes: &[u8],
+        file_name: Option<&str>,
+        content_type: Option<&str>,
+    ) {
+        if self.load_options.is_empty() {
+            return;
+        }
+
+        let mime = detect_input_format(bytes, file_name, content_type)
+            .map(|value| value.to_ascii_lowercase());
+        let file_extension = file_name
+            .and_then(|value| Path::new(value).extension())
+            .and_then(|value| value.to_str())
+            .map(|value| value.to_ascii_lowercase());
+
+        // Options for the mime type take priority over options for the file
+        // extension, followed by the other extensions of the mime type
+        let format_options = mime
+            .as_deref()
+            .and_then(|mime| self.load_options.get(mime))
+            .or_else(|| {
+                file_extension
+                    .as_deref()
+                    .and_then(|extension| self.load_options.get(extension))
+            })
+            .or_else(|| {
+                mime.as_deref()
+                    .and_then(mime_guess::get_mime_extensions_str)?
+                    .iter()
+                    .find_map(|extension| self.load_options.get(*extension))
+            });
+
+        let load_options: Vec<&str> = [
+            self.load_options.get(ALL_FORMATS),
+            format_options,
+            options.raw_load_options.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+
+        if !load_options.is_empty() {
+            options.raw_load_options = Some(load_options.join(","));
+        }
+    }
+
+    /// Gets the export options for the preset with the provided name
+    pub fn preset(&self, name: &str) -> Result<&PdfExportOptions, PresetError> {
+        self.presets
+            .get(name)
+            .ok_or_else(|| PresetError::Unknown(name.to_string()))
+    }
+}
+
+/// Checks that the environment variables can be set, names can't be empty or
+/// contain "=" and neither names nor values can contain NUL characters
+fn validate_office_env(env: &BTreeMap<String, String>) -> anyhow::Result<()> {
+    for (name, value) in env {
+        if name.is_empty() || name.contains(['=', '\0']) {
+            anyhow::bail!("invalid variable name \"{name}\"");
+        }
+
+        if value.contains('\0') {
+            anyhow::bail!("value of \"{name}\" must not contain NUL characters");
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,725 @@
+use crate::{
+    archive::ZIP_SIGNATURE,
+    compression::decompress_file,
+    error::{DynHttpError, HttpError},
+    ws::MaxFileSize,
+    xml::{find_tag, get_attribute},
+};
+use axum::{http::StatusCode, Extension, Json};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use cfb::CompoundFile;
+use imagesize::ImageType;
+use office_convert_core::{Converter, DocumentKind, OfficeDetails};
+use serde::Serialize;
+use std::{
+    io::{Cursor, Read, Seek},
+    path::Path,
+    sync::Arc,
+};
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Maximum length of the mimetype part of an OpenDocument archive that is read
+const MAX_MIMETYPE_LENGTH: u64 = 256;
+
+/// Signature at the start of an OLE compound file (Legacy Office formats and Outlook messages)
+const CFB_SIGNATURE: &[u8] = b"\xD0\xCF\x11\xE0\xA1\xB1\x1A\xE1";
+
+/// Formats that can be detected along with their file extension and the kind
+/// of document office loads them as, [None] for formats office can't load
+const KNOWN_FORMATS: &[(&str, &str, Option<DocumentKind>)] = &[
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+        Some(DocumentKind::Text),
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+        Some(DocumentKind::Spreadsheet),
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+        Some(DocumentKind::Presentation),
+    ),
+    (
+        "application/vnd.oasis.opendocument.text",
+        "odt",
+        Some(DocumentKind::Text),
+    ),
+    (
+        "application/vnd.oasis.opendocument.spreadsheet",
+        "ods",
+        Some(DocumentKind::Spreadsheet),
+    ),
+    (
+        "application/vnd.oasis.opendocument.presentation",
+        "odp",
+        Some(DocumentKind::Presentation),
+    ),
+    (
+        "application/vnd.oasis.opendocument.graphics",
+        "odg",
+        Some(DocumentKind::Drawing),
+    ),
+    (
+        "application/vnd.oasis.opendocument.text-flat-xml",
+        "fodt",
+        Some(DocumentKind::Text),
+    ),
+    (
+        "application/vnd.oasis.opendocument.spreadsheet-flat-xml",
+        "fods",
+        Some(DocumentKind::Spreadsheet),
+    ),
+    (
+        "application/vnd.oasis.opendocument.presentation-flat-xml",
+        "fodp",
+        Some(DocumentKind::Presentation),
+    ),
+    (
+        "application/vnd.oasis.opendocument.graphics-flat-xml",
+        "fodg",
+        Some(DocumentKind::Drawing),
+    ),
+    ("application/msword", "doc", Some(DocumentKind::Text)),
+    (
+        "application/vnd.ms-excel",
+        "xls",
+        Some(DocumentKind::Spreadsheet),
+    ),
+    (
+        "application/vnd.ms-powerpoint",
+        "ppt",
+        Some(DocumentKind::Presentation),
+    ),
+    // Emails are rendered by the server rather than loaded by office
+    ("application/vnd.ms-outlook", "msg", None),
+    ("message/rfc822", "eml", None),
+    ("application/rtf", "rtf", Some(DocumentKind::Text)),
+    (
+        "application/vnd.wordperfect",
+        "wpd",
+        Some(DocumentKind::Text),
+    ),
+    ("application/pdf", "pdf", Some(DocumentKind::Drawing)),
+    ("image/png", "png", Some(DocumentKind::Drawing)),
+    ("image/jpeg", "jpg", Some(DocumentKind::Drawing)),
+    ("image/gif", "gif", Some(DocumentKind::Drawing)),
+    ("image/bmp", "bmp", Some(DocumentKind::Drawing)),
+    ("image/tiff", "tif", Some(DocumentKind::Drawing)),
+    ("image/webp", "webp", Some(DocumentKind::Drawing)),
+    ("text/plain", "txt", Some(DocumentKind::Text)),
+    ("text/csv", "csv", Some(DocumentKind::Spreadsheet)),
+    ("text/html", "html", Some(DocumentKind::Text)),
+];
+
+/// Formats loaded using import filters that are packaged separately by some
+/// distributions of office, along with a description of the format and the
+/// name of the office filter type that imports it
+const IMPORT_FILTERS: &[(&str, &str, &str)] = &[
+    (
+        "application/vnd.oasis.opendocument.text-flat-xml",
+        "OpenDocument flat XML text (.fodt)",
+        "writer_ODT_FlatXML",
+    ),
+    (
+        "application/vnd.oasis.opendocument.spreadsheet-flat-xml",
+        "OpenDocument flat XML spreadsheet (.fods)",
+        "calc_ODS_FlatXML",
+    ),
+    (
+        "application/vnd.oasis.opendocument.presentation-flat-xml",
+        "OpenDocument flat XML presentation (.fodp)",
+        "impress_ODP_FlatXML",
+    ),
+    (
+        "application/vnd.oasis.opendocument.graphics-flat-xml",
+        "OpenDocument flat XML drawing (.fodg)",
+        "draw_ODG_FlatXML",
+    ),
+    (
+        "application/msword",
+        "Word 97-2003 document (.doc)",
+        "writer_MS_Word_97",
+    ),
+    (
+        "application/vnd.ms-excel",
+        "Excel 97-2003 workbook (.xls)",
+        "calc_MS_Excel_97",
+    ),
+    (
+        "application/vnd.ms-powerpoint",
+        "PowerPoint 97-2003 presentation (.ppt)",
+        "impress_MS_PowerPoint_97",
+    ),
+    (
+        "application/vnd.wordperfect",
+        "WordPerfect document (.wpd)",
+        "writer_WordPerfect_Document",
+    ),
+    (
+        "application/rtf",
+        "Rich text format (.rtf)",
+        "writer_Rich_Text_Format",
+    ),
+    ("application/pdf", "PDF document (.pdf)", "draw_pdf_import"),
+];
+
+/// Signature at the start of a WordPerfect document
+const WORDPERFECT_SIGNATURE: &[u8] = b"\xFFWPC";
+
+/// Number of bytes at the start of a flat XML document searched for the root
+/// element, the root follows the XML declaration and any comments
+const FLAT_XML_PREFIX_LENGTH: usize = 4096;
+
+/// Mime type reported when the format of a file could only be narrowed down
+/// to the kind of document office loaded it as
+const UNKNOWN_MIME: &str = "application/octet-stream";
+
+/// Errors that can occur while detecting the format of a file
+#[derive(Debug, Error)]
+pub enum DetectError {
+    /// File wasn't recognized and could not be loaded by office
+    #[error("format of the file could not be detected")]
+    UnknownFormat,
+}
+
+impl HttpError for DetectError {
+    fn status(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("UNKNOWN_FORMAT")
+    }
+}
+
+/// Error for files in a format whose import filter is missing from the
+/// installed office
+#[derive(Debug, Error)]
+#[error(
+    "the installed LibreOffice is missing the \"{filter}\" import filter for {format}, \
+     check that the {component} component of LibreOffice is installed"
+)]
+pub struct MissingImportFilter {
+    /// Description of the format
+    format: &'static str,
+    /// Name of the office filter type that imports the format
+    filter: &'static str,
+    /// Office component providing the filter (i.e "writer")
+    component: &'static str,
+}
+
+impl HttpError for MissingImportFilter {
+    fn status(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("MISSING_IMPORT_FILTER")
+    }
+}
+
+/// How certain the detected format is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Confidence {
+    /// Format was identified from the signature and structure of the file
+    High,
+    /// Format provided by the file name or content type matches the kind of
+    /// document office loaded the file as
+    Medium,
+    /// Format is based on the file name or content type alone or only the
+    /// kind of document is known
+    Low,
+}
+
+/// Format detected for an uploaded file
+#[derive(Debug, Serialize)]
+pub struct DetectedFormat {
+    /// Name of the office filter type used to import the format, [None] when
+    /// office doesn't support the format
+    pub name: Option<String>,
+    /// Mime type of the format
+    pub mime: String,
+    /// File extension for the format
+    pub extension: Option<String>,
+    /// Kind of document office loaded the file as, [None] when office could
+    /// not load the file
+    pub document_type: Option<DocumentKind>,
+    /// How certain the detected format is
+    pub confidence: Confidence,
+}
+
+/// Request to detect the format of a file
+#[derive(TryFromMultipart)]
+pub struct DetectRequest {
+    /// The file to detect
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+}
+
+/// POST /detect
+///
+/// Detects the format of the provided file using the file signature and the
+/// type detection office uses when loading documents, the file is loaded by
+/// office but not converted
+pub async fn detect(
+    Extension(office): Extension<Converter>,
+    Extension(details): Extension<Arc<OfficeDetails>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    TypedMultipart(DetectRequest { file }): TypedMultipart<DetectRequest>,
+) -> Result<Json<DetectedFormat>, DynHttpError> {
+    let file = decompress_file(file, max_file_size).await?;
+    let file_name = file.metadata.file_name.as_deref();
+    let hint = detect_hint(file_name, file.metadata.content_type.as_deref());
+    let signature = detect_contents(&file.contents);
+
+    let document_type = office.detect(file.contents).await?;
+
+    let (mime, confidence) = match (signature, document_type) {
+        (Some(mime), _) => (mime.to_string(), Confidence::High),
+        (None, Some(kind)) => match hint {
+            // Office loaded the file as the kind of document the hint describes
+            Some(hint) if known_format(&hint).is_some_and(|(_, value)| value == Some(kind)) => {
+                (hint, Confidence::Medium)
+            }
+            // Hint is for a format without a known kind
+            Some(hint) if known_format(&hint).is_none() => (hint, Confidence::Low),
+            _ => (fallback_mime(kind).to_string(), Confidence::Low),
+        },
+        (None, None) => match hint {
+            Some(hint) => (hint, Confidence::Low),
+            None => return Err(DetectError::UnknownFormat.into()),
+        },
+    };
+
+    let name = details
+        .filter_types
+        .as_ref()
+        .and_then(|filter_types| filter_types.get_by_mime(&mime))
+        .map(str::to_string);
+
+    let extension = format_extension(&mime, file_name);
+
+    Ok(Json(DetectedFormat {
+        name,
+        mime,
+        extension,
+        document_type,
+        confidence,
+    }))
+}
+
+/// Finds the extension and kind of document for a known format
+fn known_format(mime: &str) -> Option<(&'static str, Option<DocumentKind>)> {
+    KNOWN_FORMATS
+        .iter()
+        .find(|(value, _, _)| value.eq_ignore_ascii_case(mime))
+        .map(|(_, extension, kind)| (*extension, *kind))
+}
+
+/// File extension of a known format
+pub fn known_extension(mime: &str) -> Option<&'static str> {
+    known_format(mime).map(|(extension, _)| extension)
+}
+
+/// Checks that the installed office can import the provided format, only the
+/// formats with separately packaged import filters are checked. All formats
+/// are allowed when the filter types are not available
+pub fn check_import_filter(
+    details: &OfficeDetails,
+    mime: Option<&str>,
+) -> Result<(), MissingImportFilter> {
+    let (Some(filter_types), Some(mime)) = (&details.filter_types, mime) else {
+        return Ok(());
+    };
+
+    let Some((_, format, filter)) = IMPORT_FILTERS
+        .iter()
+        .find(|(value, _, _)| value.eq_ignore_ascii_case(mime))
+    else {
+        return Ok(());
+    };
+
+    if filter_types.values.contains_key(*filter) {
+        return Ok(());
+    }
+
+    // Filter types are named after the component providing them
+    let component = filter.split('_').next().unwrap_or(filter);
+
+    Err(MissingImportFilter {
+        format,
+        filter,
+        component,
+    })
+}
+
+/// Mime type for text formats that can only be identified by the kind of
+/// document office loaded them as, formats without a signature are plain text
+fn fallback_mime(kind: DocumentKind) -> &'static str {
+    match kind {
+        DocumentKind::Text => "text/plain",
+        DocumentKind::Spreadsheet => "text/csv",
+        _ => UNKNOWN_MIME,
+    }
+}
+
+/// Determines the file extension for the format, uses the extension of the
+/// file name when it matches the format
+fn format_extension(mime: &str, file_name: Option<&str>) -> Option<String> {
+    if let Some((extension, _)) = known_format(mime) {
+        return Some(extension.to_string());
+    }
+
+    if mime == UNKNOWN_MIME {
+        return None;
+    }
+
+    let extension = file_name
+        .map(Path::new)
+        .and_then(Path::extension)
+        .and_then(|value| value.to_str())?;
+
+    mime_guess::from_ext(extension)
+        .iter()
+        .any(|value| value.essence_str() == mime)
+        .then(|| extension.to_ascii_lowercase())
+}
+
+/// Detects the mime type of an uploaded file from its contents, falls back to
+/// the provided content type or the file name extension when the contents
+/// aren't recognized
+pub fn detect_input_format(
+    bytes: &[u8],
+    file_name: Option<&str>,
+    content_type: Option<&str>,
+) -> Option<String> {
+    if let Some(mime) = detect_contents(bytes) {
+        return Some(mime.to_string());
+    }
+
+    detect_hint(file_name, content_type)
+}
+
+/// Determines the mime type from the content type or file name extension
+/// provided with the file
+fn detect_hint(file_name: Option<&str>, content_type: Option<&str>) -> Option<String> {
+    // Generic binary content types don't describe the file
+    if let Some(content_type) =
+        content_type.filter(|value| !value.eq_ignore_ascii_case("application/octet-stream"))
+    {
+        return Some(content_type.to_string());
+    }
+
+    let path = file_name.map(Path::new)?;
+
+    if let Some(mime) = mime_guess::from_path(path).first() {
+        return Some(mime.essence_str().to_string());
+    }
+
+    // Extensions of known formats that aren't in the mime database (i.e .fodt)
+    let extension = path.extension().and_then(|value| value.to_str())?;
+    KNOWN_FORMATS
+        .iter()
+        .find(|(_, value, _)| value.eq_ignore_ascii_case(extension))
+        .map(|(mime, _, _)| mime.to_string())
+}
+
+/// Detects the mime type from the file signature and structure
+fn detect_contents(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"%PDF-") {
+        return Some("application/pdf");
+    }
+
+    if bytes.starts_with(b"{\\rtf") {
+        return Some("application/rtf");
+    }
+
+    if bytes.starts_with(WORDPERFECT_SIGNATURE) {
+        return Some("application/vnd.wordperfect");
+    }
+
+    if bytes.starts_with(ZIP_SIGNATURE) {
+        return detect_zip(bytes);
+    }
+
+    if bytes.starts_with(CFB_SIGNATURE) {
+        return detect_cfb(bytes);
+    }
+
+    if let Some(mime) = detect_flat_xml(bytes) {
+        return Some(mime);
+    }
+
+    let image_type = imagesize::image_type(bytes).ok()?;
+    Some(match image_type {
+        ImageType::Png => "image/png",
+        ImageType::Jpeg => "image/jpeg",
+        ImageType::Gif => "image/gif",
+        ImageType::Bmp => "image/bmp",
+        ImageType::Tiff => "image/tiff",
+        ImageType::Webp => "image/webp",
+        _ => return None,
+    })
+}
+
+/// Detects Office Open XML and OpenDocument files from the parts of the archive
+fn detect_zip(bytes: &[u8]) -> Option<&'static str> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).ok()?;
+    detect_archive(&mut archive)
+}
+
+/// Detects the mime type of an opened Office Open XML or OpenDocument archive
+pub fn detect_archive<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<&'static str> {
+    if archive.index_for_name("word/document.xml").is_some() {
+        return Some("application/vnd.openxmlformats-officedocument.wordprocessingml.document");
+    }
+
+    if archive.index_for_name("xl/workbook.xml").is_some() {
+        return Some("application/vnd.openxmlformats-officedocument.spreadsheetml.sheet");
+    }
+
+    if archive.index_for_name("ppt/presentation.xml").is_some() {
+        return Some("application/vnd.openxmlformats-officedocument.presentationml.presentation");
+    }
+
+    // OpenDocument files store their mime type uncompressed as the first entry
+    let mut mimetype = String::new();
+    archive
+        .by_name("mimetype")
+        .ok()?
+        .take(MAX_MIMETYPE_LENGTH)
+        .read_to_string(&mut mimetype)
+        .ok()?;
+
+    [
+        "application/vnd.oasis.opendocument.text",
+        "application/vnd.oasis.opendocument.spreadsheet",
+        "application/vnd.oasis.opendocument.presentation",
+        "application/vnd.oasis.opendocument.graphics",
+    ]
+    .into_iter()
+    .find(|value| mimetype.trim() == *value)
+}
+
+/// Detects OpenDocument flat XML files from the mime type on the root
+/// element of the document
+fn detect_flat_xml(bytes: &[u8]) -> Option<&'static str> {
+    let prefix = &bytes[..bytes.len().min(FLAT_XML_PREFIX_LENGTH)];
+    let prefix = prefix.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(prefix);
+
+    if !prefix.starts_with(b"<?xml") {
+        return None;
+    }
+
+    // Prefix may end part way through a character
+    let prefix = match std::str::from_utf8(prefix) {
+        Ok(value) => value,
+        Err(err) => std::str::from_utf8(&prefix[..err.valid_up_to()]).ok()?,
+    };
+
+    let root = find_tag(prefix, "office:document", 0)?;
+    let mimetype = get_attribute(&root.attributes, "office:mimetype")?;
+
+    [
+        (
+            "application/vnd.oasis.opendocument.text",
+            "application/vnd.oasis.opendocument.text-flat-xml",
+        ),
+        (
+            "application/vnd.oasis.opendocument.spreadsheet",
+            "application/vnd.oasis.opendocument.spreadsheet-flat-xml",
+        ),
+        (
+            "application/vnd.oasis.opendocument.presentation",
+            "application/vnd.oasis.opendocument.presentation-flat-xml",
+        ),
+        (
+            "application/vnd.oasis.opendocument.graphics",
+            "application/vnd.oasis.opendocument.graphics-flat-xml",
+        ),
+    ]
+    .into_iter()
+    .find(|(value, _)| mimetype.trim() == *value)
+    .map(|(_, mime)| mime)
+}
+
+/// Detects legacy Office files and Outlook messages from the streams of the compound file
+fn detect_cfb(bytes: &[u8]) -> Option<&'static str> {
+    let file = CompoundFile::open(Cursor::new(bytes)).ok()?;
+
+    if file.is_stream("/WordDocument") {
+        return Some("application/msword");
+    }
+
+    if file.is_stream("/Workbook") || file.is_stream("/Book") {
+        return Some("application/vnd.ms-excel");
+    }
+
+    if file.is_stream("/PowerPoint Document") {
+        return Some("application/vnd.ms-powerpoint");
+    }
+
+    // Outlook messages store their properties in substorage streams
+    if file.is_stream("/__properties_version1.0") {
+        return Some("application/vnd.ms-outlook");
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_import_filter, detect_contents};
+    use crate::error::HttpError;
+    use axum::http::StatusCode;
+    use cfb::CompoundFile;
+    use office_convert_core::OfficeDetails;
+    use std::io::{Cursor, Write};
+
+    /// Creates a flat XML document with the provided mime type on its root element
+    fn flat_xml(mimetype: &str) -> Vec<u8> {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!-- Comment before the root element -->\n\
+             <office:document xmlns:office=\"urn:oasis:names:tc:opendocument:xmlns:office:1.0\" \
+             office:version=\"1.3\" office:mimetype=\"{mimetype}\"></office:document>"
+        )
+        .into_bytes()
+    }
+
+    /// Creates a compound file containing a stream with the provided name
+    fn compound_file(stream: &str) -> Vec<u8> {
+        let mut file = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        file.create_stream(stream)
+            .unwrap()
+            .write_all(b"contents")
+            .unwrap();
+        file.flush().unwrap();
+        file.into_inner().into_inner()
+    }
+
+    /// Creates office details with filter types for the provided filters
+    fn office_details(filters: &[(&str, &str)]) -> OfficeDetails {
+        let filter_types = filters
+            .iter()
+            .map(|(name, mime)| (name.to_string(), serde_json::json!({ "MediaType": mime })))
+            .collect::<serde_json::Map<_, _>>();
+
+        OfficeDetails {
+            filter_types: Some(serde_json::from_value(filter_types.into()).unwrap()),
+            version: None,
+        }
+    }
+
+    #[test]
+    fn detects_flat_xml() {
+        for (mimetype, expected) in [
+            (
+                "application/vnd.oasis.opendocument.text",
+                "application/vnd.oasis.opendocument.text-flat-xml",
+            ),
+            (
+                "application/vnd.oasis.opendocument.spreadsheet",
+                "application/vnd.oasis.opendocument.spreadsheet-flat-xml",
+            ),
+            (
+                "application/vnd.oasis.opendocument.presentation",
+                "application/vnd.oasis.opendocument.presentation-flat-xml",
+            ),
+        ] {
+            assert_eq!(detect_contents(&flat_xml(mimetype)), Some(expected));
+        }
+    }
+
+    #[test]
+    fn detects_flat_xml_with_byte_order_mark() {
+        let mut bytes = b"\xEF\xBB\xBF".to_vec();
+        bytes.extend(flat_xml("application/vnd.oasis.opendocument.text"));
+
+        assert_eq!(
+            detect_contents(&bytes),
+            Some("application/vnd.oasis.opendocument.text-flat-xml")
+        );
+    }
+
+    #[test]
+    fn ignores_other_xml() {
+        let bytes = b"<?xml version=\"1.0\"?><root office:mimetype=\"text/plain\"/>";
+        assert_eq!(detect_contents(bytes), None);
+    }
+
+    #[test]
+    fn detects_legacy_office() {
+        for (stream, expected) in [
+            ("/WordDocument", "application/msword"),
+            ("/Workbook", "application/vnd.ms-excel"),
+            ("/Book", "application/vnd.ms-excel"),
+            ("/PowerPoint Document", "application/vnd.ms-powerpoint"),
+            ("/__properties_version1.0", "application/vnd.ms-outlook"),
+        ] {
+            assert_eq!(detect_contents(&compound_file(stream)), Some(expected));
+        }
+    }
+
+    #[test]
+    fn ignores_unknown_compound_files() {
+        assert_eq!(detect_contents(&compound_file("/Unknown")), None);
+    }
+
+    #[test]
+    fn detects_wordperfect() {
+        assert_eq!(
+            detect_contents(b"\xFFWPC\x10\x00\x00\x00\x01\x0A"),
+            Some("application/vnd.wordperfect")
+        );
+    }
+
+    #[test]
+    fn detects_rtf() {
+        assert_eq!(
+            detect_contents(b"{\\rtf1\\ansi Hello}"),
+            Some("application/rtf")
+        );
+    }
+
+    #[test]
+    fn missing_import_filter_is_reported() {
+        let details = office_details(&[("writer8", "application/vnd.oasis.opendocument.text")]);
+
+        let err = check_import_filter(&details, Some("application/vnd.wordperfect")).unwrap_err();
+
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.code(), Some("MISSING_IMPORT_FILTER"));
+        assert_eq!(
+            err.to_string(),
+            "the installed LibreOffice is missing the \"writer_WordPerfect_Document\" import \
+             filter for WordPerfect document (.wpd), check that the writer component of \
+             LibreOffice is installed"
+        );
+    }
+
+    #[test]
+    fn installed_import_filter_is_allowed() {
+        let details =
+            office_details(&[("writer_WordPerfect_Document", "application/vnd.wordperfect")]);
+
+        assert!(check_import_filter(&details, Some("application/vnd.wordperfect")).is_ok());
+    }
+
+    #[test]
+    fn import_filters_are_not_checked_without_filter_types() {
+        let details = OfficeDetails {
+            filter_types: None,
+            version: None,
+        };
+
+        assert!(check_import_filter(&details, Some("application/vnd.wordperfect")).is_ok());
+        assert!(check_import_filter(&office_details(&[]), None).is_ok());
+    }
+}
@@ -0,0 +1,132 @@
+use crate::error::HttpError;
+use axum::http::{HeaderValue, StatusCode};
+use thiserror::Error;
+
+/// Maximum length in bytes of the file name of the converted file
+const MAX_FILE_NAME_LENGTH: usize = 255;
+
+/// File name used for the converted file in multipart responses when the
+/// request has no file name to derive one from
+pub const DEFAULT_OUTPUT_FILE_NAME: &str = "converted.pdf";
+
+/// Extension of the converted file
+const PDF_EXTENSION: &str = ".pdf";
+
+/// Output filename provided with a request was invalid
+#[derive(Debug, Error)]
+#[error("invalid output filename: {0}")]
+pub struct InvalidOutputFilename(&'static str);
+
+impl HttpError for InvalidOutputFilename {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("INVALID_OUTPUT_FILENAME")
+    }
+}
+
+/// Picks the file name of the converted file, the requested name is used when
+/// provided otherwise the name of the input file with its extension replaced by
+/// ".pdf". Provides [None] when neither name is available
+pub fn output_file_name(
+    requested: Option<&str>,
+    input: Option<&str>,
+) -> Result<Option<String>, InvalidOutputFilename> {
+    if let Some(requested) = requested {
+        let name = sanitize_file_name(requested);
+
+        if name.is_empty() {
+            return Err(InvalidOutputFilename("must contain a file name"));
+        }
+
+        if name.len() > MAX_FILE_NAME_LENGTH {
+            return Err(InvalidOutputFilename("must be at most 255 bytes"));
+        }
+
+        return Ok(Some(name));
+    }
+
+    let Some(input) = input
+        .map(sanitize_file_name)
+        .filter(|name| !name.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    let stem = match input.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => &input,
+    };
+
+    // Long input names are shortened to fit the extension
+    let mut length = stem.len().min(MAX_FILE_NAME_LENGTH - PDF_EXTENSION.len());
+    while !stem.is_char_boundary(length) {
+        length -= 1;
+    }
+
+    Ok(Some(format!("{}{PDF_EXTENSION}", &stem[..length])))
+}
+
+/// Removes the directories (i.e "../../report.pdf") and control characters
+/// from a file name, names only made of dots are removed entirely
+fn sanitize_file_name(name: &str) -> String {
+    let name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let name: String = name
+        .chars()
+        .filter(|character| !character.is_control())
+        .collect();
+    let name = name.trim();
+
+    if name.chars().all(|character| character == '.') {
+        return String::new();
+    }
+
+    name.to_string()
+}
+
+/// Creates the filename parameters of a Content-Disposition header (RFC 6266),
+/// names that aren't plain ASCII are provided using the extended "filename*"
+/// parameter along with an ASCII fallback for older clients
+pub fn file_name_parameters(file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|character| match character {
+            '"' | '\\' => '_',
+            character if character.is_ascii() => character,
+            _ => '_',
+        })
+        .collect();
+
+    if fallback == file_name {
+        return format!("filename=\"{fallback}\"");
+    }
+
+    format!(
+        "filename=\"{fallback}\"; filename*=UTF-8''{}",
+        percent_encode(file_name)
+    )
+}
+
+/// Creates an attachment Content-Disposition header for the file name
+pub fn content_disposition(file_name: &str) -> HeaderValue {
+    HeaderValue::from_str(&format!("attachment; {}", file_name_parameters(file_name)))
+        .unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+/// Percent encodes the characters of a value that aren't allowed in an
+/// extended parameter value (RFC 8187 "attr-char")
+fn percent_encode(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            output.push(byte as char);
+        } else {
+            output.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    output
+}
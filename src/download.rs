@@ -0,0 +1,205 @@
+use crate::{
+    base_path::BasePath,
+    error::{DynHttpError, HttpError},
+    jobs::{result_etag, JobError, JobStatus, JobStore},
+    range::range_response,
+    tenant::Tenant,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    Extension, Json,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::Sha256;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Signs and verifies the tokens used by download links, a token is the
+/// job ID and expiry time followed by a HMAC-SHA256 signature of both
+/// ("{id}.{expires_at}.{signature}")
+pub struct DownloadSigner {
+    /// Key used for signing
+    key: Vec<u8>,
+    /// Time download links are valid for
+    ttl: Duration,
+}
+
+/// Errors that can occur when using a download link
+#[derive(Debug, Error)]
+pub enum DownloadError {
+    /// Token was malformed or its signature didn't match
+    #[error("invalid download link")]
+    InvalidToken,
+
+    /// Token was valid but has expired
+    #[error("download link has expired")]
+    Expired,
+}
+
+impl HttpError for DownloadError {
+    fn status(&self) -> StatusCode {
+        match self {
+            DownloadError::InvalidToken => StatusCode::FORBIDDEN,
+            DownloadError::Expired => StatusCode::GONE,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            DownloadError::InvalidToken => Some("INVALID_DOWNLOAD_LINK"),
+            DownloadError::Expired => Some("DOWNLOAD_LINK_EXPIRED"),
+        }
+    }
+}
+
+/// Gets the current unix timestamp in seconds
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
+
+impl DownloadSigner {
+    /// Creates a new signer using the provided secret, when no secret is
+    /// provided a random one is generated and links will stop working
+    /// when the server restarts
+    pub fn new(secret: Option<String>, ttl: Duration) -> Self {
+        let key = match secret {
+            Some(secret) => secret.into_bytes(),
+            None => {
+                let mut key = vec![0; 32];
+                rand::thread_rng().fill_bytes(&mut key);
+                key
+            }
+        };
+
+        Self { key, ttl }
+    }
+
+    /// Creates a MAC over the provided job ID and expiry time
+    fn mac(&self, id: &str, expires_at: u64) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.key).expect("hmac accepts keys of any length");
+        mac.update(id.as_bytes());
+        mac.update(b".");
+        mac.update(expires_at.to_string().as_bytes());
+        mac
+    }
+
+    /// Creates a token for downloading the result of the provided job,
+    /// provides the token and the unix timestamp it expires at
+    pub fn sign(&self, id: &str) -> (String, u64) {
+        let expires_at = now_secs() + self.ttl.as_secs();
+        let signature = self.mac(id, expires_at).finalize().into_bytes();
+        let signature = URL_SAFE_NO_PAD.encode(signature);
+
+        (format!("{id}.{expires_at}.{signature}"), expires_at)
+    }
+
+    /// Verifies the provided token providing the ID of the job it is for
+    pub fn verify<'a>(&self, token: &'a str) -> Result<&'a str, DownloadError> {
+        let mut parts = token.splitn(3, '.');
+        let (id, expires_at, signature) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(expires_at), Some(signature)) => (id, expires_at, signature),
+            _ => return Err(DownloadError::InvalidToken),
+        };
+
+        let expires_at: u64 = expires_at
+            .parse()
+            .map_err(|_| DownloadError::InvalidToken)?;
+        let signature = URL_SAFE_NO_PAD
+            .decode(signature)
+            .map_err(|_| DownloadError::InvalidToken)?;
+
+        // Signature is checked in constant time
+        self.mac(id, expires_at)
+            .verify_slice(&signature)
+            .map_err(|_| DownloadError::InvalidToken)?;
+
+        if now_secs() >= expires_at {
+            return Err(DownloadError::Expired);
+        }
+
+        Ok(id)
+    }
+}
+
+#[derive(Serialize)]
+pub struct DownloadUrlResponse {
+    /// Path to download the result from, relative to the server address
+    url: String,
+    /// Unix timestamp in seconds of when the link expires
+    expires_at: u64,
+}
+
+/// POST /jobs/:id/download-url
+///
+/// Creates a time limited link for downloading the result of a completed
+/// job, the link can be used without providing an API key
+pub async fn create_download_url(
+    Extension(store): Extension<Arc<JobStore>>,
+    Extension(signer): Extension<Arc<DownloadSigner>>,
+    Extension(base_path): Extension<BasePath>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<Json<DownloadUrlResponse>, DynHttpError> {
+    let tenant = tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str());
+    let metadata = store.get(&id, tenant).ok_or(JobError::NotFound)?;
+
+    match metadata.status {
+        JobStatus::Completed => {}
+        JobStatus::Failed => {
+            return Err(JobError::Failed(metadata.error.unwrap_or_default()).into());
+        }
+        JobStatus::Cancelled => return Err(JobError::Cancelled.into()),
+        _ => return Err(JobError::NotFinished.into()),
+    }
+
+    let (token, expires_at) = signer.sign(&metadata.id);
+
+    Ok(Json(DownloadUrlResponse {
+        url: base_path.url(&format!("/download/{token}")),
+        expires_at,
+    }))
+}
+
+/// GET /download/:token
+///
+/// Provides the converted PDF file for the job the signed token is for,
+/// supports range requests for resuming interrupted downloads
+pub async fn download(
+    Extension(store): Extension<Arc<JobStore>>,
+    Extension(signer): Extension<Arc<DownloadSigner>>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    let id = signer.verify(&token)?;
+
+    // The signature proves access to the job so the tenant isn't checked
+    let bytes = store.result_unchecked(id).await?;
+    let (content_type, extension) = store.result_type(id);
+
+    let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{id}.{extension}\""))
+        .context("invalid content disposition")?;
+
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, HeaderValue::from_static(content_type))
+        .header(header::CONTENT_DISPOSITION, disposition);
+
+    let response = range_response(&headers, builder, bytes, result_etag(id)?)?;
+    Ok(response)
+}
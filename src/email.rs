@@ -0,0 +1,419 @@
+use crate::{error::HttpError, html::remove_external_references, pdf::merge_pdfs};
+use anyhow::Context;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use cfb::CompoundFile;
+use mail_parser::{Address, MessageParser, MimeHeaders};
+use office_convert_core::{ConvertError, Converter, OfficeDetails, PdfExportOptions};
+use std::{
+    io::{Cursor, Read},
+    path::Path,
+};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Email file formats that can be converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailFormat {
+    /// RFC 822 email message (.eml)
+    Eml,
+    /// Outlook email message (.msg)
+    Msg,
+}
+
+impl EmailFormat {
+    /// Determines the email format from the file name or content type of the
+    /// uploaded file, emails cannot be reliably detected from their contents
+    /// alone so one of these must be provided
+    pub fn detect(file_name: Option<&str>, content_type: Option<&str>) -> Option<Self> {
+        let extension = file_name
+            .and_then(|value| Path::new(value).extension())
+            .map(|value| value.to_string_lossy().to_ascii_lowercase());
+
+        match (extension.as_deref(), content_type) {
+            (Some("eml"), _) | (_, Some("message/rfc822")) => Some(EmailFormat::Eml),
+            (Some("msg"), _) | (_, Some("application/vnd.ms-outlook")) => Some(EmailFormat::Msg),
+            _ => None,
+        }
+    }
+}
+
+/// Errors that can occur while reading an email
+#[derive(Debug, Error)]
+pub enum EmailError {
+    /// EML file could not be parsed
+    #[error("file is not a valid eml email")]
+    InvalidEml,
+
+    /// MSG file could not be read
+    #[error("file is not a valid msg email: {0}")]
+    InvalidMsg(#[from] std::io::Error),
+}
+
+impl HttpError for EmailError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// Email contents extracted from an email file
+#[derive(Debug, Default)]
+pub struct Email {
+    subject: Option<String>,
+    from: Option<String>,
+    to: Option<String>,
+    cc: Option<String>,
+    date: Option<String>,
+    body: EmailBody,
+    attachments: Vec<EmailAttachment>,
+}
+
+/// Body of an email message
+#[derive(Debug, Default)]
+enum EmailBody {
+    Html(String),
+    Text(String),
+    #[default]
+    Empty,
+}
+
+/// File attached to an email
+#[derive(Debug)]
+struct EmailAttachment {
+    /// File name of the attachment
+    name: String,
+    /// Contents of the attachment
+    bytes: Bytes,
+}
+
+/// Reads an email from the provided file bytes
+pub fn parse_email(format: EmailFormat, bytes: &[u8]) -> Result<Email, EmailError> {
+    match format {
+        EmailFormat::Eml => parse_eml(bytes),
+        EmailFormat::Msg => parse_msg(bytes),
+    }
+}
+
+/// Converts the email into a PDF, rendering the email headers and body with
+/// the converted attachments optionally appended as additional pages. The
+/// email and each attachment are converted using the provided export options,
+/// the merged PDF is checked against the output limits of the converter
+pub async fn convert_email(
+    office: &Converter,
+    details: &OfficeDetails,
+    email: Email,
+    include_attachments: bool,
+    export_options: PdfExportOptions,
+) -> Result<Bytes, ConvertError> {
+    let document = render_email(&email);
+    let converted = office
+        .convert_with_options(Bytes::from(document), export_options.clone())
+        .await?;
+
+    if !include_attachments || email.attachments.is_empty() {
+        return Ok(converted);
+    }
+
+    let mut documents = vec![converted];
+
+    for attachment in email.attachments {
+        if !details.is_supported_path(Path::new(&attachment.name)) {
+            debug!(name = %attachment.name, "skipping unsupported email attachment");
+            continue;
+        }
+
+        match office
+            .convert_with_options(attachment.bytes, export_options.clone())
+            .await
+        {
+            Ok(value) => documents.push(value),
+            Err(cause) => {
+                warn!(%cause, name = %attachment.name, "failed to convert email attachment")
+            }
+        }
+    }
+
+    if documents.len() == 1 {
+        return Ok(documents.remove(0));
+    }
+
+    let merged = tokio::task::spawn_blocking(move || merge_pdfs(documents))
+        .await
+        .context("failed to merge attachments")??;
+
+    office.check_output_limits(&merged).await?;
+
+    Ok(merged)
+}
+
+/// Reads an RFC 822 email message
+fn parse_eml(bytes: &[u8]) -> Result<Email, EmailError> {
+    let message = MessageParser::default()
+        .parse(bytes)
+        .ok_or(EmailError::InvalidEml)?;
+
+    let body = if let Some(html) = message.body_html(0) {
+        EmailBody::Html(html.into_owned())
+    } else if let Some(text) = message.body_text(0) {
+        EmailBody::Text(text.into_owned())
+    } else {
+        EmailBody::Empty
+    };
+
+    let attachments = message
+        .attachments()
+        .enumerate()
+        .map(|(index, part)| EmailAttachment {
+            name: part
+                .attachment_name()
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("attachment-{}", index + 1)),
+            bytes: Bytes::copy_from_slice(part.contents()),
+        })
+        .collect();
+
+    Ok(Email {
+        subject: message.subject().map(str::to_string),
+        from: message.from().map(format_addresses),
+        to: message.to().map(format_addresses),
+        cc: message.cc().map(format_addresses),
+        date: message.date().map(|value| value.to_rfc822()),
+        body,
+        attachments,
+    })
+}
+
+/// Formats a list of email addresses for display
+fn format_addresses(address: &Address<'_>) -> String {
+    address
+        .iter()
+        .map(|addr| match (&addr.name, &addr.address) {
+            (Some(name), Some(address)) => format!("{name} <{address}>"),
+            (Some(value), None) | (None, Some(value)) => value.to_string(),
+            (None, None) => String::new(),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Property IDs of the MSG properties that are read
+mod msg_property {
+    pub const SUBJECT: &str = "0037";
+    pub const CLIENT_SUBMIT_TIME: u32 = 0x0039;
+    pub const SENDER_NAME: &str = "0C1A";
+    pub const SENDER_EMAIL: &str = "0C1F";
+    pub const DISPLAY_CC: &str = "0E03";
+    pub const DISPLAY_TO: &str = "0E04";
+    pub const BODY: &str = "1000";
+    pub const BODY_HTML: &str = "1013";
+    pub const ATTACH_DATA: &str = "3701";
+    pub const ATTACH_FILENAME: &str = "3704";
+    pub const ATTACH_LONG_FILENAME: &str = "3707";
+}
+
+/// Reads an Outlook MSG email message (Compound File Binary format)
+fn parse_msg(bytes: &[u8]) -> Result<Email, EmailError> {
+    let mut file = CompoundFile::open(Cursor::new(bytes))?;
+
+    let sender_name = read_msg_string(&mut file, "", msg_property::SENDER_NAME);
+    let sender_email = read_msg_string(&mut file, "", msg_property::SENDER_EMAIL);
+    let from = match (sender_name, sender_email) {
+        (Some(name), Some(email)) => Some(format!("{name} <{email}>")),
+        (name, email) => name.or(email),
+    };
+
+    let body = if let Some(html) = read_msg_binary(&mut file, "", msg_property::BODY_HTML)
+        .map(|value| String::from_utf8_lossy(&value).into_owned())
+        .or_else(|| read_msg_string(&mut file, "", msg_property::BODY_HTML))
+    {
+        EmailBody::Html(html)
+    } else if let Some(text) = read_msg_string(&mut file, "", msg_property::BODY) {
+        EmailBody::Text(text)
+    } else {
+        EmailBody::Empty
+    };
+
+    // Attachments are stored in their own storages
+    let attachment_storages: Vec<String> = file
+        .read_root_storage()
+        .filter(|entry| entry.is_storage() && entry.name().starts_with("__attach_version1.0_"))
+        .map(|entry| entry.name().to_string())
+        .collect();
+
+    let mut attachments = Vec::new();
+
+    for (index, storage) in attachment_storages.iter().enumerate() {
+        // Attachments without binary data are embedded messages or OLE objects
+        let bytes = match read_msg_binary(&mut file, storage, msg_property::ATTACH_DATA) {
+            Some(value) => value,
+            None => continue,
+        };
+
+        let name = read_msg_string(&mut file, storage, msg_property::ATTACH_LONG_FILENAME)
+            .or_else(|| read_msg_string(&mut file, storage, msg_property::ATTACH_FILENAME))
+            .unwrap_or_else(|| format!("attachment-{}", index + 1));
+
+        attachments.push(EmailAttachment {
+            name,
+            bytes: Bytes::from(bytes),
+        });
+    }
+
+    Ok(Email {
+        subject: read_msg_string(&mut file, "", msg_property::SUBJECT),
+        from,
+        to: read_msg_string(&mut file, "", msg_property::DISPLAY_TO),
+        cc: read_msg_string(&mut file, "", msg_property::DISPLAY_CC),
+        date: read_msg_date(&mut file),
+        body,
+        attachments,
+    })
+}
+
+/// Reads a property stream from the MSG file
+fn read_msg_stream(
+    file: &mut CompoundFile<Cursor<&[u8]>>,
+    storage: &str,
+    name: &str,
+) -> Option<Vec<u8>> {
+    let mut stream = file.open_stream(format!("/{storage}/{name}")).ok()?;
+    let mut buffer = Vec::new();
+    stream.read_to_end(&mut buffer).ok()?;
+    Some(buffer)
+}
+
+/// Reads a binary property from the MSG file
+fn read_msg_binary(
+    file: &mut CompoundFile<Cursor<&[u8]>>,
+    storage: &str,
+    property: &str,
+) -> Option<Vec<u8>> {
+    read_msg_stream(file, storage, &format!("__substg1.0_{property}0102"))
+}
+
+/// Reads a string property from the MSG file, strings are either stored
+/// as UTF-16 (type 001F) or as 8-bit strings (type 001E)
+fn read_msg_string(
+    file: &mut CompoundFile<Cursor<&[u8]>>,
+    storage: &str,
+    property: &str,
+) -> Option<String> {
+    let value = if let Some(value) =
+        read_msg_stream(file, storage, &format!("__substg1.0_{property}001F"))
+    {
+        let units: Vec<u16> = value
+            .chunks_exact(2)
+            .map(|chunk| u16::from_le_bytes([chunk[0], chunk[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        let value = read_msg_stream(file, storage, &format!("__substg1.0_{property}001E"))?;
+        String::from_utf8_lossy(&value).into_owned()
+    };
+
+    let value = value.trim_end_matches('\0');
+    if value.is_empty() {
+        return None;
+    }
+
+    Some(value.to_string())
+}
+
+/// Reads the sent date of the MSG file from the fixed length properties stream
+fn read_msg_date(file: &mut CompoundFile<Cursor<&[u8]>>) -> Option<String> {
+    /// Size of the header before the properties in the top level properties stream
+    const HEADER_SIZE: usize = 32;
+    /// Type of a FILETIME property
+    const TYPE_TIME: u32 = 0x0040;
+    /// Seconds between the FILETIME epoch (1601) and the unix epoch
+    const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+    let properties = read_msg_stream(file, "", "__properties_version1.0")?;
+
+    properties
+        .get(HEADER_SIZE..)?
+        .chunks_exact(16)
+        .find_map(|entry| {
+            let tag = u32::from_le_bytes(entry[0..4].try_into().ok()?);
+            if tag != (msg_property::CLIENT_SUBMIT_TIME << 16 | TYPE_TIME) {
+                return None;
+            }
+
+            // FILETIME is the number of 100ns intervals since 1601
+            let filetime = u64::from_le_bytes(entry[8..16].try_into().ok()?);
+            let timestamp = (filetime / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+            Some(mail_parser::DateTime::from_timestamp(timestamp).to_rfc822())
+        })
+}
+
+/// Renders the email as a HTML document for conversion
+fn render_email(email: &Email) -> String {
+    let mut html =
+        String::from("<!DOCTYPE html><html><head><meta charset=\"utf-8\"></head><body><table>");
+
+    let attachments = email
+        .attachments
+        .iter()
+        .map(|attachment| attachment.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let headers = [
+        ("From", email.from.as_deref()),
+        ("To", email.to.as_deref()),
+        ("Cc", email.cc.as_deref()),
+        ("Date", email.date.as_deref()),
+        ("Subject", email.subject.as_deref()),
+        (
+            "Attachments",
+            Some(attachments.as_str()).filter(|value| !value.is_empty()),
+        ),
+    ];
+
+    for (name, value) in headers {
+        if let Some(value) = value {
+            html.push_str(&format!(
+                "<tr><th align=\"left\" valign=\"top\">{name}:</th><td>{}</td></tr>",
+                html_escape::encode_text(value)
+            ));
+        }
+    }
+
+    html.push_str("</table><hr>");
+
+    match &email.body {
+        // Office would otherwise load the remote images and frames of the email
+        EmailBody::Html(value) => {
+            html.push_str(&remove_external_references(html_body_contents(value)))
+        }
+        EmailBody::Text(value) => {
+            html.push_str("<pre style=\"white-space: pre-wrap; font-family: inherit\">");
+            html.push_str(&html_escape::encode_text(value));
+            html.push_str("</pre>");
+        }
+        EmailBody::Empty => {}
+    }
+
+    html.push_str("</body></html>");
+    html
+}
+
+/// Extracts the contents of the body element from a HTML document so it
+/// can be embedded in the rendered email, documents without a body
+/// element are used as-is
+fn html_body_contents(html: &str) -> &str {
+    // ASCII lowercase keeps the same byte offsets as the original
+    let lowercase = html.to_ascii_lowercase();
+
+    let start = lowercase
+        .find("<body")
+        .and_then(|start| lowercase[start..].find('>').map(|end| start + end + 1))
+        .unwrap_or(0);
+
+    let end = lowercase
+        .rfind("</body")
+        .filter(|end| *end >= start)
+        .unwrap_or(html.len());
+
+    &html[start..end]
+}
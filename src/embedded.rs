@@ -0,0 +1,225 @@
+use crate::{
+    archive::{open_archive, read_part},
+    detect::{detect_archive, known_extension},
+    xml::{find_tag, get_attribute, unescape},
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{Read, Seek},
+};
+use zip::ZipArchive;
+
+/// Directory OpenDocument stores the replacement images of objects in
+const ODF_REPLACEMENTS_DIR: &str = "ObjectReplacements/";
+
+/// Whether office draws embedded objects using their replacement images,
+/// objects without a replacement image are reported when enabled
+#[derive(Debug, Clone, Copy)]
+pub struct PreferReplacementImages(pub bool);
+
+/// Document formats that embedded objects can be read from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DocumentFormat {
+    /// Word document (.docx)
+    Docx,
+    /// PowerPoint presentation (.pptx)
+    Pptx,
+    /// OpenDocument text, spreadsheet, presentation or drawing
+    OpenDocument,
+}
+
+impl DocumentFormat {
+    /// Detects the format of the document archive
+    fn detect<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<Self> {
+        match known_extension(detect_archive(archive)?)? {
+            "docx" => Some(DocumentFormat::Docx),
+            "pptx" => Some(DocumentFormat::Pptx),
+            "odt" | "ods" | "odp" | "odg" => Some(DocumentFormat::OpenDocument),
+            _ => None,
+        }
+    }
+
+    /// Whether the part of the document can contain embedded objects
+    fn has_objects(&self, name: &str) -> bool {
+        match self {
+            DocumentFormat::Docx => name.starts_with("word/") && name.ends_with(".xml"),
+            DocumentFormat::Pptx => name.starts_with("ppt/") && name.ends_with(".xml"),
+            DocumentFormat::OpenDocument => name == "content.xml" || name == "styles.xml",
+        }
+    }
+}
+
+/// Embedded object found within a document part
+struct EmbeddedObject {
+    /// Name of the object (i.e the ProgID "Visio.Drawing.15")
+    name: String,
+    /// Whether the object has a replacement image office can draw
+    has_image: bool,
+}
+
+/// Creates a warning listing the embedded objects within the document that
+/// don't have a replacement image, office draws these as placeholders when it
+/// can't convert them. [None] when every object has a replacement image
+pub fn missing_replacement_images_warning(input: &[u8]) -> Option<String> {
+    let missing = missing_replacement_images(input);
+    if missing.is_empty() {
+        return None;
+    }
+
+    let missing: Vec<String> = missing
+        .into_iter()
+        .map(|(name, count)| match count {
+            1 => name,
+            count => format!("{name} ({count})"),
+        })
+        .collect();
+
+    Some(format!(
+        "embedded objects without a replacement image could not be rendered: {}",
+        missing.join(", ")
+    ))
+}
+
+/// Finds the embedded objects without a replacement image along with the
+/// number of each, objects can be read from Word, PowerPoint and OpenDocument
+/// documents, other files provide no objects
+fn missing_replacement_images(input: &[u8]) -> BTreeMap<String, usize> {
+    let mut missing = BTreeMap::new();
+
+    let mut archive = match open_archive(input) {
+        Some(value) => value,
+        None => return missing,
+    };
+
+    let format = match DocumentFormat::detect(&mut archive) {
+        Some(value) => value,
+        None => return missing,
+    };
+
+    // Objects are keyed by part and relationship, alternate content repeats
+    // the same object and only one of the alternatives needs an image
+    let mut objects: HashMap<(String, String), EmbeddedObject> = HashMap::new();
+
+    let parts: Vec<String> = archive
+        .file_names()
+        .filter(|name| format.has_objects(name))
+        .map(str::to_string)
+        .collect();
+
+    for part in parts {
+        // Parts that can't be read (i.e too large) are skipped
+        let Ok(xml) = read_part(&mut archive, &part) else {
+            continue;
+        };
+
+        let found = match format {
+            DocumentFormat::Docx => ooxml_objects(&xml, "w:object", "o:OLEObject", "ProgID"),
+            DocumentFormat::Pptx => ooxml_objects(&xml, "p:oleObj", "p:oleObj", "progId"),
+            DocumentFormat::OpenDocument => odf_objects(&xml, &archive),
+        };
+
+        for (key, object) in found {
+            objects
+                .entry((part.clone(), key))
+                .and_modify(|existing| existing.has_image |= object.has_image)
+                .or_insert(object);
+        }
+    }
+
+    for object in objects.into_values().filter(|object| !object.has_image) {
+        *missing.entry(object.name).or_insert(0) += 1;
+    }
+
+    missing
+}
+
+/// Finds the OLE objects within a Word or PowerPoint part, the name of each
+/// object is read from the provided attribute of the OLE tag. Objects have a
+/// replacement image when the element contains a picture or a legacy drawing
+/// shape
+fn ooxml_objects(
+    xml: &str,
+    element: &str,
+    ole_tag: &str,
+    name_attribute: &str,
+) -> Vec<(String, EmbeddedObject)> {
+    let mut objects = Vec::new();
+    let closing = format!("</{element}>");
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, element, position) {
+        let content = match tag.self_closing {
+            true => "",
+            false => match xml[tag.end..].find(&closing) {
+                Some(end) => &xml[tag.end..tag.end + end],
+                None => &xml[tag.end..],
+            },
+        };
+        position = tag.end + content.len();
+
+        // Word wraps the OLE tag in the object element, PowerPoint uses the
+        // same element for both
+        let ole_attributes = match element == ole_tag {
+            true => Some(tag.attributes),
+            false => find_tag(content, ole_tag, 0).map(|tag| tag.attributes),
+        };
+
+        let Some(attributes) = ole_attributes else {
+            continue;
+        };
+
+        let key = match get_attribute(&attributes, "r:id") {
+            Some(value) => value.to_string(),
+            None => format!("@{}", objects.len()),
+        };
+
+        let name = get_attribute(&attributes, name_attribute)
+            .map(unescape)
+            .filter(|value| !value.trim().is_empty())
+            .unwrap_or_else(|| "unknown object".to_string());
+
+        let has_image = find_tag(content, "v:imagedata", 0).is_some()
+            || find_tag(content, "p:pic", 0).is_some()
+            || get_attribute(&attributes, "spid").is_some();
+
+        objects.push((key, EmbeddedObject { name, has_image }));
+    }
+
+    objects
+}
+
+/// Finds the OLE objects within an OpenDocument part, objects have a
+/// replacement image when the archive contains an image with the same name
+/// in the replacements directory
+fn odf_objects<R: Read + Seek>(
+    xml: &str,
+    archive: &ZipArchive<R>,
+) -> Vec<(String, EmbeddedObject)> {
+    let mut objects = Vec::new();
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, "draw:object-ole", position) {
+        position = tag.end;
+
+        let Some(href) = get_attribute(&tag.attributes, "xlink:href") else {
+            continue;
+        };
+
+        let name = unescape(href);
+        let name = name.trim_start_matches("./").trim_end_matches('/');
+
+        let has_image = archive
+            .index_for_name(&format!("{ODF_REPLACEMENTS_DIR}{name}"))
+            .is_some();
+
+        objects.push((
+            name.to_string(),
+            EmbeddedObject {
+                name: name.to_string(),
+                has_image,
+            },
+        ));
+    }
+
+    objects
+}
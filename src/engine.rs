@@ -0,0 +1,361 @@
+use crate::{
+    error::{DynHttpError, HttpError},
+    tenant::{forward_usage, Tenant, TenantUsage, Tenants},
+};
+use axum::{
+    extract::{Query, Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::warn;
+
+/// Name of the engine using the office install the server was started with
+pub const DEFAULT_ENGINE: &str = "default";
+
+/// Header engine workers report the tenant usage of a forwarded request in
+const ENGINE_USAGE_HEADER: &str = "x-engine-usage";
+
+/// Time to wait before restarting an engine worker that exited
+#[cfg(unix)]
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Configuration for an additional office install requests can select
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Path to the office installation
+    pub office_path: PathBuf,
+    /// Environment variables set for the office of the engine, replacing
+    /// the variables from the top level "office_env" with the same name
+    #[serde(default)]
+    pub office_env: BTreeMap<String, String>,
+}
+
+/// Checks that an engine name can be used, names are used in the socket
+/// path of the engine worker so are limited to alphanumeric characters,
+/// dashes and underscores
+pub fn validate_engine_name(name: &str) -> Result<(), String> {
+    if name == DEFAULT_ENGINE {
+        return Err(format!(
+            "\"{DEFAULT_ENGINE}\" is reserved for the default engine"
+        ));
+    }
+
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|value| value.is_ascii_alphanumeric() || value == '-' || value == '_')
+    {
+        return Err(
+            "names can only contain alphanumeric characters, dashes and underscores".to_string(),
+        );
+    }
+
+    Ok(())
+}
+
+/// Path to the Unix domain socket the engine worker for an engine listens on
+pub fn engine_socket_path(temp_dir: &Path, name: &str) -> PathBuf {
+    temp_dir.join(format!("office-convert-engine-{name}.sock"))
+}
+
+/// Engines conversions can be forwarded to, each engine is a worker process
+/// running its own office install and runner
+pub enum Engines {
+    /// Main server, requests selecting another engine are forwarded to the
+    /// worker for the engine
+    Main {
+        /// Socket paths of the engine workers keyed by engine name
+        sockets: HashMap<String, PathBuf>,
+        /// Process IDs of the running engine workers keyed by engine name
+        workers: Arc<Mutex<HashMap<String, u32>>>,
+    },
+    /// Engine worker, handles requests forwarded from the main server
+    Worker,
+}
+
+/// Errors that can occur when selecting an engine
+#[derive(Debug, Error)]
+pub enum EngineError {
+    /// Requested engine is not defined in the config
+    #[error("unknown engine \"{0}\"")]
+    Unknown(String),
+
+    /// Worker for the engine couldn't be reached or failed to respond
+    #[error("engine \"{0}\" is unavailable")]
+    Unavailable(String),
+}
+
+impl HttpError for EngineError {
+    fn status(&self) -> StatusCode {
+        match self {
+            EngineError::Unknown(_) => StatusCode::BAD_REQUEST,
+            EngineError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            EngineError::Unknown(_) => Some("UNKNOWN_ENGINE"),
+            EngineError::Unavailable(_) => Some("ENGINE_UNAVAILABLE"),
+        }
+    }
+}
+
+/// Starts a worker process for each of the configured engines, workers are
+/// restarted when they exit and stop when the server stops
+#[cfg(unix)]
+pub fn spawn_engine_workers(
+    engines: &HashMap<String, EngineConfig>,
+    temp_dir: &Path,
+) -> anyhow::Result<Engines> {
+    let workers: Arc<Mutex<HashMap<String, u32>>> = Arc::default();
+    let sockets = engines
+        .keys()
+        .map(|name| {
+            tokio::spawn(supervise_engine_worker(name.clone(), workers.clone()));
+            (name.clone(), engine_socket_path(temp_dir, name))
+        })
+        .collect();
+
+    Ok(Engines::Main { sockets, workers })
+}
+
+/// Engine workers listen on Unix domain sockets so are only supported on
+/// unix platforms
+#[cfg(not(unix))]
+pub fn spawn_engine_workers(
+    engines: &HashMap<String, EngineConfig>,
+    _temp_dir: &Path,
+) -> anyhow::Result<Engines> {
+    if !engines.is_empty() {
+        anyhow::bail!("engines are only supported on unix platforms")
+    }
+
+    Ok(Engines::Main {
+        sockets: HashMap::new(),
+        workers: Arc::default(),
+    })
+}
+
+/// Runs the worker process for an engine, restarting it whenever it exits
+#[cfg(unix)]
+async fn supervise_engine_worker(name: String, workers: Arc<Mutex<HashMap<String, u32>>>) {
+    use tracing::{debug, error};
+
+    loop {
+        debug!(engine = %name, "starting engine worker");
+
+        match run_engine_worker(&name, &workers).await {
+            Ok(status) => warn!(engine = %name, %status, "engine worker exited, restarting"),
+            Err(cause) => error!(engine = %name, %cause, "failed to run engine worker, restarting"),
+        }
+
+        tokio::time::sleep(RESTART_DELAY).await;
+    }
+}
+
+/// Runs the worker process for an engine until it exits, the worker is the
+/// server started again with the same arguments in engine worker mode
+#[cfg(unix)]
+async fn run_engine_worker(
+    name: &str,
+    workers: &Mutex<HashMap<String, u32>>,
+) -> anyhow::Result<std::process::ExitStatus> {
+    use crate::systemd::NOTIFY_VARIABLES;
+    use anyhow::Context;
+    use std::process::Stdio;
+    use tokio::process::Command;
+
+    let program = std::env::current_exe().context("failed to determine server executable")?;
+
+    let mut command = Command::new(program);
+    command
+        .args(std::env::args_os().skip(1))
+        .arg("--engine-worker")
+        .arg(name);
+
+    // Only the server notifies systemd
+    for variable in NOTIFY_VARIABLES {
+        command.env_remove(variable);
+    }
+
+    let mut child = command
+        // Worker exits when its stdin is closed by the server stopping
+        .stdin(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .context("failed to spawn engine worker")?;
+
+    if let Some(pid) = child.id() {
+        workers.lock().insert(name.to_string(), pid);
+    }
+
+    let status = child.wait().await;
+    workers.lock().remove(name);
+
+    status.context("failed to wait for engine worker")
+}
+
+impl Engines {
+    /// Asks each running engine worker to reload its config by sending it
+    /// SIGHUP, workers reload the same way as the main server
+    pub fn reload_workers(&self) {
+        let Engines::Main { workers, .. } = self else {
+            return;
+        };
+
+        #[cfg(unix)]
+        for (name, pid) in workers.lock().iter() {
+            let Ok(pid) = libc::pid_t::try_from(*pid) else {
+                continue;
+            };
+
+            // SAFETY: Sending a signal doesn't access any memory
+            if unsafe { libc::kill(pid, libc::SIGHUP) } != 0 {
+                warn!(engine = %name, "failed to signal engine worker to reload");
+            }
+        }
+
+        #[cfg(not(unix))]
+        let _ = workers;
+    }
+}
+
+/// Exits the engine worker once the main server stops, the main server holds
+/// the stdin of the worker which is closed when the main server exits
+pub fn exit_with_main_server() {
+    tokio::spawn(async {
+        _ = tokio::io::copy(&mut tokio::io::stdin(), &mut tokio::io::sink()).await;
+        warn!("main server stopped, stopping engine worker");
+        std::process::exit(0);
+    });
+}
+
+#[derive(Deserialize)]
+pub struct EngineQuery {
+    /// Name of the engine to convert using
+    engine: Option<String>,
+}
+
+/// Middleware forwarding conversions to the engine selected by the request or
+/// the default engine of the tenant, conversions using the default engine are
+/// handled by this server. On engine workers the tenant usage of forwarded
+/// requests is reported back to the main server
+pub async fn select_engine(
+    State(engines): State<Arc<Engines>>,
+    Extension(tenants): Extension<Arc<Tenants>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Query(EngineQuery { engine }): Query<EngineQuery>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    let sockets = match &*engines {
+        Engines::Main { sockets, .. } => sockets,
+        Engines::Worker => {
+            let (mut response, usage) = forward_usage(next.run(request)).await;
+
+            if usage.conversions > 0 {
+                if let Some(value) = serde_json::to_string(&usage)
+                    .ok()
+                    .and_then(|value| HeaderValue::try_from(value).ok())
+                {
+                    response.headers_mut().insert(ENGINE_USAGE_HEADER, value);
+                }
+            }
+
+            return Ok(response);
+        }
+    };
+
+    let engine = engine
+        .as_deref()
+        .or_else(|| tenant.as_deref().and_then(|tenant| tenant.engine()))
+        .filter(|engine| *engine != DEFAULT_ENGINE)
+        .map(str::to_string);
+
+    let Some(engine) = engine else {
+        return Ok(next.run(request).await);
+    };
+
+    let socket = sockets
+        .get(&engine)
+        .ok_or_else(|| EngineError::Unknown(engine.clone()))?;
+
+    let mut response = forward_request(socket, request).await.map_err(|cause| {
+        warn!(%engine, %cause, "failed to forward request to engine worker");
+        EngineError::Unavailable(engine.clone())
+    })?;
+
+    if let Some(usage) = response.headers_mut().remove(ENGINE_USAGE_HEADER) {
+        let usage: Option<TenantUsage> = serde_json::from_slice(usage.as_bytes()).ok();
+
+        if let (Some(Extension(tenant)), Some(usage)) = (tenant, usage) {
+            tenants.record_usage(&tenant.name, &usage);
+        }
+    }
+
+    Ok(response)
+}
+
+/// Forwards the request to the engine worker listening on the socket
+/// providing its response, the request and response bodies are streamed
+#[cfg(unix)]
+async fn forward_request(socket: &Path, request: Request) -> anyhow::Result<Response> {
+    use anyhow::Context;
+    use axum::{body::Body, http::Uri};
+    use hyper::client::conn::http1;
+    use hyper_util::rt::TokioIo;
+    use tokio::net::UnixStream;
+    use tracing::debug;
+
+    let stream = UnixStream::connect(socket)
+        .await
+        .context("failed to connect to engine worker")?;
+
+    let (mut sender, connection) = http1::handshake(TokioIo::new(stream))
+        .await
+        .context("failed to start engine worker connection")?;
+
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            debug!(%err, "engine worker connection failed");
+        }
+    });
+
+    // Requests over the connection only use the path and query
+    let (mut parts, body) = request.into_parts();
+    parts.uri = parts
+        .uri
+        .path_and_query()
+        .map(|value| Uri::from(value.clone()))
+        .unwrap_or_else(|| Uri::from_static("/"));
+
+    // Spans of the engine worker continue the trace of this request
+    #[cfg(feature = "otel")]
+    crate::telemetry::inject_context(&mut parts.headers);
+
+    let response = sender
+        .send_request(Request::from_parts(parts, body))
+        .await
+        .context("failed to send request to engine worker")?;
+
+    Ok(response.map(Body::new))
+}
+
+/// Engine workers are only supported on unix platforms
+#[cfg(not(unix))]
+async fn forward_request(_socket: &Path, _request: Request) -> anyhow::Result<Response> {
+    anyhow::bail!("engines are only supported on unix platforms")
+}
@@ -33,23 +33,53 @@ impl Display for DynHttpError {
 
 impl Error for DynHttpError {}
 
-/// Handles converting the error into a response (Also logs the error before conversion)
-impl IntoResponse for DynHttpError {
-    fn into_response(self) -> Response {
+impl DynHttpError {
+    /// Machine readable code identifying the error
+    pub fn code(&self) -> Option<&'static str> {
+        self.inner.code()
+    }
+
+    /// Logs the underlying error and creates the status and body used when
+    /// responding with the error
+    pub fn to_raw(&self) -> (StatusCode, RawHttpError) {
         // Log the underlying error
         self.inner.log();
 
-        // Create the response body
-        let body = Json(RawHttpError {
+        let body = RawHttpError {
             reason: self.inner.reason(),
+            code: self.inner.code(),
             backtrace: self.inner.backtrace(),
-        });
-        let status = self.inner.status();
+        };
 
-        (status, body).into_response()
+        (self.inner.status(), body)
     }
 }
 
+/// Handles converting the error into a response (Also logs the error before conversion)
+impl IntoResponse for DynHttpError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.to_raw();
+        let details = ErrorDetails {
+            code: body.code,
+            reason: body.reason.clone(),
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        response.extensions_mut().insert(details);
+        response
+    }
+}
+
+/// Details about the error added to the extensions of error responses so
+/// middleware can see why a request failed
+#[derive(Debug, Clone)]
+pub struct ErrorDetails {
+    /// Machine readable code identifying the error
+    pub code: Option<&'static str>,
+    /// Reason message from the error response
+    pub reason: String,
+}
+
 /// Trait implemented by errors that can be converted into [HttpError]s
 /// and used as error responses
 pub trait HttpError: Error + Send + Sync + 'static {
@@ -69,6 +99,12 @@ pub trait HttpError: Error + Send + Sync + 'static {
         self.to_string()
     }
 
+    /// Provides a machine readable code identifying the error for the
+    /// error response, errors without a code use [None]
+    fn code(&self) -> Option<&'static str> {
+        None
+    }
+
     /// Provides the full type name for the actual error type thats been
     /// erased by dynamic typing (For better error source clarity)
     fn type_name(&self) -> &str {
@@ -130,5 +166,6 @@ where
 #[serde(rename_all = "camelCase")]
 pub struct RawHttpError {
     pub reason: String,
+    pub code: Option<&'static str>,
     pub backtrace: Option<String>,
 }
@@ -0,0 +1,55 @@
+use anyhow::Context;
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+/// Creates a strong entity tag for a conversion from the input file and a
+/// description of the options that change the converted output. Converting
+/// the same file with the same options always produces the same tag
+pub async fn conversion_etag(input: Bytes, options: String) -> anyhow::Result<HeaderValue> {
+    // Hashing large files would block the runtime
+    let digest = tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        hasher.update((options.len() as u64).to_le_bytes());
+        hasher.update(options.as_bytes());
+        hasher.update(&input);
+        hasher.finalize()
+    })
+    .await
+    .context("failed to hash conversion")?;
+
+    let etag = format!("\"{}\"", URL_SAFE_NO_PAD.encode(digest));
+    HeaderValue::try_from(etag).context("invalid etag")
+}
+
+/// Checks if the "If-None-Match" header of the request matches the entity tag,
+/// weak comparison is used as required for "If-None-Match"
+pub fn if_none_match(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let etag = strip_weak(etag.as_bytes());
+
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .flat_map(|value| value.as_bytes().split(|value| *value == b','))
+        .map(|value| value.trim_ascii())
+        .any(|value| value == b"*" || strip_weak(value) == etag)
+}
+
+/// Removes the weak indicator from an entity tag
+fn strip_weak(value: &[u8]) -> &[u8] {
+    value.strip_prefix(b"W/").unwrap_or(value)
+}
+
+/// Creates a response telling the client their stored copy of the
+/// conversion is still valid
+pub fn not_modified(etag: HeaderValue) -> anyhow::Result<Response<Body>> {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header(header::ETAG, etag)
+        .body(Body::empty())
+        .context("failed to create response")
+}
@@ -0,0 +1,518 @@
+use crate::{
+    archive::read_part,
+    compression::decompress_file,
+    detect::detect_input_format,
+    error::{DynHttpError, HttpError},
+    ws::MaxFileSize,
+    xml::{find_tag, get_attribute, unescape},
+};
+use anyhow::Context;
+use axum::{http::StatusCode, Extension, Json};
+use axum_typed_multipart::{FieldData, TryFromField, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use serde::Serialize;
+use std::io::Cursor;
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Mime type of PowerPoint presentations
+const PPTX_MIME: &str = "application/vnd.openxmlformats-officedocument.presentationml.presentation";
+
+/// Mime type of OpenDocument presentations
+const ODP_MIME: &str = "application/vnd.oasis.opendocument.presentation";
+
+/// Mime type of flat XML OpenDocument presentations
+const FODP_MIME: &str = "application/vnd.oasis.opendocument.presentation-flat-xml";
+
+/// Relationship type of the notes of a PowerPoint slide
+const NOTES_SLIDE_RELATIONSHIP: &str = "/notesSlide";
+
+/// Placeholder types holding the title of a PowerPoint slide
+const PPTX_TITLE_PLACEHOLDERS: &[&str] = &["title", "ctrTitle"];
+
+/// Placeholder types holding the outline text of a PowerPoint slide,
+/// placeholders without a type are content placeholders ("obj")
+const PPTX_OUTLINE_PLACEHOLDERS: &[&str] = &["body", "subTitle", "obj"];
+
+/// Classes of the frames holding the outline text of an OpenDocument slide
+const ODP_OUTLINE_CLASSES: &[&str] = &["outline", "subtitle"];
+
+/// Text extracted from each slide of a presentation
+#[derive(Debug, Clone, Copy, PartialEq, TryFromField, Serialize)]
+#[try_from_field(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractMode {
+    /// Text of the title and the outline placeholders of the slide
+    Outline,
+    /// Speaker notes of the slide
+    Notes,
+}
+
+/// Errors that can occur when extracting text
+#[derive(Debug, Error)]
+pub enum ExtractError {
+    /// File isn't a presentation text can be extracted from
+    #[error(
+        "text can only be extracted from PowerPoint (.pptx) and OpenDocument (.odp, .fodp) presentations"
+    )]
+    UnsupportedFormat,
+
+    /// Presentation is missing parts or could not be read
+    #[error("presentation could not be read: {0}")]
+    InvalidPresentation(&'static str),
+}
+
+impl HttpError for ExtractError {
+    fn status(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some(match self {
+            ExtractError::UnsupportedFormat => "UNSUPPORTED_PRESENTATION",
+            ExtractError::InvalidPresentation(_) => "INVALID_PRESENTATION",
+        })
+    }
+}
+
+/// Text extracted from a slide
+#[derive(Debug, Serialize)]
+pub struct SlideText {
+    /// Number of the slide starting from 1
+    pub number: usize,
+    /// Title of the slide, [None] when the slide has no title
+    pub title: Option<String>,
+    /// Paragraphs of the outline or the speaker notes of the slide, empty
+    /// paragraphs are skipped
+    pub paragraphs: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExtractTextResponse {
+    /// Text that was extracted
+    mode: ExtractMode,
+    /// Text of each slide in order
+    slides: Vec<SlideText>,
+}
+
+/// Request to extract the text of a presentation
+#[derive(TryFromMultipart)]
+pub struct ExtractTextRequest {
+    /// The presentation to extract text from
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+    /// Text to extract from each slide
+    mode: ExtractMode,
+}
+
+/// POST /extract-text
+///
+/// Extracts the outline text or the speaker notes of each slide of the
+/// provided presentation, the text is read from the presentation itself
+/// without loading it in office
+pub async fn extract_text(
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    TypedMultipart(ExtractTextRequest { file, mode }): TypedMultipart<ExtractTextRequest>,
+) -> Result<Json<ExtractTextResponse>, DynHttpError> {
+    let file = decompress_file(file, max_file_size).await?;
+    let format = detect_input_format(
+        &file.contents,
+        file.metadata.file_name.as_deref(),
+        file.metadata.content_type.as_deref(),
+    );
+
+    let slides = tokio::task::spawn_blocking(move || match format.as_deref() {
+        Some(PPTX_MIME) => extract_pptx(&file.contents, mode),
+        Some(ODP_MIME) => extract_odp(&file.contents, mode),
+        Some(FODP_MIME) => {
+            let xml = std::str::from_utf8(&file.contents)
+                .map_err(|_| ExtractError::InvalidPresentation("presentation is not UTF-8"))?;
+            Ok(extract_odp_content(xml, mode))
+        }
+        _ => Err(ExtractError::UnsupportedFormat),
+    })
+    .await
+    .context("failed to extract text")??;
+
+    Ok(Json(ExtractTextResponse { mode, slides }))
+}
+
+/// Extracts the text of the slides of a PowerPoint presentation in the
+/// order they are listed by the presentation
+fn extract_pptx(bytes: &[u8], mode: ExtractMode) -> Result<Vec<SlideText>, ExtractError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|_| ExtractError::InvalidPresentation("file is not a valid archive"))?;
+
+    let presentation = read_part(&mut archive, "ppt/presentation.xml")
+        .map_err(|_| ExtractError::InvalidPresentation("missing presentation part"))?;
+    let relationships = read_part(&mut archive, "ppt/_rels/presentation.xml.rels")
+        .map_err(|_| ExtractError::InvalidPresentation("missing presentation relationships"))?;
+    let relationships = parse_relationships(&relationships);
+
+    let mut slides = Vec::new();
+    let mut position = 0;
+
+    while let Some(slide_id) = find_tag(&presentation, "p:sldId", position) {
+        position = slide_id.end;
+
+        let Some(target) = get_attribute(&slide_id.attributes, "r:id").and_then(|id| {
+            relationships
+                .iter()
+                .find(|relationship| relationship.id == id)
+                .map(|relationship| resolve_target("ppt", &relationship.target))
+        }) else {
+            continue;
+        };
+
+        let slide = read_part(&mut archive, &target)
+            .map_err(|_| ExtractError::InvalidPresentation("missing slide part"))?;
+        let title = pptx_placeholder_text(&slide, PPTX_TITLE_PLACEHOLDERS, false)
+            .into_iter()
+            .reduce(|title, value| format!("{title} {value}"));
+
+        let paragraphs = match mode {
+            ExtractMode::Outline => pptx_placeholder_text(&slide, PPTX_OUTLINE_PLACEHOLDERS, true),
+            ExtractMode::Notes => pptx_notes(&mut archive, &target),
+        };
+
+        slides.push(SlideText {
+            number: slides.len() + 1,
+            title,
+            paragraphs,
+        });
+    }
+
+    Ok(slides)
+}
+
+/// Extracts the paragraphs of the speaker notes of a PowerPoint slide,
+/// empty when the slide has no notes
+fn pptx_notes(archive: &mut ZipArchive<Cursor<&[u8]>>, slide: &str) -> Vec<String> {
+    let (directory, name) = slide.rsplit_once('/').unwrap_or(("", slide));
+    let Ok(relationships) = read_part(archive, &format!("{directory}/_rels/{name}.rels")) else {
+        return Vec::new();
+    };
+
+    let Some(notes) = parse_relationships(&relationships)
+        .into_iter()
+        .find(|relationship| relationship.kind.ends_with(NOTES_SLIDE_RELATIONSHIP))
+        .and_then(|relationship| {
+            read_part(archive, &resolve_target(directory, &relationship.target)).ok()
+        })
+    else {
+        return Vec::new();
+    };
+
+    pptx_placeholder_text(&notes, &["body"], false)
+}
+
+/// Extracts the paragraphs of the shapes of a PowerPoint slide that are
+/// placeholders of the provided types
+fn pptx_placeholder_text(xml: &str, types: &[&str], untyped: bool) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut position = 0;
+
+    while let Some(shape) = find_tag(xml, "p:sp", position) {
+        let end = match shape.self_closing {
+            true => shape.end,
+            false => xml[shape.end..]
+                .find("</p:sp>")
+                .map_or(xml.len(), |value| shape.end + value),
+        };
+        position = end;
+
+        let shape = &xml[shape.end..end];
+        let Some(placeholder) = find_tag(shape, "p:ph", 0) else {
+            continue;
+        };
+
+        let matches = match get_attribute(&placeholder.attributes, "type") {
+            Some(kind) => types.contains(&kind),
+            None => untyped,
+        };
+
+        if matches {
+            paragraphs.extend(pptx_paragraphs(shape));
+        }
+    }
+
+    paragraphs
+}
+
+/// Extracts the text of the non empty paragraphs of a PowerPoint shape
+fn pptx_paragraphs(xml: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut position = 0;
+
+    while let Some(paragraph) = find_tag(xml, "a:p", position) {
+        if paragraph.self_closing {
+            position = paragraph.end;
+            continue;
+        }
+
+        let end = xml[paragraph.end..]
+            .find("</a:p>")
+            .map_or(xml.len(), |value| paragraph.end + value);
+        position = end;
+
+        let text = pptx_runs(&xml[paragraph.end..end]);
+        let text = text.trim();
+        if !text.is_empty() {
+            paragraphs.push(text.to_string());
+        }
+    }
+
+    paragraphs
+}
+
+/// Joins the text runs of a PowerPoint paragraph, line breaks within the
+/// paragraph are kept
+fn pptx_runs(xml: &str) -> String {
+    let mut text = String::new();
+    let mut position = 0;
+
+    loop {
+        let run = find_tag(xml, "a:t", position);
+
+        // Line breaks before the next run
+        let line_break = find_tag(xml, "a:br", position).filter(|line_break| match &run {
+            Some(run) => line_break.start < run.start,
+            None => true,
+        });
+        if let Some(line_break) = line_break {
+            text.push('\n');
+            position = line_break.end;
+            continue;
+        }
+
+        let Some(run) = run else {
+            break;
+        };
+
+        if run.self_closing {
+            position = run.end;
+            continue;
+        }
+
+        let end = xml[run.end..]
+            .find("</a:t>")
+            .map_or(xml.len(), |value| run.end + value);
+        text.push_str(&unescape(&xml[run.end..end]));
+        position = end;
+    }
+
+    text
+}
+
+/// Extracts the text of the slides of an OpenDocument presentation
+fn extract_odp(bytes: &[u8], mode: ExtractMode) -> Result<Vec<SlideText>, ExtractError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|_| ExtractError::InvalidPresentation("file is not a valid archive"))?;
+    let content = read_part(&mut archive, "content.xml")
+        .map_err(|_| ExtractError::InvalidPresentation("missing content part"))?;
+
+    Ok(extract_odp_content(&content, mode))
+}
+
+/// Extracts the text of the slides (draw pages) from the content of an
+/// OpenDocument presentation
+fn extract_odp_content(xml: &str, mode: ExtractMode) -> Vec<SlideText> {
+    let mut slides = Vec::new();
+    let mut position = 0;
+
+    while let Some(page) = find_tag(xml, "draw:page", position) {
+        let end = match page.self_closing {
+            true => page.end,
+            false => xml[page.end..]
+                .find("</draw:page>")
+                .map_or(xml.len(), |value| page.end + value),
+        };
+        position = end;
+
+        // Speaker notes are stored at the end of the page
+        let page = &xml[page.end..end];
+        let (slide, notes) = match find_tag(page, "presentation:notes", 0) {
+            Some(notes) => (&page[..notes.start], &page[notes.start..]),
+            None => (page, ""),
+        };
+
+        let title = odp_frame_text(slide, &["title"])
+            .into_iter()
+            .reduce(|title, value| format!("{title} {value}"));
+
+        let paragraphs = match mode {
+            ExtractMode::Outline => odp_frame_text(slide, ODP_OUTLINE_CLASSES),
+            ExtractMode::Notes => odp_frame_text(notes, &["notes"]),
+        };
+
+        slides.push(SlideText {
+            number: slides.len() + 1,
+            title,
+            paragraphs,
+        });
+    }
+
+    slides
+}
+
+/// Extracts the paragraphs of the frames with the provided presentation
+/// classes from an OpenDocument page
+fn odp_frame_text(xml: &str, classes: &[&str]) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut position = 0;
+
+    while let Some(frame) = find_tag(xml, "draw:frame", position) {
+        let end = match frame.self_closing {
+            true => frame.end,
+            false => xml[frame.end..]
+                .find("</draw:frame>")
+                .map_or(xml.len(), |value| frame.end + value),
+        };
+        position = end;
+
+        let matches = get_attribute(&frame.attributes, "presentation:class")
+            .is_some_and(|class| classes.contains(&class));
+        if matches {
+            paragraphs.extend(odp_paragraphs(&xml[frame.end..end]));
+        }
+    }
+
+    paragraphs
+}
+
+/// Extracts the text of the non empty paragraphs and headings of an
+/// OpenDocument frame
+fn odp_paragraphs(xml: &str) -> Vec<String> {
+    let mut paragraphs = Vec::new();
+    let mut position = 0;
+
+    loop {
+        // Paragraphs and headings in the order they appear
+        let paragraph = [("text:p", "</text:p>"), ("text:h", "</text:h>")]
+            .into_iter()
+            .filter_map(|(name, close)| Some((find_tag(xml, name, position)?, close)))
+            .min_by_key(|(tag, _)| tag.start);
+
+        let Some((paragraph, close)) = paragraph else {
+            break;
+        };
+
+        if paragraph.self_closing {
+            position = paragraph.end;
+            continue;
+        }
+
+        let end = xml[paragraph.end..]
+            .find(close)
+            .map_or(xml.len(), |value| paragraph.end + value);
+        position = end;
+
+        let text = odp_text(&xml[paragraph.end..end]);
+        let text = text.trim();
+        if !text.is_empty() {
+            paragraphs.push(text.to_string());
+        }
+    }
+
+    paragraphs
+}
+
+/// Extracts the text from the contents of an OpenDocument paragraph,
+/// removing the spans and expanding the space, tab and line break elements
+fn odp_text(xml: &str) -> String {
+    let mut text = String::new();
+    let mut remaining = xml;
+
+    while let Some(start) = remaining.find('<') {
+        text.push_str(&unescape(&remaining[..start]));
+
+        let Some(end) = remaining[start..].find('>').map(|value| start + value + 1) else {
+            remaining = "";
+            break;
+        };
+
+        let tag = &remaining[start..end];
+        let name = tag[1..]
+            .split(|value: char| value.is_whitespace() || value == '/' || value == '>')
+            .next()
+            .unwrap_or_default();
+
+        match name {
+            "text:s" => {
+                let count = find_tag(tag, "text:s", 0)
+                    .and_then(|tag| get_attribute(&tag.attributes, "text:c")?.parse().ok())
+                    .unwrap_or(1);
+                text.push_str(&" ".repeat(count));
+            }
+            "text:tab" => text.push('\t'),
+            "text:line-break" => text.push('\n'),
+            _ => {}
+        }
+
+        remaining = &remaining[end..];
+    }
+
+    text.push_str(&unescape(remaining));
+    text
+}
+
+/// Relationship between a part of an Office Open XML package and another part
+struct Relationship {
+    /// ID the part refers to the relationship by
+    id: String,
+    /// Type of the relationship
+    kind: String,
+    /// Path of the related part relative to the directory of the part
+    target: String,
+}
+
+/// Parses the relationships of a relationships part
+fn parse_relationships(xml: &str) -> Vec<Relationship> {
+    let mut relationships = Vec::new();
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, "Relationship", position) {
+        position = tag.end;
+
+        let (Some(id), Some(kind), Some(target)) = (
+            get_attribute(&tag.attributes, "Id"),
+            get_attribute(&tag.attributes, "Type"),
+            get_attribute(&tag.attributes, "Target"),
+        ) else {
+            continue;
+        };
+
+        relationships.push(Relationship {
+            id: unescape(id),
+            kind: unescape(kind),
+            target: unescape(target),
+        });
+    }
+
+    relationships
+}
+
+/// Resolves the target of a relationship to the path of the part within the
+/// package, targets are relative to the directory of the source part unless
+/// they start with "/"
+fn resolve_target(directory: &str, target: &str) -> String {
+    let mut segments: Vec<&str> = match target.strip_prefix('/') {
+        Some(_) => Vec::new(),
+        None => directory
+            .split('/')
+            .filter(|value| !value.is_empty())
+            .collect(),
+    };
+
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+
+    segments.join("/")
+}
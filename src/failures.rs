@@ -0,0 +1,176 @@
+use crate::{detect::detect_input_format, error::ErrorDetails, jobs::now_secs};
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use axum_typed_multipart::FieldData;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Arc};
+
+/// Maximum length in characters of the reason kept for a failure
+const MAX_REASON_LENGTH: usize = 500;
+
+/// Details about a failed conversion
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversionFailure {
+    /// Unix timestamp in seconds of when the conversion failed
+    pub failed_at: u64,
+    /// Route the failed request was made to or "job" for failed jobs
+    pub source: String,
+    /// Machine readable code for the reason the conversion failed
+    pub code: Option<&'static str>,
+    /// Reason the conversion failed, truncated when long
+    pub reason: String,
+    /// Mime type detected for the uploaded file
+    pub input_format: Option<String>,
+    /// Size of the uploaded file in bytes
+    pub input_size: Option<usize>,
+}
+
+/// Most recent conversion failures kept in memory so operators can see
+/// what is failing without access to the logs, older failures are dropped
+/// once full
+pub struct RecentFailures {
+    /// Maximum number of failures kept, failures are not kept when zero
+    capacity: usize,
+    failures: Mutex<VecDeque<ConversionFailure>>,
+}
+
+impl RecentFailures {
+    pub fn new(capacity: usize) -> Arc<Self> {
+        Arc::new(Self {
+            capacity,
+            failures: Mutex::new(VecDeque::with_capacity(capacity)),
+        })
+    }
+
+    /// Records a failed conversion
+    pub fn record(
+        &self,
+        source: &str,
+        code: Option<&'static str>,
+        reason: &str,
+        input: InputDetails,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let failure = ConversionFailure {
+            failed_at: now_secs(),
+            source: source.to_string(),
+            code,
+            reason: truncate_reason(reason),
+            input_format: input.format,
+            input_size: input.size,
+        };
+
+        let failures = &mut *self.failures.lock();
+
+        if failures.len() == self.capacity {
+            failures.pop_front();
+        }
+
+        failures.push_back(failure);
+    }
+
+    /// Provides the failures, most recent first
+    pub fn recent(&self) -> Vec<ConversionFailure> {
+        self.failures.lock().iter().rev().cloned().collect()
+    }
+}
+
+/// Truncates the reason to the maximum length
+fn truncate_reason(reason: &str) -> String {
+    match reason.char_indices().nth(MAX_REASON_LENGTH) {
+        Some((index, _)) => format!("{}...", &reason[..index]),
+        None => reason.to_string(),
+    }
+}
+
+/// Details about the file uploaded for a conversion
+#[derive(Debug, Clone, Default)]
+pub struct InputDetails {
+    /// Mime type detected for the file
+    pub format: Option<String>,
+    /// Size of the file in bytes
+    pub size: Option<usize>,
+}
+
+/// Details about the uploaded file a request handler provides so they can
+/// be recorded when the request fails
+#[derive(Clone, Default)]
+pub struct FailureInput(Arc<Mutex<InputDetails>>);
+
+impl FailureInput {
+    /// Sets the details from the uploaded file
+    pub fn set_file(&self, file: &FieldData<Bytes>) {
+        let format = detect_input_format(
+            &file.contents,
+            file.metadata.file_name.as_deref(),
+            file.metadata.content_type.as_deref(),
+        );
+
+        *self.0.lock() = InputDetails {
+            format,
+            size: Some(file.contents.len()),
+        };
+    }
+
+    /// Sets the size of the uploaded file
+    pub fn set_size(&self, size: usize) {
+        self.0.lock().size = Some(size);
+    }
+
+    /// Sets the mime type detected for the uploaded file
+    pub fn set_format(&self, format: Option<String>) {
+        self.0.lock().format = format;
+    }
+}
+
+/// Middleware recording the requests that fail with an error, handlers
+/// provide the details of the uploaded file through the [FailureInput]
+/// extension
+pub async fn record_failures(
+    State(failures): State<Arc<RecentFailures>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let source = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let input = FailureInput::default();
+    request.extensions_mut().insert(input.clone());
+
+    let response = next.run(request).await;
+
+    if let Some(error) = response.extensions().get::<ErrorDetails>() {
+        let input = input.0.lock().clone();
+        failures.record(&source, error.code, &error.reason, input);
+    }
+
+    response
+}
+
+#[derive(Serialize)]
+pub struct RecentFailuresResponse {
+    errors: Vec<ConversionFailure>,
+}
+
+/// GET /status/errors
+///
+/// Reports the most recent conversion failures, most recent first
+pub async fn recent_errors(
+    Extension(failures): Extension<Arc<RecentFailures>>,
+) -> Json<RecentFailuresResponse> {
+    Json(RecentFailuresResponse {
+        errors: failures.recent(),
+    })
+}
@@ -0,0 +1,268 @@
+use crate::{
+    archive::{open_archive, read_part},
+    detect::{detect_archive, known_extension},
+    xml::{find_tag, get_attribute, unescape},
+};
+use anyhow::Context;
+use axum::{
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use office_convert_core::font_families;
+use parking_lot::RwLock;
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::{Read, Seek},
+    path::Path,
+    sync::Arc,
+};
+use tracing::{debug, warn};
+use zip::ZipArchive;
+
+/// Loads the font substitution table from a TOML file mapping the names of
+/// missing fonts to the installed fonts used in their place:
+///
+/// ```toml
+/// Calibri = "Carlito"
+/// "Times New Roman" = "Liberation Serif"
+/// ```
+pub fn load_font_substitutions(path: &Path) -> anyhow::Result<BTreeMap<String, String>> {
+    let contents =
+        std::fs::read_to_string(path).context("failed to read font substitutions file")?;
+    let substitutions: BTreeMap<String, String> =
+        toml::from_str(&contents).context("failed to parse font substitutions file")?;
+
+    for (font, substitute) in &substitutions {
+        if font.trim().is_empty() || substitute.trim().is_empty() {
+            anyhow::bail!("font substitution for \"{font}\" must not be empty");
+        }
+    }
+
+    Ok(substitutions)
+}
+
+/// Fonts available to LibreOffice on the server, used to report fonts
+/// referenced by documents that will be substituted during conversion
+pub struct FontCatalog {
+    /// Names of the installed font families, [None] when the installed
+    /// fonts could not be determined
+    families: Option<BTreeSet<String>>,
+    /// Lowercase names of the installed font families
+    installed: Option<HashSet<String>>,
+    /// Fonts used in place of missing fonts keyed by the name of the
+    /// missing font, replaced when the config is reloaded
+    substitutions: RwLock<BTreeMap<String, String>>,
+}
+
+impl FontCatalog {
+    /// Loads the installed fonts, must be loaded after office has started
+    /// so that any additional font directories are included
+    pub fn load(substitutions: &BTreeMap<String, String>) -> Self {
+        let families = font_families();
+        let installed: Option<HashSet<String>> = families.as_ref().map(|families| {
+            families
+                .iter()
+                .map(|family| family.to_lowercase())
+                .collect()
+        });
+
+        match &installed {
+            Some(installed) => debug!(count = installed.len(), "loaded installed fonts"),
+            None => warn!("unable to list installed fonts, missing fonts will not be reported"),
+        }
+
+        let catalog = Self {
+            families,
+            installed,
+            substitutions: RwLock::default(),
+        };
+        catalog.set_substitutions(substitutions.clone());
+        catalog
+    }
+
+    /// Number of fonts in the substitution table
+    pub fn substitution_count(&self) -> usize {
+        self.substitutions.read().len()
+    }
+
+    /// Replaces the font substitution table used when reporting missing fonts
+    pub fn set_substitutions(&self, substitutions: BTreeMap<String, String>) {
+        if let Some(installed) = &self.installed {
+            for substitute in substitutions.values() {
+                if !installed.contains(&substitute.to_lowercase()) {
+                    warn!(%substitute, "font used as a substitute is not installed");
+                }
+            }
+        }
+
+        *self.substitutions.write() = substitutions;
+    }
+
+    /// Finds the fonts referenced by the document that aren't installed
+    pub fn missing_fonts(&self, input: &[u8]) -> Vec<String> {
+        let installed = match &self.installed {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+
+        document_fonts(input)
+            .into_iter()
+            .filter(|font| !installed.contains(&font.to_lowercase()))
+            .collect()
+    }
+
+    /// Creates a warning describing the fonts referenced by the document
+    /// that aren't installed along with the fonts from the substitution
+    /// table used in their place, [None] when all the fonts are installed
+    pub fn missing_fonts_warning(&self, input: &[u8]) -> Option<String> {
+        let missing = self.missing_fonts(input);
+        if missing.is_empty() {
+            return None;
+        }
+
+        let missing: Vec<String> = missing
+            .into_iter()
+            .map(|font| {
+                // Office matches the replaced font ignoring case
+                let substitutions = self.substitutions.read();
+                let substitute = substitutions
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(&font));
+
+                match substitute {
+                    Some((_, substitute)) => format!("{font} (as {substitute})"),
+                    None => font,
+                }
+            })
+            .collect();
+
+        Some(format!(
+            "fonts not installed on the server were substituted: {}",
+            missing.join(", ")
+        ))
+    }
+}
+
+/// Fonts available to LibreOffice and the configured substitutions
+#[derive(Serialize)]
+pub struct FontsResponse<'a> {
+    /// Names of the installed font families, [None] when the installed
+    /// fonts could not be determined
+    fonts: Option<&'a BTreeSet<String>>,
+    /// Fonts used in place of missing fonts keyed by the name of the
+    /// missing font
+    substitutions: BTreeMap<String, String>,
+}
+
+/// GET /fonts
+///
+/// Lists the font families available to LibreOffice and the font
+/// substitution table
+pub async fn list_fonts(Extension(fonts): Extension<Arc<FontCatalog>>) -> Response {
+    Json(FontsResponse {
+        fonts: fonts.families.as_ref(),
+        substitutions: fonts.substitutions.read().clone(),
+    })
+    .into_response()
+}
+
+/// Document formats that fonts can be read from
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DocumentFormat {
+    /// Word document (.docx)
+    Docx,
+    /// Excel workbook (.xlsx)
+    Xlsx,
+    /// PowerPoint presentation (.pptx)
+    Pptx,
+    /// OpenDocument text, spreadsheet, presentation or drawing
+    OpenDocument,
+}
+
+impl DocumentFormat {
+    /// Detects the format of the document archive
+    fn detect<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<Self> {
+        match known_extension(detect_archive(archive)?)? {
+            "docx" => Some(DocumentFormat::Docx),
+            "xlsx" => Some(DocumentFormat::Xlsx),
+            "pptx" => Some(DocumentFormat::Pptx),
+            "odt" | "ods" | "odp" | "odg" => Some(DocumentFormat::OpenDocument),
+            _ => None,
+        }
+    }
+
+    /// Whether the part of the document declares fonts
+    fn has_fonts(&self, name: &str) -> bool {
+        match self {
+            DocumentFormat::Docx => name == "word/fontTable.xml",
+            DocumentFormat::Xlsx => name == "xl/styles.xml",
+            DocumentFormat::Pptx => name.starts_with("ppt/") && name.ends_with(".xml"),
+            DocumentFormat::OpenDocument => name == "content.xml" || name == "styles.xml",
+        }
+    }
+
+    /// Element and attribute that declare a font name
+    fn font_tag(&self) -> (&'static str, &'static str) {
+        match self {
+            DocumentFormat::Docx => ("w:font", "w:name"),
+            DocumentFormat::Xlsx => ("name", "val"),
+            DocumentFormat::Pptx => ("a:latin", "typeface"),
+            DocumentFormat::OpenDocument => ("style:font-face", "svg:font-family"),
+        }
+    }
+}
+
+/// Reads the names of the fonts referenced by the document, fonts can be read
+/// from Word, Excel, PowerPoint and OpenDocument documents, other files provide
+/// no fonts
+fn document_fonts(input: &[u8]) -> BTreeSet<String> {
+    let mut fonts = BTreeSet::new();
+
+    let mut archive = match open_archive(input) {
+        Some(value) => value,
+        None => return fonts,
+    };
+
+    let format = match DocumentFormat::detect(&mut archive) {
+        Some(value) => value,
+        None => return fonts,
+    };
+
+    let (element, attribute) = format.font_tag();
+
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| format.has_fonts(name))
+        .map(str::to_string)
+        .collect();
+
+    for name in names {
+        // Parts that can't be read (i.e too large) are skipped
+        let Ok(xml) = read_part(&mut archive, &name) else {
+            continue;
+        };
+
+        let mut position = 0;
+        while let Some(tag) = find_tag(&xml, element, position) {
+            position = tag.end;
+
+            let name = match get_attribute(&tag.attributes, attribute) {
+                Some(value) => unescape(value),
+                None => continue,
+            };
+
+            // OpenDocument font families may be quoted
+            let name = name.trim().trim_matches(|c| c == '\'' || c == '"').trim();
+
+            // Empty names and theme font references (i.e "+mn-lt") aren't fonts
+            if name.is_empty() || name.starts_with('+') {
+                continue;
+            }
+
+            fonts.insert(name.to_string());
+        }
+    }
+
+    fonts
+}
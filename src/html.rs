@@ -0,0 +1,397 @@
+//! Removes references to external resources from untrusted HTML (i.e the body
+//! of an email) so that office doesn't fetch remote or local files while
+//! converting it. Only "cid:" references to the parts of the email are kept
+
+/// Elements that load an external resource or change how URLs are resolved,
+/// their tags are removed while any fallback content between them is kept
+const REMOVED_ELEMENTS: &[&str] = &[
+    "applet", "base", "embed", "frame", "frameset", "iframe", "link", "meta", "object",
+];
+
+/// Elements whose content is raw text that isn't parsed as HTML
+const RAW_TEXT_ELEMENTS: &[&str] = &["script", "style"];
+
+/// Attributes that reference a resource loaded along with the document
+const RESOURCE_ATTRIBUTES: &[&str] = &[
+    "background",
+    "codebase",
+    "data",
+    "dynsrc",
+    "lowsrc",
+    "poster",
+    "src",
+    "srcset",
+    "xlink:href",
+];
+
+/// Elements whose "href" is a link followed by the reader rather than a
+/// resource loaded with the document
+const LINK_ELEMENTS: &[&str] = &["a", "area"];
+
+/// Removes the references to external resources from the HTML. Elements that
+/// load resources (i.e iframes and stylesheet links) are removed, resource
+/// attributes (i.e the "src" of images) and CSS urls are removed unless they
+/// reference a part of the email ("cid:"), scripts and comments are removed.
+/// Links followed by the reader are kept
+pub fn remove_external_references(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut position = 0;
+
+    while let Some(offset) = html[position..].find('<') {
+        let start = position + offset;
+        output.push_str(&html[position..start]);
+
+        // Comments may hide content that some parsers handle (i.e conditional comments)
+        if let Some(comment) = html[start..].strip_prefix("<!--") {
+            position = match comment.find("-->") {
+                Some(end) => start + "<!--".len() + end + "-->".len(),
+                None => html.len(),
+            };
+            continue;
+        }
+
+        let Some(tag) = parse_tag(html, start) else {
+            // Not a tag, the "<" is text
+            output.push_str("&lt;");
+            position = start + 1;
+            continue;
+        };
+
+        position = tag.end;
+
+        if REMOVED_ELEMENTS.contains(&tag.name.as_str()) {
+            continue;
+        }
+
+        // Attributes of closing tags are ignored
+        if tag.closing {
+            output.push_str(&format!("</{}>", tag.name));
+            continue;
+        }
+
+        output.push_str(&rewrite_tag(html, &tag));
+
+        if !RAW_TEXT_ELEMENTS.contains(&tag.name.as_str()) || tag.self_closing {
+            continue;
+        }
+
+        // Raw text continues until the closing tag of the element
+        let close = format!("</{}", tag.name);
+        let end = html[position..]
+            .to_ascii_lowercase()
+            .find(&close)
+            .map_or(html.len(), |end| position + end);
+
+        if tag.name == "style" {
+            output.push_str(&remove_css_urls(&html[position..end]));
+        }
+
+        position = end;
+    }
+
+    output.push_str(&html[position..]);
+    output
+}
+
+/// Tag within a HTML document
+struct HtmlTag {
+    /// Lowercase name of the element
+    name: String,
+    /// Position after the end of the tag ('>')
+    end: usize,
+    /// Whether this is a closing tag ("</name>")
+    closing: bool,
+    /// Whether the tag is self closing ("/>")
+    self_closing: bool,
+    /// Lowercase name and position of each attribute (including the value)
+    attributes: Vec<(String, usize, usize)>,
+}
+
+/// Parses the tag starting at the provided position, provides [None] when
+/// the "<" doesn't start a tag
+fn parse_tag(html: &str, start: usize) -> Option<HtmlTag> {
+    let bytes = html.as_bytes();
+    let mut position = start + 1;
+
+    let closing = bytes.get(position) == Some(&b'/');
+    if closing {
+        position += 1;
+    }
+
+    if !bytes.get(position)?.is_ascii_alphabetic() {
+        return None;
+    }
+
+    let name_start = position;
+    while bytes
+        .get(position)
+        .is_some_and(|value| value.is_ascii_alphanumeric() || matches!(value, b'-' | b':'))
+    {
+        position += 1;
+    }
+    let name = html[name_start..position].to_ascii_lowercase();
+
+    let mut attributes = Vec::new();
+
+    loop {
+        while bytes
+            .get(position)
+            .is_some_and(|value| value.is_ascii_whitespace() || *value == b'/')
+        {
+            position += 1;
+        }
+
+        match bytes.get(position) {
+            Some(b'>') => {
+                return Some(HtmlTag {
+                    name,
+                    end: position + 1,
+                    closing,
+                    self_closing: bytes[position - 1] == b'/',
+                    attributes,
+                })
+            }
+            // Unclosed tags continue to the end of the document
+            None => {
+                return Some(HtmlTag {
+                    name,
+                    end: html.len(),
+                    closing,
+                    self_closing: false,
+                    attributes,
+                })
+            }
+            Some(_) => {}
+        }
+
+        let attribute_start = position;
+        while bytes.get(position).is_some_and(|value| {
+            !value.is_ascii_whitespace() && !matches!(value, b'=' | b'>' | b'/')
+        }) {
+            position += 1;
+        }
+        let attribute_name = html[attribute_start..position].to_ascii_lowercase();
+
+        // Whitespace is allowed around the "=" of the value
+        let mut value_position = position;
+        while bytes
+            .get(value_position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            value_position += 1;
+        }
+
+        if bytes.get(value_position) == Some(&b'=') {
+            position = value_position + 1;
+            while bytes.get(position).is_some_and(u8::is_ascii_whitespace) {
+                position += 1;
+            }
+
+            match bytes.get(position) {
+                Some(quote @ (b'"' | b'\'')) => {
+                    position = match html[position + 1..].find(*quote as char) {
+                        Some(end) => position + 1 + end + 1,
+                        None => html.len(),
+                    };
+                }
+                _ => {
+                    while bytes
+                        .get(position)
+                        .is_some_and(|value| !value.is_ascii_whitespace() && *value != b'>')
+                    {
+                        position += 1;
+                    }
+                }
+            }
+        }
+
+        // Values without a name (i.e a stray "=") are dropped
+        if attribute_name.is_empty() {
+            if position == attribute_start {
+                position += 1;
+            }
+            continue;
+        }
+
+        attributes.push((attribute_name, attribute_start, position));
+    }
+}
+
+/// Rewrites the start tag without the attributes referencing external resources
+fn rewrite_tag(html: &str, tag: &HtmlTag) -> String {
+    let mut output = format!("<{}", tag.name);
+
+    for (name, start, end) in &tag.attributes {
+        let attribute = &html[*start..*end];
+        let value = attribute_value(attribute);
+
+        let is_resource = RESOURCE_ATTRIBUTES.contains(&name.as_str())
+            || (name == "href" && !LINK_ELEMENTS.contains(&tag.name.as_str()));
+
+        if is_resource && !is_part_reference(value) {
+            continue;
+        }
+
+        // Event handlers can't run but may contain URLs
+        if name.starts_with("on") {
+            continue;
+        }
+
+        output.push(' ');
+
+        if name == "style" {
+            output.push_str(&remove_css_urls(attribute));
+        } else {
+            output.push_str(attribute);
+        }
+    }
+
+    output.push_str(if tag.self_closing { "/>" } else { ">" });
+    output
+}
+
+/// Value of an attribute from its source (i.e `src="cid:logo"`), without quotes
+fn attribute_value(attribute: &str) -> &str {
+    let Some((_, value)) = attribute.split_once('=') else {
+        return "";
+    };
+
+    value
+        .trim()
+        .trim_matches(|value| value == '"' || value == '\'')
+}
+
+/// Whether the URL references a part of the email ("cid:")
+fn is_part_reference(url: &str) -> bool {
+    url.trim()
+        .get(..4)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("cid:"))
+}
+
+/// Replaces the CSS urls (i.e backgrounds and imports) that don't reference
+/// a part of the email with "none"
+fn remove_css_urls(css: &str) -> String {
+    // ASCII lowercase keeps the same byte offsets as the original
+    let lowercase = css.to_ascii_lowercase();
+    let mut output = String::with_capacity(css.len());
+    let mut position = 0;
+
+    while let Some(offset) = lowercase[position..].find("url(") {
+        let start = position + offset;
+        let value_start = start + "url(".len();
+        let end = lowercase[value_start..]
+            .find(')')
+            .map_or(css.len(), |end| value_start + end + 1);
+
+        let url = css[value_start..end]
+            .trim_end_matches(')')
+            .trim()
+            .trim_matches(|value| value == '"' || value == '\'');
+
+        output.push_str(&css[position..start]);
+        if is_part_reference(url) {
+            output.push_str(&css[start..end]);
+        } else {
+            output.push_str("none");
+        }
+
+        position = end;
+    }
+
+    output.push_str(&css[position..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::remove_external_references;
+
+    #[test]
+    fn removes_remote_images() {
+        assert_eq!(
+            remove_external_references(
+                "<p>Hi</p><IMG SRC=\"https://tracker.example/pixel.gif\" width=1 alt='x'>"
+            ),
+            "<p>Hi</p><img width=1 alt='x'>"
+        );
+        assert_eq!(
+            remove_external_references("<img src=file:///etc/passwd>"),
+            "<img>"
+        );
+        assert_eq!(
+            remove_external_references("<img srcset=\"http://a.example/1.png 2x\"/>"),
+            "<img/>"
+        );
+    }
+
+    #[test]
+    fn keeps_part_references() {
+        let html = "<img src=\"cid:logo@example\" alt=\"Logo\">";
+        assert_eq!(remove_external_references(html), html);
+    }
+
+    #[test]
+    fn keeps_links() {
+        let html = "<a href=\"https://example.com\">Example</a>";
+        assert_eq!(remove_external_references(html), html);
+    }
+
+    #[test]
+    fn removes_resource_elements() {
+        assert_eq!(
+            remove_external_references(
+                "<link rel=\"stylesheet\" href=\"https://a.example/s.css\">\
+                 <iframe src=\"https://a.example\">Fallback</iframe>\
+                 <base href=\"https://a.example/\">\
+                 <meta http-equiv=\"refresh\" content=\"0;url=https://a.example\">\
+                 <object data=\"https://a.example/o\"></object>"
+            ),
+            "Fallback"
+        );
+    }
+
+    #[test]
+    fn removes_css_urls() {
+        assert_eq!(
+            remove_external_references(
+                "<style>@import url(\"https://a.example/s.css\");\
+                 body { background: URL(https://a.example/b.png) }\
+                 .logo { background: url('cid:logo') }</style>"
+            ),
+            "<style>@import none;\
+             body { background: none }\
+             .logo { background: url('cid:logo') }</style>"
+        );
+        assert_eq!(
+            remove_external_references(
+                "<td style=\"background-image: url(http://a.example/b.png)\" background=\"http://a.example/b.png\">"
+            ),
+            "<td style=\"background-image: none\">"
+        );
+    }
+
+    #[test]
+    fn removes_scripts_and_comments() {
+        assert_eq!(
+            remove_external_references(
+                "<!--[if mso]><img src=\"http://a.example\"><![endif]-->\
+                 <script>new Image().src = \"<img src=http://a.example>\"</script>\
+                 <p onclick=\"fetch('http://a.example')\">Text</p>"
+            ),
+            "<script></script><p>Text</p>"
+        );
+    }
+
+    #[test]
+    fn drops_values_without_names() {
+        assert_eq!(
+            remove_external_references("<p = \"x\" =y>Text</p>"),
+            "<p>Text</p>"
+        );
+    }
+
+    #[test]
+    fn escapes_text_that_isnt_a_tag() {
+        assert_eq!(remove_external_references("1 < 2"), "1 &lt; 2");
+    }
+}
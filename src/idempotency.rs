@@ -0,0 +1,465 @@
+use crate::{
+    error::{DynHttpError, HttpError},
+    tenant::Tenant,
+};
+use axum::{
+    body::{to_bytes, Body, HttpBody},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use parking_lot::Mutex;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::{debug, error};
+
+/// Header clients provide the idempotency key in
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header set on responses that were replayed from an earlier request
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Maximum length of an idempotency key
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Time between runs of the expired response collector
+const COLLECT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Errors that can occur when handling idempotent requests
+#[derive(Debug, Error)]
+pub enum IdempotencyError {
+    /// Idempotency key header was empty, too long or contained invalid characters
+    #[error("idempotency key must be between 1 and 255 visible ASCII characters")]
+    InvalidKey,
+
+    /// Idempotency key was used for an earlier request with a different
+    /// body or query
+    #[error("idempotency key was already used for a different request")]
+    KeyReused,
+
+    /// Request body is larger than the maximum body size
+    #[error("request body exceeds the maximum size of {0} bytes")]
+    RequestTooLarge(u64),
+
+    /// Failed to read the request body to compare with earlier requests
+    #[error("failed to read request body")]
+    RequestBody(#[source] axum::Error),
+
+    /// Failed to read the response to store for replaying
+    #[error("failed to read response body")]
+    ResponseBody(#[source] axum::Error),
+}
+
+impl HttpError for IdempotencyError {
+    fn log(&self) {
+        if let IdempotencyError::ResponseBody(err) = self {
+            error!(%err, "{self}");
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            IdempotencyError::InvalidKey | IdempotencyError::RequestBody(_) => {
+                StatusCode::BAD_REQUEST
+            }
+            IdempotencyError::KeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            IdempotencyError::RequestTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            IdempotencyError::ResponseBody(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            IdempotencyError::InvalidKey => Some("INVALID_IDEMPOTENCY_KEY"),
+            IdempotencyError::KeyReused => Some("IDEMPOTENCY_KEY_REUSED"),
+            IdempotencyError::RequestTooLarge(_) => Some("FILE_TOO_LARGE"),
+            IdempotencyError::RequestBody(_) | IdempotencyError::ResponseBody(_) => None,
+        }
+    }
+}
+
+/// Reads the idempotency key from the request headers, provides [None]
+/// when the request did not include a key
+pub fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, IdempotencyError> {
+    let value = match headers.get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+
+    let value = value.to_str().map_err(|_| IdempotencyError::InvalidKey)?;
+
+    if value.is_empty()
+        || value.len() > MAX_KEY_LENGTH
+        || !value.bytes().all(|value| value.is_ascii_graphic())
+    {
+        return Err(IdempotencyError::InvalidKey);
+    }
+
+    Ok(Some(value.to_string()))
+}
+
+/// Key identifying requests that are the same, keys are scoped to
+/// the tenant and the route so they can't collide
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct EntryKey {
+    /// Name of the tenant that made the request
+    tenant: Option<String>,
+    /// Path the request was made to
+    path: String,
+    /// Idempotency key provided by the client
+    key: String,
+}
+
+/// Response stored to replay for repeated requests
+struct StoredResponse {
+    /// Hash of the query and body of the request the response is for
+    fingerprint: [u8; 32],
+    /// Status of the response
+    status: StatusCode,
+    /// Headers of the response
+    headers: HeaderMap,
+    /// Response body
+    body: Bytes,
+}
+
+impl StoredResponse {
+    /// Creates a copy of the response marked as replayed
+    fn replay(&self) -> Response {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+            .headers_mut()
+            .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+        response
+    }
+}
+
+/// Entry for an idempotency key
+enum Entry {
+    /// Request with the key is still running, the sender is dropped once
+    /// the request finishes to wake any repeated requests waiting on it
+    Pending(watch::Sender<()>),
+    /// Request with the key completed successfully
+    Completed {
+        /// Response to replay
+        response: Arc<StoredResponse>,
+        /// When the response should no longer be replayed
+        expires_at: Instant,
+    },
+}
+
+impl Entry {
+    /// Size in bytes of the stored response body
+    fn size(&self) -> u64 {
+        match self {
+            Entry::Pending(_) => 0,
+            Entry::Completed { response, .. } => response.body.len() as u64,
+        }
+    }
+}
+
+/// Entries for each idempotency key
+#[derive(Default)]
+struct Entries {
+    /// Entries keyed by the request they are for
+    entries: HashMap<EntryKey, Entry>,
+    /// Total size in bytes of the stored response bodies
+    size: u64,
+}
+
+impl Entries {
+    /// Inserts the entry for the key replacing any previous entry
+    fn insert(&mut self, key: EntryKey, entry: Entry) {
+        self.size += entry.size();
+
+        if let Some(previous) = self.entries.insert(key, entry) {
+            self.size -= previous.size();
+        }
+    }
+
+    /// Removes the entry for the key
+    fn remove(&mut self, key: &EntryKey) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.size -= entry.size();
+        }
+    }
+
+    /// Removes the completed entry that expires first, provides whether
+    /// an entry was removed
+    fn remove_oldest(&mut self) -> bool {
+        let oldest = self
+            .entries
+            .iter()
+            .filter_map(|(key, entry)| match entry {
+                Entry::Completed { expires_at, .. } => Some((key, *expires_at)),
+                Entry::Pending(_) => None,
+            })
+            .min_by_key(|(_, expires_at)| *expires_at)
+            .map(|(key, _)| key.clone());
+
+        match oldest {
+            Some(key) => {
+                self.remove(&key);
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Outcome of claiming an idempotency key
+enum Claim<'a> {
+    /// No other request has used the key, the request should run
+    Run(PendingGuard<'a>),
+    /// Request with the same key is running, wait for it to finish
+    Wait(watch::Receiver<()>),
+    /// Request with the same key completed, replay its response
+    Replay(Arc<StoredResponse>),
+}
+
+/// Store of the responses for requests made with an idempotency key so
+/// repeated requests (i.e retries) don't convert the same file again
+pub struct IdempotencyStore {
+    /// Entries for each idempotency key
+    entries: Mutex<Entries>,
+    /// Time to keep completed responses for
+    ttl: Duration,
+    /// Maximum total size in bytes of the stored responses, the responses
+    /// that expire first are removed to make room for new responses
+    max_size: u64,
+    /// Maximum size in bytes of a request body read to compare requests
+    max_request_size: u64,
+}
+
+impl IdempotencyStore {
+    /// Creates a new store keeping responses for the provided duration, up to
+    /// the provided total size. Requests with bodies larger than the provided
+    /// maximum request size are rejected
+    pub fn new(ttl: Duration, max_size: u64, max_request_size: u64) -> Arc<Self> {
+        Arc::new(Self {
+            entries: Default::default(),
+            ttl,
+            max_size,
+            max_request_size,
+        })
+    }
+
+    /// Claims the idempotency key for a request
+    fn claim(&self, key: &EntryKey) -> Claim<'_> {
+        let entries = &mut *self.entries.lock();
+
+        match entries.entries.get(key) {
+            Some(Entry::Pending(sender)) => return Claim::Wait(sender.subscribe()),
+            Some(Entry::Completed {
+                response,
+                expires_at,
+            }) if *expires_at > Instant::now() => return Claim::Replay(response.clone()),
+            _ => {}
+        }
+
+        let (sender, _) = watch::channel(());
+        entries.insert(key.clone(), Entry::Pending(sender));
+
+        Claim::Run(PendingGuard {
+            store: self,
+            key: Some(key.clone()),
+        })
+    }
+
+    /// Removes completed responses that have expired
+    fn collect_expired(&self) {
+        let now = Instant::now();
+
+        let entries = &mut *self.entries.lock();
+        let expired: Vec<EntryKey> = entries
+            .entries
+            .iter()
+            .filter(|(_, entry)| match entry {
+                Entry::Pending(_) => false,
+                Entry::Completed { expires_at, .. } => *expires_at <= now,
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in &expired {
+            entries.remove(key);
+        }
+    }
+}
+
+/// Guard over a claimed idempotency key, the key is released if the
+/// request fails or is cancelled before completing
+struct PendingGuard<'a> {
+    /// Store the key was claimed from
+    store: &'a IdempotencyStore,
+    /// Claimed key, taken once completed
+    key: Option<EntryKey>,
+}
+
+impl PendingGuard<'_> {
+    /// Stores the response for the claimed key, responses that would remove
+    /// every other response aren't stored
+    fn complete(mut self, response: StoredResponse) {
+        if response.body.len() as u64 > self.store.max_size {
+            return;
+        }
+
+        let key = match self.key.take() {
+            Some(value) => value,
+            None => return,
+        };
+
+        let entry = Entry::Completed {
+            response: Arc::new(response),
+            expires_at: Instant::now() + self.store.ttl,
+        };
+
+        let entries = &mut *self.store.entries.lock();
+
+        // Replacing the pending entry drops the sender waking any waiters
+        entries.insert(key, entry);
+
+        while entries.size > self.store.max_size && entries.remove_oldest() {
+            debug!("removing idempotent response to stay within the size limit");
+        }
+    }
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            self.store.entries.lock().remove(&key);
+        }
+    }
+}
+
+/// Spawns the background task that removes expired responses
+pub fn spawn_idempotency_collector(store: Arc<IdempotencyStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(COLLECT_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            store.collect_expired();
+        }
+    });
+}
+
+/// Reads the request body providing the body along with the hash of the
+/// query and body identifying the request, fails when the body is larger
+/// than the maximum size
+async fn fingerprint_request(
+    body: Body,
+    query: Option<&str>,
+    max_size: u64,
+) -> Result<(Bytes, [u8; 32]), IdempotencyError> {
+    let mut hasher = Sha256::new();
+    hasher.update(query.unwrap_or_default());
+    // Separates the query from the body
+    hasher.update([0]);
+
+    let mut buffer = BytesMut::new();
+    let mut stream = body.into_data_stream();
+
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(IdempotencyError::RequestBody)?;
+
+        if (buffer.len() + chunk.len()) as u64 > max_size {
+            return Err(IdempotencyError::RequestTooLarge(max_size));
+        }
+
+        hasher.update(&chunk);
+        buffer.extend_from_slice(&chunk);
+    }
+
+    Ok((buffer.freeze(), hasher.finalize().into()))
+}
+
+/// Middleware replaying the response of an earlier request made with the
+/// same idempotency key. Repeated requests made while the original is still
+/// running wait for it to finish. Only successful responses are stored so
+/// failed requests can be retried, requests with a different body or query
+/// than the request the response was stored for are rejected
+pub async fn idempotent(
+    State(store): State<Arc<IdempotencyStore>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    let key = match idempotency_key(request.headers())? {
+        Some(value) => value,
+        None => return Ok(next.run(request).await),
+    };
+
+    let key = EntryKey {
+        tenant: request
+            .extensions()
+            .get::<Arc<Tenant>>()
+            .map(|tenant| tenant.name.clone()),
+        path: request.uri().path().to_string(),
+        key,
+    };
+
+    let (parts, body) = request.into_parts();
+    let (body, fingerprint) =
+        fingerprint_request(body, parts.uri.query(), store.max_request_size).await?;
+    let request = Request::from_parts(parts, Body::from(body));
+
+    let guard = loop {
+        let mut receiver = match store.claim(&key) {
+            Claim::Run(guard) => break guard,
+            Claim::Wait(receiver) => receiver,
+            Claim::Replay(response) if response.fingerprint != fingerprint => {
+                return Err(IdempotencyError::KeyReused.into());
+            }
+            Claim::Replay(response) => {
+                debug!(key = key.key, "replaying idempotent response");
+                return Ok(response.replay());
+            }
+        };
+
+        // Resolves once the original request finishes and drops the sender
+        _ = receiver.changed().await;
+    };
+
+    let response = next.run(request).await;
+
+    if !response.status().is_success() {
+        return Ok(response);
+    }
+
+    // Responses too large to store are sent without being read into memory,
+    // dropping the guard releases the key
+    let max_size = store.max_size;
+    let fits = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size <= max_size);
+    if !fits {
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::try_from(max_size).unwrap_or(usize::MAX))
+        .await
+        .map_err(IdempotencyError::ResponseBody)?;
+
+    guard.complete(StoredResponse {
+        fingerprint,
+        status: parts.status,
+        headers: parts.headers.clone(),
+        body: body.clone(),
+    });
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
@@ -0,0 +1,175 @@
+use crate::{
+    error::HttpError,
+    page::{Orientation, PageSetup},
+};
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use imagesize::ImageType;
+use thiserror::Error;
+
+/// Errors that can occur while preparing an image for conversion
+#[derive(Debug, Error)]
+pub enum ImageError {
+    /// Image dimensions could not be read from the image header
+    #[error("failed to read image dimensions: {0}")]
+    InvalidImage(#[from] imagesize::ImageError),
+
+    /// Margin leaves no space on the page for the image
+    #[error("margin of {0}mm is too large for the requested page size")]
+    InvalidMargin(f32),
+
+    /// Multi-page TIFF images can only use their own page sizes
+    #[error("page size cannot be applied to multi-page TIFF images")]
+    MultiPageTiff,
+}
+
+impl HttpError for ImageError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+}
+
+/// Prepares the provided file for conversion when its an image and a page
+/// setup was requested.
+///
+/// Images are imported by LibreOffice Draw onto a page that matches the size
+/// of the image, to use a specific page size the image is instead embedded
+/// within a flat OpenDocument drawing (FODG) that uses the requested page
+/// layout with the image scaled to fit within the margins.
+///
+/// Files that aren't images (or when no page setup was requested) are left
+/// unchanged and use the standard import path
+pub fn prepare_image(bytes: Bytes, page_setup: Option<PageSetup>) -> Result<Bytes, ImageError> {
+    let page_setup = match page_setup {
+        Some(value) => value,
+        None => return Ok(bytes),
+    };
+
+    let image_type = match imagesize::image_type(&bytes) {
+        Ok(value) if is_supported_image(value) => value,
+        _ => return Ok(bytes),
+    };
+
+    if image_type == ImageType::Tiff && is_multi_page_tiff(&bytes) {
+        return Err(ImageError::MultiPageTiff);
+    }
+
+    let size = imagesize::blob_size(&bytes)?;
+    let document = create_drawing(&bytes, (size.width, size.height), page_setup)?;
+
+    Ok(Bytes::from(document))
+}
+
+/// Image formats that can be embedded within a drawing
+fn is_supported_image(image_type: ImageType) -> bool {
+    matches!(
+        image_type,
+        ImageType::Png | ImageType::Jpeg | ImageType::Tiff | ImageType::Gif | ImageType::Bmp
+    )
+}
+
+/// Checks if a TIFF image contains more than one image file directory (page)
+fn is_multi_page_tiff(bytes: &[u8]) -> bool {
+    let little_endian = match bytes.get(0..2) {
+        Some(b"II") => true,
+        Some(b"MM") => false,
+        _ => return false,
+    };
+
+    let read_u16 = |offset: usize| -> Option<usize> {
+        let value: [u8; 2] = bytes.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(value)
+        } else {
+            u16::from_be_bytes(value)
+        } as usize)
+    };
+
+    let read_u32 = |offset: usize| -> Option<usize> {
+        let value: [u8; 4] = bytes.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(value)
+        } else {
+            u32::from_be_bytes(value)
+        } as usize)
+    };
+
+    let next_ifd_offset = || -> Option<usize> {
+        // Only classic TIFF files are checked (BigTIFF uses version 43)
+        if read_u16(2)? != 42 {
+            return None;
+        }
+
+        let first_ifd = read_u32(4)?;
+        let entry_count = read_u16(first_ifd)?;
+
+        // Each directory entry is 12 bytes followed by the next directory offset
+        read_u32(first_ifd + 2 + entry_count * 12)
+    };
+
+    next_ifd_offset().is_some_and(|offset| offset != 0)
+}
+
+/// Creates a flat OpenDocument drawing containing the provided image centered
+/// and scaled to fit within the margins of the page
+fn create_drawing(
+    image: &[u8],
+    (width_px, height_px): (usize, usize),
+    page_setup: PageSetup,
+) -> Result<String, ImageError> {
+    let (mut page_width, mut page_height) = page_setup.size.dimensions_mm();
+    let margin = page_setup.margin_mm;
+
+    // Landscape images use a landscape page unless an orientation was requested
+    let landscape = match page_setup.orientation {
+        Some(orientation) => orientation == Orientation::Landscape,
+        None => width_px > height_px,
+    };
+    if landscape {
+        std::mem::swap(&mut page_width, &mut page_height);
+    }
+
+    let area_width = page_width - margin * 2.0;
+    let area_height = page_height - margin * 2.0;
+
+    if !margin.is_finite() || margin < 0.0 || area_width <= 0.0 || area_height <= 0.0 {
+        return Err(ImageError::InvalidMargin(margin));
+    }
+
+    // Scale the image to fit within the printable area
+    let scale = f32::min(
+        area_width / width_px.max(1) as f32,
+        area_height / height_px.max(1) as f32,
+    );
+    let width = width_px as f32 * scale;
+    let height = height_px as f32 * scale;
+    let x = (page_width - width) / 2.0;
+    let y = (page_height - height) / 2.0;
+
+    let orientation = if landscape { "landscape" } else { "portrait" };
+    let image = STANDARD.encode(image);
+
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:draw="urn:oasis:names:tc:opendocument:xmlns:drawing:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0" xmlns:xlink="http://www.w3.org/1999/xlink" office:version="1.3" office:mimetype="application/vnd.oasis.opendocument.graphics">
+<office:automatic-styles>
+<style:page-layout style:name="PM1">
+<style:page-layout-properties fo:margin-top="{margin}mm" fo:margin-bottom="{margin}mm" fo:margin-left="{margin}mm" fo:margin-right="{margin}mm" fo:page-width="{page_width}mm" fo:page-height="{page_height}mm" style:print-orientation="{orientation}"/>
+</style:page-layout>
+</office:automatic-styles>
+<office:master-styles>
+<style:master-page style:name="Default" style:page-layout-name="PM1"/>
+</office:master-styles>
+<office:body>
+<office:drawing>
+<draw:page draw:name="page1" draw:master-page-name="Default">
+<draw:frame svg:x="{x}mm" svg:y="{y}mm" svg:width="{width}mm" svg:height="{height}mm">
+<draw:image><office:binary-data>{image}</office:binary-data></draw:image>
+</draw:frame>
+</draw:page>
+</office:drawing>
+</office:body>
+</office:document>"#
+    ))
+}
@@ -0,0 +1,1461 @@
+use crate::{
+    batch::{create_archive, extract_archive, ArchiveEntry, BatchError, BatchLimits},
+    compression::decompress_file,
+    config::{Config, InvalidExportOptions, SharedConfig},
+    detect::detect_input_format,
+    error::{DynHttpError, HttpError},
+    failures::{InputDetails, RecentFailures},
+    idempotency::{idempotency_key, IDEMPOTENT_REPLAYED_HEADER},
+    priority::{request_priority, RequestPriority},
+    range::range_response,
+    storage::Storage,
+    tenant::{Tenant, Tenants},
+    ws::MaxFileSize,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use office_convert_core::{
+    ConversionWarning, Converter, OfficeDetails, OfficeEvent, PdfExportOptions, Priority,
+};
+use parking_lot::Mutex;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tokio::sync::{
+    mpsc::{self, UnboundedReceiver},
+    Notify,
+};
+use tracing::{debug, error, info, warn};
+
+/// Time between runs of the expired job collector
+const COLLECT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Current state of a job
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    /// Job is waiting to be converted
+    Queued,
+    /// Job is currently being converted
+    Processing,
+    /// Job was converted successfully
+    Completed,
+    /// Job failed to convert
+    Failed,
+    /// Job was cancelled before it finished
+    Cancelled,
+}
+
+impl JobStatus {
+    /// Whether the job has finished processing
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// Details about a job, persisted alongside the job files when
+/// using a queue directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobMetadata {
+    /// Unique ID of the job
+    pub id: String,
+    /// Current state of the job
+    pub status: JobStatus,
+    /// Unix timestamp in seconds of when the job was created
+    pub created_at: u64,
+    /// Unix timestamp in seconds of when the job finished
+    pub completed_at: Option<u64>,
+    /// Reason the job failed
+    pub error: Option<String>,
+    /// Machine readable code for the reason the job failed
+    pub error_code: Option<String>,
+    /// Name of the tenant that submitted the job
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Idempotency key the job was submitted with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+    /// Priority of the job when waiting for office
+    #[serde(default)]
+    pub priority: Priority,
+    /// Progress of the current office operation (loading or exporting) as
+    /// a percentage while the job is processing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<u8>,
+    /// Problems office recovered from while converting, the converted PDF
+    /// may be missing content
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<ConversionWarning>,
+    /// Number of pages exported as a preview before the full conversion
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview_pages: Option<u32>,
+    /// Whether the preview of the first pages is available
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub preview_ready: bool,
+    /// Progress converting the documents of a batch job, only present
+    /// for batch jobs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub batch: Option<BatchProgress>,
+}
+
+/// Progress converting the documents within the ZIP archive of a batch job
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchProgress {
+    /// Number of documents within the archive, known once the archive
+    /// has been extracted
+    pub total: Option<usize>,
+    /// Number of documents converted, documents are converted in order
+    /// and each converted document is checkpointed so a job resumed after
+    /// a restart continues from the next document
+    pub completed: usize,
+}
+
+/// Job tracked by the store
+struct Job {
+    /// Details about the job
+    metadata: JobMetadata,
+    /// Input file bytes, only stored in memory when not using a queue directory
+    input: Option<Bytes>,
+    /// Converted file bytes, only stored in memory when not using a queue directory
+    result: Option<Bytes>,
+    /// Preview of the first pages, only stored in memory when not using a queue directory
+    preview: Option<Bytes>,
+    /// Notified when the job is cancelled while processing
+    cancel: Arc<Notify>,
+}
+
+/// Errors that can occur when accessing jobs
+#[derive(Debug, Error)]
+pub enum JobError {
+    /// Job does not exist or has expired
+    #[error("job not found")]
+    NotFound,
+
+    /// Job result was requested before the job finished
+    #[error("job has not finished")]
+    NotFinished,
+
+    /// Job result was requested for a failed job
+    #[error("job failed: {0}")]
+    Failed(String),
+
+    /// Job result was requested for a cancelled job
+    #[error("job was cancelled")]
+    Cancelled,
+
+    /// Preview was requested for a job submitted without preview_pages
+    #[error("job was submitted without a preview")]
+    NoPreview,
+
+    /// Preview was requested before it was exported
+    #[error("job preview is not ready")]
+    PreviewNotReady,
+
+    /// Failed to read or write job files
+    #[error(transparent)]
+    Storage(#[from] anyhow::Error),
+}
+
+impl HttpError for JobError {
+    fn log(&self) {
+        match self {
+            // Anyhow errors contain a stacktrace so only the debug variant is used
+            JobError::Storage(err) => error!("{err:#?}"),
+            _ => error!("{self}: {self:?}"),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            JobError::NotFound | JobError::NoPreview => StatusCode::NOT_FOUND,
+            JobError::NotFinished
+            | JobError::Failed(_)
+            | JobError::Cancelled
+            | JobError::PreviewNotReady => StatusCode::CONFLICT,
+            JobError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            JobError::NotFound => Some("JOB_NOT_FOUND"),
+            JobError::NotFinished => Some("JOB_NOT_FINISHED"),
+            JobError::Failed(_) => Some("JOB_FAILED"),
+            JobError::Cancelled => Some("JOB_CANCELLED"),
+            JobError::NoPreview => Some("JOB_NO_PREVIEW"),
+            JobError::PreviewNotReady => Some("JOB_PREVIEW_NOT_READY"),
+            JobError::Storage(_) => None,
+        }
+    }
+}
+
+/// Queue of jobs waiting to be processed, each tenant has its own queue and
+/// jobs are taken from the queues in weighted round-robin order so a tenant
+/// queueing many jobs doesn't hold up the jobs of other tenants
+#[derive(Default)]
+struct JobQueue {
+    /// State of the queues
+    state: Mutex<JobQueueState>,
+    /// Notified when a job is queued
+    notify: Notify,
+}
+
+#[derive(Default)]
+struct JobQueueState {
+    /// IDs of the queued jobs for each tenant, jobs without a tenant
+    /// share a queue
+    queues: HashMap<Option<String>, VecDeque<String>>,
+    /// Tenants with queued jobs in the order they take turns, the
+    /// tenant at the front is taking its turn
+    turns: VecDeque<Option<String>>,
+    /// Number of jobs taken during the current turn
+    taken: u32,
+}
+
+impl JobQueue {
+    /// Adds a job to the end of the queue for its tenant
+    fn push(&self, tenant: Option<String>, id: String) {
+        {
+            let state = &mut *self.state.lock();
+            let queue = state.queues.entry(tenant.clone()).or_default();
+
+            // Tenants without queued jobs join the end of the turns
+            if queue.is_empty() {
+                state.turns.push_back(tenant);
+            }
+
+            queue.push_back(id);
+        }
+
+        self.notify.notify_one();
+    }
+
+    /// Removes a queued job from the queue for its tenant, provides whether
+    /// the job was queued
+    fn remove(&self, tenant: &Option<String>, id: &str) -> bool {
+        let state = &mut *self.state.lock();
+        let Some(queue) = state.queues.get_mut(tenant) else {
+            return false;
+        };
+
+        let Some(index) = queue.iter().position(|queued| queued == id) else {
+            return false;
+        };
+
+        queue.remove(index);
+
+        // Tenants without queued jobs give up their turn
+        if queue.is_empty() {
+            state.queues.remove(tenant);
+
+            if let Some(turn) = state.turns.iter().position(|turn| turn == tenant) {
+                if turn == 0 {
+                    state.taken = 0;
+                }
+
+                state.turns.remove(turn);
+            }
+        }
+
+        true
+    }
+
+    /// Takes the next job from the tenant taking its turn, the turn passes to
+    /// the next tenant once the tenant has taken as many jobs as its weight
+    fn pop(&self, weight: &impl Fn(Option<&str>) -> u32) -> Option<String> {
+        let state = &mut *self.state.lock();
+        let tenant = state.turns.front()?.clone();
+        let queue = state.queues.get_mut(&tenant)?;
+        let id = queue.pop_front()?;
+
+        state.taken += 1;
+
+        if queue.is_empty() {
+            state.queues.remove(&tenant);
+            state.turns.pop_front();
+            state.taken = 0;
+        } else if state.taken >= weight(tenant.as_deref()) {
+            state.turns.rotate_left(1);
+            state.taken = 0;
+        }
+
+        Some(id)
+    }
+
+    /// Waits for the next job to process
+    async fn next(&self, weight: impl Fn(Option<&str>) -> u32) -> String {
+        loop {
+            if let Some(id) = self.pop(&weight) {
+                return id;
+            }
+
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// Store for tracking asynchronous conversion jobs, optionally persisting
+/// the jobs to storage so that they survive a restart
+pub struct JobStore {
+    /// Jobs tracked by the store
+    jobs: Mutex<HashMap<String, Job>>,
+    /// Queue of jobs waiting to be processed
+    queue: JobQueue,
+    /// Storage to persist jobs to
+    storage: Option<Arc<dyn Storage>>,
+    /// Time to keep finished jobs for before they are removed
+    ttl: Duration,
+}
+
+/// Gets the current unix timestamp in seconds
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default()
+}
+
+impl JobStore {
+    /// Creates a new job store
+    pub fn new(storage: Option<Arc<dyn Storage>>, ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            jobs: Default::default(),
+            queue: JobQueue::default(),
+            storage,
+            ttl,
+        })
+    }
+
+    /// Storage key for the metadata of a job
+    fn metadata_key(id: &str) -> String {
+        format!("{id}.json")
+    }
+
+    /// Storage key for the input file of a job
+    fn input_key(id: &str) -> String {
+        format!("{id}.input")
+    }
+
+    /// Storage key for the result file of a job
+    fn result_key(id: &str) -> String {
+        format!("{id}.pdf")
+    }
+
+    /// Storage key for the preview file of a job
+    fn preview_key(id: &str) -> String {
+        format!("{id}.preview.pdf")
+    }
+
+    /// Storage key for a converted document of a batch job
+    fn checkpoint_key(id: &str, index: usize) -> String {
+        format!("{id}.entry-{index}.pdf")
+    }
+
+    /// Writes the job metadata to storage if jobs are persisted
+    async fn persist(&self, metadata: &JobMetadata) -> anyhow::Result<()> {
+        let storage = match &self.storage {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        let bytes = serde_json::to_vec(metadata).context("failed to serialize job")?;
+
+        storage
+            .put(&Self::metadata_key(&metadata.id), Bytes::from(bytes))
+            .await
+            .context("failed to write job metadata")
+    }
+
+    /// Removes all the files for a job from storage
+    async fn remove_files(&self, id: &str) {
+        let storage = match &self.storage {
+            Some(value) => value,
+            None => return,
+        };
+
+        for key in [
+            Self::metadata_key(id),
+            Self::input_key(id),
+            Self::result_key(id),
+            Self::preview_key(id),
+        ] {
+            storage.delete(&key).await;
+        }
+    }
+
+    /// Removes the checkpointed documents of a batch job from storage
+    async fn remove_checkpoints(&self, id: &str) {
+        let storage = match &self.storage {
+            Some(value) => value,
+            None => return,
+        };
+
+        let count = self
+            .jobs
+            .lock()
+            .get(id)
+            .and_then(|job| job.metadata.batch.as_ref())
+            .map_or(0, |progress| progress.total.unwrap_or(progress.completed));
+
+        for index in 0..count {
+            storage.delete(&Self::checkpoint_key(id, index)).await;
+        }
+    }
+
+    /// Updates the metadata of a job, persisting the changes
+    async fn update<F>(&self, id: &str, update: F) -> anyhow::Result<()>
+    where
+        F: FnOnce(&mut JobMetadata),
+    {
+        let metadata = {
+            let jobs = &mut *self.jobs.lock();
+            let job = jobs.get_mut(id).context("unknown job")?;
+            update(&mut job.metadata);
+            job.metadata.clone()
+        };
+
+        self.persist(&metadata).await
+    }
+
+    /// Submits a new job to convert the provided input, provides the job
+    /// metadata along with whether the job was created. When a job from the
+    /// same tenant with the same idempotency key exists that job is provided
+    /// instead of creating a new job. Jobs with `preview_pages` export the
+    /// first pages as a preview before the full conversion. Batch jobs
+    /// convert each document within the ZIP archive input
+    pub async fn submit(
+        &self,
+        input: Bytes,
+        tenant: Option<String>,
+        idempotency_key: Option<String>,
+        priority: Priority,
+        preview_pages: Option<u32>,
+        batch: bool,
+    ) -> anyhow::Result<(JobMetadata, bool)> {
+        let id = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(|value| value as char)
+            .collect::<String>();
+
+        let metadata = JobMetadata {
+            id: id.clone(),
+            status: JobStatus::Queued,
+            created_at: now_secs(),
+            completed_at: None,
+            error: None,
+            error_code: None,
+            tenant,
+            idempotency_key,
+            priority,
+            progress: None,
+            warnings: Vec::new(),
+            preview_pages,
+            preview_ready: false,
+            batch: batch.then(BatchProgress::default),
+        };
+
+        // Persisted jobs keep their input in storage instead of in memory
+        let (input, stored_input) = match &self.storage {
+            Some(_) => (Some(input), None),
+            None => (None, Some(input)),
+        };
+
+        // Job is tracked before its files are written so that repeated
+        // submissions with the same idempotency key find it
+        {
+            let jobs = &mut *self.jobs.lock();
+
+            if let Some(existing) = metadata.idempotency_key.as_ref().and_then(|key| {
+                jobs.values().find(|job| {
+                    job.metadata.idempotency_key.as_ref() == Some(key)
+                        && job.metadata.tenant == metadata.tenant
+                })
+            }) {
+                return Ok((existing.metadata.clone(), false));
+            }
+
+            jobs.insert(
+                id.clone(),
+                Job {
+                    metadata: metadata.clone(),
+                    input: stored_input,
+                    result: None,
+                    preview: None,
+                    cancel: Arc::default(),
+                },
+            );
+        }
+
+        if let Err(err) = self.write_submitted(&metadata, input).await {
+            self.jobs.lock().remove(&id);
+            self.remove_files(&id).await;
+            return Err(err);
+        }
+
+        self.queue.push(metadata.tenant.clone(), id);
+
+        Ok((metadata, true))
+    }
+
+    /// Writes the files for a newly submitted job
+    async fn write_submitted(
+        &self,
+        metadata: &JobMetadata,
+        input: Option<Bytes>,
+    ) -> anyhow::Result<()> {
+        if let (Some(storage), Some(input)) = (&self.storage, input) {
+            storage
+                .put(&Self::input_key(&metadata.id), input)
+                .await
+                .context("failed to write job input")?;
+        }
+
+        self.persist(metadata).await
+    }
+
+    /// Gets the metadata for a job, jobs submitted by a tenant are
+    /// only accessible to the same tenant
+    pub fn get(&self, id: &str, tenant: Option<&str>) -> Option<JobMetadata> {
+        self.jobs
+            .lock()
+            .get(id)
+            .filter(|job| job.metadata.tenant.as_deref() == tenant)
+            .map(|job| job.metadata.clone())
+    }
+
+    /// Mime type and file extension of the result of a job, the result of
+    /// a batch job is a ZIP archive of the converted documents
+    pub fn result_type(&self, id: &str) -> (&'static str, &'static str) {
+        let batch = self
+            .jobs
+            .lock()
+            .get(id)
+            .is_some_and(|job| job.metadata.batch.is_some());
+
+        match batch {
+            true => ("application/zip", "zip"),
+            false => ("application/pdf", "pdf"),
+        }
+    }
+
+    /// Gets the converted result for a job, jobs submitted by a tenant
+    /// are only accessible to the same tenant
+    pub async fn result(&self, id: &str, tenant: Option<&str>) -> Result<Bytes, JobError> {
+        self.load_result(id, |metadata| metadata.tenant.as_deref() == tenant)
+            .await
+    }
+
+    /// Gets the converted result for a job without checking which tenant
+    /// submitted the job, for use when access has already been verified
+    pub async fn result_unchecked(&self, id: &str) -> Result<Bytes, JobError> {
+        self.load_result(id, |_| true).await
+    }
+
+    /// Gets the converted result for a job if the job metadata
+    /// passes the provided access check
+    async fn load_result<F>(&self, id: &str, check_access: F) -> Result<Bytes, JobError>
+    where
+        F: FnOnce(&JobMetadata) -> bool,
+    {
+        let result = {
+            let jobs = &*self.jobs.lock();
+            let job = jobs
+                .get(id)
+                .filter(|job| check_access(&job.metadata))
+                .ok_or(JobError::NotFound)?;
+
+            match job.metadata.status {
+                JobStatus::Completed => {}
+                JobStatus::Failed => {
+                    return Err(JobError::Failed(
+                        job.metadata.error.clone().unwrap_or_default(),
+                    ))
+                }
+                JobStatus::Cancelled => return Err(JobError::Cancelled),
+                _ => return Err(JobError::NotFinished),
+            }
+
+            job.result.clone()
+        };
+
+        if let Some(result) = result {
+            return Ok(result);
+        }
+
+        let storage = self.storage.as_ref().ok_or(JobError::NotFound)?;
+        let bytes = storage
+            .get(&Self::result_key(id))
+            .await
+            .context("failed to read job result")?
+            .ok_or(JobError::NotFound)?;
+
+        Ok(bytes)
+    }
+
+    /// Gets the preview of the first pages of a job, jobs submitted by a
+    /// tenant are only accessible to the same tenant. Completed jobs whose
+    /// preview couldn't be exported provide the full result instead
+    pub async fn preview(&self, id: &str, tenant: Option<&str>) -> Result<Bytes, JobError> {
+        let preview = {
+            let jobs = &*self.jobs.lock();
+            let job = jobs
+                .get(id)
+                .filter(|job| job.metadata.tenant.as_deref() == tenant)
+                .ok_or(JobError::NotFound)?;
+
+            if job.metadata.preview_pages.is_none() {
+                return Err(JobError::NoPreview);
+            }
+
+            match job.metadata.status {
+                JobStatus::Failed => {
+                    return Err(JobError::Failed(
+                        job.metadata.error.clone().unwrap_or_default(),
+                    ))
+                }
+                JobStatus::Cancelled => return Err(JobError::Cancelled),
+                JobStatus::Completed if !job.metadata.preview_ready => None,
+                _ if !job.metadata.preview_ready => return Err(JobError::PreviewNotReady),
+                _ => Some(job.preview.clone()),
+            }
+        };
+
+        let preview = match preview {
+            Some(preview) => preview,
+            None => return self.result(id, tenant).await,
+        };
+
+        if let Some(preview) = preview {
+            return Ok(preview);
+        }
+
+        let storage = self.storage.as_ref().ok_or(JobError::NotFound)?;
+        let bytes = storage
+            .get(&Self::preview_key(id))
+            .await
+            .context("failed to read job preview")?
+            .ok_or(JobError::NotFound)?;
+
+        Ok(bytes)
+    }
+
+    /// Stores the exported preview of a job, marking the preview as ready
+    async fn store_preview(&self, id: &str, preview: Bytes) -> anyhow::Result<()> {
+        match &self.storage {
+            Some(storage) => storage
+                .put(&Self::preview_key(id), preview)
+                .await
+                .context("failed to write job preview")?,
+            None => {
+                if let Some(job) = self.jobs.lock().get_mut(id) {
+                    job.preview = Some(preview);
+                }
+            }
+        }
+
+        self.update(id, |metadata| metadata.preview_ready = true)
+            .await
+    }
+
+    /// Cancels a job, queued jobs are removed from the queue and processing
+    /// jobs stop waiting for office (the remaining steps of a conversion office
+    /// has started are skipped). Provides the final state of the job, jobs that
+    /// already finished are left unchanged
+    pub async fn cancel(&self, id: &str, tenant: Option<&str>) -> Result<JobMetadata, JobError> {
+        let metadata = {
+            let jobs = &mut *self.jobs.lock();
+            let job = jobs
+                .get_mut(id)
+                .filter(|job| job.metadata.tenant.as_deref() == tenant)
+                .ok_or(JobError::NotFound)?;
+
+            match job.metadata.status {
+                JobStatus::Queued => {
+                    // Jobs already taken from the queue are skipped when processed
+                    self.queue.remove(&job.metadata.tenant, id);
+                }
+                JobStatus::Processing => job.cancel.notify_one(),
+                _ => return Ok(job.metadata.clone()),
+            }
+
+            job.input = None;
+            job.metadata.status = JobStatus::Cancelled;
+            job.metadata.completed_at = Some(now_secs());
+            job.metadata.progress = None;
+            job.metadata.clone()
+        };
+
+        info!(job = id, "job cancelled");
+
+        if let Some(storage) = &self.storage {
+            storage.delete(&Self::input_key(id)).await;
+        }
+
+        self.remove_checkpoints(id).await;
+        self.persist(&metadata).await?;
+
+        Ok(metadata)
+    }
+
+    /// Whether the job with the provided ID was cancelled
+    fn is_cancelled(&self, id: &str) -> bool {
+        self.jobs
+            .lock()
+            .get(id)
+            .is_some_and(|job| job.metadata.status == JobStatus::Cancelled)
+    }
+
+    /// Converts the queued job with the provided ID, cancelled jobs are skipped
+    #[allow(clippy::too_many_arguments)]
+    async fn process(
+        &self,
+        office: &Converter,
+        tenants: &Tenants,
+        config: &Config,
+        failures: &RecentFailures,
+        limits: BatchLimits,
+        details: Arc<OfficeDetails>,
+        id: &str,
+    ) -> anyhow::Result<()> {
+        let (metadata, cancel) = {
+            let jobs = &mut *self.jobs.lock();
+            let job = jobs.get_mut(id).context("unknown job")?;
+
+            if job.metadata.status != JobStatus::Queued {
+                debug!(job = id, status = ?job.metadata.status, "skipping job that is no longer queued");
+                return Ok(());
+            }
+
+            job.metadata.status = JobStatus::Processing;
+            (job.metadata.clone(), job.cancel.clone())
+        };
+
+        // Errors after the job is marked as processing fail the job, otherwise
+        // it would be left processing
+        if let Err(cause) = self.persist(&metadata).await {
+            return self.fail(id, format!("{cause:#}"), None).await;
+        }
+
+        let input = self
+            .jobs
+            .lock()
+            .get_mut(id)
+            .and_then(|job| job.input.take());
+
+        let input = match (input, &self.storage) {
+            (Some(input), _) => input,
+            (None, Some(storage)) => match storage
+                .get(&Self::input_key(id))
+                .await
+                .context("failed to read job input")
+            {
+                Ok(Some(input)) => input,
+                Ok(None) => {
+                    return self
+                        .fail(id, "job input is missing".to_string(), None)
+                        .await
+                }
+                Err(cause) => return self.fail(id, format!("{cause:#}"), None).await,
+            },
+            (None, None) => {
+                return self
+                    .fail(id, "job input is missing".to_string(), None)
+                    .await
+            }
+        };
+
+        let (priority, tenant) = self
+            .jobs
+            .lock()
+            .get(id)
+            .map(|job| (job.metadata.priority, job.metadata.tenant.clone()))
+            .unwrap_or_default();
+
+        let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+
+        // Jobs from a tenant take turns with the other conversions from tenants
+        let mut office = office.with_priority(priority).with_events(events_tx);
+        if let Some(tenant) = &tenant {
+            office = office.with_fair_share(tenant, tenants.weight(tenant));
+        }
+
+        let input_size = input.len();
+        let input_format = detect_input_format(&input, None, None);
+
+        let result = match metadata.batch {
+            Some(_) => {
+                let converted = match extract_batch(input, limits, details).await {
+                    Ok(entries) => {
+                        self.convert_batch(
+                            id,
+                            &office,
+                            config,
+                            tenants,
+                            tenant.as_deref(),
+                            entries,
+                            &mut events_rx,
+                            &cancel,
+                        )
+                        .await
+                    }
+                    Err(cause) => Err(cause),
+                };
+
+                match converted {
+                    Ok(Some(bytes)) => Ok(bytes),
+                    Ok(None) => return Ok(()),
+                    Err(cause) => Err(cause),
+                }
+            }
+            None => {
+                let mut options = PdfExportOptions::default();
+                config.apply_load_options(&mut options, &input, None, None);
+
+                // Only the first pages are exported for the preview so it's available
+                // well before the full conversion
+                if let Some(pages) = metadata.preview_pages {
+                    let mut preview_options = options.clone();
+                    preview_options.page_range = Some(format!("1-{pages}"));
+
+                    let preview = office.convert_with_options(input.clone(), preview_options);
+                    let Some(preview) = self
+                        .wait_for_conversion(id, preview, &mut events_rx, &cancel)
+                        .await
+                    else {
+                        return Ok(());
+                    };
+
+                    // Failing to export the preview doesn't stop the full conversion
+                    match preview {
+                        Ok(bytes) if !self.is_cancelled(id) => {
+                            if let Err(cause) = self.store_preview(id, bytes).await {
+                                warn!(job = id, "failed to store job preview: {cause:#}");
+                            }
+                        }
+                        Ok(_) => return Ok(()),
+                        Err(cause) => warn!(job = id, %cause, "failed to export job preview"),
+                    }
+                }
+
+                let convert = office.convert_with_options(input, options);
+                let Some(result) = self
+                    .wait_for_conversion(id, convert, &mut events_rx, &cancel)
+                    .await
+                else {
+                    return Ok(());
+                };
+
+                // Results of jobs cancelled as the conversion finished are discarded
+                if self.is_cancelled(id) {
+                    return Ok(());
+                }
+
+                // Record usage for the tenant that submitted the job
+                if let (Ok(bytes), Some(tenant)) = (&result, &tenant) {
+                    tenants.record(tenant, input_size, bytes);
+                }
+
+                result.map_err(DynHttpError::from)
+            }
+        };
+
+        let result = match result {
+            Ok(bytes) => match &self.storage {
+                Some(storage) => storage
+                    .put(&Self::result_key(id), bytes)
+                    .await
+                    .map(|_| None)
+                    .context("failed to write job result")
+                    .map_err(|err| (err.to_string(), None)),
+                None => Ok(Some(bytes)),
+            },
+            Err(cause) => {
+                failures.record(
+                    "job",
+                    cause.code(),
+                    &cause.to_string(),
+                    InputDetails {
+                        format: input_format,
+                        size: Some(input_size),
+                    },
+                );
+                Err((cause.to_string(), cause.code()))
+            }
+        };
+
+        if let Some(storage) = &self.storage {
+            storage.delete(&Self::input_key(id)).await;
+        }
+
+        self.remove_checkpoints(id).await;
+
+        let completed_at = now_secs();
+
+        match result {
+            Ok(bytes) => {
+                if let Some(job) = self.jobs.lock().get_mut(id) {
+                    job.result = bytes;
+                }
+
+                self.update(id, |metadata| {
+                    if metadata.status == JobStatus::Cancelled {
+                        return;
+                    }
+
+                    metadata.status = JobStatus::Completed;
+                    metadata.completed_at = Some(completed_at);
+                    metadata.progress = None;
+                })
+                .await
+            }
+            Err((error, error_code)) => self.fail(id, error, error_code).await,
+        }
+    }
+
+    /// Marks the job with the provided ID as failed with the provided error,
+    /// jobs cancelled while processing stay cancelled
+    async fn fail(
+        &self,
+        id: &str,
+        error: String,
+        error_code: Option<&'static str>,
+    ) -> anyhow::Result<()> {
+        warn!(job = id, %error, "job failed");
+
+        let completed_at = now_secs();
+
+        self.update(id, |metadata| {
+            if metadata.status == JobStatus::Cancelled {
+                return;
+            }
+
+            metadata.status = JobStatus::Failed;
+            metadata.completed_at = Some(completed_at);
+            metadata.progress = None;
+            metadata.error = Some(error);
+            metadata.error_code = error_code.map(str::to_string);
+        })
+        .await
+    }
+
+    /// Converts each of the documents extracted from the archive of a batch
+    /// job into a ZIP archive of the PDFs, provides [None] when the job was
+    /// cancelled. Documents converted before the job was interrupted are
+    /// loaded from their checkpoint instead of being converted again
+    #[allow(clippy::too_many_arguments)]
+    async fn convert_batch(
+        &self,
+        id: &str,
+        office: &Converter,
+        config: &Config,
+        tenants: &Tenants,
+        tenant: Option<&str>,
+        entries: Vec<ArchiveEntry>,
+        events_rx: &mut UnboundedReceiver<OfficeEvent>,
+        cancel: &Notify,
+    ) -> Result<Option<Bytes>, DynHttpError> {
+        let completed = self
+            .jobs
+            .lock()
+            .get(id)
+            .and_then(|job| job.metadata.batch.as_ref())
+            .map_or(0, |progress| progress.completed);
+
+        let total = entries.len();
+        self.update(id, |metadata| {
+            if let Some(progress) = &mut metadata.batch {
+                progress.total = Some(total);
+            }
+        })
+        .await?;
+
+        if completed > 0 {
+            info!(
+                job = id,
+                completed, total, "resuming batch job from checkpoint"
+            );
+        }
+
+        let mut converted = Vec::with_capacity(total);
+
+        for (index, entry) in entries.into_iter().enumerate() {
+            if index < completed {
+                if let Some(bytes) = self.load_checkpoint(id, index).await {
+                    converted.push((entry.path, bytes));
+                    continue;
+                }
+            }
+
+            let input_size = entry.bytes.len();
+            let mut options = PdfExportOptions::default();
+            config.apply_load_options(
+                &mut options,
+                &entry.bytes,
+                entry.path.file_name().and_then(|value| value.to_str()),
+                None,
+            );
+
+            let convert = office.convert_with_options(entry.bytes, options);
+            let Some(result) = self
+                .wait_for_conversion(id, convert, events_rx, cancel)
+                .await
+            else {
+                return Ok(None);
+            };
+
+            // Results of jobs cancelled as the conversion finished are discarded
+            if self.is_cancelled(id) {
+                return Ok(None);
+            }
+
+            let bytes = result.map_err(|cause| BatchError::ConvertEntry {
+                path: entry.path.display().to_string(),
+                cause,
+            })?;
+
+            if let Some(tenant) = tenant {
+                tenants.record(tenant, input_size, &bytes);
+            }
+
+            self.store_checkpoint(id, index, bytes.clone()).await;
+            converted.push((entry.path, bytes));
+        }
+
+        let archive = tokio::task::spawn_blocking(move || create_archive(converted))
+            .await
+            .context("failed to create archive")?
+            .context("failed to create archive")?;
+
+        Ok(Some(Bytes::from(archive)))
+    }
+
+    /// Loads a document of a batch job converted before the job was
+    /// interrupted, provides [None] when the checkpoint is missing
+    async fn load_checkpoint(&self, id: &str, index: usize) -> Option<Bytes> {
+        let storage = self.storage.as_ref()?;
+
+        match storage.get(&Self::checkpoint_key(id, index)).await {
+            Ok(bytes) => bytes,
+            Err(cause) => {
+                warn!(
+                    job = id,
+                    index, "failed to read batch checkpoint: {cause:#}"
+                );
+                None
+            }
+        }
+    }
+
+    /// Checkpoints a converted document of a batch job, failing to write the
+    /// checkpoint only means the document is converted again if the job is
+    /// interrupted
+    async fn store_checkpoint(&self, id: &str, index: usize, bytes: Bytes) {
+        if let Some(storage) = &self.storage {
+            if let Err(cause) = storage.put(&Self::checkpoint_key(id, index), bytes).await {
+                warn!(
+                    job = id,
+                    index, "failed to write batch checkpoint: {cause:#}"
+                );
+                return;
+            }
+        }
+
+        let result = self
+            .update(id, |metadata| {
+                if let Some(progress) = &mut metadata.batch {
+                    progress.completed = progress.completed.max(index + 1);
+                }
+            })
+            .await;
+
+        if let Err(cause) = result {
+            warn!(
+                job = id,
+                index, "failed to persist batch progress: {cause:#}"
+            );
+        }
+    }
+
+    /// Waits for a conversion of the job to finish while applying the events
+    /// office reports, provides [None] when the job was cancelled
+    async fn wait_for_conversion<F: Future>(
+        &self,
+        id: &str,
+        convert: F,
+        events_rx: &mut UnboundedReceiver<OfficeEvent>,
+        cancel: &Notify,
+    ) -> Option<F::Output> {
+        tokio::pin!(convert);
+
+        loop {
+            tokio::select! {
+                result = &mut convert => return Some(result),
+                Some(event) = events_rx.recv() => self.apply_event(id, event),
+                // Dropping the conversion stops waiting for office
+                _ = cancel.notified() => {
+                    debug!(job = id, "stopped processing cancelled job");
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Updates the progress and warnings of a processing job from an event
+    /// office reported, changes are only kept in memory until the job finishes
+    fn apply_event(&self, id: &str, event: OfficeEvent) {
+        let jobs = &mut *self.jobs.lock();
+        let Some(job) = jobs.get_mut(id) else {
+            return;
+        };
+
+        let metadata = &mut job.metadata;
+        match event {
+            OfficeEvent::ProgressStart { .. } => metadata.progress = Some(0),
+            OfficeEvent::Progress { percent } => metadata.progress = Some(percent),
+            OfficeEvent::ProgressFinish => metadata.progress = Some(100),
+            OfficeEvent::Warning { warning } if !metadata.warnings.contains(&warning) => {
+                metadata.warnings.push(warning);
+            }
+            _ => {}
+        }
+    }
+
+    /// Loads the persisted jobs from storage, jobs that hadn't finished
+    /// are queued again and expired jobs are removed
+    pub async fn recover(&self) -> anyhow::Result<()> {
+        let storage = match &self.storage {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+
+        let keys = storage
+            .keys()
+            .await
+            .context("failed to list persisted jobs")?;
+
+        let now = now_secs();
+        let mut pending = Vec::new();
+
+        for key in keys.iter().filter(|key| key.ends_with(".json")) {
+            let mut metadata: JobMetadata = match storage
+                .get(key)
+                .await
+                .and_then(|bytes| bytes.context("job metadata is missing"))
+                .and_then(|bytes| serde_json::from_slice(&bytes).map_err(anyhow::Error::from))
+            {
+                Ok(value) => value,
+                Err(cause) => {
+                    warn!(%cause, %key, "failed to load persisted job");
+                    continue;
+                }
+            };
+
+            if metadata.status.is_finished() {
+                let expired = metadata.completed_at.is_some_and(|completed_at| {
+                    now.saturating_sub(completed_at) >= self.ttl.as_secs()
+                });
+
+                if expired {
+                    self.remove_files(&metadata.id).await;
+                    continue;
+                }
+            } else {
+                // Jobs interrupted mid conversion are started again, batch jobs
+                // continue from the first document that wasn't checkpointed
+                metadata.status = JobStatus::Queued;
+                pending.push((
+                    metadata.created_at,
+                    metadata.id.clone(),
+                    metadata.tenant.clone(),
+                ));
+            }
+
+            self.jobs.lock().insert(
+                metadata.id.clone(),
+                Job {
+                    metadata,
+                    input: None,
+                    result: None,
+                    preview: None,
+                    cancel: Arc::default(),
+                },
+            );
+        }
+
+        // Resume in the order the jobs were submitted
+        pending.sort();
+
+        info!(count = pending.len(), "resuming persisted jobs");
+
+        for (_, id, tenant) in pending {
+            self.queue.push(tenant, id);
+        }
+
+        Ok(())
+    }
+
+    /// Removes finished jobs that have expired
+    async fn collect_expired(&self) {
+        let now = now_secs();
+        let ttl = self.ttl.as_secs();
+
+        let expired: Vec<String> = {
+            let jobs = &mut *self.jobs.lock();
+            let expired: Vec<String> = jobs
+                .values()
+                .filter(|job| {
+                    job.metadata
+                        .completed_at
+                        .is_some_and(|completed_at| now.saturating_sub(completed_at) >= ttl)
+                })
+                .map(|job| job.metadata.id.clone())
+                .collect();
+
+            for id in &expired {
+                jobs.remove(id);
+            }
+
+            expired
+        };
+
+        for id in expired {
+            debug!(job = id, "removing expired job");
+            self.remove_files(&id).await;
+        }
+    }
+}
+
+/// Extracts the documents from the ZIP archive input of a batch job
+async fn extract_batch(
+    input: Bytes,
+    limits: BatchLimits,
+    details: Arc<OfficeDetails>,
+) -> Result<Vec<ArchiveEntry>, DynHttpError> {
+    let entries = tokio::task::spawn_blocking(move || extract_archive(input, limits, &details))
+        .await
+        .context("failed to extract archive")??;
+
+    Ok(entries)
+}
+
+/// Spawns the background tasks for processing queued jobs and
+/// removing expired jobs
+pub fn spawn_job_tasks(
+    store: Arc<JobStore>,
+    office: Converter,
+    tenants: Arc<Tenants>,
+    config: SharedConfig,
+    failures: Arc<RecentFailures>,
+    limits: BatchLimits,
+    details: Arc<OfficeDetails>,
+) {
+    tokio::spawn({
+        let store = store.clone();
+
+        async move {
+            loop {
+                let id = store
+                    .queue
+                    .next(|tenant| tenant.map_or(1, |name| tenants.weight(name)))
+                    .await;
+
+                if let Err(cause) = store
+                    .process(
+                        &office,
+                        &tenants,
+                        &config.current(),
+                        &failures,
+                        limits,
+                        details.clone(),
+                        &id,
+                    )
+                    .await
+                {
+                    error!(job = id, "failed to process job: {cause:#}");
+                }
+            }
+        }
+    });
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(COLLECT_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            store.collect_expired().await;
+        }
+    });
+}
+
+/// Request to submit a conversion job
+#[derive(TryFromMultipart)]
+pub struct SubmitJobRequest {
+    /// The file to convert
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+    /// Priority of the job when waiting for office
+    priority: Option<RequestPriority>,
+    /// Number of pages to export as a preview before the full conversion
+    preview_pages: Option<u32>,
+    /// Whether the file is a ZIP archive of documents to convert
+    batch: Option<bool>,
+}
+
+/// POST /jobs
+///
+/// Queues the provided file for conversion responding with the job details
+pub async fn submit_job(
+    Extension(store): Extension<Arc<JobStore>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    headers: HeaderMap,
+    TypedMultipart(SubmitJobRequest {
+        file,
+        priority,
+        preview_pages,
+        batch,
+    }): TypedMultipart<SubmitJobRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    if preview_pages == Some(0) {
+        return Err(
+            InvalidExportOptions("preview_pages must be greater than 0".to_string()).into(),
+        );
+    }
+
+    let batch = batch.unwrap_or_default();
+    if batch && preview_pages.is_some() {
+        return Err(InvalidExportOptions(
+            "preview_pages can't be used with batch jobs".to_string(),
+        )
+        .into());
+    }
+
+    let file = decompress_file(file, max_file_size).await?;
+    let priority = request_priority(tenant.as_deref().map(Arc::as_ref), priority);
+    let tenant = tenant.map(|Extension(tenant)| tenant.name.clone());
+    let idempotency_key = idempotency_key(&headers)?;
+    let (metadata, created) = store
+        .submit(
+            file.contents,
+            tenant,
+            idempotency_key,
+            priority,
+            preview_pages,
+            batch,
+        )
+        .await?;
+
+    let mut response = (StatusCode::ACCEPTED, Json(metadata)).into_response();
+
+    // Existing jobs are provided for repeated requests with the same idempotency key
+    if !created {
+        response
+            .headers_mut()
+            .insert(IDEMPOTENT_REPLAYED_HEADER, HeaderValue::from_static("true"));
+    }
+
+    Ok(response)
+}
+
+/// GET /jobs/:id
+///
+/// Provides the details about a job
+pub async fn get_job(
+    Extension(store): Extension<Arc<JobStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobMetadata>, DynHttpError> {
+    let tenant = tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str());
+    let metadata = store.get(&id, tenant).ok_or(JobError::NotFound)?;
+    Ok(Json(metadata))
+}
+
+/// DELETE /jobs/:id
+///
+/// Cancels a queued or processing job responding with the final details
+/// of the job
+pub async fn cancel_job(
+    Extension(store): Extension<Arc<JobStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<Json<JobMetadata>, DynHttpError> {
+    let tenant = tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str());
+    let metadata = store.cancel(&id, tenant).await?;
+    Ok(Json(metadata))
+}
+
+/// GET /jobs/:id/preview
+///
+/// Provides the preview of the first pages of a job
+pub async fn get_job_preview(
+    Extension(store): Extension<Arc<JobStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<Response<Body>, DynHttpError> {
+    let tenant = tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str());
+    let bytes = store.preview(&id, tenant).await?;
+
+    let response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/pdf"),
+        )
+        .body(Body::from(bytes))
+        .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// Creates the entity tag for the result of a job, results never change
+/// once the job has completed so the job ID identifies the result
+pub fn result_etag(id: &str) -> anyhow::Result<HeaderValue> {
+    HeaderValue::try_from(format!("\"{id}\"")).context("invalid etag")
+}
+
+/// GET /jobs/:id/result
+///
+/// Provides the converted PDF file for a completed job, or the ZIP archive
+/// of converted PDF files for a completed batch job. Supports range requests
+/// for resuming interrupted downloads
+pub async fn get_job_result(
+    Extension(store): Extension<Arc<JobStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    let tenant = tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str());
+    let bytes = store.result(&id, tenant).await?;
+    let (content_type, _) = store.result_type(&id);
+
+    let builder =
+        Response::builder().header(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+    let response = range_response(&headers, builder, bytes, result_etag(&id)?)?;
+    Ok(response)
+}
@@ -0,0 +1,160 @@
+use crate::error::HttpError;
+use anyhow::Context;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use office_convert_core::TempFile;
+use std::{path::PathBuf, process::Stdio};
+use thiserror::Error;
+use tokio::{io::AsyncWriteExt, process::Command};
+use tracing::error;
+
+/// Exit code qpdf uses when it succeeded with warnings
+const QPDF_EXIT_WARNINGS: i32 = 3;
+
+/// Linearizes (optimizes for fast web view) PDF files using qpdf, LibreOffice
+/// is unable to produce linearized output itself. Linearized files have the
+/// first page and the data required to render it at the start of the file so
+/// viewers can progressively display the file using range requests
+pub struct Linearizer {
+    /// Path to the qpdf binary
+    qpdf_path: PathBuf,
+    /// Directory to write temporary files
+    temp_dir: PathBuf,
+    /// Whether to overwrite temporary files before deleting them
+    secure_delete: bool,
+}
+
+/// Errors that can occur when linearizing a PDF
+#[derive(Debug, Error)]
+pub enum LinearizeError {
+    /// qpdf is not installed or could not be started
+    #[error("pdf linearization is unavailable, failed to start qpdf: {0}")]
+    Unavailable(std::io::Error),
+
+    /// qpdf failed to linearize the file
+    #[error("failed to linearize pdf: {0}")]
+    Failed(String),
+
+    /// Failed to read or write the temporary files
+    #[error(transparent)]
+    Io(#[from] anyhow::Error),
+}
+
+impl HttpError for LinearizeError {
+    fn log(&self) {
+        match self {
+            // Anyhow errors contain a stacktrace so only the debug variant is used
+            LinearizeError::Io(err) => error!("{err:#?}"),
+            _ => error!("{self}: {self:?}"),
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            LinearizeError::Unavailable(_) => StatusCode::NOT_IMPLEMENTED,
+            LinearizeError::Failed(_) | LinearizeError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            LinearizeError::Unavailable(_) => Some("LINEARIZE_UNAVAILABLE"),
+            LinearizeError::Failed(_) => Some("LINEARIZE_FAILED"),
+            LinearizeError::Io(_) => None,
+        }
+    }
+}
+
+impl Linearizer {
+    pub fn new(qpdf_path: PathBuf, temp_dir: PathBuf, secure_delete: bool) -> Self {
+        Self {
+            qpdf_path,
+            temp_dir,
+            secure_delete,
+        }
+    }
+
+    /// Linearizes the provided PDF file bytes, the password is required
+    /// to linearize files that are encrypted with a user password
+    pub async fn linearize(
+        &self,
+        bytes: Bytes,
+        password: Option<&str>,
+    ) -> Result<Bytes, LinearizeError> {
+        let input = TempFile::new(&self.temp_dir, "linearize_input");
+        let output = TempFile::new(&self.temp_dir, "linearize_output");
+
+        let result = self.run(&input, &output, bytes, password).await;
+
+        // Remove the temporary files regardless of the outcome
+        let secure_delete = self.secure_delete;
+        _ = tokio::task::spawn_blocking(move || {
+            input.remove(secure_delete);
+            output.remove(secure_delete);
+        })
+        .await;
+
+        result
+    }
+
+    /// Runs qpdf to linearize the file
+    async fn run(
+        &self,
+        input: &TempFile,
+        output: &TempFile,
+        bytes: Bytes,
+        password: Option<&str>,
+    ) -> Result<Bytes, LinearizeError> {
+        tokio::fs::write(&input.path, bytes)
+            .await
+            .context("failed to write linearize input")?;
+
+        let mut command = Command::new(&self.qpdf_path);
+        command.arg("--linearize");
+
+        // Password is provided through stdin to avoid exposing it in the process arguments
+        if password.is_some() {
+            command.arg("--password-file=-");
+        }
+
+        let mut child = command
+            .arg(&input.path)
+            .arg(&output.path)
+            .stdin(if password.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(LinearizeError::Unavailable)?;
+
+        if let (Some(password), Some(mut stdin)) = (password, child.stdin.take()) {
+            stdin
+                .write_all(password.as_bytes())
+                .await
+                .context("failed to write qpdf password")?;
+        }
+
+        let result = child
+            .wait_with_output()
+            .await
+            .context("failed to wait for qpdf")?;
+
+        // Warnings are allowed, qpdf still writes the output
+        let success = result.status.success() || result.status.code() == Some(QPDF_EXIT_WARNINGS);
+
+        if !success {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(LinearizeError::Failed(stderr.trim().to_string()));
+        }
+
+        let bytes = tokio::fs::read(&output.path)
+            .await
+            .context("failed to read linearize output")?;
+
+        Ok(Bytes::from(bytes))
+    }
+}
@@ -1,32 +1,171 @@
-use anyhow::{anyhow, Context};
+use admin::{
+    dashboard, require_admin, restart_office, restrict_to_allowlist, stats, AdminAllowlist,
+    AdminToken, IpRange,
+};
+use anyhow::Context;
+use autoscale::{AutoScale, SystemResources, LOW_MEMORY_MAX_QUEUED};
 use axum::{
     body::Body,
     extract::DefaultBodyLimit,
-    http::{header, HeaderValue, Response, StatusCode},
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    middleware,
     routing::{get, post},
     Extension, Json, Router,
 };
 use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use base_path::BasePath;
+use batch::{convert_batch, BatchLimits};
+use bench::BenchArgs;
 use bytes::Bytes;
-use clap::Parser;
-use error::DynHttpError;
-use libreofficekit::{
-    CallbackType, DocUrl, FilterTypes, Office, OfficeError, OfficeOptionalFeatures,
-    OfficeVersionInfo,
+use capabilities::{capabilities, Capabilities, CapabilityLimits};
+use clap::{Parser, Subcommand};
+use compression::{decompress_file, response_compression};
+use config::{AllowRawOptions, Config, InvalidExportOptions, RawOptionsDisabled, SharedConfig};
+use detect::{check_import_filter, detect, detect_input_format, known_extension};
+use disposition::{content_disposition, output_file_name, DEFAULT_OUTPUT_FILE_NAME};
+use download::{create_download_url, download, DownloadSigner};
+use email::{convert_email, parse_email, EmailFormat};
+use embedded::{missing_replacement_images_warning, PreferReplacementImages};
+use engine::{
+    engine_socket_path, exit_with_main_server, select_engine, spawn_engine_workers, Engines,
+    DEFAULT_ENGINE,
+};
+use error::{DynHttpError, HttpError};
+use etag::{conversion_etag, if_none_match, not_modified};
+use extract::extract_text;
+use failures::{recent_errors, record_failures, FailureInput, RecentFailures};
+use fonts::{list_fonts, load_font_substitutions, FontCatalog};
+use idempotency::{idempotent, spawn_idempotency_collector, IdempotencyStore};
+use image::prepare_image;
+use jobs::{
+    cancel_job, get_job, get_job_preview, get_job_result, spawn_job_tasks, submit_job, JobStore,
+};
+use libreofficekit::Office;
+use linearize::Linearizer;
+use metadata::{
+    duration_ms, multipart_response, office_warnings, ConversionMetadata, ConversionTimings,
+    ResponseFormat,
 };
-use parking_lot::Mutex;
-use rand::{distributions::Alphanumeric, Rng};
+use metrics::metrics;
+use office_convert_core::{
+    memory_temp_dir, reap_temp_files, run_conversion_process, sanitize_pdf, set_office_environment,
+    spawn_temp_reaper, ConversionProcess, ConvertError, Converter, ConverterOptions,
+    DocumentSecurity, OfficeDetails, OutputLimits, PdfExportOptions, PriorityAging, QueueWaitStats,
+    RetryPolicy, SandboxOptions, SandboxUser, WorkerState,
+};
+use output::{capture_office_output, OfficeLogLevel};
+use page::{Orientation, PageOverrides, PageSetup, PageSize};
+use page_style::apply_page_overrides;
+use pages::render_pages;
+use pdf::{PdfPassthrough, PDF_MIME};
+use pipeline::{Pipeline, StageContext, StagePoint};
+use priority::{request_priority, RequestPriority};
+use queue::{limit_queue, QueueLimit};
+use reload::{log_filter, reload_config, spawn_reload_signal, Reloader};
+use repair::convert_with_repair;
+use results::{get_result, spawn_result_collector, ResultStore, RESULT_ID_HEADER};
+use selftest::{ready, self_test, spawn_self_test, Readiness};
 use serde::Serialize;
-use std::{env::temp_dir, ffi::CStr, path::PathBuf, rc::Rc, sync::Arc};
-use tokio::sync::{mpsc, oneshot};
-use tracing::{debug, error};
-use tracing_subscriber::EnvFilter;
-
+use signing::{load_pkcs12, SigningError};
+use spreadsheet::{fit_wide_spreadsheet, FitWideSpreadsheets};
+use std::{
+    ffi::OsString,
+    net::SocketAddr,
+    num::NonZeroUsize,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use storage::{create_storage, StorageKind};
+use systemd::{activated_listener, notify_ready, spawn_watchdog, ActivatedListener};
+use telemetry::trace_request;
+use template::render;
+use tenant::{require_tenant, spawn_usage_writer, usage, Tenant, Tenants};
+use tower_http::decompression::RequestDecompressionLayer;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, layer::SubscriberExt, Layer};
+use uds::serve_unix;
+#[cfg(unix)]
+use uds::serve_unix_listener;
+use uploads::{
+    append_upload, convert_upload, create_upload, delete_upload, get_upload,
+    spawn_upload_collector, UploadStore,
+};
+use validate::validate;
+use writer::{apply_tracked_changes, TrackedChanges, WriterOptions};
+use ws::{convert_socket, MaxFileSize};
+
+mod admin;
+mod alloc;
+mod archive;
+mod autoscale;
+mod base_path;
+mod batch;
+mod bench;
+mod capabilities;
+mod compat;
+mod compression;
+mod config;
+mod detect;
+mod disposition;
+mod download;
+mod email;
+mod embedded;
+mod engine;
 mod error;
+mod etag;
+mod extract;
+mod failures;
+mod fonts;
+mod html;
+mod idempotency;
+mod image;
+mod jobs;
+mod linearize;
+mod metadata;
+mod metrics;
+mod output;
+mod page;
+mod page_style;
+mod pages;
+mod pdf;
+mod pipeline;
+mod priority;
+mod queue;
+mod range;
+mod reload;
+mod repair;
+mod results;
+mod selftest;
+mod signing;
+mod spreadsheet;
+mod storage;
+mod systemd;
+mod telemetry;
+mod template;
+mod tenant;
+mod uds;
+mod uploads;
+mod validate;
+mod writer;
+mod ws;
+mod xml;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to a TOML config file
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Path to a TOML file mapping the names of fonts that aren't installed to installed
+    /// fonts LibreOffice uses in their place
+    #[arg(long)]
+    font_substitutions: Option<PathBuf>,
+
     /// Path to the office installation (Omit to determine automatically)
     #[arg(long)]
     office_path: Option<String>,
@@ -38,35 +177,451 @@ struct Args {
     /// Host to bind the server to, defaults to 0.0.0.0
     #[arg(long)]
     host: Option<String>,
+
+    /// Path to a Unix domain socket to listen on instead of TCP
+    #[arg(long)]
+    uds: Option<PathBuf>,
+
+    /// Path prefix to serve all routes under (i.e "/convert-api") when running behind a
+    /// reverse proxy that routes by path, URLs generated by the server include the prefix
+    #[arg(long)]
+    base_path: Option<String>,
+
+    /// Directory to write temporary input and output files, defaults to the system temp directory
+    #[arg(long)]
+    temp_dir: Option<PathBuf>,
+
+    /// Write the files LibreOffice loads and saves during a conversion to memory (/dev/shm)
+    /// instead of the temp directory, the temp directory is used when memory is unavailable
+    #[arg(long)]
+    in_memory: bool,
+
+    /// Free disk space in bytes to keep available in the temp directory after
+    /// accounting for a conversion, defaults to 100MiB
+    #[arg(long)]
+    min_free_disk: Option<u64>,
+
+    /// Overwrite temporary files with zeros before deleting them
+    #[arg(long)]
+    secure_delete: bool,
+
+    /// Interval in seconds to convert a tiny document at to check LibreOffice is working,
+    /// the self-test is disabled when not provided
+    #[arg(long)]
+    self_test_interval: Option<u64>,
+
+    /// Number of self-tests that must fail in a row before the server is marked not ready
+    /// and LibreOffice is restarted, defaults to 3
+    #[arg(long)]
+    self_test_failures: Option<u32>,
+
+    /// Age in seconds after which leftover temporary files are considered orphaned
+    /// and removed, defaults to 3600 (1 hour)
+    #[arg(long)]
+    temp_max_age: Option<u64>,
+
+    /// Additional directory to load fonts from, can be provided multiple times
+    #[arg(long = "fonts-dir")]
+    fonts_dirs: Vec<PathBuf>,
+
+    /// Path to a PKCS#12 (.p12 / .pfx) file containing the certificate and private
+    /// key used to sign PDFs, signing is unavailable when not provided
+    #[arg(long)]
+    signing_certificate: Option<PathBuf>,
+
+    /// Password for the signing certificate file
+    #[arg(long)]
+    signing_password: Option<String>,
+
+    /// Maximum time in seconds LibreOffice can spend loading, exporting or signing a document
+    /// before the conversion fails, LibreOffice is restarted once the call returns and the server
+    /// exits if it hasn't returned after twice the time. No limit when not provided
+    #[arg(long)]
+    max_convert_seconds: Option<u64>,
+
+    /// Maximum time in seconds LibreOffice can spend on a single conversion or restart before
+    /// the server stops pinging the systemd watchdog so systemd restarts it, defaults to twice
+    /// --max-convert-seconds. Only the async runtime is checked when neither is provided
+    #[arg(long)]
+    watchdog_max_busy_seconds: Option<u64>,
+
+    /// Number of times a conversion that fails inside LibreOffice is attempted again before
+    /// the request fails, conversions are not retried by default
+    #[arg(long, default_value_t = 0)]
+    retry_failed_conversions: u32,
+
+    /// Restart LibreOffice before retrying a failed conversion so the retry runs on a fresh
+    /// LibreOffice instance
+    #[arg(long)]
+    restart_before_retry: bool,
+
+    /// Size in bytes below which uploaded files are considered small, waiting conversions of
+    /// small files are converted before waiting conversions of larger files with the same
+    /// priority. Waiting conversions are converted in order regardless of size when not provided
+    #[arg(long)]
+    small_file_threshold: Option<u64>,
+
+    /// Seconds a waiting conversion waits before its priority is raised by one level, the
+    /// priority is raised again each time it waits this long so low priority conversions are
+    /// converted even while higher priority conversions keep arriving. Priorities of waiting
+    /// conversions are not raised when not provided
+    #[arg(long)]
+    priority_aging_seconds: Option<u64>,
+
+    /// Highest priority waiting conversions can be raised to by priority aging
+    #[arg(long, value_enum, default_value = "high")]
+    priority_aging_max: RequestPriority,
+
+    /// Maximum number of pages in a converted PDF, conversions producing more pages fail.
+    /// No limit when not provided
+    #[arg(long)]
+    max_output_pages: Option<u64>,
+
+    /// Maximum number of pages rendered by /render-pages, each page is rendered using a separate
+    /// export of the document so documents with more pages fail before any page is rendered
+    #[arg(long, default_value_t = 50)]
+    max_render_pages: u64,
+
+    /// Maximum size in bytes of a converted PDF, conversions producing larger files fail.
+    /// No limit when not provided
+    #[arg(long)]
+    max_output_bytes: Option<u64>,
+
+    /// Spreadsheets estimated to print on more than this many pages have their sheets that
+    /// are wider than a page scaled to fit the page width, scaling is disabled by default
+    #[arg(long)]
+    fit_wide_spreadsheets: Option<u64>,
+
+    /// User to run LibreOffice as in the "uid" or "uid:gid" format, requires the server to be
+    /// started as root (or with CAP_SETUID and CAP_SETGID). Linux only
+    #[arg(long)]
+    sandbox_user: Option<SandboxUser>,
+
+    /// Restrict LibreOffice to reading the office install, font directories and system
+    /// directories and writing to the temp directory. Linux only
+    #[arg(long)]
+    sandbox_filesystem: bool,
+
+    /// Additional path LibreOffice can read when the filesystem is restricted, can be
+    /// provided multiple times
+    #[arg(long = "sandbox-read-path")]
+    sandbox_read_paths: Vec<PathBuf>,
+
+    /// Block LibreOffice from opening network sockets, prevents documents from loading
+    /// remote resources. Linux only
+    #[arg(long)]
+    sandbox_block_network: bool,
+
+    /// Allow macros embedded in documents to run according to the LibreOffice macro
+    /// security settings, macros are never run when not provided
+    #[arg(long)]
+    allow_macros: bool,
+
+    /// Allow LibreOffice to update external links (linked files, images and DDE references)
+    /// using its defaults, external links are never updated when not provided
+    #[arg(long)]
+    allow_external_links: bool,
+
+    /// Draw embedded objects (i.e equations, diagrams and embedded spreadsheets) using the
+    /// replacement image stored with them instead of converting them, embedded objects without
+    /// a replacement image are reported in the conversion warnings
+    #[arg(long)]
+    prefer_replacement_images: bool,
+
+    /// Allow requests to provide raw `filter_options` and `load_options` that are passed
+    /// to LibreOffice as-is, raw options are rejected when not provided
+    #[arg(long)]
+    allow_raw_options: bool,
+
+    /// Path to the qpdf binary used to linearize PDFs, defaults to "qpdf"
+    #[arg(long)]
+    qpdf_path: Option<PathBuf>,
+
+    /// Level to log output written by LibreOffice at, defaults to off (not captured)
+    #[arg(long, value_enum)]
+    office_log_level: Option<OfficeLogLevel>,
+
+    /// Directory to persist queued jobs to so they survive a restart, jobs are only
+    /// kept in memory when not provided
+    #[arg(long)]
+    queue_dir: Option<PathBuf>,
+
+    /// Time in seconds to keep finished jobs and their results for, defaults to 3600 (1 hour)
+    #[arg(long)]
+    job_ttl: Option<u64>,
+
+    /// Time in seconds to replay the response of a request made with an idempotency key
+    /// for, defaults to 900 (15 minutes)
+    #[arg(long)]
+    idempotency_ttl: Option<u64>,
+
+    /// Maximum total size in bytes of the responses replayed for requests made with an
+    /// idempotency key, the responses that expire first are removed to make room for new
+    /// responses. Defaults to 256MiB
+    #[arg(long)]
+    idempotency_max_size: Option<u64>,
+
+    /// Time in seconds to keep resumable uploads that haven't received any bytes
+    /// for, defaults to 3600 (1 hour)
+    #[arg(long)]
+    upload_ttl: Option<u64>,
+
+    /// Secret used to sign job result download links, a random secret is generated
+    /// when not provided which causes links to stop working when the server restarts
+    #[arg(long)]
+    download_secret: Option<String>,
+
+    /// Time in seconds job result download links are valid for, defaults to 900 (15 minutes)
+    #[arg(long)]
+    download_ttl: Option<u64>,
+
+    /// Time in seconds to retain conversion results for so they can be downloaded again
+    /// using GET /results/{id}, results are not retained when not provided
+    #[arg(long)]
+    result_ttl: Option<u64>,
+
+    /// Maximum total size in bytes of the retained conversion results, the oldest results
+    /// are removed to make room for new results. Defaults to 1GiB
+    #[arg(long)]
+    result_max_size: Option<u64>,
+
+    /// Directory to write retained conversion results to, defaults to the
+    /// "office-convert-results" directory within the temp directory
+    #[arg(long)]
+    results_dir: Option<PathBuf>,
+
+    /// Backend to store job files and retained conversion results in, "memory" keeps them
+    /// in the server memory and writes the remaining files to memory (/dev/shm) as with
+    /// --in-memory so nothing is written to disk. Defaults to "local"
+    #[arg(long, value_enum)]
+    storage: Option<StorageKind>,
+
+    /// Bearer token required to access the admin endpoints, the admin endpoints
+    /// are disabled when not provided
+    #[arg(long)]
+    admin_token: Option<String>,
+
+    /// IP address or CIDR range (i.e "10.0.0.0/8") allowed to access the admin endpoints
+    /// and /collect-garbage, can be provided multiple times. Any address is allowed when
+    /// not provided
+    #[arg(long = "admin-allow-ip")]
+    admin_allow_ips: Vec<IpRange>,
+
+    /// Require the admin token for /collect-garbage, requires --admin-token
+    #[arg(long)]
+    protect_collect_garbage: bool,
+
+    /// Maximum number of entries allowed in a batch ZIP archive, defaults to 500
+    #[arg(long)]
+    batch_max_entries: Option<usize>,
+
+    /// Maximum total decompressed size in bytes of a batch ZIP archive, defaults to 1GiB
+    #[arg(long)]
+    batch_max_size: Option<u64>,
+
+    /// Maximum size in bytes of a request body for routes receiving files, defaults to 1GiB
+    #[arg(long, visible_alias = "max-upload-size")]
+    max_body_size: Option<u64>,
+
+    /// Maximum size in bytes of a request body for routes that don't receive files
+    /// (status, garbage collection and admin routes)
+    #[arg(long, default_value_t = 64 * 1024)]
+    max_json_body_size: usize,
+
+    /// Maximum number of conversions that can be waiting for LibreOffice while another
+    /// conversion is running, further conversions are rejected. No limit when not provided
+    #[arg(long)]
+    max_queued: Option<usize>,
+
+    /// Number of threads for handling requests, defaults to the number of CPUs
+    #[arg(long)]
+    worker_threads: Option<NonZeroUsize>,
+
+    /// Pick the worker threads, maximum queued conversions and maximum body size from
+    /// the CPUs and memory available (including container limits), provided values take
+    /// priority over the picked values
+    #[arg(long)]
+    auto_scale: bool,
+
+    /// Reduce memory usage on hosts with little memory, LibreOffice memory is trimmed and
+    /// freed memory is returned to the system after every conversion, at most one conversion
+    /// can wait for LibreOffice unless --max-queued is provided and the files of conversions
+    /// are never written to memory
+    #[arg(long, conflicts_with = "in_memory")]
+    low_memory: bool,
+
+    /// Number of recent conversion failures reported by /status/errors, failures are not
+    /// kept when 0
+    #[arg(long, default_value_t = 50)]
+    recent_errors: usize,
+
+    /// URL of the OpenTelemetry collector endpoint to export traces to using
+    /// OTLP over HTTP (i.e http://localhost:4318/v1/traces), traces are exported
+    /// when provided or when the "OTEL_EXPORTER_OTLP_ENDPOINT" variable is set
+    #[cfg(feature = "otel")]
+    #[arg(long)]
+    otlp_endpoint: Option<String>,
+
+    /// Runs as the worker for the named engine from the config file, engine
+    /// workers are started by the server
+    #[arg(long, hide = true)]
+    engine_worker: Option<String>,
+
+    /// Run each conversion in a new short-lived process so a document crashing or
+    /// corrupting LibreOffice can't affect the conversions after it, adds the LibreOffice
+    /// startup time to every conversion
+    #[arg(long)]
+    process_per_conversion: bool,
+
+    /// Runs a single conversion requested by the server, conversion processes are
+    /// started by the server when using --process-per-conversion
+    #[arg(long, hide = true)]
+    conversion_process: bool,
+}
+
+/// Commands that run instead of the server
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Benchmarks a running server by converting a file repeatedly, reporting
+    /// the throughput and latency percentiles
+    Bench(BenchArgs),
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
+/// Address the server listens on
+enum ServerAddress {
+    /// TCP socket address
+    Tcp(String),
+    /// Path to a Unix domain socket
+    Unix(PathBuf),
+    /// Socket passed by systemd socket activation
+    Activated(ActivatedListener),
+}
+
+fn main() -> anyhow::Result<()> {
     _ = dotenvy::dotenv();
 
-    // Start configuring a `fmt` subscriber
-    let subscriber = tracing_subscriber::fmt()
-        // Use the logging options from env variables
-        .with_env_filter(EnvFilter::from_default_env())
+    let args = Args::parse();
+
+    // Defaults are picked before the runtime is created as they include its threads
+    let auto_scale = args
+        .auto_scale
+        .then(|| AutoScale::from_resources(SystemResources::detect()));
+
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    runtime.enable_all();
+
+    let worker_threads = args
+        .worker_threads
+        .map(NonZeroUsize::get)
+        .or(auto_scale.map(|auto_scale| auto_scale.worker_threads));
+
+    if let Some(worker_threads) = worker_threads {
+        runtime.worker_threads(worker_threads);
+    }
+
+    // Load the config file, the config provides the logging filter
+    let config = match &args.config {
+        Some(path) => Config::load(path).context("failed to load config")?,
+        None => Config::default(),
+    };
+
+    // Office environment is set before the runtime starts its threads
+    set_office_environment(&config.office_env(args.engine_worker.as_deref()));
+
+    runtime
+        .build()
+        .context("failed to create runtime")?
+        .block_on(run(args, auto_scale, config))
+}
+
+async fn run(args: Args, auto_scale: Option<AutoScale>, config: Config) -> anyhow::Result<()> {
+    if let Some(Command::Bench(bench_args)) = args.command {
+        return bench::run(bench_args).await;
+    }
+
+    // Office output must be captured before logging is setup as logging
+    // needs to write to the original stdout once its captured
+    let log_writer = match args.office_log_level.unwrap_or(OfficeLogLevel::Off) {
+        OfficeLogLevel::Off => BoxMakeWriter::new(std::io::stdout),
+        level => {
+            let stdout = capture_office_output(level).context("failed to capture office output")?;
+            BoxMakeWriter::new(std::sync::Mutex::new(stdout))
+        }
+    };
+
+    // Logging filter can be replaced when the config is reloaded
+    let (log_filter, log_filter_handle) =
+        tracing_subscriber::reload::Layer::new(log_filter(config.log_level.as_deref()));
+
+    // Start configuring a `fmt` layer
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        // Write logs to the original stdout
+        .with_writer(log_writer)
         // Display source code file paths
         .with_file(true)
         // Display source code line numbers
         .with_line_number(true)
         // Don't display the event's target (module path)
         .with_target(false)
-        // Build the subscriber
-        .finish();
+        // Use the logging options from the config or env variables
+        .with_filter(log_filter);
+
+    let subscriber = tracing_subscriber::registry().with(fmt_layer);
+
+    // Spans are exported separately from the logging options when an OTLP endpoint is configured
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(
+        telemetry::otel_layer(args.otlp_endpoint.clone())
+            .context("failed to setup trace export")?
+            .with_filter(tracing_subscriber::filter::LevelFilter::INFO),
+    );
 
     // use that subscriber to process traces emitted after this point
     tracing::subscriber::set_global_default(subscriber)?;
 
-    let args = Args::parse();
+    if let Some(auto_scale) = &auto_scale {
+        info!(
+            cpus = auto_scale.resources.cpus,
+            memory = ?auto_scale.resources.memory,
+            worker_threads = auto_scale.worker_threads,
+            max_queued = auto_scale.max_queued,
+            max_body_size = auto_scale.max_body_size,
+            "auto scaled defaults from available resources"
+        );
+    }
+
+    debug!(allocator = alloc::ALLOCATOR, "using global allocator");
+
+    if args.low_memory {
+        info!("low memory mode enabled");
+    }
+
+    // Arguments take priority over the low memory and auto scaled defaults
+    let max_queued = args
+        .max_queued
+        .or(args.low_memory.then_some(LOW_MEMORY_MAX_QUEUED))
+        .or(auto_scale.map(|auto_scale| auto_scale.max_queued));
+    let max_body_size = args
+        .max_body_size
+        .or(auto_scale.map(|auto_scale| auto_scale.max_body_size))
+        .unwrap_or(1024 * 1024 * 1024);
 
     let mut office_path: Option<PathBuf> = None;
 
+    // Engine workers use the office install of their engine
+    if let Some(name) = &args.engine_worker {
+        let engine = config
+            .engines
+            .get(name)
+            .with_context(|| format!("unknown engine \"{name}\""))?;
+        office_path = Some(engine.office_path.clone());
+    }
+
     // Try loading office path from command line
-    if let Some(path) = args.office_path {
-        office_path = Some(PathBuf::from(&path));
+    if office_path.is_none() {
+        if let Some(path) = args.office_path {
+            office_path = Some(PathBuf::from(&path));
+        }
     }
 
     // Try loading office path from environment variables
@@ -92,324 +647,1218 @@ async fn main() -> anyhow::Result<()> {
 
     debug!("using libreoffice install from: {}", office_path.display());
 
-    // Determine the address to run the server on
-    let server_address = if args.host.is_some() || args.port.is_some() {
+    // Determine the directory to store temporary files
+    let temp_dir = args.temp_dir.unwrap_or_else(std::env::temp_dir);
+    std::fs::create_dir_all(&temp_dir).context("failed to create temp directory")?;
+
+    debug!("using temp directory: {}", temp_dir.display());
+
+    // Determine the address to run the server on, engine workers
+    // listen on a socket in the temp directory
+    let server_address = if let Some(name) = &args.engine_worker {
+        ServerAddress::Unix(engine_socket_path(&temp_dir, name))
+    } else if let Some(listener) = activated_listener()? {
+        ServerAddress::Activated(listener)
+    } else if let Some(path) = args.uds {
+        ServerAddress::Unix(path)
+    } else if args.host.is_some() || args.port.is_some() {
         let host = args.host.unwrap_or_else(|| "0.0.0.0".to_string());
         let port = args.port.unwrap_or(8080);
 
-        format!("{host}:{port}")
+        ServerAddress::Tcp(format!("{host}:{port}"))
     } else {
-        std::env::var("SERVER_ADDRESS").context("missing SERVER_ADDRESS")?
+        ServerAddress::Tcp(std::env::var("SERVER_ADDRESS").context("missing SERVER_ADDRESS")?)
+    };
+
+    let batch_limits = BatchLimits {
+        max_entries: args.batch_max_entries.unwrap_or(500),
+        max_total_size: args.batch_max_size.unwrap_or(1024 * 1024 * 1024),
+    };
+
+    // Remove temporary files orphaned by a previous crash, then keep
+    // checking for orphaned files periodically
+    let temp_max_age = Duration::from_secs(args.temp_max_age.unwrap_or(60 * 60));
+    reap_temp_files(&temp_dir, temp_max_age, args.secure_delete);
+    spawn_temp_reaper(temp_dir.clone(), temp_max_age, args.secure_delete);
+
+    let storage = args.storage.unwrap_or_default();
+    if storage == StorageKind::Memory && args.queue_dir.is_some() {
+        anyhow::bail!("--queue-dir can't be used with memory storage");
+    }
+
+    // Conversion files are written to memory when a memory backed directory is available,
+    // files in memory count towards the memory of the host so they are kept on disk in
+    // low memory mode
+    let memory_dir = if !args.low_memory && (args.in_memory || storage == StorageKind::Memory) {
+        let memory_dir = memory_temp_dir();
+        match &memory_dir {
+            Some(memory_dir) => {
+                debug!("using memory directory: {}", memory_dir.display());
+                reap_temp_files(memory_dir, temp_max_age, args.secure_delete);
+                spawn_temp_reaper(memory_dir.clone(), temp_max_age, args.secure_delete);
+            }
+            None => warn!("memory backed directory is unavailable, using temp directory"),
+        }
+        memory_dir
+    } else {
+        None
+    };
+
+    // Files processed outside of office are kept off disk with memory storage
+    let files_dir = match (storage, &memory_dir) {
+        (StorageKind::Memory, Some(memory_dir)) => memory_dir.clone(),
+        _ => temp_dir.clone(),
     };
 
+    let linearizer = Arc::new(Linearizer::new(
+        args.qpdf_path.unwrap_or_else(|| PathBuf::from("qpdf")),
+        files_dir.clone(),
+        args.secure_delete,
+    ));
+
+    // Load the certificate used to sign PDFs
+    let signing = match &args.signing_certificate {
+        Some(path) => {
+            // Try loading the password from the command line or environment variables
+            let password = args
+                .signing_password
+                .or_else(|| std::env::var("SIGNING_PASSWORD").ok())
+                .unwrap_or_default();
+
+            let certificate =
+                load_pkcs12(path, &password).context("failed to load signing certificate")?;
+            debug!("loaded signing certificate from: {}", path.display());
+            Some(certificate)
+        }
+        None => None,
+    };
+
+    // Fonts office uses in place of fonts that aren't installed
+    let font_substitutions = match &args.font_substitutions {
+        Some(path) => load_font_substitutions(path)?,
+        None => Default::default(),
+    };
+
+    let converter_options = ConverterOptions {
+        office_path,
+        temp_dir: temp_dir.clone(),
+        memory_dir,
+        min_free_disk: args.min_free_disk.unwrap_or(100 * 1024 * 1024),
+        secure_delete: args.secure_delete,
+        font_dirs: args.fonts_dirs,
+        signing,
+        max_convert_duration: args.max_convert_seconds.map(Duration::from_secs),
+        output_limits: OutputLimits {
+            max_pages: args.max_output_pages,
+            max_bytes: args.max_output_bytes,
+            max_render_pages: Some(args.max_render_pages),
+        },
+        sandbox: SandboxOptions {
+            user: args.sandbox_user,
+            restrict_filesystem: args.sandbox_filesystem,
+            read_paths: args.sandbox_read_paths,
+            block_network: args.sandbox_block_network,
+        },
+        document_security: DocumentSecurity {
+            allow_macros: args.allow_macros,
+            allow_external_links: args.allow_external_links,
+        },
+        retry: RetryPolicy {
+            retries: args.retry_failed_conversions,
+            restart: args.restart_before_retry,
+        },
+        small_file_threshold: args.small_file_threshold,
+        priority_aging: args.priority_aging_seconds.map(|seconds| PriorityAging {
+            interval: Duration::from_secs(seconds.max(1)),
+            max: args.priority_aging_max.into(),
+        }),
+        prefer_replacement_images: args.prefer_replacement_images,
+        font_substitutions: font_substitutions.clone(),
+        conversion_process: match args.process_per_conversion {
+            true => Some(ConversionProcess {
+                program: std::env::current_exe()
+                    .context("failed to determine server executable")?,
+                args: std::env::args_os()
+                    .skip(1)
+                    .chain([OsString::from("--conversion-process")])
+                    .collect(),
+            }),
+            false => None,
+        },
+        low_memory: args.low_memory,
+    };
+
+    // Conversion processes convert a single file and exit
+    if args.conversion_process {
+        return run_conversion_process(converter_options).await;
+    }
+
     // Create office access and get office details
-    let (office_details, office_handle) = create_office_runner(office_path).await?;
+    let (office_details, converter) = Converter::start(converter_options).await?;
+    let office_details = Arc::new(office_details);
+
+    // Office is periodically tested and restarted when the test keeps failing
+    let readiness = Readiness::new();
+    if let Some(interval) = args.self_test_interval {
+        spawn_self_test(
+            converter.clone(),
+            readiness.clone(),
+            Duration::from_secs(interval.max(1)),
+            args.self_test_failures.unwrap_or(3).max(1),
+        );
+    }
+
+    // systemd restarts the server when the watchdog stops being pinged
+    let watchdog_max_busy = args
+        .watchdog_max_busy_seconds
+        .or(args
+            .max_convert_seconds
+            .map(|value| value.saturating_mul(2)))
+        .map(Duration::from_secs);
+    spawn_watchdog(converter.clone(), watchdog_max_busy);
+
+    // Installed fonts are loaded after office has registered the font directories
+    let fonts = Arc::new(FontCatalog::load(&font_substitutions));
+
+    // Engine workers report usage to the main server and leave jobs to the main server
+    let (engines, usage_file, queue_dir) = match &args.engine_worker {
+        Some(_) => {
+            exit_with_main_server();
+            (Engines::Worker, None, None)
+        }
+        None => (
+            spawn_engine_workers(&config.engines, &temp_dir)
+                .context("failed to start engine workers")?,
+            config.usage_file.clone(),
+            args.queue_dir,
+        ),
+    };
+    let engines = Arc::new(engines);
+
+    let tenants =
+        Arc::new(Tenants::new(&config.tenants, usage_file).context("failed to load tenants")?);
+    spawn_usage_writer(tenants.clone());
+
+    // Create the job store, recovering any persisted jobs
+    let job_storage = queue_dir
+        .map(|queue_dir| create_storage(storage, queue_dir, args.secure_delete))
+        .transpose()
+        .context("failed to create queue storage")?;
+
+    let job_ttl = Duration::from_secs(args.job_ttl.unwrap_or(60 * 60));
+    let job_store = JobStore::new(job_storage, job_ttl);
+    job_store
+        .recover()
+        .await
+        .context("failed to recover persisted jobs")?;
+    let config = SharedConfig::new(config);
+    let recent_failures = RecentFailures::new(args.recent_errors);
+    spawn_job_tasks(
+        job_store.clone(),
+        converter.clone(),
+        tenants.clone(),
+        config.clone(),
+        recent_failures.clone(),
+        batch_limits,
+        office_details.clone(),
+    );
+
+    // Config is reloaded on SIGHUP or through the admin endpoint
+    let reloader = Arc::new(Reloader {
+        config_path: args.config.clone(),
+        font_substitutions_path: args.font_substitutions.clone(),
+        config: config.clone(),
+        tenants: tenants.clone(),
+        fonts: fonts.clone(),
+        engines: engines.clone(),
+        log_filter: log_filter_handle,
+        lock: Default::default(),
+    });
+    spawn_reload_signal(reloader.clone())?;
+
+    // Responses stored for requests with an idempotency key
+    let idempotency_ttl = Duration::from_secs(args.idempotency_ttl.unwrap_or(15 * 60));
+    let idempotency_store = IdempotencyStore::new(
+        idempotency_ttl,
+        args.idempotency_max_size.unwrap_or(256 * 1024 * 1024),
+        max_body_size,
+    );
+    spawn_idempotency_collector(idempotency_store.clone());
+
+    // Resumable uploads are written to the temp directory
+    let upload_ttl = Duration::from_secs(args.upload_ttl.unwrap_or(60 * 60));
+    let upload_store = UploadStore::new(files_dir.clone(), args.secure_delete, upload_ttl);
+    spawn_upload_collector(upload_store.clone());
+
+    // Conversion results are retained when a TTL is provided, engine workers
+    // leave retaining results to the main server
+    let result_storage = match (&args.engine_worker, args.result_ttl) {
+        (None, Some(_)) => {
+            let results_dir = args
+                .results_dir
+                .unwrap_or_else(|| temp_dir.join("office-convert-results"));
+            let result_storage = create_storage(storage, results_dir, args.secure_delete)
+                .context("failed to create results storage")?;
+            Some(result_storage)
+        }
+        _ => None,
+    };
+
+    let result_store = ResultStore::new(
+        result_storage,
+        Duration::from_secs(args.result_ttl.unwrap_or_default()),
+        args.result_max_size.unwrap_or(1024 * 1024 * 1024),
+    );
+
+    // Results retained before a restart can no longer be fetched
+    result_store
+        .clear()
+        .await
+        .context("failed to remove previously retained results")?;
+    spawn_result_collector(result_store.clone());
+
+    // Try loading the download secret from the command line or environment variables
+    let download_secret = args
+        .download_secret
+        .or_else(|| std::env::var("DOWNLOAD_SECRET").ok())
+        .filter(|value| !value.is_empty());
+    let download_ttl = Duration::from_secs(args.download_ttl.unwrap_or(15 * 60));
+    let download_signer = Arc::new(DownloadSigner::new(download_secret, download_ttl));
+
+    // Try loading the admin token from the command line or environment variables
+    let admin_token = args
+        .admin_token
+        .or_else(|| std::env::var("ADMIN_TOKEN").ok())
+        .filter(|value| !value.is_empty())
+        .map(AdminToken::new);
+
+    if args.protect_collect_garbage && admin_token.is_none() {
+        anyhow::bail!("--protect-collect-garbage requires an admin token");
+    }
+
+    // Admin endpoints are restricted to the allowlist when one is provided
+    let admin_allowlist = (!args.admin_allow_ips.is_empty())
+        .then(|| AdminAllowlist::new(args.admin_allow_ips.clone()));
+
+    // Conversions waiting for office across all clients
+    let queue_limit = QueueLimit::new(max_queued);
+
+    // Conversions using another engine are forwarded to the engine worker
+    let select_engine = middleware::from_fn_with_state(engines, select_engine);
+
+    // Garbage collection can be restricted to admins
+    let mut collect_garbage_route = Router::new().route("/collect-garbage", post(collect_garbage));
+    if let (true, Some(admin_token)) = (args.protect_collect_garbage, &admin_token) {
+        collect_garbage_route = collect_garbage_route.route_layer(middleware::from_fn_with_state(
+            admin_token.clone(),
+            require_admin,
+        ));
+    }
+    if let Some(admin_allowlist) = &admin_allowlist {
+        collect_garbage_route = collect_garbage_route.route_layer(middleware::from_fn_with_state(
+            admin_allowlist.clone(),
+            restrict_to_allowlist,
+        ));
+    }
+
+    // Engines are listed in the capabilities by name
+    let mut engine_names: Vec<String> = config.current().engines.keys().cloned().collect();
+    engine_names.sort();
+    engine_names.insert(0, DEFAULT_ENGINE.to_string());
+
+    let capabilities_details = Capabilities::new(
+        admin_token.is_some(),
+        engine_names,
+        args.allow_raw_options,
+        CapabilityLimits {
+            max_file_size: max_body_size,
+            max_queued,
+            max_output_pages: args.max_output_pages,
+            max_output_bytes: args.max_output_bytes,
+            max_render_pages: args.max_render_pages,
+            batch_max_entries: batch_limits.max_entries,
+            batch_max_size: batch_limits.max_total_size,
+        },
+    );
+
+    // Engine workers receive requests forwarded from the server with the base path removed
+    let base_path = match (&args.base_path, &args.engine_worker) {
+        (Some(base_path), None) => BasePath::parse(base_path).context("invalid --base-path")?,
+        _ => BasePath::default(),
+    };
 
     // Create the router
-    let app = Router::new()
+    let mut app = Router::new()
         .route("/status", get(status))
+        .route("/status/errors", get(recent_errors))
         .route("/office-version", get(office_version))
         .route("/supported-formats", get(supported_formats))
-        .route("/convert", post(convert))
-        .route("/collect-garbage", post(collect_garbage))
-        .layer(DefaultBodyLimit::max(1024 * 1024 * 1024))
-        .layer(Extension(office_handle))
-        .layer(Extension(Arc::new(office_details)));
-
-    // Create a TCP listener
-    let listener = tokio::net::TcpListener::bind(&server_address)
-        .await
-        .context("failed to bind http server")?;
+        .route("/capabilities", get(capabilities))
+        .route("/ready", get(ready))
+        .route("/fonts", get(list_fonts))
+        .route("/metrics", get(metrics))
+        .merge(collect_garbage_route)
+        // Download links are signed so don't require an API key
+        .route("/download/:token", get(download))
+        // Conversion routes require an API key when tenants are configured
+        .merge(
+            Router::new()
+                // Repeated requests with the same idempotency key replay the first response
+                .merge(
+                    Router::new()
+                        .route("/convert", post(convert))
+                        .route("/convert-batch", post(convert_batch))
+                        .route("/render", post(render))
+                        .route("/render-pages", post(render_pages))
+                        .route_layer(middleware::from_fn_with_state(
+                            recent_failures.clone(),
+                            record_failures,
+                        ))
+                        .route_layer(select_engine.clone())
+                        .route_layer(middleware::from_fn_with_state(
+                            idempotency_store,
+                            idempotent,
+                        )),
+                )
+                .route("/detect", post(detect).route_layer(select_engine.clone()))
+                .route("/validate", post(validate).route_layer(select_engine))
+                .route("/jobs", post(submit_job))
+                .route("/jobs/:id", get(get_job).delete(cancel_job))
+                .route("/jobs/:id/result", get(get_job_result))
+                .route("/jobs/:id/preview", get(get_job_preview))
+                .route("/jobs/:id/download-url", post(create_download_url))
+                .route("/uploads/:id/convert", post(convert_upload))
+                .route("/ws", get(convert_socket))
+                .route("/selftest", get(self_test))
+                .route_layer(middleware::from_fn_with_state(
+                    queue_limit.clone(),
+                    limit_queue,
+                ))
+                // Text is extracted without office so isn't counted by the queue limit
+                .route("/extract-text", post(extract_text))
+                // Receiving upload chunks doesn't use office so isn't counted by the queue limit
+                .route("/results/:id", get(get_result))
+                .route("/uploads", post(create_upload))
+                .route(
+                    "/uploads/:id",
+                    get(get_upload).patch(append_upload).delete(delete_upload),
+                )
+                .route_layer(middleware::from_fn_with_state(
+                    tenants.clone(),
+                    require_tenant,
+                ))
+                // Routes receiving files replace the small default body limit
+                .layer(DefaultBodyLimit::max(
+                    usize::try_from(max_body_size).unwrap_or(usize::MAX),
+                )),
+        );
 
-    debug!("server started on: {server_address}");
+    // Admin endpoints are only available when a token is configured
+    if let Some(admin_token) = admin_token {
+        let mut admin = Router::new()
+            .route("/restart-office", post(restart_office))
+            .route("/reload", post(reload_config))
+            .route("/usage", get(usage))
+            .route("/stats", get(stats))
+            .layer(middleware::from_fn_with_state(admin_token, require_admin))
+            // Dashboard asks for the token itself so is served without it
+            .route("/", get(dashboard));
+
+        if let Some(admin_allowlist) = admin_allowlist {
+            admin = admin.route_layer(middleware::from_fn_with_state(
+                admin_allowlist,
+                restrict_to_allowlist,
+            ));
+        }
 
-    // Serve the app from the listener
-    axum::serve(listener, app)
-        .await
-        .context("failed to serve")?;
+        app = app.nest("/admin", admin);
+    }
 
-    Ok(())
-}
+    let app = app
+        // Spans are named after the route so are only created for matched routes
+        .route_layer(middleware::from_fn(trace_request))
+        // Routes that don't receive files (status, garbage collection, admin)
+        // only accept small bodies
+        .layer(DefaultBodyLimit::max(args.max_json_body_size))
+        // Request bodies compressed using the "Content-Encoding" header are
+        // decompressed before the body limit is applied
+        .layer(RequestDecompressionLayer::new())
+        .layer(response_compression())
+        .layer(Extension(converter))
+        .layer(Extension(MaxFileSize(max_body_size)))
+        .layer(Extension(AllowRawOptions(args.allow_raw_options)))
+        .layer(Extension(PreferReplacementImages(
+            args.prefer_replacement_images,
+        )))
+        .layer(Extension(FitWideSpreadsheets(args.fit_wide_spreadsheets)))
+        .layer(Extension(batch_limits))
+        .layer(Extension(capabilities_details))
+        .layer(Extension(readiness))
+        .layer(Extension(job_store))
+        .layer(Extension(recent_failures))
+        .layer(Extension(queue_limit))
+        .layer(Extension(upload_store))
+        .layer(Extension(result_store))
+        .layer(Extension(download_signer))
+        .layer(Extension(linearizer))
+        .layer(Extension(fonts))
+        .layer(Extension(tenants))
+        .layer(Extension(config))
+        .layer(Extension(reloader))
+        .layer(Extension(office_details))
+        .layer(Extension(base_path.clone()));
+
+    // Routes are served under the base path when behind a reverse proxy
+    let app = match base_path.is_root() {
+        true => app,
+        false => {
+            debug!("serving routes under {}", base_path.as_str());
+            Router::new().nest(base_path.as_str(), app)
+        }
+    };
+
+    let listener = match server_address {
+        ServerAddress::Tcp(server_address) => {
+            // Create a TCP listener
+            let listener = tokio::net::TcpListener::bind(&server_address)
+                .await
+                .context("failed to bind http server")?;
 
-/// Messages the office runner can process
-pub enum OfficeMsg {
-    /// Message to convert a file
-    Convert {
-        /// The file bytes to convert
-        bytes: Bytes,
+            debug!("server started on: {server_address}");
+            listener
+        }
+        ServerAddress::Unix(path) => return serve_unix(&path, app).await,
+        ServerAddress::Activated(ActivatedListener::Tcp(listener)) => {
+            listener
+                .set_nonblocking(true)
+                .context("failed to use socket passed by systemd")?;
+            tokio::net::TcpListener::from_std(listener)
+                .context("failed to use socket passed by systemd")?
+        }
+        #[cfg(unix)]
+        ServerAddress::Activated(ActivatedListener::Unix(listener)) => {
+            listener
+                .set_nonblocking(true)
+                .context("failed to use socket passed by systemd")?;
+            let listener = tokio::net::UnixListener::from_std(listener)
+                .context("failed to use socket passed by systemd")?;
+            return serve_unix_listener(listener, app).await;
+        }
+    };
 
-        /// The return channel for sending back the result
-        tx: oneshot::Sender<anyhow::Result<Bytes>>,
-    },
+    notify_ready();
 
-    /// Tells office to clean up and trim its memory usage
-    CollectGarbage,
+    // Serve the app from the listener, the address of the client is
+    // provided for checking the admin allowlist
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await
+    .context("failed to serve")?;
 
-    /// Message to check if the server is busy, ignored
-    BusyCheck,
+    Ok(())
 }
 
-/// Handle to send messages to the office runner
-#[derive(Clone)]
-pub struct OfficeHandle(mpsc::Sender<OfficeMsg>);
+/// Escapes non ASCII characters within a JSON string so that it can be used
+/// as a header value (i.e font names in warnings)
+fn escape_non_ascii(json: &str) -> String {
+    let mut output = String::with_capacity(json.len());
+    let mut buffer = [0u16; 2];
 
-/// Creates a new office runner on its own thread providing
-/// a handle to access it via messages
-async fn create_office_runner(path: PathBuf) -> anyhow::Result<(OfficeDetails, OfficeHandle)> {
-    let (tx, rx) = mpsc::channel(1);
+    for character in json.chars() {
+        if character.is_ascii() {
+            output.push(character);
+            continue;
+        }
 
-    let (startup_tx, startup_rx) = oneshot::channel();
+        for unit in character.encode_utf16(&mut buffer) {
+            output.push_str(&format!("\\u{unit:04x}"));
+        }
+    }
 
-    std::thread::spawn(move || {
-        let mut startup_tx = Some(startup_tx);
+    output
+}
 
-        if let Err(cause) = office_runner(path, rx, &mut startup_tx) {
-            error!(%cause, "failed to start office runner");
+impl HttpError for ConvertError {
+    fn log(&self) {
+        match self {
+            // Anyhow errors contain a stacktrace so only the debug variant is used
+            ConvertError::Office(err) => error!("{err:#?}"),
+            _ => error!("{self}: {self:?}"),
+        }
+    }
 
-            // Send the error to the startup channel if its still available
-            if let Some(startup_tx) = startup_tx.take() {
-                _ = startup_tx.send(Err(cause));
+    fn status(&self) -> StatusCode {
+        match self {
+            ConvertError::InsufficientDisk { .. } => StatusCode::INSUFFICIENT_STORAGE,
+            ConvertError::SigningUnavailable | ConvertError::TimezoneUnavailable => {
+                StatusCode::NOT_IMPLEMENTED
+            }
+            ConvertError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            ConvertError::OutputTooLarge { .. } | ConvertError::TooManyPages { .. } => {
+                StatusCode::UNPROCESSABLE_ENTITY
             }
+            ConvertError::Office(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
-    });
+    }
 
-    // Wait for a successful startup
-    let office_details = startup_rx.await.context("startup channel unavailable")??;
-    let office_handle = OfficeHandle(tx);
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ConvertError::InsufficientDisk { .. } => Some("INSUFFICIENT_DISK"),
+            ConvertError::SigningUnavailable => Some("SIGNING_UNAVAILABLE"),
+            ConvertError::TimezoneUnavailable => Some("TIMEZONE_UNAVAILABLE"),
+            ConvertError::Timeout { .. } => Some("CONVERSION_TIMEOUT"),
+            ConvertError::OutputTooLarge { .. } => Some("OUTPUT_SIZE_EXCEEDED"),
+            ConvertError::TooManyPages { .. } => Some("OUTPUT_PAGES_EXCEEDED"),
+            ConvertError::Office(_) => None,
+        }
+    }
 
-    Ok((office_details, office_handle))
+    #[cfg(debug_assertions)]
+    fn backtrace(&self) -> Option<String> {
+        match self {
+            ConvertError::Office(err) => Some(format!("{:?}", err.backtrace())),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Default)]
-struct RunnerState {
-    password_requested: bool,
-}
+/// Request to convert a file
+#[derive(TryFromMultipart)]
+struct UploadAssetRequest {
+    /// The file to convert
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
 
-#[derive(Debug)]
-struct OfficeDetails {
-    filter_types: Option<FilterTypes>,
-    version: Option<OfficeVersionInfo>,
-}
+    /// Page size to use for the output pages
+    page_size: Option<PageSize>,
 
-/// Main event loop for an office runner
-fn office_runner(
-    path: PathBuf,
-    mut rx: mpsc::Receiver<OfficeMsg>,
-    startup_tx: &mut Option<oneshot::Sender<anyhow::Result<OfficeDetails>>>,
-) -> anyhow::Result<()> {
-    // Create office instance
-    let office = Office::new(&path).context("failed to create office instance")?;
-
-    let tmp_dir = temp_dir();
-
-    // Generate random ID for the path name
-    let random_id = rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(10)
-        .map(|value| value as char)
-        .collect::<String>();
-
-    // Create input and output paths
-    let temp_in = tmp_dir.join(format!("lo_native_input_{random_id}"));
-    let temp_out = tmp_dir.join(format!("lo_native_output_{random_id}.pdf"));
-
-    // Convert paths to strings
-    let temp_in_path = temp_in.to_str().context("failed to create temp in path")?;
-    let temp_out_path = temp_out
-        .to_str()
-        .context("failed to create temp out path")?;
-
-    // Create office type safe paths
-    let input_url =
-        DocUrl::from_absolute_path(temp_in_path).context("failed to create input url")?;
-    let output_url =
-        DocUrl::from_absolute_path(temp_out_path).context("failed to create output url")?;
-
-    let runner_state = Rc::new(Mutex::new(RunnerState::default()));
-
-    // Allow prompting for passwords
-    office
-        .set_optional_features(OfficeOptionalFeatures::DOCUMENT_PASSWORD)
-        .context("failed to set optional features")?;
-
-    // Load supported filters and office version details
-    let filter_types = office.get_filter_types().ok();
-    let version = office.get_version_info().ok();
-
-    office
-        .register_callback({
-            let runner_state = runner_state.clone();
-            let input_url = input_url.clone();
-
-            move |office, ty, payload| {
-                debug!(?ty, "callback invoked");
-
-                let state = &mut *runner_state.lock();
-
-                if let CallbackType::DocumentPassword = ty {
-                    state.password_requested = true;
-
-                    // Provide now password
-                    if let Err(cause) = office.set_document_password(&input_url, None) {
-                        error!(?cause, "failed to set document password");
-                    }
-                }
+    /// Orientation to use for the output pages
+    orientation: Option<Orientation>,
 
-                if let CallbackType::JSDialog = ty {
-                    let payload = unsafe { CStr::from_ptr(payload) };
-                    let value: serde_json::Value =
-                        serde_json::from_slice(payload.to_bytes()).unwrap();
+    /// Page margin in millimeters to use for the output pages
+    margin: Option<f32>,
 
-                    debug!(?value, "js dialog request");
-                }
-            }
-        })
-        .context("failed to register office callback")?;
+    /// Whether to append the converted attachments when converting emails
+    include_attachments: Option<bool>,
 
-    // Report successful startup
-    if let Some(startup_tx) = startup_tx.take() {
-        _ = startup_tx.send(Ok(OfficeDetails {
-            filter_types,
-            version,
-        }));
-    }
+    /// Name of a preset from the server config to use for the export options
+    preset: Option<String>,
 
-    // Get next message
-    while let Some(msg) = rx.blocking_recv() {
-        let (input, output) = match msg {
-            OfficeMsg::Convert { bytes, tx } => (bytes, tx),
+    /// Whether to linearize the PDF for fast web view
+    linearize: Option<bool>,
 
-            OfficeMsg::CollectGarbage => {
-                if let Err(cause) = office.trim_memory(2000) {
-                    error!(%cause, "failed to collect garbage")
-                }
-                continue;
-            }
-            // Busy checks are ignored
-            OfficeMsg::BusyCheck => continue,
-        };
-
-        // Convert document
-        let result = convert_document(
-            &office,
-            temp_in_path,
-            temp_out_path,
-            &input_url,
-            &output_url,
-            input,
-            &runner_state,
-        );
+    /// Whether to digitally sign the PDF using the configured certificate
+    sign: Option<bool>,
 
-        // Send response
-        _ = output.send(result);
+    /// Password required to open the PDF
+    user_password: Option<String>,
 
-        // Reset runner state
-        *runner_state.lock() = RunnerState::default();
-    }
+    /// Password required to change the permissions of the PDF
+    owner_password: Option<String>,
 
-    Ok(())
-}
+    /// Whether to disallow printing the PDF, requires an owner password
+    no_print: Option<bool>,
 
-/// Converts the provided document bytes into PDF format returning
-/// the converted bytes
-fn convert_document(
-    office: &Office,
+    /// Whether to disallow copying content from the PDF, requires an owner password
+    no_copy: Option<bool>,
 
-    temp_in_str: &str,
-    temp_out_str: &str,
+    /// Whether to disallow modifying the PDF, requires an owner password
+    no_modify: Option<bool>,
 
-    temp_in_path: &DocUrl,
-    temp_out_path: &DocUrl,
-    input: Bytes,
+    /// Whether to export a tagged PDF for screen readers
+    tagged: Option<bool>,
 
-    runner_state: &Rc<Mutex<RunnerState>>,
-) -> anyhow::Result<Bytes> {
-    // Write to temp file
-    std::fs::write(temp_in_str, input).context("failed to write temp input")?;
+    /// Whether to export a PDF conforming to PDF/UA, implies a tagged PDF
+    pdf_ua: Option<bool>,
 
-    // Load document
-    let mut doc =
-        match office.document_load_with_options(temp_in_path, "InteractionHandler=0,Batch=1") {
-            Ok(value) => value,
-            Err(err) => match err {
-                OfficeError::OfficeError(err) => {
-                    error!(%err, "failed to load document");
+    /// Whether to remove metadata, comments, attached files and scripts
+    /// from the PDF
+    sanitize: Option<bool>,
 
-                    let _state = &*runner_state.lock();
+    /// Whether to attempt to repair damaged documents, repairs made are
+    /// reported in the warnings header
+    repair: Option<bool>,
 
-                    // File was encrypted with a password
-                    if err.contains("Unsupported URL") {
-                        return Err(anyhow!("file is encrypted"));
-                    }
+    /// Format of the response, defaults to only the PDF
+    response: Option<ResponseFormat>,
 
-                    // File is malformed or corrupted
-                    if err.contains("loadComponentFromURL returned an empty reference") {
-                        return Err(anyhow!("file is corrupted"));
-                    }
+    /// JSON object containing the options for presentations (notes pages,
+    /// hidden slides and the slide range)
+    impress_options: Option<String>,
 
-                    return Err(OfficeError::OfficeError(err).into());
-                }
-                err => return Err(err.into()),
-            },
-        };
+    /// JSON object containing the options for text documents (comments,
+    /// tracked changes and form fields)
+    writer_options: Option<String>,
 
-    debug!("document loaded");
+    /// Priority of the conversion when waiting for office
+    priority: Option<RequestPriority>,
 
-    // Convert document
-    let result = doc.save_as(temp_out_path, "pdf", None)?;
+    /// Filter options passed to the PDF export filter as-is, requires the
+    /// server to allow raw options
+    filter_options: Option<String>,
 
-    // Attempt to free up some memory
-    _ = office.trim_memory(1000);
+    /// Options appended to the document load options as-is, requires the
+    /// server to allow raw options
+    load_options: Option<String>,
 
-    if !result {
-        return Err(anyhow!("failed to convert file"));
-    }
+    /// Locale to render dates, numbers and currencies in (i.e "de-DE")
+    locale: Option<String>,
 
-    // Read document context
-    let bytes = std::fs::read(temp_out_str).context("failed to read temp out file")?;
+    /// Time zone to render the current date and time in (i.e "Europe/Berlin")
+    timezone: Option<String>,
 
-    // Delete the document after processing (To prevent conversions sticking around in temp)
-    _ = std::fs::remove_file(temp_out_str);
+    /// Password to modify for documents protected with one, documents are
+    /// converted read-only when not provided
+    modify_password: Option<String>,
 
-    Ok(Bytes::from(bytes))
-}
+    /// How uploaded files that are already a PDF are converted, defaults to
+    /// normalizing them through office
+    pdf_passthrough: Option<PdfPassthrough>,
 
-/// Request to convert a file
-#[derive(TryFromMultipart)]
-struct UploadAssetRequest {
-    /// The file to convert
-    #[form_data(limit = "unlimited")]
-    file: FieldData<Bytes>,
+    /// File name of the converted file used in the Content-Disposition header,
+    /// defaults to the uploaded file name with a ".pdf" extension
+    output_filename: Option<String>,
+
+    /// Whether to scale the sheets of spreadsheets that are wider than a page
+    /// to fit the page width, defaults to only scaling spreadsheets estimated
+    /// to print on more pages than the server limit
+    fit_to_width: Option<bool>,
 }
 
+/// Header listing warnings about repairs and substituted fonts from a conversion
+const CONVERSION_WARNINGS_HEADER: &str = "x-conversion-warnings";
+
+/// Header containing the number of attempts made converting the file
+const CONVERSION_ATTEMPTS_HEADER: &str = "x-conversion-attempts";
+
+/// Header containing the time in milliseconds the conversion waited for office
+const QUEUE_WAIT_HEADER: &str = "x-queue-wait-ms";
+
 /// POST /convert
 ///
 /// Converts the provided file to PDF format responding with the PDF file
+#[allow(clippy::too_many_arguments)]
 async fn convert(
-    Extension(office): Extension<OfficeHandle>,
-    TypedMultipart(UploadAssetRequest { file }): TypedMultipart<UploadAssetRequest>,
+    Extension(office): Extension<Converter>,
+    Extension(details): Extension<Arc<OfficeDetails>>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(tenants): Extension<Arc<Tenants>>,
+    Extension(linearizer): Extension<Arc<Linearizer>>,
+    Extension(fonts): Extension<Arc<FontCatalog>>,
+    Extension(results): Extension<Arc<ResultStore>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    Extension(AllowRawOptions(allow_raw_options)): Extension<AllowRawOptions>,
+    Extension(PreferReplacementImages(replacement_images)): Extension<PreferReplacementImages>,
+    Extension(FitWideSpreadsheets(fit_wide_spreadsheets)): Extension<FitWideSpreadsheets>,
+    Extension(failure_input): Extension<FailureInput>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    headers: HeaderMap,
+    TypedMultipart(UploadAssetRequest {
+        file,
+        page_size,
+        orientation,
+        margin,
+        include_attachments,
+        preset,
+        linearize,
+        sign,
+        user_password,
+        owner_password,
+        no_print,
+        no_copy,
+        no_modify,
+        tagged,
+        pdf_ua,
+        sanitize,
+        repair,
+        response: response_format,
+        impress_options,
+        writer_options,
+        priority,
+        filter_options,
+        load_options,
+        locale,
+        timezone,
+        modify_password,
+        pdf_passthrough,
+        output_filename,
+        fit_to_width,
+    }): TypedMultipart<UploadAssetRequest>,
 ) -> Result<Response<Body>, DynHttpError> {
-    let (tx, rx) = oneshot::channel();
-
-    // Convert the file
-    office
-        .0
-        .send(OfficeMsg::Convert {
-            bytes: file.contents,
-            tx,
-        })
-        .await
-        .context("failed to send convert request")?;
+    let started_at = Instant::now();
+    let file = decompress_file(file, max_file_size).await?;
+    failure_input.set_size(file.contents.len());
+    let config = config.current();
+    let office = office.with_priority(request_priority(
+        tenant.as_deref().map(Arc::as_ref),
+        priority,
+    ));
+    let response_format = response_format.unwrap_or_default();
+    let output_filename = output_file_name(
+        output_filename.as_deref(),
+        file.metadata.file_name.as_deref(),
+    )?;
+
+    let mut export_options = match &preset {
+        Some(name) => config.preset(name)?.clone(),
+        None => PdfExportOptions::default(),
+    };
+
+    if sign.unwrap_or_default() {
+        export_options.sign = Some(true);
+    }
+
+    // Security options from the request take priority over the preset
+    if user_password.is_some() {
+        export_options.user_password = user_password;
+    }
+
+    if owner_password.is_some() {
+        export_options.owner_password = owner_password;
+    }
+
+    export_options.no_print = no_print.or(export_options.no_print);
+    export_options.no_copy = no_copy.or(export_options.no_copy);
+    export_options.no_modify = no_modify.or(export_options.no_modify);
+
+    // Accessibility options from the request take priority over the preset
+    export_options.tagged = tagged.or(export_options.tagged);
+    export_options.pdf_ua = pdf_ua.or(export_options.pdf_ua);
+
+    // Sanitizing from the request takes priority over the preset
+    export_options.sanitize = sanitize.or(export_options.sanitize);
+    let sanitize = export_options.sanitize == Some(true);
+
+    // Locale options from the request take priority over the preset
+    if locale.is_some() {
+        export_options.locale = locale;
+    }
 
-    // Wait for the response
-    let converted = rx.await.context("failed to get convert response")??;
+    if timezone.is_some() {
+        export_options.timezone = timezone;
+    }
+
+    if modify_password.is_some() {
+        export_options.modify_password = modify_password;
+    }
+
+    // Presentation options from the request replace the preset options
+    if let Some(impress_options) = impress_options {
+        let impress_options = serde_json::from_str(&impress_options).map_err(|err| {
+            InvalidExportOptions(format!("impress_options must be a JSON object: {err}"))
+        })?;
+        export_options.impress = Some(impress_options);
+    }
+
+    // Text document options from the request replace the preset options
+    let mut tracked_changes = None;
+    if let Some(writer_options) = writer_options {
+        let writer_options: WriterOptions =
+            serde_json::from_str(&writer_options).map_err(|err| {
+                InvalidExportOptions(format!("writer_options must be a JSON object: {err}"))
+            })?;
+        let (writer_export_options, mode) = writer_options.split();
+        export_options.writer = Some(writer_export_options);
+        tracked_changes = mode;
+    }
+
+    // Sanitized documents only show the final text of tracked changes
+    if sanitize {
+        if tracked_changes == Some(TrackedChanges::Markup) {
+            return Err(InvalidExportOptions(
+                "tracked changes cannot be shown as markup with sanitize".to_string(),
+            )
+            .into());
+        }
+
+        tracked_changes = Some(TrackedChanges::Accept);
+    }
+
+    // Raw options from the request replace the preset raw options
+    if filter_options.is_some() || load_options.is_some() {
+        if !allow_raw_options {
+            return Err(RawOptionsDisabled.into());
+        }
+
+        if filter_options.is_some() {
+            export_options.raw_filter_options = filter_options;
+        }
+
+        if load_options.is_some() {
+            export_options.raw_load_options = load_options;
+        }
+    }
+
+    // Input format is checked against the installed import filters and
+    // reported in the metadata
+    let input_format = detect_input_format(
+        &file.contents,
+        file.metadata.file_name.as_deref(),
+        file.metadata.content_type.as_deref(),
+    );
+    failure_input.set_format(input_format.clone());
+
+    // Uploaded PDFs are responded with as-is when passed through unchanged,
+    // options that would change the PDF cannot be applied
+    let passthrough = input_format.as_deref() == Some(PDF_MIME)
+        && pdf_passthrough == Some(PdfPassthrough::Unchanged);
+
+    if passthrough
+        && (export_options != PdfExportOptions::default()
+            || page_size.is_some()
+            || orientation.is_some()
+            || margin.is_some()
+            || tracked_changes.is_some()
+            || fit_to_width.is_some())
+    {
+        return Err(InvalidExportOptions(
+            "export and page options cannot be used with pdf_passthrough=unchanged".to_string(),
+        )
+        .into());
+    }
+
+    // Load options configured for the format are added to the raw load options
+    config.apply_load_options(
+        &mut export_options,
+        &file.contents,
+        file.metadata.file_name.as_deref(),
+        file.metadata.content_type.as_deref(),
+    );
+
+    export_options.validate().map_err(InvalidExportOptions)?;
+
+    let signing = export_options.sign.unwrap_or_default();
+    let linearize = linearize.unwrap_or_default();
+
+    // Modifying the PDF after it has been signed would invalidate the signature
+    if signing && linearize {
+        return Err(SigningError::Conflict("signed pdfs cannot be linearized").into());
+    }
+
+    if signing && export_options.is_encrypted() {
+        return Err(SigningError::Conflict("encrypted pdfs cannot be signed").into());
+    }
+
+    // Stages configured in the pipeline, passed through PDFs are left unchanged
+    let pipeline = Pipeline::from_config(&config.pipeline, &linearizer);
+    let after_export_stages = !passthrough && pipeline.has_stages(StagePoint::AfterExport);
+
+    if signing && after_export_stages {
+        return Err(
+            SigningError::Conflict("signed pdfs cannot be processed by pipeline stages").into(),
+        );
+    }
+
+    if after_export_stages && export_options.is_encrypted() {
+        return Err(InvalidExportOptions(
+            "passwords cannot be used when pipeline stages process the pdf".to_string(),
+        )
+        .into());
+    }
+
+    if !passthrough {
+        check_import_filter(&details, input_format.as_deref())?;
+    }
+
+    // qpdf requires the password to open files encrypted with a user password
+    let linearize_password = export_options.user_password.clone();
+
+    // Every option that changes the converted output is part of the entity tag
+    let etag_options = format!(
+        "{:?}",
+        (
+            env!("CARGO_PKG_VERSION"),
+            &details.version,
+            &export_options,
+            (page_size, orientation, margin),
+            (include_attachments, tracked_changes, linearize, repair),
+            fit_to_width,
+            &config.pipeline,
+            passthrough,
+            response_format,
+            (&file.metadata.file_name, &file.metadata.content_type),
+            &output_filename,
+        )
+    );
+    let etag = conversion_etag(file.contents.clone(), etag_options).await?;
+
+    // Client already has the result of this conversion
+    if if_none_match(&headers, &etag) {
+        return Ok(not_modified(etag)?);
+    }
+
+    let email_format = EmailFormat::detect(
+        file.metadata.file_name.as_deref(),
+        file.metadata.content_type.as_deref(),
+    );
+
+    let input_size = file.contents.len();
+
+    // Warnings about repairs made to damaged documents and substituted fonts
+    let mut warnings: Vec<String> = Vec::new();
+
+    // Attempts made converting the file, emails are converted in multiple parts
+    let mut attempts = None;
+
+    // Whether the document was converted read-only as its password to modify was missing
+    let mut was_read_only = false;
+
+    // Resources used by office, emails are converted in multiple parts
+    let mut resource_usage = None;
+
+    // Time spent waiting for office, emails wait once for each part
+    let mut queue_wait_ms = None;
+
+    // Warnings from the pipeline stages
+    let mut stage_context = StageContext::default();
+
+    let converted = match email_format {
+        // PDFs passed through unchanged aren't loaded into office
+        _ if passthrough => file.contents,
+        // Emails are merged with their attachments after converting
+        Some(_) if signing => {
+            return Err(SigningError::Conflict("emails cannot be signed").into());
+        }
+        // Merged PDFs can't be encrypted, emails without attachments are
+        // encrypted by the single conversion
+        Some(_) if include_attachments.unwrap_or_default() && export_options.is_encrypted() => {
+            return Err(InvalidExportOptions(
+                "passwords cannot be used with include_attachments".to_string(),
+            )
+            .into());
+        }
+        // Merging keeps the structure of the email but not of the attachments
+        Some(_)
+            if include_attachments.unwrap_or_default()
+                && (export_options.tagged == Some(true) || export_options.pdf_ua == Some(true)) =>
+        {
+            return Err(InvalidExportOptions(
+                "tagged and pdf_ua cannot be used with include_attachments".to_string(),
+            )
+            .into());
+        }
+        // Emails are rendered into a document before converting
+        Some(format) => {
+            let email = parse_email(format, &file.contents)?;
+            let converted = convert_email(
+                &office,
+                &details,
+                email,
+                include_attachments.unwrap_or_default(),
+                export_options,
+            )
+            .await?;
+
+            // Emails are merged from multiple conversions so are sanitized once merged
+            if sanitize {
+                let converted = tokio::task::spawn_blocking(move || sanitize_pdf(&converted))
+                    .await
+                    .context("failed to sanitize pdf")??;
+                Bytes::from(converted)
+            } else {
+                converted
+            }
+        }
+        None => {
+            let page_overrides = PageOverrides {
+                size: page_size,
+                orientation,
+                margin_mm: margin,
+            };
+
+            // Configured stages transform the upload before the request options apply
+            let input = pipeline
+                .run(StagePoint::BeforeLoad, file.contents, &mut stage_context)
+                .await?;
+
+            // Place images onto the requested page layout
+            let page_setup = PageSetup::from_overrides(&page_overrides);
+            let input = prepare_image(input, page_setup)?;
+
+            // Apply the requested page layout to documents
+            let input =
+                tokio::task::spawn_blocking(move || apply_page_overrides(input, &page_overrides))
+                    .await
+                    .context("failed to apply page overrides")??;
+
+            // Accept or show the tracked changes in text documents
+            let input =
+                tokio::task::spawn_blocking(move || apply_tracked_changes(input, tracked_changes))
+                    .await
+                    .context("failed to apply tracked changes")??;
+
+            // Scale wide spreadsheets that would print on too many pages to the page width
+            let fit_pages = match fit_to_width {
+                Some(true) => Some(0),
+                Some(false) => None,
+                None => fit_wide_spreadsheets,
+            };
+            let input = match fit_pages {
+                Some(max_pages) => {
+                    let (input, fit_warning) =
+                        tokio::task::spawn_blocking(move || fit_wide_spreadsheet(input, max_pages))
+                            .await
+                            .context("failed to fit wide spreadsheet")??;
+                    warnings.extend(fit_warning);
+                    input
+                }
+                None => input,
+            };
+
+            let fonts_warning = {
+                let (fonts, input) = (fonts.clone(), input.clone());
+                tokio::task::spawn_blocking(move || fonts.missing_fonts_warning(&input))
+                    .await
+                    .context("failed to find missing fonts")?
+            };
+            warnings.extend(fonts_warning);
+
+            // Objects without a replacement image are drawn as placeholders
+            if replacement_images {
+                let input = input.clone();
+                let images_warning =
+                    tokio::task::spawn_blocking(move || missing_replacement_images_warning(&input))
+                        .await
+                        .context("failed to find embedded objects")?;
+                warnings.extend(images_warning);
+            }
+
+            let conversion = if repair.unwrap_or_default() {
+                let (conversion, repair_warning) =
+                    convert_with_repair(&office, input, export_options).await?;
+                warnings.extend(repair_warning);
+                conversion
+            } else {
+                office.convert_with_warnings(input, export_options).await?
+            };
+
+            warnings.extend(office_warnings(conversion.warnings));
+            attempts = Some(conversion.attempts);
+            was_read_only = conversion.read_only;
+            resource_usage = Some(conversion.usage);
+            queue_wait_ms = Some(conversion.queue_wait_ms);
+            conversion.pdf
+        }
+    };
+
+    let mut timings = ConversionTimings {
+        convert_ms: duration_ms(started_at.elapsed()),
+        queue_wait_ms,
+        ..Default::default()
+    };
+
+    // Configured stages process the exported PDF
+    let converted = if after_export_stages {
+        let pipeline_started_at = Instant::now();
+        let converted = pipeline
+            .run(StagePoint::AfterExport, converted, &mut stage_context)
+            .await?;
+        timings.pipeline_ms = Some(duration_ms(pipeline_started_at.elapsed()));
+        converted
+    } else {
+        converted
+    };
+    warnings.extend(stage_context.warnings);
+
+    // Optimize the PDF for fast web view
+    let converted = if linearize {
+        let linearize_started_at = Instant::now();
+        let converted = linearizer
+            .linearize(converted, linearize_password.as_deref())
+            .await?;
+        timings.linearize_ms = Some(duration_ms(linearize_started_at.elapsed()));
+        converted
+    } else {
+        converted
+    };
+
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+    if let Some(tenant) = &tenant {
+        tenants.record(&tenant.name, input_size, &converted);
+    }
+
+    // Retained results can be downloaded again when the response is lost
+    let result_id = results
+        .retain(
+            tenant.as_ref().map(|tenant| tenant.name.as_str()),
+            &converted,
+            &etag,
+        )
+        .await;
+
+    if response_format == ResponseFormat::Multipart {
+        timings.total_ms = duration_ms(started_at.elapsed());
+
+        let mut metadata = ConversionMetadata::new(
+            input_format,
+            input_size,
+            &converted,
+            warnings,
+            attempts,
+            was_read_only,
+            timings,
+        );
+        metadata.result_id = result_id.clone();
+        metadata.resource_usage = resource_usage;
+
+        let mut response = multipart_response(
+            &metadata,
+            converted,
+            output_filename
+                .as_deref()
+                .unwrap_or(DEFAULT_OUTPUT_FILE_NAME),
+        )?;
+        let headers = response.headers_mut();
+        headers.insert(header::ETAG, etag);
+
+        if let Some(result_id) = result_id {
+            headers.insert(
+                RESULT_ID_HEADER,
+                HeaderValue::try_from(result_id).context("invalid result id")?,
+            );
+        }
+
+        return Ok(response);
+    }
 
     // Build the response
-    let response = Response::builder()
+    let mut response = Response::builder()
         .header(
             header::CONTENT_TYPE,
             HeaderValue::from_static("application/pdf"),
         )
+        .header(header::ETAG, etag);
+
+    if let Some(output_filename) = &output_filename {
+        response = response.header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(output_filename),
+        );
+    }
+
+    if let Some(result_id) = result_id {
+        response = response.header(RESULT_ID_HEADER, result_id);
+    }
+
+    // Warnings are provided as a JSON array of strings
+    if !warnings.is_empty() {
+        let warnings = serde_json::to_string(&warnings).context("failed to encode warnings")?;
+        let warnings = HeaderValue::from_str(&escape_non_ascii(&warnings))
+            .context("invalid warnings header")?;
+        response = response.header(CONVERSION_WARNINGS_HEADER, warnings);
+    }
+
+    if let Some(attempts) = attempts {
+        response = response.header(CONVERSION_ATTEMPTS_HEADER, attempts);
+    }
+
+    if let Some(queue_wait_ms) = queue_wait_ms {
+        response = response.header(QUEUE_WAIT_HEADER, queue_wait_ms);
+    }
+
+    let response = response
         .body(Body::from(converted))
         .context("failed to create response")?;
 
@@ -419,16 +1868,75 @@ async fn convert(
 /// Result from checking the server busy state
 #[derive(Serialize)]
 struct StatusResponse {
-    /// Whether the server is busy
+    /// Whether the server is busy, true when no workers are available
     is_busy: bool,
+    /// Number of workers available to start a conversion
+    available_workers: usize,
+    /// State of each worker
+    workers: Vec<WorkerStatusResponse>,
+    /// Percentiles of the time recent requests waited for a worker
+    queue_wait: QueueWaitStats,
+}
+
+/// State of a worker
+#[derive(Serialize)]
+struct WorkerStatusResponse {
+    /// Index of the worker
+    id: usize,
+    /// Current state of the worker
+    state: WorkerStateResponse,
+    /// ID of the job being converted when converting
+    job_id: Option<u64>,
+    /// Time in milliseconds the worker has been in its current state
+    state_duration_ms: u64,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum WorkerStateResponse {
+    Idle,
+    Converting,
+    Restarting,
+}
+
+impl From<WorkerState> for WorkerStateResponse {
+    fn from(value: WorkerState) -> Self {
+        match value {
+            WorkerState::Idle => WorkerStateResponse::Idle,
+            WorkerState::Converting => WorkerStateResponse::Converting,
+            WorkerState::Restarting => WorkerStateResponse::Restarting,
+        }
+    }
 }
 
 /// GET /status
 ///
-/// Checks if the converter is currently busy
-async fn status(Extension(office): Extension<OfficeHandle>) -> Json<StatusResponse> {
-    let is_locked = office.0.try_send(OfficeMsg::BusyCheck).is_err();
-    Json(StatusResponse { is_busy: is_locked })
+/// Reports the state of each worker and how many are available
+async fn status(Extension(office): Extension<Converter>) -> Json<StatusResponse> {
+    // LibreOffice only supports a single instance per process so
+    // the server has a single worker
+    let workers: Vec<WorkerStatusResponse> = [office.status()]
+        .into_iter()
+        .enumerate()
+        .map(|(id, status)| WorkerStatusResponse {
+            id,
+            state: status.state.into(),
+            job_id: status.job_id,
+            state_duration_ms: status.since.elapsed().as_millis() as u64,
+        })
+        .collect();
+
+    let available_workers = workers
+        .iter()
+        .filter(|worker| matches!(worker.state, WorkerStateResponse::Idle))
+        .count();
+
+    Json(StatusResponse {
+        is_busy: available_workers == 0,
+        available_workers,
+        workers,
+        queue_wait: office.queue_wait_stats(),
+    })
 }
 
 #[derive(Serialize)]
@@ -463,6 +1971,8 @@ struct SupportedFormat {
     name: String,
     /// Mime type of the format
     mime: String,
+    /// File extension of the format when it is a known format
+    extension: Option<&'static str>,
 }
 
 /// GET /supported-formats
@@ -479,6 +1989,7 @@ async fn supported_formats(
         .map(|(key, value)| SupportedFormat {
             name: key.to_string(),
             mime: value.media_type.to_string(),
+            extension: known_extension(&value.media_type),
         })
         .collect();
 
@@ -488,7 +1999,7 @@ async fn supported_formats(
 /// POST /collect-garbage
 ///
 /// Collects garbage from the office converter
-async fn collect_garbage(Extension(office): Extension<OfficeHandle>) -> StatusCode {
-    _ = office.0.send(OfficeMsg::CollectGarbage).await;
+async fn collect_garbage(Extension(office): Extension<Converter>) -> StatusCode {
+    _ = office.collect_garbage().await;
     StatusCode::OK
 }
@@ -0,0 +1,163 @@
+use crate::disposition::file_name_parameters;
+use anyhow::Context;
+use axum::{
+    body::Body,
+    http::{header, Response},
+};
+use axum_typed_multipart::TryFromField;
+use bytes::{BufMut, Bytes, BytesMut};
+use office_convert_core::{page_count, ConversionWarning, ResourceUsage};
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Format of the response to a conversion
+#[derive(Debug, Clone, Copy, PartialEq, Default, TryFromField)]
+#[try_from_field(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Only the PDF file
+    #[default]
+    Pdf,
+    /// Multipart response containing the conversion metadata and the PDF file
+    Multipart,
+}
+
+/// Details about a completed conversion
+#[derive(Debug, Serialize)]
+pub struct ConversionMetadata {
+    /// Mime type detected for the input file
+    pub input_format: Option<String>,
+    /// Size of the input file in bytes
+    pub input_size: usize,
+    /// Size of the PDF in bytes
+    pub output_size: usize,
+    /// Number of pages in the PDF, [None] when the PDF could not be read
+    pub page_count: Option<u64>,
+    /// Warnings about repairs and substituted fonts
+    pub warnings: Vec<String>,
+    /// Number of attempts made converting the file, [None] when the file
+    /// was converted in multiple parts (i.e emails and their attachments)
+    pub attempts: Option<u32>,
+    /// Whether the document was protected with a password to modify and was
+    /// converted read-only as the password was not provided or incorrect
+    pub was_read_only: bool,
+    /// ID the PDF can be downloaded again with using GET /results/{id},
+    /// [None] when results are not retained
+    pub result_id: Option<String>,
+    /// Resources (CPU time, peak memory and temp disk) used by office,
+    /// [None] when the file was converted in multiple parts or passed
+    /// through unchanged
+    pub resource_usage: Option<ResourceUsage>,
+    /// Time spent on each step of the conversion
+    pub timings: ConversionTimings,
+}
+
+/// Time spent on each step of a conversion in milliseconds
+#[derive(Debug, Default, Serialize)]
+pub struct ConversionTimings {
+    /// Time spent converting the file including time waiting for office
+    pub convert_ms: u64,
+    /// Time spent waiting for office before converting, included in the
+    /// convert time. Not provided for emails and passed through PDFs
+    pub queue_wait_ms: Option<u64>,
+    /// Time spent running the configured pipeline stages on the exported PDF
+    pub pipeline_ms: Option<u64>,
+    /// Time spent linearizing the PDF when requested
+    pub linearize_ms: Option<u64>,
+    /// Total time spent handling the request after the upload
+    pub total_ms: u64,
+}
+
+impl ConversionMetadata {
+    /// Creates the metadata for a converted PDF
+    pub fn new(
+        input_format: Option<String>,
+        input_size: usize,
+        output: &[u8],
+        warnings: Vec<String>,
+        attempts: Option<u32>,
+        was_read_only: bool,
+        timings: ConversionTimings,
+    ) -> Self {
+        Self {
+            input_format,
+            input_size,
+            output_size: output.len(),
+            page_count: page_count(output).ok(),
+            warnings,
+            attempts,
+            was_read_only,
+            result_id: None,
+            resource_usage: None,
+            timings,
+        }
+    }
+}
+
+/// Converts a duration to whole milliseconds
+pub fn duration_ms(duration: Duration) -> u64 {
+    duration.as_millis() as u64
+}
+
+/// Creates a multipart/mixed response containing a JSON "metadata" part
+/// followed by a "file" part containing the PDF named `file_name`
+pub fn multipart_response(
+    metadata: &ConversionMetadata,
+    pdf: Bytes,
+    file_name: &str,
+) -> anyhow::Result<Response<Body>> {
+    let metadata = serde_json::to_vec(metadata).context("failed to encode metadata")?;
+
+    let boundary = multipart_boundary();
+
+    let mut body = BytesMut::with_capacity(metadata.len() + pdf.len() + 512);
+
+    body.put(
+        format!(
+            "--{boundary}\r\n\
+            Content-Type: application/json\r\n\
+            Content-Disposition: inline; name=\"metadata\"\r\n\r\n"
+        )
+        .as_bytes(),
+    );
+    body.put(metadata.as_slice());
+
+    body.put(
+        format!(
+            "\r\n--{boundary}\r\n\
+            Content-Type: application/pdf\r\n\
+            Content-Disposition: attachment; name=\"file\"; {}\r\n\r\n",
+            file_name_parameters(file_name)
+        )
+        .as_bytes(),
+    );
+    body.put(pdf);
+
+    body.put(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            format!("multipart/mixed; boundary={boundary}"),
+        )
+        .body(Body::from(body.freeze()))
+        .context("failed to create response")
+}
+
+/// Creates a random boundary separating the parts of a multipart response
+pub fn multipart_boundary() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(|value| value as char)
+        .collect()
+}
+
+/// Creates the warning messages for the problems office reported while
+/// converting, provided alongside the other conversion warnings
+pub fn office_warnings(warnings: Vec<ConversionWarning>) -> Vec<String> {
+    warnings
+        .into_iter()
+        .map(|warning| warning.to_string())
+        .collect()
+}
@@ -0,0 +1,62 @@
+use axum::{
+    http::{header, HeaderValue},
+    response::{IntoResponse, Response},
+    Extension,
+};
+use office_convert_core::{Converter, UsageHistogram};
+use std::fmt::Write;
+
+/// Content type of the Prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// GET /metrics
+///
+/// Reports histograms of the resources used by each conversion in the
+/// Prometheus text format
+pub async fn metrics(Extension(office): Extension<Converter>) -> Response {
+    let usage = office.resource_usage();
+    let mut output = String::new();
+
+    write_histogram(
+        &mut output,
+        "office_convert_cpu_seconds",
+        "CPU time used by office converting each document",
+        &usage.cpu_seconds,
+    );
+    write_histogram(
+        &mut output,
+        "office_convert_peak_rss_bytes",
+        "Increase in peak resident memory of office converting each document",
+        &usage.peak_rss_bytes,
+    );
+    write_histogram(
+        &mut output,
+        "office_convert_temp_disk_bytes",
+        "Temp disk used converting each document",
+        &usage.temp_disk_bytes,
+    );
+
+    (
+        [(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROMETHEUS_CONTENT_TYPE),
+        )],
+        output,
+    )
+        .into_response()
+}
+
+/// Writes a histogram in the Prometheus text format
+fn write_histogram(output: &mut String, name: &str, help: &str, histogram: &UsageHistogram) {
+    // Writing to a string cannot fail
+    _ = writeln!(output, "# HELP {name} {help}");
+    _ = writeln!(output, "# TYPE {name} histogram");
+
+    for (bound, count) in &histogram.buckets {
+        _ = writeln!(output, "{name}_bucket{{le=\"{bound}\"}} {count}");
+    }
+
+    _ = writeln!(output, "{name}_bucket{{le=\"+Inf\"}} {}", histogram.count);
+    _ = writeln!(output, "{name}_sum {}", histogram.sum);
+    _ = writeln!(output, "{name}_count {}", histogram.count);
+}
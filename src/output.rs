@@ -0,0 +1,128 @@
+use clap::ValueEnum;
+#[cfg(unix)]
+use office_convert_core::current_job;
+
+/// Level to log captured office output at
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OfficeLogLevel {
+    /// Output is not captured
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Logs a line of captured output at the provided level
+#[cfg(unix)]
+macro_rules! log_line {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            OfficeLogLevel::Off => {}
+            OfficeLogLevel::Error => tracing::error!($($arg)+),
+            OfficeLogLevel::Warn => tracing::warn!($($arg)+),
+            OfficeLogLevel::Info => tracing::info!($($arg)+),
+            OfficeLogLevel::Debug => tracing::debug!($($arg)+),
+            OfficeLogLevel::Trace => tracing::trace!($($arg)+),
+        }
+    };
+}
+
+/// Forwards each line read from the provided reader into tracing
+#[cfg(unix)]
+fn forward_output<R: std::io::Read>(reader: R, stream: &'static str, level: OfficeLogLevel) {
+    use std::io::BufRead;
+
+    let mut reader = std::io::BufReader::new(reader);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+
+        match reader.read_until(b'\n', &mut line) {
+            // Pipe was closed
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+
+        let value = String::from_utf8_lossy(&line);
+        let value = value.trim_end();
+        if value.is_empty() {
+            continue;
+        }
+
+        match current_job() {
+            Some(job_id) => log_line!(level, stream, job_id, "office: {value}"),
+            None => log_line!(level, stream, "office: {value}"),
+        }
+    }
+}
+
+/// Redirects stdout and stderr into pipes that are forwarded into tracing at
+/// the provided level.
+///
+/// LibreOffice runs within the server process so its output is written to the
+/// same stdout/stderr as the server, capturing allows the output to be tagged
+/// with the job that was being converted at the time.
+///
+/// Provides a file handle to the original stdout which should be used as the
+/// writer for the tracing subscriber (Otherwise the logs would be captured too)
+#[cfg(unix)]
+pub fn capture_office_output(level: OfficeLogLevel) -> std::io::Result<std::fs::File> {
+    use std::{
+        fs::File,
+        io,
+        os::fd::{FromRawFd, RawFd},
+    };
+
+    /// Creates a pipe providing the (read, write) ends
+    fn create_pipe() -> io::Result<(RawFd, RawFd)> {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((fds[0], fds[1]))
+    }
+
+    /// Replaces the target file descriptor with the provided one
+    fn redirect(fd: RawFd, target: RawFd) -> io::Result<()> {
+        if unsafe { libc::dup2(fd, target) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        unsafe { libc::close(fd) };
+        Ok(())
+    }
+
+    // Keep a handle to the original stdout for logging
+    let stdout = unsafe { libc::dup(libc::STDOUT_FILENO) };
+    if stdout < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stdout = unsafe { File::from_raw_fd(stdout) };
+
+    for (target, stream) in [
+        (libc::STDOUT_FILENO, "stdout"),
+        (libc::STDERR_FILENO, "stderr"),
+    ] {
+        let (read, write) = create_pipe()?;
+        redirect(write, target)?;
+
+        let reader = unsafe { File::from_raw_fd(read) };
+        std::thread::Builder::new()
+            .name(format!("office-{stream}"))
+            .spawn(move || forward_output(reader, stream, level))?;
+    }
+
+    Ok(stdout)
+}
+
+/// Output capturing relies on file descriptor redirection which is only
+/// supported on unix platforms
+#[cfg(not(unix))]
+pub fn capture_office_output(_level: OfficeLogLevel) -> std::io::Result<std::fs::File> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "office output capturing is only supported on unix platforms",
+    ))
+}
@@ -0,0 +1,81 @@
+use axum_typed_multipart::TryFromField;
+
+/// Standard paper sizes that can be requested for output pages
+#[derive(Debug, Clone, Copy, PartialEq, TryFromField)]
+#[try_from_field(rename_all = "snake_case")]
+pub enum PageSize {
+    /// ISO A4 (210mm x 297mm)
+    A4,
+    /// US Letter (8.5in x 11in)
+    Letter,
+    /// US Legal (8.5in x 14in)
+    Legal,
+}
+
+impl PageSize {
+    /// Portrait dimensions of the page size in millimeters (width, height)
+    pub fn dimensions_mm(&self) -> (f32, f32) {
+        match self {
+            PageSize::A4 => (210.0, 297.0),
+            PageSize::Letter => (215.9, 279.4),
+            PageSize::Legal => (215.9, 355.6),
+        }
+    }
+}
+
+/// Orientation that can be requested for output pages
+#[derive(Debug, Clone, Copy, PartialEq, TryFromField)]
+#[try_from_field(rename_all = "snake_case")]
+pub enum Orientation {
+    Portrait,
+    Landscape,
+}
+
+/// Page setup overrides requested for the output document, fields
+/// that aren't provided keep the value from the document
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PageOverrides {
+    /// Paper size for the pages
+    pub size: Option<PageSize>,
+    /// Orientation of the pages
+    pub orientation: Option<Orientation>,
+    /// Margin on every edge of the page in millimeters
+    pub margin_mm: Option<f32>,
+}
+
+impl PageOverrides {
+    /// Whether no overrides were requested
+    pub fn is_empty(&self) -> bool {
+        self.size.is_none() && self.orientation.is_none() && self.margin_mm.is_none()
+    }
+}
+
+/// Page setup used when placing images onto pages
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageSetup {
+    /// Paper size for the pages
+    pub size: PageSize,
+    /// Orientation of the pages, determined from the image when not provided
+    pub orientation: Option<Orientation>,
+    /// Margin on every edge of the page in millimeters
+    pub margin_mm: f32,
+}
+
+impl PageSetup {
+    /// Default margin used when only a page size is requested
+    pub const DEFAULT_MARGIN_MM: f32 = 10.0;
+
+    /// Creates a page setup from the requested overrides, when no overrides
+    /// are provided no page setup is used
+    pub fn from_overrides(overrides: &PageOverrides) -> Option<Self> {
+        if overrides.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            size: overrides.size.unwrap_or(PageSize::A4),
+            orientation: overrides.orientation,
+            margin_mm: overrides.margin_mm.unwrap_or(Self::DEFAULT_MARGIN_MM),
+        })
+    }
+}
@@ -0,0 +1,378 @@
+use crate::{
+    archive::{open_archive, rewrite_parts, ArchiveError},
+    detect::{detect_archive, known_extension},
+    error::HttpError,
+    page::{Orientation, PageOverrides, PageSize},
+    xml::{find_tag, get_attribute, remove_attribute, rewrite_tags, set_attribute, Attributes},
+};
+use axum::http::StatusCode;
+use bytes::Bytes;
+use std::io::{Read, Seek};
+use thiserror::Error;
+use tracing::warn;
+use zip::ZipArchive;
+
+/// Twips (1/20 of a point) in a millimeter
+const TWIPS_PER_MM: f32 = 1440.0 / 25.4;
+
+/// Worksheet elements that come after the page setup, used to find where
+/// to insert page setup elements in worksheets that don't have them
+pub const WORKSHEET_ELEMENTS_AFTER_PAGE_SETUP: &[&str] = &[
+    "headerFooter",
+    "rowBreaks",
+    "colBreaks",
+    "customProperties",
+    "cellWatches",
+    "ignoredErrors",
+    "smartTags",
+    "drawing",
+    "legacyDrawing",
+    "legacyDrawingHF",
+    "drawingHF",
+    "picture",
+    "oleObjects",
+    "controls",
+    "webPublishItems",
+    "tableParts",
+    "extLst",
+];
+
+/// Errors that can occur while applying page overrides to a document
+#[derive(Debug, Error)]
+pub enum PageStyleError {
+    /// Margin is negative or leaves no space on the page
+    #[error("margin of {0}mm is invalid for the requested page size")]
+    InvalidMargin(f32),
+
+    /// Failed to rewrite the document archive
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+}
+
+impl HttpError for PageStyleError {
+    fn status(&self) -> StatusCode {
+        match self {
+            PageStyleError::InvalidMargin(_) => StatusCode::BAD_REQUEST,
+            PageStyleError::Archive(err) => err.status(),
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            PageStyleError::InvalidMargin(_) => None,
+            PageStyleError::Archive(err) => err.code(),
+        }
+    }
+}
+
+/// Document formats that page overrides can be applied to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DocumentFormat {
+    /// Word document (.docx)
+    Docx,
+    /// Excel workbook (.xlsx)
+    Xlsx,
+    /// OpenDocument text, spreadsheet or drawing (.odt, .ods, .odg)
+    OpenDocument,
+}
+
+impl DocumentFormat {
+    /// Detects the format of the document archive
+    fn detect<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<Self> {
+        match known_extension(detect_archive(archive)?)? {
+            "docx" => Some(DocumentFormat::Docx),
+            "xlsx" => Some(DocumentFormat::Xlsx),
+            // Slides in presentations are not laid out on paper pages
+            "odt" | "ods" | "odg" => Some(DocumentFormat::OpenDocument),
+            _ => None,
+        }
+    }
+
+    /// Whether the part of the document contains page styles
+    fn has_page_styles(&self, name: &str) -> bool {
+        match self {
+            DocumentFormat::Docx => name == "word/document.xml",
+            DocumentFormat::Xlsx => name.starts_with("xl/worksheets/") && name.ends_with(".xml"),
+            DocumentFormat::OpenDocument => name == "styles.xml",
+        }
+    }
+
+    /// Applies the page overrides to the XML of a part
+    fn rewrite(&self, xml: &str, overrides: &PageOverrides) -> String {
+        match self {
+            DocumentFormat::Docx => rewrite_docx(xml, overrides),
+            DocumentFormat::Xlsx => rewrite_worksheet(xml, overrides),
+            DocumentFormat::OpenDocument => rewrite_odf_styles(xml, overrides),
+        }
+    }
+}
+
+/// Applies the requested page size, orientation and margins to the page styles
+/// of the provided document before it is converted. Overrides can be applied to
+/// Word, Excel and OpenDocument (text, spreadsheet and drawing) documents, other
+/// files are left unchanged
+pub fn apply_page_overrides(
+    input: Bytes,
+    overrides: &PageOverrides,
+) -> Result<Bytes, PageStyleError> {
+    if overrides.is_empty() {
+        return Ok(input);
+    }
+
+    if let Some(margin) = overrides.margin_mm {
+        let fits_page = match overrides.size {
+            Some(size) => {
+                let (width, height) = size.dimensions_mm();
+                margin * 2.0 < width.min(height)
+            }
+            None => true,
+        };
+
+        if !margin.is_finite() || margin < 0.0 || !fits_page {
+            return Err(PageStyleError::InvalidMargin(margin));
+        }
+    }
+
+    // Archives that can't be read are left for office to handle
+    let mut archive = match open_archive(&input) {
+        Some(value) => value,
+        None => return Ok(input),
+    };
+
+    let format = match DocumentFormat::detect(&mut archive) {
+        Some(value) => value,
+        None => return Ok(input),
+    };
+
+    // Parts without page styles are copied as-is
+    let output = rewrite_parts(
+        &mut archive,
+        |name| format.has_page_styles(name),
+        |xml| format.rewrite(xml, overrides),
+    )?;
+
+    Ok(output)
+}
+
+/// Determines the page dimensions (width, height) after applying the overrides,
+/// dimensions are in the same unit as the current dimensions. Provides [None]
+/// when neither the size or orientation is being changed
+fn page_dimensions(
+    overrides: &PageOverrides,
+    current: Option<(f32, f32)>,
+    currently_landscape: bool,
+    size_to_unit: impl Fn(PageSize) -> (f32, f32),
+) -> Option<(f32, f32, bool)> {
+    if overrides.size.is_none() && overrides.orientation.is_none() {
+        return None;
+    }
+
+    // Portrait dimensions of the page
+    let (width, height) = match overrides.size {
+        Some(size) => size_to_unit(size),
+        None => {
+            let (width, height) = current?;
+            (width.min(height), width.max(height))
+        }
+    };
+
+    let landscape = match overrides.orientation {
+        Some(orientation) => orientation == Orientation::Landscape,
+        None => currently_landscape || current.is_some_and(|(width, height)| width > height),
+    };
+
+    if landscape {
+        Some((height, width, true))
+    } else {
+        Some((width, height, false))
+    }
+}
+
+/// Applies the overrides to the sections of a Word document
+fn rewrite_docx(xml: &str, overrides: &PageOverrides) -> String {
+    let to_twips = |size: PageSize| {
+        let (width, height) = size.dimensions_mm();
+        (
+            (width * TWIPS_PER_MM).round(),
+            (height * TWIPS_PER_MM).round(),
+        )
+    };
+
+    let xml = rewrite_tags(xml, "w:pgSz", |attributes| {
+        let current = parse_number(attributes, "w:w").zip(parse_number(attributes, "w:h"));
+        let currently_landscape = get_attribute(attributes, "w:orient") == Some("landscape");
+
+        let (width, height, landscape) =
+            match page_dimensions(overrides, current, currently_landscape, to_twips) {
+                Some(value) => value,
+                None => return,
+            };
+
+        set_attribute(attributes, "w:w", format!("{}", width.round()));
+        set_attribute(attributes, "w:h", format!("{}", height.round()));
+
+        if landscape {
+            set_attribute(attributes, "w:orient", "landscape".to_string());
+        } else {
+            remove_attribute(attributes, "w:orient");
+        }
+    });
+
+    let margin = match overrides.margin_mm {
+        Some(value) => format!("{}", (value * TWIPS_PER_MM).round()),
+        None => return xml,
+    };
+
+    rewrite_tags(&xml, "w:pgMar", |attributes| {
+        for side in ["w:top", "w:bottom", "w:left", "w:right"] {
+            set_attribute(attributes, side, margin.clone());
+        }
+    })
+}
+
+/// Excel paper size code for the page size
+fn excel_paper_size(size: PageSize) -> &'static str {
+    match size {
+        PageSize::Letter => "1",
+        PageSize::Legal => "5",
+        PageSize::A4 => "9",
+    }
+}
+
+/// Applies the overrides to the page setup of an Excel worksheet, inserting the
+/// page setup elements when the worksheet doesn't have them
+fn rewrite_worksheet(xml: &str, overrides: &PageOverrides) -> String {
+    let mut xml = xml.to_string();
+
+    if let Some(margin) = overrides.margin_mm {
+        let margin = format!("{}", margin / 25.4);
+
+        if find_tag(&xml, "pageMargins", 0).is_none() {
+            let mut before = vec!["pageSetup"];
+            before.extend_from_slice(WORKSHEET_ELEMENTS_AFTER_PAGE_SETUP);
+            xml = insert_before(
+                &xml,
+                &before,
+                "</worksheet>",
+                r#"<pageMargins left="0" right="0" top="0" bottom="0" header="0.3" footer="0.3"/>"#,
+            );
+        }
+
+        xml = rewrite_tags(&xml, "pageMargins", |attributes| {
+            for side in ["left", "right", "top", "bottom"] {
+                set_attribute(attributes, side, margin.clone());
+            }
+        });
+    }
+
+    if overrides.size.is_none() && overrides.orientation.is_none() {
+        return xml;
+    }
+
+    if find_tag(&xml, "pageSetup", 0).is_none() {
+        xml = insert_before(
+            &xml,
+            WORKSHEET_ELEMENTS_AFTER_PAGE_SETUP,
+            "</worksheet>",
+            "<pageSetup/>",
+        );
+    }
+
+    rewrite_tags(&xml, "pageSetup", |attributes| {
+        if let Some(size) = overrides.size {
+            set_attribute(attributes, "paperSize", excel_paper_size(size).to_string());
+        }
+
+        if let Some(orientation) = overrides.orientation {
+            let orientation = match orientation {
+                Orientation::Portrait => "portrait",
+                Orientation::Landscape => "landscape",
+            };
+
+            set_attribute(attributes, "orientation", orientation.to_string());
+        }
+    })
+}
+
+/// Applies the overrides to the page layouts of an OpenDocument
+fn rewrite_odf_styles(xml: &str, overrides: &PageOverrides) -> String {
+    rewrite_tags(xml, "style:page-layout-properties", |attributes| {
+        let current = get_attribute(attributes, "fo:page-width")
+            .and_then(parse_length_mm)
+            .zip(get_attribute(attributes, "fo:page-height").and_then(parse_length_mm));
+        let currently_landscape =
+            get_attribute(attributes, "style:print-orientation") == Some("landscape");
+
+        if let Some((width, height, landscape)) =
+            page_dimensions(overrides, current, currently_landscape, |size| {
+                size.dimensions_mm()
+            })
+        {
+            let orientation = if landscape { "landscape" } else { "portrait" };
+
+            set_attribute(attributes, "fo:page-width", format!("{width}mm"));
+            set_attribute(attributes, "fo:page-height", format!("{height}mm"));
+            set_attribute(
+                attributes,
+                "style:print-orientation",
+                orientation.to_string(),
+            );
+        }
+
+        if let Some(margin) = overrides.margin_mm {
+            // Shorthand margin would otherwise take priority
+            remove_attribute(attributes, "fo:margin");
+
+            for side in [
+                "fo:margin-top",
+                "fo:margin-bottom",
+                "fo:margin-left",
+                "fo:margin-right",
+            ] {
+                set_attribute(attributes, side, format!("{margin}mm"));
+            }
+        }
+    })
+}
+
+/// Parses an OpenDocument length (i.e "21.001cm") into millimeters
+pub fn parse_length_mm(value: &str) -> Option<f32> {
+    let split = value
+        .find(|value: char| value.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f32 = number.trim().parse().ok()?;
+
+    let scale = match unit {
+        "mm" => 1.0,
+        "cm" => 10.0,
+        "in" => 25.4,
+        "pt" => 25.4 / 72.0,
+        "pc" => 25.4 / 6.0,
+        _ => return None,
+    };
+
+    Some(number * scale)
+}
+
+pub fn parse_number(attributes: &Attributes, name: &str) -> Option<f32> {
+    get_attribute(attributes, name)?.parse().ok()
+}
+
+/// Inserts the element before the first of the provided elements, or before
+/// the closing tag when none of the elements are present
+pub fn insert_before(xml: &str, elements: &[&str], closing: &str, element: &str) -> String {
+    let position = elements
+        .iter()
+        .filter_map(|name| find_tag(xml, name, 0).map(|tag| tag.start))
+        .min()
+        .or_else(|| xml.rfind(closing));
+
+    match position {
+        Some(position) => format!("{}{element}{}", &xml[..position], &xml[position..]),
+        None => {
+            warn!("failed to find position to insert page setup");
+            xml.to_string()
+        }
+    }
+}
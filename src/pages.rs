@@ -0,0 +1,218 @@
+use crate::{
+    compression::decompress_file,
+    error::{DynHttpError, HttpError},
+    failures::FailureInput,
+    metadata::multipart_boundary,
+    priority::{request_priority, RequestPriority},
+    tenant::{Tenant, Tenants},
+    ws::MaxFileSize,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    http::{header, HeaderValue, Response, StatusCode},
+    Extension,
+};
+use axum_typed_multipart::{FieldData, TryFromField, TryFromMultipart, TypedMultipart};
+use bytes::{BufMut, Bytes, BytesMut};
+use office_convert_core::{Converter, ImageFormat, RenderOptions};
+use std::{
+    io::{Cursor, Write},
+    sync::Arc,
+};
+use thiserror::Error;
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+/// Header containing the number of rendered pages
+const PAGE_COUNT_HEADER: &str = "x-page-count";
+
+/// Options for rendering pages were invalid
+#[derive(Debug, Error)]
+#[error("invalid render options: {0}")]
+pub struct InvalidRenderOptions(pub String);
+
+impl HttpError for InvalidRenderOptions {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("INVALID_RENDER_OPTIONS")
+    }
+}
+
+/// Image format requested for the rendered pages
+#[derive(Debug, Clone, Copy, PartialEq, TryFromField)]
+#[try_from_field(rename_all = "snake_case")]
+pub enum PageImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl From<PageImageFormat> for ImageFormat {
+    fn from(value: PageImageFormat) -> Self {
+        match value {
+            PageImageFormat::Png => ImageFormat::Png,
+            PageImageFormat::Jpeg => ImageFormat::Jpeg,
+        }
+    }
+}
+
+/// Format of the response containing the rendered pages
+#[derive(Debug, Clone, Copy, PartialEq, Default, TryFromField)]
+#[try_from_field(rename_all = "snake_case")]
+pub enum PagesResponseFormat {
+    /// ZIP archive containing an image for each page
+    #[default]
+    Zip,
+    /// Multipart response with a part for each page
+    Multipart,
+}
+
+/// Request to render the pages of a file
+#[derive(TryFromMultipart)]
+pub struct RenderPagesRequest {
+    /// The file to render
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// Image format to render the pages as, defaults to PNG
+    format: Option<PageImageFormat>,
+
+    /// Resolution to render the pages at in DPI, defaults to 96
+    dpi: Option<u32>,
+
+    /// Format of the response, defaults to a ZIP archive
+    response: Option<PagesResponseFormat>,
+
+    /// Priority of the rendering when waiting for office
+    priority: Option<RequestPriority>,
+}
+
+/// POST /render-pages
+///
+/// Renders each page of the provided file as an image responding with
+/// a ZIP archive (or multipart response) of the images in page order
+pub async fn render_pages(
+    Extension(office): Extension<Converter>,
+    Extension(tenants): Extension<Arc<Tenants>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    Extension(failure_input): Extension<FailureInput>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    TypedMultipart(RenderPagesRequest {
+        file,
+        format,
+        dpi,
+        response: response_format,
+        priority,
+    }): TypedMultipart<RenderPagesRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    let file = decompress_file(file, max_file_size).await?;
+    failure_input.set_file(&file);
+    let office = office.with_priority(request_priority(
+        tenant.as_deref().map(Arc::as_ref),
+        priority,
+    ));
+
+    let mut options = RenderOptions::default();
+    if let Some(format) = format {
+        options.format = format.into();
+    }
+    if let Some(dpi) = dpi {
+        options.dpi = dpi;
+    }
+    options.validate().map_err(InvalidRenderOptions)?;
+
+    let input_size = file.contents.len();
+    let images = office.render_pages(file.contents, options).await?;
+    let output_size: usize = images.iter().map(Bytes::len).sum();
+    let page_count = images.len();
+
+    if let Some(Extension(tenant)) = tenant {
+        tenants.record_conversion(&tenant.name, input_size, output_size, page_count as u64);
+    }
+
+    let response = match response_format.unwrap_or_default() {
+        PagesResponseFormat::Zip => {
+            let archive =
+                tokio::task::spawn_blocking(move || create_archive(images, options.format))
+                    .await
+                    .context("failed to create archive")?
+                    .context("failed to create archive")?;
+
+            Response::builder()
+                .header(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/zip"),
+                )
+                .header(PAGE_COUNT_HEADER, page_count)
+                .body(Body::from(archive))
+        }
+        PagesResponseFormat::Multipart => {
+            let boundary = multipart_boundary();
+            let body = multipart_body(&boundary, images, options.format);
+
+            Response::builder()
+                .header(
+                    header::CONTENT_TYPE,
+                    format!("multipart/mixed; boundary={boundary}"),
+                )
+                .header(PAGE_COUNT_HEADER, page_count)
+                .body(Body::from(body))
+        }
+    }
+    .context("failed to create response")?;
+
+    Ok(response)
+}
+
+/// Name of the image for a page (i.e "page-01.png"), page numbers are padded
+/// to the same width so the names sort in page order
+fn page_file_name(page: usize, page_count: usize, format: ImageFormat) -> String {
+    let width = page_count.to_string().len();
+    format!("page-{page:0width$}.{}", format.extension())
+}
+
+/// Creates a ZIP archive containing the image of each page
+fn create_archive(images: Vec<Bytes>, format: ImageFormat) -> Result<Vec<u8>, ZipError> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+
+    // Images are already compressed
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let page_count = images.len();
+
+    for (index, image) in images.into_iter().enumerate() {
+        writer.start_file(page_file_name(index + 1, page_count, format), options)?;
+        writer.write_all(&image)?;
+    }
+
+    let cursor = writer.finish()?;
+    Ok(cursor.into_inner())
+}
+
+/// Creates a multipart/mixed body with a "page" part for the image of
+/// each page
+fn multipart_body(boundary: &str, images: Vec<Bytes>, format: ImageFormat) -> Bytes {
+    let size: usize = images.iter().map(|image| image.len() + 256).sum();
+    let mut body = BytesMut::with_capacity(size + 64);
+    let page_count = images.len();
+
+    for (index, image) in images.into_iter().enumerate() {
+        let file_name = page_file_name(index + 1, page_count, format);
+
+        body.put(
+            format!(
+                "--{boundary}\r\n\
+                Content-Type: {}\r\n\
+                Content-Disposition: attachment; name=\"page\"; filename=\"{file_name}\"\r\n\r\n",
+                format.mime()
+            )
+            .as_bytes(),
+        );
+        body.put(image);
+        body.put(&b"\r\n"[..]);
+    }
+
+    body.put(format!("--{boundary}--\r\n").as_bytes());
+    body.freeze()
+}
@@ -0,0 +1,119 @@
+use anyhow::{anyhow, Context};
+use axum_typed_multipart::TryFromField;
+use bytes::Bytes;
+use lopdf::{Document, Object, ObjectId};
+
+/// Mime type of PDF files
+pub const PDF_MIME: &str = "application/pdf";
+
+/// How uploaded files that are already a PDF are converted
+#[derive(Debug, Clone, Copy, PartialEq, Default, TryFromField)]
+#[try_from_field(rename_all = "snake_case")]
+pub enum PdfPassthrough {
+    /// Load the PDF into LibreOffice and export it again using the export
+    /// options (i.e to convert it to PDF/A)
+    #[default]
+    Normalize,
+    /// Respond with the uploaded PDF unchanged without loading it into
+    /// LibreOffice
+    Unchanged,
+}
+
+/// Merges the provided PDF documents into a single PDF document, pages are
+/// kept in the order of the provided documents
+pub fn merge_pdfs(documents: Vec<Bytes>) -> anyhow::Result<Bytes> {
+    let mut max_id = 1;
+    let mut pages: Vec<(ObjectId, Object)> = Vec::new();
+    let mut objects: Vec<(ObjectId, Object)> = Vec::new();
+
+    for bytes in documents {
+        let mut document = Document::load_mem(&bytes).context("failed to load pdf")?;
+
+        // Give every object a unique ID across all the documents
+        document.renumber_objects_with(max_id);
+        max_id = document.max_id + 1;
+
+        for object_id in document.get_pages().into_values() {
+            let page = document
+                .get_object(object_id)
+                .context("pdf missing page object")?
+                .to_owned();
+            pages.push((object_id, page));
+        }
+
+        objects.extend(document.objects);
+    }
+
+    let mut output = Document::with_version("1.5");
+    let mut catalog: Option<(ObjectId, Object)> = None;
+    let mut pages_root: Option<(ObjectId, lopdf::Dictionary)> = None;
+
+    for (object_id, object) in objects {
+        match object.type_name().unwrap_or_default() {
+            // First catalog is used as the catalog for the merged document
+            "Catalog" => {
+                catalog.get_or_insert((object_id, object));
+            }
+            // Page trees are combined into the first page tree
+            "Pages" => {
+                if let Ok(dictionary) = object.as_dict() {
+                    match &mut pages_root {
+                        Some((_, root)) => root.extend(dictionary),
+                        None => pages_root = Some((object_id, dictionary.clone())),
+                    }
+                }
+            }
+            // Pages are inserted separately, outlines reference pages
+            // from the other documents so they are dropped
+            "Page" | "Outlines" | "Outline" => {}
+            _ => {
+                output.objects.insert(object_id, object);
+            }
+        }
+    }
+
+    let (catalog_id, catalog) = catalog.ok_or(anyhow!("pdf missing catalog"))?;
+    let (pages_id, mut pages_dictionary) = pages_root.ok_or(anyhow!("pdf missing pages"))?;
+
+    // Insert the pages under the combined page tree
+    let mut kids = Vec::with_capacity(pages.len());
+    for (object_id, page) in pages {
+        if let Ok(dictionary) = page.as_dict() {
+            let mut dictionary = dictionary.clone();
+            dictionary.set("Parent", pages_id);
+            output
+                .objects
+                .insert(object_id, Object::Dictionary(dictionary));
+            kids.push(Object::Reference(object_id));
+        }
+    }
+
+    pages_dictionary.set("Count", kids.len() as u32);
+    pages_dictionary.set("Kids", kids);
+    output
+        .objects
+        .insert(pages_id, Object::Dictionary(pages_dictionary));
+
+    let mut catalog = catalog
+        .as_dict()
+        .context("pdf catalog is not a dictionary")?
+        .clone();
+    catalog.set("Pages", pages_id);
+    catalog.remove(b"Outlines");
+    output
+        .objects
+        .insert(catalog_id, Object::Dictionary(catalog));
+
+    output.trailer.set("Root", catalog_id);
+    output.max_id = output.objects.keys().map(|(id, _)| *id).max().unwrap_or(0);
+    output.renumber_objects();
+    output.adjust_zero_pages();
+    output.compress();
+
+    let mut buffer = Vec::new();
+    output
+        .save_to(&mut buffer)
+        .context("failed to write merged pdf")?;
+
+    Ok(Bytes::from(buffer))
+}
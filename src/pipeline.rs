@@ -0,0 +1,618 @@
+use crate::{
+    compat::apply_compat_fixes,
+    error::{DynHttpError, HttpError},
+    linearize::Linearizer,
+    writer::{apply_tracked_changes, TrackedChanges},
+};
+use anyhow::{anyhow, Context};
+use async_trait::async_trait;
+use axum::http::StatusCode;
+use bytes::Bytes;
+use lopdf::{
+    content::{Content, Operation},
+    dictionary, Dictionary, Document, Object, ObjectId, Stream,
+};
+use office_convert_core::sanitize_pdf;
+use serde::Deserialize;
+use std::sync::Arc;
+use thiserror::Error;
+use tracing::{debug, error};
+
+/// Names of the built-in stages that can be configured
+pub const BUILT_IN_STAGES: &[&str] = &[
+    CompatStage::NAME,
+    AcceptChangesStage::NAME,
+    SanitizeStage::NAME,
+    WatermarkStage::NAME,
+    CompressStage::NAME,
+    LinearizeStage::NAME,
+];
+
+/// Resource name of the watermark font, prefixed to avoid colliding with
+/// resources already used by the page
+const WATERMARK_FONT: &str = "OcsWatermarkFont";
+
+/// Resource name of the watermark graphics state
+const WATERMARK_STATE: &str = "OcsWatermarkState";
+
+/// Page size used when a page doesn't specify its media box (A4 in points)
+const DEFAULT_MEDIA_BOX: [f32; 4] = [0.0, 0.0, 595.0, 842.0];
+
+/// Approximate width of a Helvetica character relative to the font size
+const HELVETICA_CHAR_WIDTH: f32 = 0.55;
+
+/// Approximate height of Helvetica capital letters relative to the font size
+const HELVETICA_CAP_HEIGHT: f32 = 0.72;
+
+/// Processing stages run on every conversion, configured in the `[pipeline]`
+/// section of the config file
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    /// Names of the stages to run, stages run in the order they are listed
+    /// at the point in the conversion they apply to
+    #[serde(default)]
+    pub stages: Vec<String>,
+
+    /// Options for the "watermark" stage
+    pub watermark: Option<WatermarkOptions>,
+}
+
+/// Options for the text drawn by the watermark stage
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatermarkOptions {
+    /// Text drawn diagonally across every page
+    pub text: String,
+
+    /// Opacity of the text from 0 (invisible) to 1 (opaque)
+    #[serde(default = "default_watermark_opacity")]
+    pub opacity: f32,
+
+    /// Font size of the text in points
+    #[serde(default = "default_watermark_font_size")]
+    pub font_size: f32,
+}
+
+fn default_watermark_opacity() -> f32 {
+    0.15
+}
+
+fn default_watermark_font_size() -> f32 {
+    64.0
+}
+
+impl PipelineConfig {
+    /// Validates the configured stages are known and have their options
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (index, name) in self.stages.iter().enumerate() {
+            if !BUILT_IN_STAGES.contains(&name.as_str()) {
+                anyhow::bail!(
+                    "unknown pipeline stage \"{name}\", expected one of: {}",
+                    BUILT_IN_STAGES.join(", ")
+                );
+            }
+
+            if self.stages[..index].contains(name) {
+                anyhow::bail!("pipeline stage \"{name}\" is listed more than once");
+            }
+        }
+
+        if self.stages.iter().any(|name| name == WatermarkStage::NAME) {
+            let watermark = self
+                .watermark
+                .as_ref()
+                .context("the watermark stage requires [pipeline.watermark] options")?;
+
+            if watermark.text.trim().is_empty() {
+                anyhow::bail!("watermark text must not be empty");
+            }
+
+            if !(0.0..=1.0).contains(&watermark.opacity) {
+                anyhow::bail!("watermark opacity must be between 0 and 1");
+            }
+
+            if !(watermark.font_size > 0.0 && watermark.font_size.is_finite()) {
+                anyhow::bail!("watermark font_size must be greater than 0");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Point in the conversion a stage runs at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StagePoint {
+    /// Stage transforms the uploaded document before it is loaded by office
+    BeforeLoad,
+    /// Stage processes the PDF exported by office
+    AfterExport,
+}
+
+/// Details about the conversion provided to stages
+#[derive(Debug, Default)]
+pub struct StageContext {
+    /// Warnings added to the conversion response
+    pub warnings: Vec<String>,
+}
+
+/// Processing step run as part of every conversion, stages are composed into
+/// a [Pipeline] so new processing can be added without changing the
+/// conversion itself
+#[async_trait]
+pub trait PipelineStage: Send + Sync {
+    /// Name the stage is configured by
+    fn name(&self) -> &'static str;
+
+    /// Point in the conversion the stage runs at
+    fn point(&self) -> StagePoint;
+
+    /// Processes the file, provides the file passed to the next stage
+    async fn process(&self, file: Bytes, context: &mut StageContext)
+        -> Result<Bytes, DynHttpError>;
+}
+
+/// Error from a stage that failed to process the file
+#[derive(Debug, Error)]
+#[error("pipeline stage \"{stage}\" failed: {error:#}")]
+pub struct StageFailed {
+    /// Name of the stage that failed
+    stage: &'static str,
+    /// Underlying error
+    error: anyhow::Error,
+}
+
+impl HttpError for StageFailed {
+    fn log(&self) {
+        error!("pipeline stage \"{}\" failed: {:?}", self.stage, self.error);
+    }
+
+    fn status(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("PIPELINE_STAGE_FAILED")
+    }
+}
+
+/// Stages configured to run on conversions
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Arc<dyn PipelineStage>>,
+}
+
+impl Pipeline {
+    /// Creates the pipeline of the configured built-in stages
+    pub fn from_config(config: &PipelineConfig, linearizer: &Arc<Linearizer>) -> Self {
+        let mut pipeline = Pipeline::default();
+
+        for name in &config.stages {
+            let stage: Arc<dyn PipelineStage> = match name.as_str() {
+                CompatStage::NAME => Arc::new(CompatStage),
+                AcceptChangesStage::NAME => Arc::new(AcceptChangesStage),
+                SanitizeStage::NAME => Arc::new(SanitizeStage),
+                CompressStage::NAME => Arc::new(CompressStage),
+                LinearizeStage::NAME => Arc::new(LinearizeStage(linearizer.clone())),
+                WatermarkStage::NAME => match &config.watermark {
+                    Some(options) => Arc::new(WatermarkStage(options.clone())),
+                    None => continue,
+                },
+                // Stage names are validated when the config is loaded
+                _ => continue,
+            };
+
+            pipeline.register(stage);
+        }
+
+        pipeline
+    }
+
+    /// Adds a stage to the end of the pipeline
+    pub fn register(&mut self, stage: Arc<dyn PipelineStage>) {
+        self.stages.push(stage);
+    }
+
+    /// Whether any stages run at the provided point
+    pub fn has_stages(&self, point: StagePoint) -> bool {
+        self.stages.iter().any(|stage| stage.point() == point)
+    }
+
+    /// Runs the stages for the provided point on the file in order
+    pub async fn run(
+        &self,
+        point: StagePoint,
+        mut file: Bytes,
+        context: &mut StageContext,
+    ) -> Result<Bytes, DynHttpError> {
+        for stage in self.stages.iter().filter(|stage| stage.point() == point) {
+            debug!(stage = stage.name(), "running pipeline stage");
+            file = stage.process(file, context).await?;
+        }
+
+        Ok(file)
+    }
+}
+
+/// Runs blocking processing of a file for a stage on the blocking thread pool
+async fn run_blocking<F>(stage: &'static str, file: Bytes, action: F) -> Result<Bytes, DynHttpError>
+where
+    F: FnOnce(&[u8]) -> anyhow::Result<Vec<u8>> + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(move || action(&file))
+        .await
+        .context("failed to join stage task")
+        .and_then(|result| result);
+
+    match result {
+        Ok(value) => Ok(Bytes::from(value)),
+        Err(error) => Err(StageFailed { stage, error }.into()),
+    }
+}
+
+/// Rewrites parts of documents exported by Google Docs and Office 365 that
+/// office is known to import incorrectly before they are loaded
+pub struct CompatStage;
+
+impl CompatStage {
+    const NAME: &'static str = "compat";
+}
+
+#[async_trait]
+impl PipelineStage for CompatStage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn point(&self) -> StagePoint {
+        StagePoint::BeforeLoad
+    }
+
+    async fn process(
+        &self,
+        file: Bytes,
+        context: &mut StageContext,
+    ) -> Result<Bytes, DynHttpError> {
+        let (file, warning) = tokio::task::spawn_blocking(move || apply_compat_fixes(file))
+            .await
+            .context("failed to join stage task")??;
+        context.warnings.extend(warning);
+        Ok(file)
+    }
+}
+
+/// Accepts the tracked changes in text documents before they are loaded
+pub struct AcceptChangesStage;
+
+impl AcceptChangesStage {
+    const NAME: &'static str = "accept_changes";
+}
+
+#[async_trait]
+impl PipelineStage for AcceptChangesStage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn point(&self) -> StagePoint {
+        StagePoint::BeforeLoad
+    }
+
+    async fn process(&self, file: Bytes, _: &mut StageContext) -> Result<Bytes, DynHttpError> {
+        let file = tokio::task::spawn_blocking(move || {
+            apply_tracked_changes(file, Some(TrackedChanges::Accept))
+        })
+        .await
+        .context("failed to join stage task")??;
+
+        Ok(file)
+    }
+}
+
+/// Removes metadata, scripts and other hidden content from the exported PDF
+pub struct SanitizeStage;
+
+impl SanitizeStage {
+    const NAME: &'static str = "sanitize";
+}
+
+#[async_trait]
+impl PipelineStage for SanitizeStage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn point(&self) -> StagePoint {
+        StagePoint::AfterExport
+    }
+
+    async fn process(&self, file: Bytes, _: &mut StageContext) -> Result<Bytes, DynHttpError> {
+        run_blocking(Self::NAME, file, sanitize_pdf).await
+    }
+}
+
+/// Compresses the streams of the exported PDF and removes unused objects
+pub struct CompressStage;
+
+impl CompressStage {
+    const NAME: &'static str = "compress";
+}
+
+#[async_trait]
+impl PipelineStage for CompressStage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn point(&self) -> StagePoint {
+        StagePoint::AfterExport
+    }
+
+    async fn process(&self, file: Bytes, _: &mut StageContext) -> Result<Bytes, DynHttpError> {
+        run_blocking(Self::NAME, file, |bytes| {
+            let mut document = Document::load_mem(bytes).context("failed to load pdf")?;
+            document.prune_objects();
+            document.compress();
+            save_document(&mut document)
+        })
+        .await
+    }
+}
+
+/// Linearizes the exported PDF for fast web view
+pub struct LinearizeStage(Arc<Linearizer>);
+
+impl LinearizeStage {
+    const NAME: &'static str = "linearize";
+}
+
+#[async_trait]
+impl PipelineStage for LinearizeStage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn point(&self) -> StagePoint {
+        StagePoint::AfterExport
+    }
+
+    async fn process(&self, file: Bytes, _: &mut StageContext) -> Result<Bytes, DynHttpError> {
+        // Encrypted output is rejected before stages run so no password is needed
+        Ok(self.0.linearize(file, None).await?)
+    }
+}
+
+/// Draws text diagonally across every page of the exported PDF
+pub struct WatermarkStage(WatermarkOptions);
+
+impl WatermarkStage {
+    const NAME: &'static str = "watermark";
+}
+
+#[async_trait]
+impl PipelineStage for WatermarkStage {
+    fn name(&self) -> &'static str {
+        Self::NAME
+    }
+
+    fn point(&self) -> StagePoint {
+        StagePoint::AfterExport
+    }
+
+    async fn process(
+        &self,
+        file: Bytes,
+        context: &mut StageContext,
+    ) -> Result<Bytes, DynHttpError> {
+        let (text, replaced) = encode_win_ansi(&self.0.text);
+        if replaced {
+            context.warnings.push(format!(
+                "watermark text \"{}\" contains characters the watermark font can't draw, they were replaced with \"?\"",
+                self.0.text
+            ));
+        }
+
+        let options = self.0.clone();
+        run_blocking(Self::NAME, file, move |bytes| {
+            let mut document = Document::load_mem(bytes).context("failed to load pdf")?;
+            add_watermark(&mut document, &text, &options)?;
+            save_document(&mut document)
+        })
+        .await
+    }
+}
+
+/// Encodes text for the standard WinAnsi encoded fonts, characters outside of
+/// Latin-1 are replaced with "?". Provides whether any characters were replaced
+fn encode_win_ansi(text: &str) -> (Vec<u8>, bool) {
+    let mut replaced = false;
+    let bytes = text
+        .chars()
+        .map(|value| match u8::try_from(u32::from(value)) {
+            Ok(byte) if !byte.is_ascii_control() => byte,
+            _ => {
+                replaced = true;
+                b'?'
+            }
+        })
+        .collect();
+
+    (bytes, replaced)
+}
+
+/// Adds the watermark text to every page of the document
+fn add_watermark(
+    document: &mut Document,
+    text: &[u8],
+    options: &WatermarkOptions,
+) -> anyhow::Result<()> {
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let state_id = document.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => options.opacity,
+        "CA" => options.opacity,
+    });
+
+    // Existing content is wrapped in a saved graphics state so transforms
+    // left applied by the page don't affect the watermark
+    let save_state_id = document.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+
+    for page_id in document.get_pages().into_values() {
+        let [left, bottom, right, top] = page_media_box(document, page_id);
+        let center_x = (left + right) / 2.0;
+        let center_y = (bottom + top) / 2.0;
+
+        // Text is drawn along the diagonal from the bottom left to top right
+        let angle = (top - bottom).atan2(right - left);
+        let (sin, cos) = angle.sin_cos();
+        let text_width = text.len() as f32 * options.font_size * HELVETICA_CHAR_WIDTH;
+        let text_height = options.font_size * HELVETICA_CAP_HEIGHT;
+
+        // Offset the start of the text so its center is at the center of the page
+        let x = center_x - cos * text_width / 2.0 + sin * text_height / 2.0;
+        let y = center_y - sin * text_width / 2.0 - cos * text_height / 2.0;
+
+        let content = Content {
+            operations: vec![
+                Operation::new("Q", vec![]),
+                Operation::new("q", vec![]),
+                Operation::new("gs", vec![Object::Name(WATERMARK_STATE.into())]),
+                Operation::new("g", vec![0.5.into()]),
+                Operation::new("BT", vec![]),
+                Operation::new(
+                    "Tf",
+                    vec![
+                        Object::Name(WATERMARK_FONT.into()),
+                        options.font_size.into(),
+                    ],
+                ),
+                Operation::new(
+                    "Tm",
+                    vec![
+                        cos.into(),
+                        sin.into(),
+                        (-sin).into(),
+                        cos.into(),
+                        x.into(),
+                        y.into(),
+                    ],
+                ),
+                Operation::new("Tj", vec![Object::string_literal(text)]),
+                Operation::new("ET", vec![]),
+                Operation::new("Q", vec![]),
+            ],
+        };
+        let content = content.encode().context("failed to encode watermark")?;
+        let content_id = document.add_object(Stream::new(Dictionary::new(), content));
+
+        let mut contents: Vec<Object> = vec![save_state_id.into()];
+        contents.extend(
+            document
+                .get_page_contents(page_id)
+                .into_iter()
+                .map(Object::Reference),
+        );
+        contents.push(content_id.into());
+
+        let resources = watermark_resources(document, page_id, font_id, state_id)?;
+
+        let page = document
+            .get_dictionary_mut(page_id)
+            .context("pdf missing page object")?;
+        page.set("Contents", contents);
+        page.set("Resources", resources);
+    }
+
+    Ok(())
+}
+
+/// Provides the resources of the page with the watermark font and graphics
+/// state added, resources inherited from the page tree are copied to the page
+fn watermark_resources(
+    document: &Document,
+    page_id: ObjectId,
+    font_id: ObjectId,
+    state_id: ObjectId,
+) -> anyhow::Result<Dictionary> {
+    let (inline, inherited) = document
+        .get_page_resources(page_id)
+        .context("failed to read page resources")?;
+
+    let mut resources = match (inline, inherited.first()) {
+        (Some(resources), _) => resources.clone(),
+        (None, Some(resources_id)) => document
+            .get_dictionary(*resources_id)
+            .context("failed to read page resources")?
+            .clone(),
+        (None, None) => Dictionary::new(),
+    };
+
+    let mut add_resource = |category: &[u8], name: &str, id: ObjectId| -> anyhow::Result<()> {
+        let mut entries = match resources.get(category) {
+            Ok(value) => document
+                .dereference(value)
+                .and_then(|(_, value)| value.as_dict())
+                .map_err(|err| anyhow!("invalid page resources: {err}"))?
+                .clone(),
+            Err(_) => Dictionary::new(),
+        };
+        entries.set(name, id);
+        resources.set(category, entries);
+        Ok(())
+    };
+
+    add_resource(b"Font", WATERMARK_FONT, font_id)?;
+    add_resource(b"ExtGState", WATERMARK_STATE, state_id)?;
+
+    Ok(resources)
+}
+
+/// Provides the media box of the page, the media box can be inherited
+/// from the page tree
+fn page_media_box(document: &Document, page_id: ObjectId) -> [f32; 4] {
+    let mut current = document.get_dictionary(page_id).ok();
+
+    while let Some(dictionary) = current {
+        if let Ok(media_box) = dictionary
+            .get(b"MediaBox")
+            .and_then(|value| document.dereference(value))
+            .and_then(|(_, value)| value.as_array())
+        {
+            let values: Vec<f32> = media_box
+                .iter()
+                .filter_map(|value| value.as_float().ok())
+                .collect();
+
+            if let [x1, y1, x2, y2] = values[..] {
+                return [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)];
+            }
+        }
+
+        current = dictionary
+            .get(b"Parent")
+            .and_then(|value| value.as_reference())
+            .and_then(|parent_id| document.get_dictionary(parent_id))
+            .ok();
+    }
+
+    DEFAULT_MEDIA_BOX
+}
+
+/// Saves the document to bytes
+fn save_document(document: &mut Document) -> anyhow::Result<Vec<u8>> {
+    // Every object is written again so the previous cross reference table
+    // of incrementally updated files no longer applies
+    document.trailer.remove(b"Prev");
+
+    let mut output = Vec::new();
+    document
+        .save_to(&mut output)
+        .context("failed to save pdf")?;
+    Ok(output)
+}
@@ -0,0 +1,37 @@
+use crate::tenant::Tenant;
+use axum_typed_multipart::TryFromField;
+use clap::ValueEnum;
+use office_convert_core::Priority;
+use serde::Deserialize;
+
+/// Priority requested for a conversion
+#[derive(Debug, Clone, Copy, PartialEq, TryFromField, Deserialize, ValueEnum)]
+#[try_from_field(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum RequestPriority {
+    High,
+    Normal,
+    Low,
+}
+
+impl From<RequestPriority> for Priority {
+    fn from(value: RequestPriority) -> Self {
+        match value {
+            RequestPriority::High => Priority::High,
+            RequestPriority::Normal => Priority::Normal,
+            RequestPriority::Low => Priority::Low,
+        }
+    }
+}
+
+/// Picks the priority for a conversion, requests from a tenant can lower
+/// their priority below the tenant priority but can't raise it. Any priority
+/// can be requested when tenants aren't configured
+pub fn request_priority(tenant: Option<&Tenant>, requested: Option<RequestPriority>) -> Priority {
+    let requested = requested.map(Priority::from);
+
+    match tenant {
+        Some(tenant) => requested.map_or(tenant.priority(), |value| value.min(tenant.priority())),
+        None => requested.unwrap_or_default(),
+    }
+}
@@ -0,0 +1,90 @@
+use crate::error::{DynHttpError, HttpError};
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+use thiserror::Error;
+
+/// Limit on the number of conversions waiting for office across all clients
+#[derive(Debug)]
+pub struct QueueLimit {
+    /// Maximum number of conversions that can be waiting while another
+    /// conversion is running, [None] when there is no limit
+    max_queued: Option<usize>,
+    /// Number of conversions running or waiting
+    in_flight: AtomicUsize,
+}
+
+/// Errors that can occur when queueing a conversion
+#[derive(Debug, Error)]
+pub enum QueueError {
+    /// Too many conversions are already waiting
+    #[error("too many conversions are waiting, try again later")]
+    QueueFull,
+}
+
+impl HttpError for QueueError {
+    fn status(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("QUEUE_FULL")
+    }
+}
+
+impl QueueLimit {
+    pub fn new(max_queued: Option<usize>) -> Arc<Self> {
+        Arc::new(Self {
+            max_queued,
+            in_flight: AtomicUsize::new(0),
+        })
+    }
+
+    /// Maximum number of conversions that can be waiting
+    pub fn max_queued(&self) -> Option<usize> {
+        self.max_queued
+    }
+
+    /// Number of requests that are running or waiting
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+}
+
+/// Decrements the in flight counter when dropped
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware rejecting conversions when the maximum number of conversions
+/// are already waiting, office converts one document at a time so one
+/// conversion can be running alongside the waiting conversions
+pub async fn limit_queue(
+    State(limit): State<Arc<QueueLimit>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    // Requests are counted without a limit so the queue depth can be reported
+    let in_flight = limit.in_flight.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlightGuard(&limit.in_flight);
+
+    if limit
+        .max_queued
+        .is_some_and(|max_queued| in_flight > max_queued)
+    {
+        return Err(QueueError::QueueFull.into());
+    }
+
+    Ok(next.run(request).await)
+}
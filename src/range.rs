@@ -0,0 +1,114 @@
+use anyhow::Context;
+use axum::{
+    body::Body,
+    http::{header, response::Builder, HeaderMap, HeaderValue, Response, StatusCode},
+};
+use bytes::Bytes;
+
+/// Range of bytes requested using the "Range" header
+#[derive(Debug, PartialEq, Eq)]
+enum RequestedRange {
+    /// Range within the content, the start and end are inclusive
+    Satisfiable { start: u64, end: u64 },
+    /// Range starts after the end of the content
+    Unsatisfiable,
+}
+
+/// Creates a response for stored content that supports range requests so
+/// interrupted downloads can be resumed. Responds with the requested part
+/// of the content when the request has a "Range" header and the "If-Range"
+/// header (when present) matches the entity tag of the content, otherwise
+/// the whole content is provided
+pub fn range_response(
+    headers: &HeaderMap,
+    builder: Builder,
+    bytes: Bytes,
+    etag: HeaderValue,
+) -> anyhow::Result<Response<Body>> {
+    let length = bytes.len() as u64;
+    let range = headers
+        .get(header::RANGE)
+        .filter(|_| if_range_matches(headers, &etag))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| parse_range(value, length));
+
+    let builder = builder
+        .header(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"))
+        .header(header::ETAG, etag);
+
+    let response = match range {
+        None => builder.body(Body::from(bytes)),
+        Some(RequestedRange::Satisfiable { start, end }) => builder
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(
+                header::CONTENT_RANGE,
+                format!("bytes {start}-{end}/{length}"),
+            )
+            .body(Body::from(bytes.slice(start as usize..=end as usize))),
+        Some(RequestedRange::Unsatisfiable) => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{length}"))
+            .body(Body::empty()),
+    };
+
+    response.context("failed to create response")
+}
+
+/// Checks if the "If-Range" header of the request matches the entity tag,
+/// requests without the header always match. Strong comparison is used as
+/// required for "If-Range", dates are never matched as the content is only
+/// identified by its entity tag
+fn if_range_matches(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let Some(value) = headers.get(header::IF_RANGE) else {
+        return true;
+    };
+
+    let value = value.as_bytes().trim_ascii();
+    !value.starts_with(b"W/") && value == etag.as_bytes()
+}
+
+/// Parses the value of a "Range" header for content of the provided length,
+/// only a single range of bytes is supported. Provides [None] when the whole
+/// content should be sent instead (the header is invalid or requests multiple
+/// ranges)
+fn parse_range(value: &str, length: u64) -> Option<RequestedRange> {
+    let value = value.trim();
+    let (unit, range) = value.split_once('=')?;
+    if !unit.trim().eq_ignore_ascii_case("bytes") || range.contains(',') {
+        return None;
+    }
+
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    // Suffix ranges request the last bytes of the content (i.e "bytes=-500")
+    if start.is_empty() {
+        let suffix: u64 = end.parse().ok()?;
+        if suffix == 0 || length == 0 {
+            return Some(RequestedRange::Unsatisfiable);
+        }
+
+        return Some(RequestedRange::Satisfiable {
+            start: length.saturating_sub(suffix),
+            end: length - 1,
+        });
+    }
+
+    let start: u64 = start.parse().ok()?;
+    let end = match end.is_empty() {
+        true => None,
+        false => Some(end.parse::<u64>().ok()?),
+    };
+
+    if end.is_some_and(|end| end < start) {
+        return None;
+    }
+
+    if start >= length {
+        return Some(RequestedRange::Unsatisfiable);
+    }
+
+    // Ranges past the end of the content are shortened to the content
+    let end = end.map_or(length - 1, |end| end.min(length - 1));
+    Some(RequestedRange::Satisfiable { start, end })
+}
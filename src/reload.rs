@@ -0,0 +1,176 @@
+use crate::{
+    admin::AdminError,
+    config::{Config, SharedConfig},
+    engine::Engines,
+    error::DynHttpError,
+    fonts::{load_font_substitutions, FontCatalog},
+    systemd::{notify_ready, notify_reloading},
+    tenant::Tenants,
+};
+use anyhow::Context;
+use axum::{Extension, Json};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{path::PathBuf, sync::Arc};
+use tracing::{error, info, warn};
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Handle for replacing the logging filter while the server is running
+pub type LogFilterHandle = reload::Handle<EnvFilter, Registry>;
+
+/// Creates the logging filter from the log level in the config, the
+/// RUST_LOG environment variable is used when not provided
+pub fn log_filter(log_level: Option<&str>) -> EnvFilter {
+    log_level
+        .and_then(|value| EnvFilter::try_new(value).ok())
+        .unwrap_or_else(EnvFilter::from_default_env)
+}
+
+/// Reloads the config file and font substitutions, applying the settings
+/// that can change without restarting the server or office
+pub struct Reloader {
+    /// Path to the config file
+    pub config_path: Option<PathBuf>,
+    /// Path to the font substitutions file
+    pub font_substitutions_path: Option<PathBuf>,
+    /// Config used by requests
+    pub config: SharedConfig,
+    /// Tenants identified from the API keys in the config
+    pub tenants: Arc<Tenants>,
+    /// Fonts used to report substituted fonts
+    pub fonts: Arc<FontCatalog>,
+    /// Engine workers, asked to reload along with the server
+    pub engines: Arc<Engines>,
+    /// Handle for replacing the logging filter
+    pub log_filter: LogFilterHandle,
+    /// Held while reloading so only one reload is applied at a time
+    pub lock: Mutex<()>,
+}
+
+/// Settings in use after reloading
+#[derive(Debug, Serialize)]
+pub struct ReloadSummary {
+    /// Number of configured presets
+    presets: usize,
+    /// Number of configured tenants
+    tenants: usize,
+    /// Number of font substitutions
+    font_substitutions: usize,
+    /// Logging filter from the config, [None] when using the RUST_LOG
+    /// environment variable
+    log_level: Option<String>,
+}
+
+impl Reloader {
+    /// Reloads the config, nothing is applied when the config or font
+    /// substitutions are invalid. Engines and the usage file are only
+    /// used on startup so can't be changed
+    pub fn reload(&self) -> anyhow::Result<ReloadSummary> {
+        let _guard = self.lock.lock();
+
+        notify_reloading();
+        let result = self.reload_config();
+        notify_ready();
+
+        result
+    }
+
+    /// Loads and applies the config, see [Reloader::reload]
+    fn reload_config(&self) -> anyhow::Result<ReloadSummary> {
+        let current = self.config.current();
+
+        let config = match &self.config_path {
+            Some(path) => Config::load(path).context("failed to load config")?,
+            None => Config::default(),
+        };
+
+        if config.engines != current.engines {
+            anyhow::bail!("engines can't be changed without restarting the server");
+        }
+
+        if config.office_env != current.office_env {
+            anyhow::bail!("office_env can't be changed without restarting the server");
+        }
+
+        if config.usage_file != current.usage_file {
+            anyhow::bail!("usage_file can't be changed without restarting the server");
+        }
+
+        let font_substitutions = self
+            .font_substitutions_path
+            .as_deref()
+            .map(load_font_substitutions)
+            .transpose()?;
+
+        // Tenants are validated when replaced so are replaced before the other settings
+        self.tenants
+            .reload(&config.tenants)
+            .context("failed to load tenants")?;
+
+        if let Err(cause) = self
+            .log_filter
+            .reload(log_filter(config.log_level.as_deref()))
+        {
+            warn!(%cause, "failed to replace logging filter");
+        }
+
+        if let Some(font_substitutions) = font_substitutions {
+            self.fonts.set_substitutions(font_substitutions);
+        }
+
+        let summary = ReloadSummary {
+            presets: config.presets.len(),
+            tenants: self.tenants.count(),
+            font_substitutions: self.fonts.substitution_count(),
+            log_level: config.log_level.clone(),
+        };
+
+        self.config.replace(config);
+        self.engines.reload_workers();
+
+        info!(?summary, "reloaded config");
+
+        Ok(summary)
+    }
+}
+
+/// Reloads the config whenever the server receives SIGHUP
+#[cfg(unix)]
+pub fn spawn_reload_signal(reloader: Arc<Reloader>) -> anyhow::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangup = signal(SignalKind::hangup()).context("failed to listen for SIGHUP")?;
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            info!("received SIGHUP, reloading config");
+
+            if let Err(cause) = reloader.reload() {
+                error!("failed to reload config: {cause:#}");
+            }
+        }
+    });
+
+    Ok(())
+}
+
+/// SIGHUP is only available on unix platforms, the config can be reloaded
+/// using POST /admin/reload instead
+#[cfg(not(unix))]
+pub fn spawn_reload_signal(_reloader: Arc<Reloader>) -> anyhow::Result<()> {
+    Ok(())
+}
+
+/// POST /admin/reload
+///
+/// Reloads the config file and font substitutions, responds with the
+/// settings in use after reloading
+pub async fn reload_config(
+    Extension(reloader): Extension<Arc<Reloader>>,
+) -> Result<Json<ReloadSummary>, DynHttpError> {
+    let summary = reloader
+        .reload()
+        .map_err(|cause| AdminError::ReloadFailed(format!("{cause:#}")))?;
+
+    Ok(Json(summary))
+}
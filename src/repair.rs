@@ -0,0 +1,181 @@
+use crate::archive::ZIP_SIGNATURE;
+use bytes::Bytes;
+use office_convert_core::{Conversion, ConvertError, Converter, PdfExportOptions};
+use std::io::{Cursor, Read, Write};
+use tracing::{debug, warn};
+use zip::{
+    read::{read_zipfile_from_stream, ZipFile},
+    result::ZipResult,
+    write::SimpleFileOptions,
+    CompressionMethod, ZipArchive, ZipWriter,
+};
+
+/// Name of the ODF part that must be the first uncompressed entry
+const ODF_MIMETYPE: &str = "mimetype";
+
+/// Parts recovered from a damaged archive
+struct SalvagedArchive {
+    /// Rebuilt archive containing the readable parts
+    bytes: Vec<u8>,
+    /// Number of parts that were recovered
+    recovered: usize,
+    /// Number of parts that could not be read, [None] when the archive
+    /// index was unreadable and the total number of parts is unknown
+    skipped: Option<usize>,
+}
+
+/// Converts the provided file, when the conversion fails and the file is a
+/// damaged ZIP based document (OOXML, ODF) the readable parts are salvaged
+/// into a new archive and the conversion is attempted again. Provides the
+/// conversion that produced the file along with a warning describing the
+/// repair when one was made
+pub async fn convert_with_repair(
+    office: &Converter,
+    input: Bytes,
+    options: PdfExportOptions,
+) -> Result<(Conversion, Option<String>), ConvertError> {
+    let cause = match office
+        .convert_with_warnings(input.clone(), options.clone())
+        .await
+    {
+        Ok(conversion) => return Ok((conversion, None)),
+        // Only failures from office can be repaired
+        Err(cause @ ConvertError::Office(_)) => cause,
+        Err(cause) => return Err(cause),
+    };
+
+    debug!(%cause, "conversion failed, attempting repair");
+
+    let salvaged = match salvage_archive(&input) {
+        Some(value) => value,
+        None => return Err(cause),
+    };
+
+    let conversion = match office
+        .convert_with_warnings(Bytes::from(salvaged.bytes), options)
+        .await
+    {
+        Ok(value) => value,
+        Err(repair_cause) => {
+            warn!(%repair_cause, "conversion of repaired document failed");
+            return Err(cause);
+        }
+    };
+
+    let warning = match salvaged.skipped {
+        Some(0) => "document archive was damaged and has been rebuilt".to_string(),
+        Some(skipped) => format!(
+            "document archive was damaged, {skipped} of {} parts could not be read and were skipped",
+            salvaged.recovered + skipped
+        ),
+        None => format!(
+            "document archive was truncated or damaged, only the first {} parts could be recovered",
+            salvaged.recovered
+        ),
+    };
+
+    Ok((conversion, Some(warning)))
+}
+
+/// Salvages the readable parts of a damaged ZIP archive into a new archive,
+/// provides [None] when the input is not a ZIP archive or nothing was readable
+fn salvage_archive(input: &[u8]) -> Option<SalvagedArchive> {
+    if !input.starts_with(ZIP_SIGNATURE) {
+        return None;
+    }
+
+    let mut parts: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut skipped = None;
+
+    match ZipArchive::new(Cursor::new(input)) {
+        // Archive index is readable, read every part that can be read
+        Ok(mut archive) => {
+            let mut skipped_count = 0;
+
+            for index in 0..archive.len() {
+                match archive.by_index(index).and_then(read_part) {
+                    Ok(Some(part)) => parts.push(part),
+                    Ok(None) => {}
+                    Err(_) => skipped_count += 1,
+                }
+            }
+
+            skipped = Some(skipped_count);
+        }
+        // Archive index is missing (i.e truncated file), read the parts in
+        // order from the start of the file until one cannot be read
+        Err(_) => {
+            let mut reader = Cursor::new(input);
+
+            loop {
+                let entry = match read_zipfile_from_stream(&mut reader) {
+                    Ok(Some(entry)) => entry,
+                    // Reached the end of the parts or the damaged section
+                    _ => break,
+                };
+
+                match read_part(entry) {
+                    Ok(Some(part)) => parts.push(part),
+                    Ok(None) => {}
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    if parts.is_empty() {
+        return None;
+    }
+
+    let recovered = parts.len();
+    let bytes = write_archive(parts).ok()?;
+
+    Some(SalvagedArchive {
+        bytes,
+        recovered,
+        skipped,
+    })
+}
+
+/// Reads the name and contents of an archive entry, provides [None] for
+/// directories. Errors when the entry contents are corrupted
+fn read_part(mut entry: ZipFile<'_>) -> ZipResult<Option<(String, Vec<u8>)>> {
+    // Directories don't need to be recreated
+    if entry.is_dir() {
+        return Ok(None);
+    }
+
+    let name = entry.name().to_string();
+    let mut contents = Vec::new();
+
+    // Reading the entirety of the entry also validates the checksum
+    entry.read_to_end(&mut contents)?;
+
+    Ok(Some((name, contents)))
+}
+
+/// Writes the parts into a new archive, the ODF mimetype part is written
+/// first without compression as required by the ODF specification
+fn write_archive(mut parts: Vec<(String, Vec<u8>)>) -> ZipResult<Vec<u8>> {
+    if let Some(index) = parts.iter().position(|(name, _)| name == ODF_MIMETYPE) {
+        let part = parts.remove(index);
+        parts.insert(0, part);
+    }
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for (name, contents) in parts {
+        let options = if name == ODF_MIMETYPE {
+            options.compression_method(CompressionMethod::Stored)
+        } else {
+            options
+        };
+
+        writer.start_file(name, options)?;
+        writer.write_all(&contents)?;
+    }
+
+    let cursor = writer.finish()?;
+    Ok(cursor.into_inner())
+}
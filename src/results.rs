@@ -0,0 +1,344 @@
+use crate::{
+    error::{DynHttpError, HttpError},
+    jobs::now_secs,
+    storage::Storage,
+    tenant::Tenant,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::Path,
+    http::{header, HeaderValue, Response, StatusCode},
+    Extension,
+};
+use bytes::Bytes;
+use parking_lot::Mutex;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+/// Header the ID of the retained result is provided in
+pub const RESULT_ID_HEADER: &str = "x-result-id";
+
+/// Time between runs of the expired result collector
+const COLLECT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Errors that can occur when fetching retained results
+#[derive(Debug, Error)]
+pub enum ResultError {
+    /// Result does not exist, has expired or was evicted
+    #[error("result not found")]
+    NotFound,
+}
+
+impl HttpError for ResultError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ResultError::NotFound => StatusCode::NOT_FOUND,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            ResultError::NotFound => Some("RESULT_NOT_FOUND"),
+        }
+    }
+}
+
+/// Converted PDF retained in storage
+struct RetainedResult {
+    /// Name of the tenant that converted the file
+    tenant: Option<String>,
+    /// Storage key the PDF is written to
+    key: String,
+    /// Size of the PDF in bytes
+    size: u64,
+    /// Entity tag of the conversion response
+    etag: HeaderValue,
+    /// Unix timestamp in seconds of when the result expires
+    expires_at: u64,
+}
+
+#[derive(Default)]
+struct RetainedResults {
+    /// Retained results keyed by ID
+    results: HashMap<String, Arc<RetainedResult>>,
+    /// IDs of the retained results from oldest to newest
+    order: VecDeque<String>,
+    /// Total size of the retained results in bytes
+    size: u64,
+}
+
+/// Details about the retained results and how often they were fetched
+#[derive(Debug, Serialize)]
+pub struct ResultStats {
+    /// Number of retained results
+    pub retained: usize,
+    /// Total size of the retained results in bytes
+    pub size_bytes: u64,
+    /// Number of requests for results that were retained
+    pub hits: u64,
+    /// Number of requests for results that were not retained
+    pub misses: u64,
+    /// Fraction of requests for results that were retained, [None] when
+    /// no results have been requested
+    pub hit_rate: Option<f64>,
+}
+
+/// Store retaining recent conversion results in storage so clients that lose
+/// the response (i.e proxy timeouts) can download the result again without
+/// converting the file again
+pub struct ResultStore {
+    /// Storage results are written to, [None] when results are not retained
+    storage: Option<Arc<dyn Storage>>,
+    /// Time to retain results for
+    ttl: Duration,
+    /// Maximum total size of the retained results in bytes, the oldest
+    /// results are evicted to make room for new results
+    max_size: u64,
+    /// Retained results
+    results: Mutex<RetainedResults>,
+    /// Number of requests for results that were retained
+    hits: AtomicU64,
+    /// Number of requests for results that were not retained
+    misses: AtomicU64,
+}
+
+impl ResultStore {
+    /// Creates a new store writing results to the provided storage, results
+    /// are not retained when no storage is provided
+    pub fn new(storage: Option<Arc<dyn Storage>>, ttl: Duration, max_size: u64) -> Arc<Self> {
+        Arc::new(Self {
+            storage,
+            ttl,
+            max_size,
+            results: Default::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        })
+    }
+
+    /// Retains the converted PDF providing the ID it can be fetched with,
+    /// provides [None] when results are not retained or the PDF could not
+    /// be retained
+    pub async fn retain(
+        &self,
+        tenant: Option<&str>,
+        pdf: &Bytes,
+        etag: &HeaderValue,
+    ) -> Option<String> {
+        let storage = self.storage.as_ref()?;
+        let size = pdf.len() as u64;
+
+        // Results that would evict every other result aren't retained
+        if size > self.max_size {
+            return None;
+        }
+
+        let id = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(|value| value as char)
+            .collect::<String>();
+
+        let key = format!("{id}.pdf");
+        if let Err(cause) = storage.put(&key, pdf.clone()).await {
+            warn!("failed to write retained result: {cause:#}");
+            storage.delete(&key).await;
+            return None;
+        }
+
+        let result = Arc::new(RetainedResult {
+            tenant: tenant.map(str::to_string),
+            key,
+            size,
+            etag: etag.clone(),
+            expires_at: now_secs() + self.ttl.as_secs(),
+        });
+
+        let evicted = {
+            let results = &mut *self.results.lock();
+            results.results.insert(id.clone(), result);
+            results.order.push_back(id.clone());
+            results.size += size;
+
+            let mut evicted = Vec::new();
+            while results.size > self.max_size {
+                match Self::remove_oldest(results) {
+                    Some(result) => evicted.push(result),
+                    None => break,
+                }
+            }
+
+            evicted
+        };
+
+        for result in evicted {
+            debug!("evicting retained result to stay within the size limit");
+            self.remove_result(result).await;
+        }
+
+        Some(id)
+    }
+
+    /// Gets a retained result and the entity tag of its response, results
+    /// converted by a tenant are only accessible to the same tenant
+    async fn get(
+        &self,
+        id: &str,
+        tenant: Option<&str>,
+    ) -> Result<(Bytes, HeaderValue), ResultError> {
+        let now = now_secs();
+        let result = self
+            .results
+            .lock()
+            .results
+            .get(id)
+            .filter(|result| result.tenant.as_deref() == tenant && result.expires_at > now)
+            .cloned();
+
+        // Result may be evicted while it is read
+        let bytes = match (result.as_ref(), self.storage.as_ref()) {
+            (Some(result), Some(storage)) => storage.get(&result.key).await.ok().flatten(),
+            _ => None,
+        };
+
+        match (result, bytes) {
+            (Some(result), Some(bytes)) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Ok((bytes, result.etag.clone()))
+            }
+            _ => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                Err(ResultError::NotFound)
+            }
+        }
+    }
+
+    /// Provides details about the retained results
+    pub fn stats(&self) -> ResultStats {
+        let (retained, size_bytes) = {
+            let results = &*self.results.lock();
+            (results.results.len(), results.size)
+        };
+
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let requests = hits + misses;
+
+        ResultStats {
+            retained,
+            size_bytes,
+            hits,
+            misses,
+            hit_rate: (requests > 0).then(|| hits as f64 / requests as f64),
+        }
+    }
+
+    /// Stops tracking the oldest result
+    fn remove_oldest(results: &mut RetainedResults) -> Option<Arc<RetainedResult>> {
+        let id = results.order.pop_front()?;
+        let result = results.results.remove(&id)?;
+        results.size -= result.size;
+        Some(result)
+    }
+
+    /// Removes the file of a result that is no longer tracked
+    async fn remove_result(&self, result: Arc<RetainedResult>) {
+        if let Some(storage) = &self.storage {
+            storage.delete(&result.key).await;
+        }
+    }
+
+    /// Removes results left in storage by a previous run, these results
+    /// are no longer tracked so can't be fetched
+    pub async fn clear(&self) -> anyhow::Result<()> {
+        let Some(storage) = &self.storage else {
+            return Ok(());
+        };
+
+        let keys = storage
+            .keys()
+            .await
+            .context("failed to list retained results")?;
+
+        for key in keys.iter().filter(|key| key.ends_with(".pdf")) {
+            storage.delete(key).await;
+        }
+
+        Ok(())
+    }
+
+    /// Removes results that have expired, results are retained for the
+    /// same time so the oldest results expire first
+    async fn collect_expired(&self) {
+        let now = now_secs();
+
+        let expired = {
+            let results = &mut *self.results.lock();
+            let mut expired = Vec::new();
+
+            while results
+                .order
+                .front()
+                .and_then(|id| results.results.get(id))
+                .is_some_and(|result| result.expires_at <= now)
+            {
+                expired.extend(Self::remove_oldest(results));
+            }
+
+            expired
+        };
+
+        for result in expired {
+            debug!("removing expired result");
+            self.remove_result(result).await;
+        }
+    }
+}
+
+/// Spawns a background task that periodically removes expired results
+pub fn spawn_result_collector(store: Arc<ResultStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(COLLECT_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            store.collect_expired().await;
+        }
+    });
+}
+
+/// GET /results/:id
+///
+/// Provides the PDF of a retained conversion result
+pub async fn get_result(
+    Extension(store): Extension<Arc<ResultStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<Response<Body>, DynHttpError> {
+    let tenant = tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str());
+    let (bytes, etag) = store.get(&id, tenant).await?;
+
+    let response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/pdf"),
+        )
+        .header(header::ETAG, etag)
+        .body(Body::from(bytes))
+        .context("failed to create response")?;
+
+    Ok(response)
+}
@@ -0,0 +1,311 @@
+use crate::{
+    config::SharedConfig,
+    error::{DynHttpError, HttpError},
+};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Extension, Json,
+};
+use bytes::Bytes;
+use office_convert_core::{Converter, PdfExportOptions, Priority, WorkerState};
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tracing::{debug, error, info, warn};
+
+/// Tiny document converted by the self-test, RTF is detected from its
+/// contents so doesn't need a file name
+const SELF_TEST_DOCUMENT: &[u8] = b"{\\rtf1\\ansi office-convert-server self-test\\par}";
+
+/// Maximum time the self-test conversion can take before it fails
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Maximum time to wait for office to restart after the self-test failed
+const RESTART_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Signature at the start of a PDF file
+const PDF_SIGNATURE: &[u8] = b"%PDF-";
+
+/// Tiny document of a common format converted by GET /selftest
+struct SampleDocument {
+    /// Format of the document (its file extension)
+    format: &'static str,
+    /// File name the document is converted with
+    file_name: &'static str,
+    /// Contents of the document
+    bytes: &'static [u8],
+}
+
+/// Sample documents bundled with the server, each uses the smallest set of
+/// parts office needs to load the format
+const SAMPLE_DOCUMENTS: &[SampleDocument] = &[
+    SampleDocument {
+        format: "docx",
+        file_name: "sample.docx",
+        bytes: include_bytes!("samples/sample.docx"),
+    },
+    SampleDocument {
+        format: "xlsx",
+        file_name: "sample.xlsx",
+        bytes: include_bytes!("samples/sample.xlsx"),
+    },
+    SampleDocument {
+        format: "pptx",
+        file_name: "sample.pptx",
+        bytes: include_bytes!("samples/sample.pptx"),
+    },
+    SampleDocument {
+        format: "odt",
+        file_name: "sample.odt",
+        bytes: include_bytes!("samples/sample.odt"),
+    },
+    SampleDocument {
+        format: "csv",
+        file_name: "sample.csv",
+        bytes: include_bytes!("samples/sample.csv"),
+    },
+];
+
+/// Whether the server is ready to convert files, cleared when the self-test
+/// fails repeatedly and set again once it passes
+#[derive(Debug)]
+pub struct Readiness {
+    /// Whether the server is ready
+    ready: AtomicBool,
+    /// Number of self-tests that have failed in a row
+    consecutive_failures: AtomicU32,
+}
+
+/// Errors that can occur when checking readiness
+#[derive(Debug, Error)]
+pub enum ReadinessError {
+    /// Self-test failed repeatedly and office is being restarted
+    #[error("server is not ready, the self-test is failing")]
+    NotReady,
+}
+
+impl HttpError for ReadinessError {
+    fn status(&self) -> StatusCode {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("NOT_READY")
+    }
+}
+
+impl Readiness {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            ready: AtomicBool::new(true),
+            consecutive_failures: AtomicU32::new(0),
+        })
+    }
+
+    /// Whether the server is ready to convert files
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::SeqCst)
+    }
+}
+
+/// Spawns a background task that converts a tiny document every interval,
+/// after `max_failures` failures in a row the server is marked not ready and
+/// office is restarted
+pub fn spawn_self_test(
+    office: Converter,
+    readiness: Arc<Readiness>,
+    interval: Duration,
+    max_failures: u32,
+) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+
+        // Office was just started so the first test waits for the interval
+        interval.tick().await;
+
+        loop {
+            interval.tick().await;
+            run_self_test(&office, &readiness, max_failures).await;
+        }
+    });
+}
+
+/// Runs a single self-test, restarting office when it has failed too many
+/// times in a row
+async fn run_self_test(office: &Converter, readiness: &Readiness, max_failures: u32) {
+    // Stuck conversions are handled by the conversion timeout, the test would
+    // only wait behind conversions that are making progress
+    if office.status().state != WorkerState::Idle {
+        debug!("skipping self-test, office is not idle");
+        return;
+    }
+
+    let result = tokio::time::timeout(
+        SELF_TEST_TIMEOUT,
+        office
+            .with_priority(Priority::High)
+            .convert(Bytes::from_static(SELF_TEST_DOCUMENT)),
+    )
+    .await;
+
+    let cause = match result {
+        Ok(Ok(_)) => {
+            readiness.consecutive_failures.store(0, Ordering::SeqCst);
+            if !readiness.ready.swap(true, Ordering::SeqCst) {
+                info!("self-test passed, server is ready");
+            }
+            return;
+        }
+        Ok(Err(err)) => format!("{:#}", anyhow::Error::from(err)),
+        Err(_) => "self-test conversion timed out".to_string(),
+    };
+
+    let failures = readiness
+        .consecutive_failures
+        .fetch_add(1, Ordering::SeqCst)
+        + 1;
+    warn!(failures, %cause, "self-test failed");
+
+    if failures < max_failures {
+        return;
+    }
+
+    readiness.ready.store(false, Ordering::SeqCst);
+    readiness.consecutive_failures.store(0, Ordering::SeqCst);
+    error!(failures, "self-test failed repeatedly, restarting office");
+
+    match tokio::time::timeout(RESTART_TIMEOUT, office.restart()).await {
+        Ok(Ok(())) => info!("office restarted after failed self-tests"),
+        Ok(Err(err)) => error!("failed to restart office: {err:#}"),
+        Err(_) => error!("timed out restarting office"),
+    }
+}
+
+#[derive(Serialize)]
+pub struct ReadyResponse {
+    /// Whether the server is ready
+    ready: bool,
+}
+
+/// GET /ready
+///
+/// Reports whether the server is ready to convert files, responds with a
+/// 503 error while the self-test is failing
+pub async fn ready(
+    Extension(readiness): Extension<Arc<Readiness>>,
+) -> Result<Json<ReadyResponse>, DynHttpError> {
+    if !readiness.is_ready() {
+        return Err(ReadinessError::NotReady.into());
+    }
+
+    Ok(Json(ReadyResponse { ready: true }))
+}
+
+/// Result of converting one of the sample documents
+#[derive(Serialize)]
+pub struct SampleResult {
+    /// Format of the sample document
+    format: &'static str,
+    /// Whether the document converted to a PDF
+    passed: bool,
+    /// Time taken to convert the document in milliseconds
+    duration_ms: u64,
+    /// Size of the converted PDF in bytes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<usize>,
+    /// Reason the conversion failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// Machine readable code for the reason the conversion failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct SelfTestResponse {
+    /// Whether every sample document converted
+    passed: bool,
+    /// Total time taken in milliseconds
+    duration_ms: u64,
+    /// Result for each of the sample documents
+    results: Vec<SampleResult>,
+}
+
+/// GET /selftest
+///
+/// Converts each of the bundled sample documents reporting whether each
+/// format converted along with how long it took, responds with a 503 status
+/// when any of the formats failed
+pub async fn self_test(
+    Extension(office): Extension<Converter>,
+    Extension(config): Extension<SharedConfig>,
+) -> Response {
+    let config = config.current();
+    let start = Instant::now();
+    let mut results = Vec::with_capacity(SAMPLE_DOCUMENTS.len());
+
+    for sample in SAMPLE_DOCUMENTS {
+        let mut options = PdfExportOptions::default();
+        config.apply_load_options(&mut options, sample.bytes, Some(sample.file_name), None);
+
+        let sample_start = Instant::now();
+        let result = tokio::time::timeout(
+            SELF_TEST_TIMEOUT,
+            office.convert_with_options(Bytes::from_static(sample.bytes), options),
+        )
+        .await;
+        let duration_ms = sample_start.elapsed().as_millis() as u64;
+
+        let (size, error, code) = match result {
+            Ok(Ok(bytes)) if bytes.starts_with(PDF_SIGNATURE) => (Some(bytes.len()), None, None),
+            Ok(Ok(_)) => (
+                None,
+                Some("conversion did not produce a PDF".to_string()),
+                None,
+            ),
+            Ok(Err(err)) => {
+                let code = err.code();
+                (None, Some(format!("{:#}", anyhow::Error::from(err))), code)
+            }
+            Err(_) => (None, Some("conversion timed out".to_string()), None),
+        };
+
+        match &error {
+            Some(error) => warn!(format = sample.format, %error, "self-test sample failed"),
+            None => debug!(
+                format = sample.format,
+                duration_ms, "self-test sample passed"
+            ),
+        }
+
+        results.push(SampleResult {
+            format: sample.format,
+            passed: error.is_none(),
+            duration_ms,
+            size,
+            error,
+            code,
+        });
+    }
+
+    let passed = results.iter().all(|result| result.passed);
+    let status = match passed {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+
+    let response = SelfTestResponse {
+        passed,
+        duration_ms: start.elapsed().as_millis() as u64,
+        results,
+    };
+
+    (status, Json(response)).into_response()
+}
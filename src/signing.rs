@@ -0,0 +1,98 @@
+use crate::error::HttpError;
+use anyhow::{anyhow, Context};
+use axum::http::StatusCode;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use office_convert_core::SigningCertificate;
+use std::{path::Path, process::Command};
+use thiserror::Error;
+
+/// Environment variable the PKCS#12 password is provided to openssl through,
+/// avoids exposing the password in the process arguments
+const PASSWORD_ENV: &str = "OFFICE_CONVERT_PKCS12_PASSWORD";
+
+/// Errors from requests for signed PDFs
+#[derive(Debug, Error)]
+pub enum SigningError {
+    /// Signing was requested alongside an option that would invalidate the signature
+    #[error("{0}")]
+    Conflict(&'static str),
+}
+
+impl HttpError for SigningError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        Some("SIGNING_CONFLICT")
+    }
+}
+
+/// Loads the signing certificate and private key from a PKCS#12 (.p12 / .pfx)
+/// file. LibreOffice requires the DER encoded certificate and key so the file
+/// is converted using the openssl command line tool
+pub fn load_pkcs12(path: &Path, password: &str) -> anyhow::Result<SigningCertificate> {
+    let pem = match export_pem(path, password, false) {
+        Ok(value) => value,
+        // Older files use algorithms that are only available to OpenSSL 3 as legacy algorithms
+        Err(_) => export_pem(path, password, true)?,
+    };
+
+    let certificate = pem_block(&pem, "CERTIFICATE")
+        .context("signing certificate file does not contain a certificate")?;
+    let private_key = pem_block(&pem, "PRIVATE KEY")
+        .context("signing certificate file does not contain a private key")?;
+
+    Ok(SigningCertificate {
+        certificate,
+        private_key,
+    })
+}
+
+/// Exports the certificate and unencrypted private key from a PKCS#12
+/// file as PEM using openssl
+fn export_pem(path: &Path, password: &str, legacy: bool) -> anyhow::Result<String> {
+    let mut command = Command::new("openssl");
+    command
+        .arg("pkcs12")
+        .arg("-in")
+        .arg(path)
+        // Only the certificate matching the private key is required
+        .arg("-clcerts")
+        .arg("-nodes")
+        .arg("-passin")
+        .arg(format!("env:{PASSWORD_ENV}"))
+        .env(PASSWORD_ENV, password);
+
+    if legacy {
+        command.arg("-legacy");
+    }
+
+    let output = command.output().context("failed to start openssl")?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow!(
+            "failed to read signing certificate file: {}",
+            stderr.trim()
+        ));
+    }
+
+    String::from_utf8(output.stdout).context("openssl output was not valid utf8")
+}
+
+/// Decodes the first PEM block with the provided label
+fn pem_block(pem: &str, label: &str) -> Option<Vec<u8>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+
+    let start = pem.find(&begin)? + begin.len();
+    let length = pem[start..].find(&end)?;
+
+    let base64: String = pem[start..start + length]
+        .chars()
+        .filter(|value| !value.is_whitespace())
+        .collect();
+
+    STANDARD.decode(base64).ok()
+}
@@ -0,0 +1,611 @@
+use crate::{
+    archive::{open_archive, read_part, replace_parts},
+    detect::{detect_archive, known_extension},
+    page_style::{
+        insert_before, parse_length_mm, parse_number, PageStyleError,
+        WORKSHEET_ELEMENTS_AFTER_PAGE_SETUP,
+    },
+    xml::{
+        find_tag, get_attribute, remove_attribute, rewrite_tags, set_attribute, Attributes, Tag,
+    },
+};
+use bytes::Bytes;
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Seek},
+};
+use zip::ZipArchive;
+
+/// Millimeters in an inch
+const MM_PER_INCH: f32 = 25.4;
+
+/// Width in pixels of a character of the default Excel font, column widths
+/// are stored as a number of characters
+const EXCEL_CHARACTER_PX: f32 = 7.0;
+
+/// Padding in pixels Excel adds to the default column width
+const EXCEL_COLUMN_PADDING_PX: f32 = 5.0;
+
+/// Default width of an OpenDocument spreadsheet column in millimeters (0.889in)
+const ODF_DEFAULT_COLUMN_WIDTH_MM: f32 = 22.58;
+
+/// Default height of an OpenDocument spreadsheet row in millimeters (0.178in)
+const ODF_DEFAULT_ROW_HEIGHT_MM: f32 = 4.52;
+
+/// Spreadsheets estimated to print on more pages than this are scaled
+/// to fit the page width, set with --fit-wide-spreadsheets
+#[derive(Debug, Clone, Copy)]
+pub struct FitWideSpreadsheets(pub Option<u64>);
+
+/// Printed size of a sheet estimated from its used range and page setup
+#[derive(Debug, Clone, Copy)]
+struct SheetEstimate {
+    /// Number of pages the columns of the sheet are split across
+    pages_across: u64,
+    /// Number of pages the rows of the sheet are split across
+    pages_down: u64,
+}
+
+impl SheetEstimate {
+    /// Estimates the pages for content of the provided size printed onto the
+    /// printable area of a page, sizes are in millimeters
+    fn new(content: (f32, f32), printable: (f32, f32), scale: f32) -> Option<Self> {
+        let (width, height) = (content.0 * scale, content.1 * scale);
+        let (page_width, page_height) = printable;
+
+        if !(page_width > 0.0 && page_height > 0.0 && width > 0.0 && height > 0.0) {
+            return None;
+        }
+
+        Some(Self {
+            pages_across: (width / page_width).ceil() as u64,
+            pages_down: (height / page_height).ceil() as u64,
+        })
+    }
+
+    fn pages(&self) -> u64 {
+        self.pages_across.saturating_mul(self.pages_down)
+    }
+
+    /// Whether scaling the sheet to fit the page width reduces its pages
+    fn is_wide(&self) -> bool {
+        self.pages_across > 1
+    }
+}
+
+/// Spreadsheet formats that can be scaled to fit the page width
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SpreadsheetFormat {
+    /// Excel workbook (.xlsx)
+    Xlsx,
+    /// OpenDocument spreadsheet (.ods)
+    OpenDocument,
+}
+
+impl SpreadsheetFormat {
+    /// Detects the format of the spreadsheet archive
+    fn detect<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<Self> {
+        match known_extension(detect_archive(archive)?)? {
+            "xlsx" => Some(SpreadsheetFormat::Xlsx),
+            "ods" => Some(SpreadsheetFormat::OpenDocument),
+            _ => None,
+        }
+    }
+}
+
+/// Scales the sheets of a spreadsheet that would print on more than the
+/// provided number of pages to fit the page width, the number of pages is
+/// estimated from the used range, column widths and page setup of each sheet.
+/// Only sheets wider than a page are scaled and sheets already scaled to fit
+/// a number of pages are left unchanged. Provides a warning describing the
+/// scaling when any sheets were scaled, files that aren't an Excel or
+/// OpenDocument spreadsheet are left unchanged
+pub fn fit_wide_spreadsheet(
+    input: Bytes,
+    max_pages: u64,
+) -> Result<(Bytes, Option<String>), PageStyleError> {
+    // Archives that can't be read are left for office to handle
+    let mut archive = match open_archive(&input) {
+        Some(value) => value,
+        None => return Ok((input, None)),
+    };
+
+    let (parts, estimated_pages, scaled) = match SpreadsheetFormat::detect(&mut archive) {
+        Some(SpreadsheetFormat::Xlsx) => fit_xlsx(&mut archive, max_pages),
+        Some(SpreadsheetFormat::OpenDocument) => fit_ods(&mut archive, max_pages),
+        None => return Ok((input, None)),
+    };
+
+    if parts.is_empty() {
+        return Ok((input, None));
+    }
+
+    let output = replace_parts(&mut archive, parts)?;
+    let warning = format!(
+        "spreadsheet would print on an estimated {estimated_pages} pages, {scaled} wide {} scaled to fit the page width",
+        if scaled == 1 { "sheet was" } else { "sheets were" }
+    );
+
+    Ok((output, Some(warning)))
+}
+
+/// Scales the wide worksheets of an Excel workbook, provides the rewritten
+/// worksheets along with the estimated pages of the workbook and the
+/// number of worksheets scaled. No worksheets are rewritten when the
+/// workbook is estimated to fit within the maximum pages
+fn fit_xlsx(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    max_pages: u64,
+) -> (HashMap<String, String>, u64, usize) {
+    let names: Vec<String> = archive
+        .file_names()
+        .filter(|name| name.starts_with("xl/worksheets/") && name.ends_with(".xml"))
+        .map(str::to_string)
+        .collect();
+
+    let mut estimated_pages: u64 = 0;
+    let mut wide = Vec::new();
+
+    for name in names {
+        // Parts that can't be read (i.e too large) are left unchanged
+        let Ok(xml) = read_part(archive, &name) else {
+            continue;
+        };
+
+        let Some(estimate) = estimate_worksheet(&xml) else {
+            continue;
+        };
+
+        estimated_pages = estimated_pages.saturating_add(estimate.pages());
+
+        if estimate.is_wide() {
+            wide.push((name, xml));
+        }
+    }
+
+    if estimated_pages <= max_pages {
+        return (HashMap::new(), estimated_pages, 0);
+    }
+
+    let scaled = wide.len();
+    let parts = wide
+        .into_iter()
+        .map(|(name, xml)| (name, fit_worksheet(&xml)))
+        .collect();
+
+    (parts, estimated_pages, scaled)
+}
+
+/// Estimates the printed pages of an Excel worksheet, provides [None] when
+/// the worksheet is empty or already scaled to fit a number of pages
+fn estimate_worksheet(xml: &str) -> Option<SheetEstimate> {
+    let already_fitted = find_tag(xml, "pageSetUpPr", 0).is_some_and(|tag| {
+        matches!(
+            get_attribute(&tag.attributes, "fitToPage"),
+            Some("1" | "true")
+        )
+    });
+
+    if already_fitted {
+        return None;
+    }
+
+    // Used range of the worksheet (i.e "A1:AZ5000")
+    let dimension = find_tag(xml, "dimension", 0)?;
+    let range = get_attribute(&dimension.attributes, "ref")?;
+    let last_cell = range.rsplit(':').next()?;
+    let (columns, rows) = parse_cell_reference(last_cell)?;
+
+    let format = find_tag(xml, "sheetFormatPr", 0).map(|tag| tag.attributes);
+    let format_number =
+        |name: &str| parse_number(format.as_ref()?, name).filter(|value| *value >= 0.0);
+
+    let default_column_px = match format_number("defaultColWidth") {
+        Some(width) => width * EXCEL_CHARACTER_PX,
+        None => {
+            let base = format_number("baseColWidth").unwrap_or(8.0);
+            base * EXCEL_CHARACTER_PX + EXCEL_COLUMN_PADDING_PX
+        }
+    };
+    let row_height_pt = format_number("defaultRowHeight").unwrap_or(15.0);
+
+    // Widths of the columns with a custom width
+    let mut column_px = vec![default_column_px; columns as usize];
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, "col", position) {
+        position = tag.end;
+
+        let (Some(min), Some(max)) = (
+            parse_number(&tag.attributes, "min"),
+            parse_number(&tag.attributes, "max"),
+        ) else {
+            continue;
+        };
+
+        let hidden = matches!(get_attribute(&tag.attributes, "hidden"), Some("1" | "true"));
+        let width = if hidden {
+            0.0
+        } else {
+            match parse_number(&tag.attributes, "width") {
+                Some(width) => width * EXCEL_CHARACTER_PX,
+                None => continue,
+            }
+        };
+
+        let start = (min.max(1.0) as usize) - 1;
+        let end = (max as usize).min(column_px.len());
+        for column in column_px.iter_mut().take(end).skip(start) {
+            *column = width;
+        }
+    }
+
+    let width_mm = column_px.iter().sum::<f32>() * MM_PER_INCH / 96.0;
+    let height_mm = rows as f32 * row_height_pt * MM_PER_INCH / 72.0;
+
+    let setup = find_tag(xml, "pageSetup", 0).map(|tag| tag.attributes);
+    let setup = setup.as_ref();
+
+    let (mut page_width, mut page_height) = excel_paper_size_mm(
+        setup
+            .and_then(|setup| get_attribute(setup, "paperSize"))
+            .unwrap_or("1"),
+    );
+
+    if setup.and_then(|setup| get_attribute(setup, "orientation")) == Some("landscape") {
+        std::mem::swap(&mut page_width, &mut page_height);
+    }
+
+    let scale = setup
+        .and_then(|setup| parse_number(setup, "scale"))
+        .filter(|scale| *scale > 0.0)
+        .unwrap_or(100.0)
+        / 100.0;
+
+    // Margins are in inches
+    let margins = find_tag(xml, "pageMargins", 0).map(|tag| tag.attributes);
+    let margin = |name: &str, default: f32| {
+        margins
+            .as_ref()
+            .and_then(|margins| parse_number(margins, name))
+            .unwrap_or(default)
+            * MM_PER_INCH
+    };
+
+    let printable = (
+        page_width - margin("left", 0.7) - margin("right", 0.7),
+        page_height - margin("top", 0.75) - margin("bottom", 0.75),
+    );
+
+    SheetEstimate::new((width_mm, height_mm), printable, scale)
+}
+
+/// Parses a cell reference (i.e "AB12") into its 1 based column and row
+fn parse_cell_reference(reference: &str) -> Option<(u32, u32)> {
+    let reference = reference.replace('$', "");
+    let split = reference.find(|value: char| value.is_ascii_digit())?;
+    let (letters, digits) = reference.split_at(split);
+
+    if letters.is_empty() || letters.len() > 3 {
+        return None;
+    }
+
+    let column = letters.bytes().try_fold(0u32, |column, letter| {
+        letter
+            .is_ascii_alphabetic()
+            .then(|| column * 26 + (letter.to_ascii_uppercase() - b'A') as u32 + 1)
+    })?;
+    let row = digits.parse().ok()?;
+
+    Some((column, row))
+}
+
+/// Portrait dimensions in millimeters of an Excel paper size code, unknown
+/// codes are treated as Letter (the default paper size)
+fn excel_paper_size_mm(code: &str) -> (f32, f32) {
+    match code {
+        "5" => (215.9, 355.6),
+        "8" => (297.0, 420.0),
+        "9" => (210.0, 297.0),
+        "11" => (148.0, 210.0),
+        _ => (215.9, 279.4),
+    }
+}
+
+/// Scales an Excel worksheet to fit one page wide with any number of pages
+/// tall, inserting the page setup elements when the worksheet doesn't have them
+fn fit_worksheet(xml: &str) -> String {
+    const FIT_TO_PAGE: &str = r#"<pageSetUpPr fitToPage="1"/>"#;
+
+    let mut xml = match find_tag(xml, "sheetPr", 0) {
+        // Properties must be the first element of the worksheet
+        None => match find_tag(xml, "worksheet", 0) {
+            Some(worksheet) => format!(
+                "{}<sheetPr>{FIT_TO_PAGE}</sheetPr>{}",
+                &xml[..worksheet.end],
+                &xml[worksheet.end..]
+            ),
+            None => return xml.to_string(),
+        },
+        Some(properties) if properties.self_closing => {
+            let start = xml[properties.start..properties.end - 2].trim_end();
+            format!(
+                "{}{start}>{FIT_TO_PAGE}</sheetPr>{}",
+                &xml[..properties.start],
+                &xml[properties.end..]
+            )
+        }
+        Some(_) if find_tag(xml, "pageSetUpPr", 0).is_some() => {
+            rewrite_tags(xml, "pageSetUpPr", |attributes| {
+                set_attribute(attributes, "fitToPage", "1".to_string());
+            })
+        }
+        // Page setup properties are the last of the sheet properties
+        Some(_) => match xml.find("</sheetPr>") {
+            Some(end) => format!("{}{FIT_TO_PAGE}{}", &xml[..end], &xml[end..]),
+            None => return xml.to_string(),
+        },
+    };
+
+    if find_tag(&xml, "pageSetup", 0).is_none() {
+        xml = insert_before(
+            &xml,
+            WORKSHEET_ELEMENTS_AFTER_PAGE_SETUP,
+            "</worksheet>",
+            "<pageSetup/>",
+        );
+    }
+
+    rewrite_tags(&xml, "pageSetup", |attributes| {
+        set_attribute(attributes, "fitToWidth", "1".to_string());
+        set_attribute(attributes, "fitToHeight", "0".to_string());
+    })
+}
+
+/// Scales an OpenDocument spreadsheet to fit the page width, provides the
+/// rewritten page styles along with the estimated pages of the spreadsheet
+/// and the number of wide sheets. Sheets share page styles so every page
+/// style is scaled, office doesn't enlarge sheets narrower than the page
+fn fit_ods(
+    archive: &mut ZipArchive<Cursor<&[u8]>>,
+    max_pages: u64,
+) -> (HashMap<String, String>, u64, usize) {
+    let (Ok(content), Ok(styles)) = (
+        read_part(archive, "content.xml"),
+        read_part(archive, "styles.xml"),
+    ) else {
+        return (HashMap::new(), 0, 0);
+    };
+
+    // Page setup of the default page style
+    let Some(layout) = find_tag(&styles, "style:page-layout-properties", 0) else {
+        return (HashMap::new(), 0, 0);
+    };
+    let layout = layout.attributes;
+
+    let already_fitted = [
+        "style:scale-to-X",
+        "style:scale-to-pages",
+        "loext:scale-to-X",
+    ]
+    .iter()
+    .any(|name| get_attribute(&layout, name).is_some());
+
+    if already_fitted {
+        return (HashMap::new(), 0, 0);
+    }
+
+    let length = |name: &str| get_attribute(&layout, name).and_then(parse_length_mm);
+    let margin = |side: &str| length(side).or_else(|| length("fo:margin")).unwrap_or(20.0);
+
+    let printable = (
+        length("fo:page-width").unwrap_or(210.0)
+            - margin("fo:margin-left")
+            - margin("fo:margin-right"),
+        length("fo:page-height").unwrap_or(297.0)
+            - margin("fo:margin-top")
+            - margin("fo:margin-bottom"),
+    );
+
+    let scale = get_attribute(&layout, "style:scale-to")
+        .and_then(|value| value.trim_end_matches('%').parse::<f32>().ok())
+        .filter(|scale| *scale > 0.0)
+        .unwrap_or(100.0)
+        / 100.0;
+
+    let column_widths = odf_column_widths(&content);
+    let mut estimated_pages: u64 = 0;
+    let mut wide = 0;
+    let mut position = 0;
+
+    while let Some(table) = find_tag(&content, "table:table", position) {
+        let end = if table.self_closing {
+            table.end
+        } else {
+            content[table.end..]
+                .find("</table:table>")
+                .map_or(content.len(), |end| table.end + end)
+        };
+        position = end;
+
+        let Some(content_size) = odf_table_size(&content[table.end..end], &column_widths) else {
+            continue;
+        };
+
+        let Some(estimate) = SheetEstimate::new(content_size, printable, scale) else {
+            continue;
+        };
+
+        estimated_pages = estimated_pages.saturating_add(estimate.pages());
+
+        if estimate.is_wide() {
+            wide += 1;
+        }
+    }
+
+    if estimated_pages <= max_pages || wide == 0 {
+        return (HashMap::new(), estimated_pages, 0);
+    }
+
+    let styles = rewrite_tags(&styles, "style:page-layout-properties", |attributes| {
+        // Fixed scale would otherwise take priority
+        remove_attribute(attributes, "style:scale-to");
+        set_attribute(attributes, "style:scale-to-X", "1".to_string());
+        set_attribute(attributes, "style:scale-to-Y", "0".to_string());
+    });
+
+    (
+        HashMap::from([("styles.xml".to_string(), styles)]),
+        estimated_pages,
+        wide,
+    )
+}
+
+/// Widths in millimeters of the column styles of an OpenDocument spreadsheet
+fn odf_column_widths(content: &str) -> HashMap<String, f32> {
+    let mut widths = HashMap::new();
+    let mut position = 0;
+
+    while let Some(properties) = find_tag(content, "style:table-column-properties", position) {
+        position = properties.end;
+
+        // Properties belong to the style they are nested in
+        let style = content[..properties.start]
+            .rfind("<style:style")
+            .and_then(|start| find_tag(content, "style:style", start));
+
+        let name = style
+            .as_ref()
+            .and_then(|style| get_attribute(&style.attributes, "style:name"));
+        let width =
+            get_attribute(&properties.attributes, "style:column-width").and_then(parse_length_mm);
+
+        if let (Some(name), Some(width)) = (name, width) {
+            widths.insert(name.to_string(), width);
+        }
+    }
+
+    widths
+}
+
+/// Size in millimeters (width, height) of the used range of an OpenDocument
+/// table, provides [None] when the table is empty. Rows are assumed to be the
+/// default height
+fn odf_table_size(table: &str, column_widths: &HashMap<String, f32>) -> Option<(f32, f32)> {
+    let mut used_rows: u64 = 0;
+    let mut used_columns: u64 = 0;
+    let mut row_index: u64 = 0;
+    let mut position = 0;
+
+    while let Some(row) = find_tag(table, "table:table-row", position) {
+        let repeated = repeat_count(&row.attributes, "table:number-rows-repeated");
+
+        let end = if row.self_closing {
+            row.end
+        } else {
+            table[row.end..]
+                .find("</table:table-row>")
+                .map_or(table.len(), |end| row.end + end)
+        };
+        position = end;
+
+        let row_columns = odf_row_used_columns(&table[row.end..end]);
+        if row_columns > 0 {
+            used_rows = row_index.saturating_add(repeated);
+            used_columns = used_columns.max(row_columns);
+        }
+
+        row_index = row_index.saturating_add(repeated);
+    }
+
+    if used_rows == 0 || used_columns == 0 {
+        return None;
+    }
+
+    // Widths of the used columns, columns without a style are the default width
+    let mut width = 0.0;
+    let mut column_index: u64 = 0;
+    let mut position = 0;
+
+    while let Some(column) = find_tag(table, "table:table-column", position) {
+        position = column.end;
+
+        if column_index >= used_columns {
+            break;
+        }
+
+        let repeated = repeat_count(&column.attributes, "table:number-columns-repeated")
+            .min(used_columns - column_index);
+        let column_width = get_attribute(&column.attributes, "table:style-name")
+            .and_then(|name| column_widths.get(name).copied())
+            .unwrap_or(ODF_DEFAULT_COLUMN_WIDTH_MM);
+        let hidden = matches!(
+            get_attribute(&column.attributes, "table:visibility"),
+            Some("collapse" | "filter")
+        );
+
+        if !hidden {
+            width += column_width * repeated as f32;
+        }
+
+        column_index += repeated;
+    }
+
+    if column_index < used_columns {
+        width += (used_columns - column_index) as f32 * ODF_DEFAULT_COLUMN_WIDTH_MM;
+    }
+
+    Some((width, used_rows as f32 * ODF_DEFAULT_ROW_HEIGHT_MM))
+}
+
+/// Number of columns up to the last cell with content in a row of an
+/// OpenDocument table
+fn odf_row_used_columns(row: &str) -> u64 {
+    let mut used: u64 = 0;
+    let mut column: u64 = 0;
+    let mut position = 0;
+
+    while let Some(cell) = next_cell(row, position) {
+        let repeated = repeat_count(&cell.attributes, "table:number-columns-repeated");
+
+        let (has_content, end) = if cell.self_closing {
+            (
+                get_attribute(&cell.attributes, "office:value-type").is_some(),
+                cell.end,
+            )
+        } else {
+            let end = row[cell.end..]
+                .find("</table:")
+                .map_or(row.len(), |end| cell.end + end);
+            (!row[cell.end..end].trim().is_empty(), end)
+        };
+        position = end;
+
+        column = column.saturating_add(repeated);
+        if has_content {
+            used = column;
+        }
+    }
+
+    used
+}
+
+/// Finds the next cell (covered cells of merged ranges included) in a row
+fn next_cell(row: &str, position: usize) -> Option<Tag> {
+    let cell = find_tag(row, "table:table-cell", position);
+    let covered = find_tag(row, "table:covered-table-cell", position);
+
+    match (cell, covered) {
+        (Some(cell), Some(covered)) if covered.start < cell.start => Some(covered),
+        (Some(cell), _) => Some(cell),
+        (None, covered) => covered,
+    }
+}
+
+/// Number of times a row or column is repeated
+fn repeat_count(attributes: &Attributes, name: &str) -> u64 {
+    get_attribute(attributes, name)
+        .and_then(|value| value.parse().ok())
+        .filter(|value| *value > 0)
+        .unwrap_or(1)
+}
@@ -0,0 +1,151 @@
+use anyhow::Context;
+use async_trait::async_trait;
+use bytes::Bytes;
+use clap::ValueEnum;
+use office_convert_core::TempFile;
+use parking_lot::Mutex;
+use std::{collections::HashMap, io::ErrorKind, path::PathBuf, sync::Arc};
+
+/// Extension of the file values are written to before being moved into place
+const PARTIAL_EXTENSION: &str = "partial";
+
+/// Backend the server stores job files and retained results in
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StorageKind {
+    /// Files within a directory on the local filesystem (or a mounted tmpfs
+    /// or network filesystem)
+    #[default]
+    Local,
+    /// Memory of the server process, nothing is written to disk and nothing
+    /// survives a restart
+    Memory,
+}
+
+/// Storage for the files the server keeps between requests (i.e job inputs,
+/// job results and retained results) keyed by name. Keys only contain
+/// alphanumeric characters, dots, dashes and underscores
+#[async_trait]
+pub trait Storage: Send + Sync {
+    /// Stores the value replacing any existing value for the key, readers
+    /// never observe a partially written value
+    async fn put(&self, key: &str, value: Bytes) -> anyhow::Result<()>;
+
+    /// Gets the value for the key, provides [None] when there is no value
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>>;
+
+    /// Removes the value for the key (if it exists)
+    async fn delete(&self, key: &str);
+
+    /// Lists the keys of all the stored values
+    async fn keys(&self) -> anyhow::Result<Vec<String>>;
+}
+
+/// Creates storage of the provided kind, local storage writes to the provided
+/// directory creating it if it doesn't exist
+pub fn create_storage(
+    kind: StorageKind,
+    dir: PathBuf,
+    secure_delete: bool,
+) -> anyhow::Result<Arc<dyn Storage>> {
+    Ok(match kind {
+        StorageKind::Local => {
+            std::fs::create_dir_all(&dir).context("failed to create storage directory")?;
+            Arc::new(LocalStorage { dir, secure_delete })
+        }
+        StorageKind::Memory => Arc::new(MemoryStorage::default()),
+    })
+}
+
+/// Storage writing each value to a file within a directory
+pub struct LocalStorage {
+    /// Directory the files are written to
+    dir: PathBuf,
+    /// Whether to overwrite files before deleting them
+    secure_delete: bool,
+}
+
+#[async_trait]
+impl Storage for LocalStorage {
+    async fn put(&self, key: &str, value: Bytes) -> anyhow::Result<()> {
+        // Written to a temporary file first so a crash never leaves a partial file
+        let path = self.dir.join(key);
+        let partial_path = self.dir.join(format!("{key}.{PARTIAL_EXTENSION}"));
+
+        tokio::fs::write(&partial_path, value)
+            .await
+            .context("failed to write stored file")?;
+        tokio::fs::rename(&partial_path, &path)
+            .await
+            .context("failed to write stored file")?;
+
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        match tokio::fs::read(self.dir.join(key)).await {
+            Ok(value) => Ok(Some(Bytes::from(value))),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(anyhow::Error::new(err).context("failed to read stored file")),
+        }
+    }
+
+    async fn delete(&self, key: &str) {
+        let file = TempFile {
+            path: self.dir.join(key),
+        };
+        let secure = self.secure_delete;
+        _ = tokio::task::spawn_blocking(move || file.remove(secure)).await;
+    }
+
+    async fn keys(&self) -> anyhow::Result<Vec<String>> {
+        let mut entries = tokio::fs::read_dir(&self.dir)
+            .await
+            .context("failed to read storage directory")?;
+
+        let mut keys = Vec::new();
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .context("failed to read storage directory")?
+        {
+            let Ok(key) = entry.file_name().into_string() else {
+                continue;
+            };
+
+            // Files left partially written by a crash are not values
+            if !key.ends_with(&format!(".{PARTIAL_EXTENSION}")) {
+                keys.push(key);
+            }
+        }
+
+        Ok(keys)
+    }
+}
+
+/// Storage keeping the values in memory
+#[derive(Default)]
+pub struct MemoryStorage {
+    /// Stored values keyed by name
+    values: Mutex<HashMap<String, Bytes>>,
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn put(&self, key: &str, value: Bytes) -> anyhow::Result<()> {
+        self.values.lock().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<Bytes>> {
+        Ok(self.values.lock().get(key).cloned())
+    }
+
+    async fn delete(&self, key: &str) {
+        self.values.lock().remove(key);
+    }
+
+    async fn keys(&self) -> anyhow::Result<Vec<String>> {
+        Ok(self.values.lock().keys().cloned().collect())
+    }
+}
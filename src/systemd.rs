@@ -0,0 +1,233 @@
+//! Integration with systemd, listening on a socket passed by socket activation
+//! and notifying the service manager of readiness, reloads and watchdog pings.
+//! Everything is a no-op when the server isn't started by systemd
+
+use office_convert_core::{Converter, WorkerState};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Environment variables systemd uses to talk to the main server process,
+/// removed for processes started by the server so they don't notify
+/// systemd on behalf of the server
+pub const NOTIFY_VARIABLES: &[&str] = &["NOTIFY_SOCKET", "WATCHDOG_USEC", "WATCHDOG_PID"];
+
+/// Listening socket passed to the server by systemd socket activation
+pub enum ActivatedListener {
+    /// TCP socket (ListenStream=8080)
+    Tcp(std::net::TcpListener),
+    /// Unix domain socket (ListenStream=/run/office-convert.sock)
+    #[cfg(unix)]
+    Unix(std::os::unix::net::UnixListener),
+}
+
+/// Takes the listening socket passed by systemd socket activation, provides
+/// [None] when the server wasn't socket activated. Only the first socket is
+/// used when multiple are passed
+#[cfg(unix)]
+pub fn activated_listener() -> anyhow::Result<Option<ActivatedListener>> {
+    use anyhow::Context;
+    use std::os::fd::{FromRawFd, RawFd};
+
+    /// First file descriptor passed by systemd (SD_LISTEN_FDS_START)
+    const LISTEN_FDS_START: RawFd = 3;
+
+    // Sockets are only passed to the process systemd started
+    let for_process = std::env::var("LISTEN_PID")
+        .ok()
+        .and_then(|value| value.parse::<u32>().ok())
+        .is_some_and(|pid| pid == std::process::id());
+
+    if !for_process {
+        return Ok(None);
+    }
+
+    let count: u32 = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_default();
+
+    if count == 0 {
+        return Ok(None);
+    }
+
+    if count > 1 {
+        warn!(
+            count,
+            "systemd passed multiple sockets, only the first is used"
+        );
+    }
+
+    let fd = LISTEN_FDS_START;
+
+    // SAFETY: Only changes the flags of the descriptor, processes started by the
+    // server (engine workers and conversion processes) must not inherit the socket
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("invalid socket passed by systemd");
+    }
+
+    let listener = match socket_family(fd).context("invalid socket passed by systemd")? {
+        // SAFETY: Descriptor is a socket passed to this process that nothing else owns
+        libc::AF_UNIX => {
+            ActivatedListener::Unix(unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) })
+        }
+        libc::AF_INET | libc::AF_INET6 => {
+            ActivatedListener::Tcp(unsafe { std::net::TcpListener::from_raw_fd(fd) })
+        }
+        family => anyhow::bail!("unsupported socket family passed by systemd ({family})"),
+    };
+
+    debug!("using socket passed by systemd");
+
+    Ok(Some(listener))
+}
+
+/// Socket activation is only supported on unix platforms
+#[cfg(not(unix))]
+pub fn activated_listener() -> anyhow::Result<Option<ActivatedListener>> {
+    Ok(None)
+}
+
+/// Address family of a socket (i.e AF_INET)
+#[cfg(unix)]
+fn socket_family(fd: std::os::fd::RawFd) -> std::io::Result<libc::c_int> {
+    // SAFETY: Zeroed memory is a valid sockaddr_storage
+    let mut address: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+
+    // SAFETY: Address and length describe a buffer large enough for any address
+    let result = unsafe {
+        libc::getsockname(
+            fd,
+            (&mut address as *mut libc::sockaddr_storage).cast(),
+            &mut length,
+        )
+    };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(address.ss_family as libc::c_int)
+}
+
+/// Tells systemd the server is ready to accept connections
+pub fn notify_ready() {
+    notify("READY=1");
+}
+
+/// Tells systemd the server is reloading its config, [notify_ready] must be
+/// called once the reload has finished
+pub fn notify_reloading() {
+    notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()));
+}
+
+/// Sends a notification to the socket systemd provided in NOTIFY_SOCKET,
+/// failures are logged as systemd waiting on the server can't be recovered
+#[cfg(unix)]
+fn notify(state: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    if let Err(cause) = send_notification(&path, state) {
+        warn!(%cause, "failed to notify systemd");
+    }
+}
+
+/// Notifications are only supported on unix platforms
+#[cfg(not(unix))]
+fn notify(_state: &str) {}
+
+/// Sends the notification datagram, paths starting with "@" are
+/// abstract socket addresses
+#[cfg(unix)]
+fn send_notification(path: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        use std::os::{linux::net::SocketAddrExt, unix::net::SocketAddr};
+
+        let address = SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state.as_bytes(), &address)?;
+        return Ok(());
+    }
+
+    socket.send_to(state.as_bytes(), path)?;
+    Ok(())
+}
+
+/// Current time of the monotonic clock systemd uses to order reloads
+#[cfg(unix)]
+fn monotonic_usec() -> u64 {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+
+    // SAFETY: Writes the time to the provided timespec
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) };
+
+    (time.tv_sec as u64) * 1_000_000 + (time.tv_nsec as u64) / 1_000
+}
+
+#[cfg(not(unix))]
+fn monotonic_usec() -> u64 {
+    0
+}
+
+/// Interval systemd expects watchdog pings at, [None] when the watchdog
+/// isn't enabled for this process
+fn watchdog_interval() -> Option<Duration> {
+    // Pings are only expected from the process the watchdog is for
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok() != Some(std::process::id()) {
+            return None;
+        }
+    }
+
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec))
+}
+
+/// Spawns a background task pinging the systemd watchdog at half the interval
+/// systemd expects, pings stop when the async runtime is stuck or the office
+/// runner has spent longer than `max_busy` on a single conversion or restart
+/// so that systemd restarts the server
+pub fn spawn_watchdog(office: Converter, max_busy: Option<Duration>) {
+    let Some(interval) = watchdog_interval() else {
+        return;
+    };
+
+    debug!(?interval, "pinging systemd watchdog");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval / 2);
+        let mut stuck = false;
+
+        loop {
+            ticker.tick().await;
+
+            let status = office.status();
+            let busy_for = status.since.elapsed();
+
+            if status.state != WorkerState::Idle && max_busy.is_some_and(|max| busy_for > max) {
+                if !stuck {
+                    warn!(
+                        state = ?status.state,
+                        job_id = ?status.job_id,
+                        ?busy_for,
+                        "office runner is stuck, stopped pinging systemd watchdog"
+                    );
+                    stuck = true;
+                }
+                continue;
+            }
+
+            stuck = false;
+            notify("WATCHDOG=1");
+        }
+    });
+}
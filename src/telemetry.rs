@@ -0,0 +1,159 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field::Empty, info_span, Instrument};
+
+/// Middleware wrapping each request in a span named after its route, the
+/// span continues the trace from the W3C "traceparent" header of the request
+/// when traces are exported
+pub async fn trace_request(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+
+    let span = info_span!(
+        "request",
+        otel.name = %format_args!("{method} {route}"),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %method,
+        http.route = %route,
+        http.response.status_code = Empty,
+    );
+
+    #[cfg(feature = "otel")]
+    otel::set_parent(&span, request.headers());
+
+    let response = next.run(request).instrument(span.clone()).await;
+    let status = response.status();
+
+    span.record("http.response.status_code", status.as_u16());
+
+    if status.is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    response
+}
+
+#[cfg(feature = "otel")]
+pub use otel::{inject_context, otel_layer};
+
+#[cfg(feature = "otel")]
+mod otel {
+    use anyhow::Context;
+    use axum::http::{HeaderMap, HeaderName, HeaderValue};
+    use opentelemetry::{
+        global,
+        propagation::{Extractor, Injector},
+        trace::TracerProvider as _,
+        KeyValue,
+    };
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{propagation::TraceContextPropagator, runtime, trace, Resource};
+    use tracing::{Span, Subscriber};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{registry::LookupSpan, Layer};
+
+    /// Service name reported when "OTEL_SERVICE_NAME" isn't set
+    const SERVICE_NAME: &str = "office-convert-server";
+
+    /// Environment variables that configure the OTLP endpoint
+    const ENDPOINT_VARIABLES: [&str; 2] = [
+        "OTEL_EXPORTER_OTLP_ENDPOINT",
+        "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+    ];
+
+    /// Creates the layer exporting spans to an OpenTelemetry collector over
+    /// OTLP (HTTP), provides [None] when no endpoint is configured. The standard
+    /// "OTEL_EXPORTER_OTLP_*" environment variables take priority over the
+    /// provided endpoint
+    pub fn otel_layer<S>(endpoint: Option<String>) -> anyhow::Result<Option<impl Layer<S>>>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        let from_env = ENDPOINT_VARIABLES
+            .iter()
+            .any(|name| std::env::var_os(name).is_some());
+
+        if endpoint.is_none() && !from_env {
+            return Ok(None);
+        }
+
+        let mut exporter = SpanExporter::builder().with_http();
+        if let Some(endpoint) = endpoint {
+            exporter = exporter.with_endpoint(endpoint);
+        }
+
+        let exporter = exporter.build().context("failed to create otlp exporter")?;
+
+        let service_name =
+            std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| SERVICE_NAME.to_string());
+        let resource = Resource::default().merge(&Resource::new([KeyValue::new(
+            "service.name",
+            service_name,
+        )]));
+
+        let provider = trace::TracerProvider::builder()
+            .with_batch_exporter(exporter, runtime::Tokio)
+            .with_resource(resource)
+            .build();
+
+        let tracer = provider.tracer(SERVICE_NAME);
+        global::set_tracer_provider(provider);
+
+        // Trace context is accepted from and forwarded using the W3C headers
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+    }
+
+    /// Continues the trace from the trace context headers of a request
+    pub(super) fn set_parent(span: &Span, headers: &HeaderMap) {
+        let parent = global::get_text_map_propagator(|propagator| {
+            propagator.extract(&HeaderExtractor(headers))
+        });
+
+        span.set_parent(parent);
+    }
+
+    /// Adds the trace context headers for the current span to the headers
+    /// of a request forwarded to another server
+    pub fn inject_context(headers: &mut HeaderMap) {
+        let context = Span::current().context();
+
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(headers))
+        });
+    }
+
+    /// Reads trace context from request headers
+    struct HeaderExtractor<'a>(&'a HeaderMap);
+
+    impl Extractor for HeaderExtractor<'_> {
+        fn get(&self, key: &str) -> Option<&str> {
+            self.0.get(key).and_then(|value| value.to_str().ok())
+        }
+
+        fn keys(&self) -> Vec<&str> {
+            self.0.keys().map(HeaderName::as_str).collect()
+        }
+    }
+
+    /// Writes trace context to request headers
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (HeaderName::try_from(key), HeaderValue::try_from(value))
+            {
+                self.0.insert(name, value);
+            }
+        }
+    }
+}
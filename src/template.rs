@@ -0,0 +1,462 @@
+use crate::{
+    compression::decompress_file,
+    error::{DynHttpError, HttpError},
+    failures::FailureInput,
+    tenant::{Tenant, Tenants},
+    ws::MaxFileSize,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    http::{header, HeaderValue, Response, StatusCode},
+    Extension,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use office_convert_core::Converter;
+use serde_json::Value;
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    io::{Cursor, Read, Write},
+    sync::Arc,
+};
+use thiserror::Error;
+use zip::{result::ZipError, write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+/// Errors that can occur while rendering a template
+#[derive(Debug, Error)]
+pub enum TemplateError {
+    /// Template is not a DOCX, XLSX or PPTX document
+    #[error("template must be a docx, xlsx or pptx document")]
+    UnsupportedTemplate,
+
+    /// Template archive could not be read
+    #[error("template is not a valid document: {0}")]
+    InvalidTemplate(#[from] ZipError),
+
+    /// Template part could not be read
+    #[error("failed to read template: {0}")]
+    ReadTemplate(#[from] std::io::Error),
+
+    /// Provided data was not a JSON object
+    #[error("data must be a JSON object")]
+    InvalidData,
+
+    /// Template contained placeholders that were not present in the data
+    #[error("missing values for placeholders: {}", .0.join(", "))]
+    MissingValues(Vec<String>),
+}
+
+impl HttpError for TemplateError {
+    fn status(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            TemplateError::MissingValues(_) => Some("TEMPLATE_MISSING_VALUES"),
+            _ => None,
+        }
+    }
+}
+
+/// XML elements text content is stored within for a document format
+struct TextTags {
+    /// Element containing text
+    text: &'static str,
+    /// Element grouping text elements (Placeholders can span text
+    /// elements within the same group)
+    group: &'static str,
+}
+
+/// Word processing documents (DOCX)
+const WORD_TAGS: TextTags = TextTags {
+    text: "w:t",
+    group: "w:p",
+};
+
+/// Presentations (PPTX)
+const SLIDE_TAGS: TextTags = TextTags {
+    text: "a:t",
+    group: "a:p",
+};
+
+/// Spreadsheet shared strings (XLSX)
+const SHEET_TAGS: TextTags = TextTags {
+    text: "t",
+    group: "si",
+};
+
+/// Determines the text tags to use for the part of the document
+/// at the provided path, [None] for parts without text content
+fn text_tags(path: &str) -> Option<&'static TextTags> {
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let in_dir = |dir: &str| {
+        path.strip_prefix(dir)
+            .is_some_and(|rest| !rest.contains('/'))
+    };
+
+    if !file_name.ends_with(".xml") {
+        return None;
+    }
+
+    if in_dir("word/")
+        && (file_name == "document.xml"
+            || file_name.starts_with("header")
+            || file_name.starts_with("footer")
+            || file_name == "footnotes.xml"
+            || file_name == "endnotes.xml")
+    {
+        return Some(&WORD_TAGS);
+    }
+
+    if in_dir("ppt/slides/") || in_dir("ppt/notesSlides/") {
+        return Some(&SLIDE_TAGS);
+    }
+
+    if path == "xl/sharedStrings.xml" {
+        return Some(&SHEET_TAGS);
+    }
+
+    None
+}
+
+/// Renders the template document replacing placeholders (i.e `{{customer.name}}`)
+/// with values from the provided data
+pub fn render_template(template: &[u8], data: &Value) -> Result<Vec<u8>, TemplateError> {
+    let mut archive = ZipArchive::new(Cursor::new(template))?;
+
+    let is_ooxml = [
+        "word/document.xml",
+        "xl/workbook.xml",
+        "ppt/presentation.xml",
+    ]
+    .iter()
+    .any(|name| archive.index_for_name(name).is_some());
+
+    if !is_ooxml {
+        return Err(TemplateError::UnsupportedTemplate);
+    }
+
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut missing = BTreeSet::new();
+
+    for index in 0..archive.len() {
+        let tags = {
+            let entry = archive.by_index_raw(index)?;
+            text_tags(entry.name())
+        };
+
+        let tags = match tags {
+            Some(value) => value,
+            // Parts without text are copied as-is
+            None => {
+                let entry = archive.by_index_raw(index)?;
+                writer.raw_copy_file(entry)?;
+                continue;
+            }
+        };
+
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name().to_string();
+        let mut xml = String::new();
+        entry.read_to_string(&mut xml)?;
+
+        let xml = replace_placeholders(&xml, tags, data, &mut missing);
+
+        writer.start_file(name, options)?;
+        writer.write_all(xml.as_bytes())?;
+    }
+
+    if !missing.is_empty() {
+        return Err(TemplateError::MissingValues(missing.into_iter().collect()));
+    }
+
+    let cursor = writer.finish()?;
+    Ok(cursor.into_inner())
+}
+
+/// Text element within an XML part
+struct TextNode {
+    /// Position of the end of the opening tag ('>')
+    open_end: usize,
+    /// Start position of the text content
+    start: usize,
+    /// End position of the text content
+    end: usize,
+    /// Index of the group the text belongs to
+    group: usize,
+    /// Decoded text content
+    text: String,
+    /// Whether the text has been modified
+    modified: bool,
+}
+
+/// Finds the text elements within the XML
+fn find_text_nodes(xml: &str, tags: &TextTags) -> Vec<TextNode> {
+    let mut nodes = Vec::new();
+    let mut group = 0;
+    let mut position = 0;
+
+    while let Some(offset) = xml[position..].find('<') {
+        let tag_start = position + offset;
+        let tag_end = match xml[tag_start..].find('>') {
+            Some(value) => tag_start + value,
+            None => break,
+        };
+        position = tag_end + 1;
+
+        let tag = &xml[tag_start + 1..tag_end];
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(value) => (true, value),
+            None => (false, tag),
+        };
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .split(|value: char| value.is_whitespace() || value == '/')
+            .next()
+            .unwrap_or_default();
+
+        if closing && name == tags.group {
+            group += 1;
+            continue;
+        }
+
+        if closing || self_closing || name != tags.text {
+            continue;
+        }
+
+        let close_tag = format!("</{}>", tags.text);
+        let end = match xml[position..].find(&close_tag) {
+            Some(value) => position + value,
+            None => break,
+        };
+
+        nodes.push(TextNode {
+            open_end: tag_end,
+            start: position,
+            end,
+            group,
+            text: html_escape::decode_html_entities(&xml[position..end]).into_owned(),
+            modified: false,
+        });
+
+        position = end + close_tag.len();
+    }
+
+    nodes
+}
+
+/// Replaces the placeholders within the text of the provided XML part,
+/// placeholder paths that are not present in the data are added to `missing`
+fn replace_placeholders(
+    xml: &str,
+    tags: &TextTags,
+    data: &Value,
+    missing: &mut BTreeSet<String>,
+) -> String {
+    let mut nodes = find_text_nodes(xml, tags);
+
+    // Placeholders can be split across multiple text elements (i.e when part
+    // of a placeholder has different formatting) so each group is joined
+    let mut group_start = 0;
+    while group_start < nodes.len() {
+        let group = nodes[group_start].group;
+        let group_end = nodes[group_start..]
+            .iter()
+            .position(|node| node.group != group)
+            .map(|value| group_start + value)
+            .unwrap_or(nodes.len());
+
+        replace_group(&mut nodes[group_start..group_end], data, missing);
+        group_start = group_end;
+    }
+
+    if !nodes.iter().any(|node| node.modified) {
+        return xml.to_string();
+    }
+
+    let mut output = String::with_capacity(xml.len());
+    let mut position = 0;
+
+    for node in nodes.iter().filter(|node| node.modified) {
+        let open_tag = &xml[position..node.open_end];
+        output.push_str(open_tag);
+
+        // Word trims whitespace from text unless its marked to be preserved
+        if tags.text == WORD_TAGS.text {
+            let tag_start = open_tag.rfind('<').unwrap_or_default();
+            if !open_tag[tag_start..].contains("xml:space") {
+                output.push_str(" xml:space=\"preserve\"");
+            }
+        }
+
+        output.push_str(&xml[node.open_end..node.start]);
+        output.push_str(&html_escape::encode_text(&node.text));
+        position = node.end;
+    }
+
+    output.push_str(&xml[position..]);
+    output
+}
+
+/// Replaces placeholders within a group of text elements
+fn replace_group(nodes: &mut [TextNode], data: &Value, missing: &mut BTreeSet<String>) {
+    let joined: String = nodes.iter().map(|node| node.text.as_str()).collect();
+
+    // Start offset of each node within the joined text
+    let mut offsets = Vec::with_capacity(nodes.len());
+    let mut offset = 0;
+    for node in nodes.iter() {
+        offsets.push(offset);
+        offset += node.text.len();
+    }
+
+    // Replaced in reverse so earlier positions remain valid
+    for (start, end, path) in find_placeholders(&joined).into_iter().rev() {
+        let value = match lookup(data, &path) {
+            Some(value) => value,
+            None => {
+                missing.insert(path);
+                continue;
+            }
+        };
+
+        let start_node = offsets
+            .iter()
+            .rposition(|&value| value <= start)
+            .unwrap_or(0);
+        let end_node = offsets.iter().rposition(|&value| value < end).unwrap_or(0);
+
+        let start_offset = start - offsets[start_node];
+        let end_offset = end - offsets[end_node];
+
+        if start_node == end_node {
+            let node = &mut nodes[start_node];
+            node.text.replace_range(start_offset..end_offset, &value);
+            node.modified = true;
+            continue;
+        }
+
+        // Remove the placeholder from the later nodes
+        for node in &mut nodes[start_node + 1..end_node] {
+            node.text.clear();
+            node.modified = true;
+        }
+
+        let node = &mut nodes[end_node];
+        node.text.replace_range(..end_offset, "");
+        node.modified = true;
+
+        let node = &mut nodes[start_node];
+        node.text.replace_range(start_offset.., &value);
+        node.modified = true;
+    }
+}
+
+/// Finds the placeholders within the text providing the start and end
+/// positions of the placeholder along with the value path
+fn find_placeholders(text: &str) -> Vec<(usize, usize, String)> {
+    let mut placeholders = Vec::new();
+    let mut position = 0;
+
+    while let Some(offset) = text[position..].find("{{") {
+        let start = position + offset;
+        let end = match text[start + 2..].find("}}") {
+            Some(value) => start + 2 + value + 2,
+            None => break,
+        };
+
+        let path = text[start + 2..end - 2].trim();
+        let is_valid = !path.is_empty()
+            && path
+                .chars()
+                .all(|value| value.is_alphanumeric() || matches!(value, '_' | '-' | '.'));
+
+        if is_valid {
+            placeholders.push((start, end, path.to_string()));
+            position = end;
+        } else {
+            position = start + 2;
+        }
+    }
+
+    placeholders
+}
+
+/// Looks up the value at the dot separated path (i.e `customer.name` or
+/// `items.0.price`) converting it to its text form
+fn lookup(data: &Value, path: &str) -> Option<String> {
+    let value = path.split('.').try_fold(data, |value, key| match value {
+        Value::Object(object) => object.get(key),
+        Value::Array(array) => array.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })?;
+
+    let text: Cow<str> = match value {
+        Value::Null => Cow::Borrowed(""),
+        Value::String(value) => Cow::Borrowed(value),
+        Value::Bool(value) => Cow::Owned(value.to_string()),
+        Value::Number(value) => Cow::Owned(value.to_string()),
+        // Objects and arrays are not valid placeholder values
+        Value::Object(_) | Value::Array(_) => return None,
+    };
+
+    Some(text.into_owned())
+}
+
+/// Request to render a template
+#[derive(TryFromMultipart)]
+pub struct RenderRequest {
+    /// The template document
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// JSON object containing the placeholder values
+    data: String,
+}
+
+/// POST /render
+///
+/// Replaces the placeholders within the provided template document using
+/// the provided data and converts the result to PDF format
+pub async fn render(
+    Extension(office): Extension<Converter>,
+    Extension(tenants): Extension<Arc<Tenants>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    Extension(failure_input): Extension<FailureInput>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    TypedMultipart(RenderRequest { file, data }): TypedMultipart<RenderRequest>,
+) -> Result<Response<Body>, DynHttpError> {
+    let file = decompress_file(file, max_file_size).await?;
+    failure_input.set_file(&file);
+
+    let data: Value = serde_json::from_str(&data).map_err(|_| TemplateError::InvalidData)?;
+    if !data.is_object() {
+        return Err(TemplateError::InvalidData.into());
+    }
+
+    let input_size = file.contents.len();
+    let document = tokio::task::spawn_blocking(move || render_template(&file.contents, &data))
+        .await
+        .context("failed to render template")??;
+
+    let converted = office.convert(Bytes::from(document)).await?;
+
+    if let Some(Extension(tenant)) = tenant {
+        tenants.record(&tenant.name, input_size, &converted);
+    }
+
+    // Build the response
+    let response = Response::builder()
+        .header(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/pdf"),
+        )
+        .body(Body::from(converted))
+        .context("failed to create response")?;
+
+    Ok(response)
+}
@@ -0,0 +1,501 @@
+use crate::error::{DynHttpError, HttpError};
+use anyhow::Context;
+use axum::{
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+    Extension, Json,
+};
+use office_convert_core::{page_count, Converter, Priority};
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    ffi::OsString,
+    future::Future,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use thiserror::Error;
+use tokio::sync::{Notify, Semaphore};
+use tracing::warn;
+
+/// Header clients provide their API key in
+const API_KEY_HEADER: &str = "x-api-key";
+
+/// Configuration for a tenant
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TenantConfig {
+    /// API keys that identify the tenant
+    pub api_keys: Vec<String>,
+    /// Maximum number of conversions the tenant can run at once
+    pub max_concurrent: Option<usize>,
+    /// Maximum number of conversions the tenant can have waiting
+    /// for a concurrency slot
+    pub max_queued: Option<usize>,
+    /// Maximum number of pages the tenant can convert per month
+    pub monthly_page_quota: Option<u64>,
+    /// Priority of conversions from the tenant, requests can lower
+    /// their priority but can't raise it above this
+    pub priority: Option<Priority>,
+    /// Engine used for conversions from the tenant when the request
+    /// doesn't select one, the default engine when not provided
+    pub engine: Option<String>,
+    /// Weight of the tenant when taking turns with other tenants for office,
+    /// a tenant with a weight of two is given twice as many turns as a tenant
+    /// with a weight of one. Defaults to one
+    pub weight: Option<u32>,
+}
+
+/// Usage recorded for a tenant
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TenantUsage {
+    /// Number of completed conversions
+    pub conversions: u64,
+    /// Total bytes of the input files
+    pub bytes_in: u64,
+    /// Total bytes of the converted files
+    pub bytes_out: u64,
+    /// Total pages of the converted files
+    pub pages: u64,
+}
+
+impl TenantUsage {
+    /// Adds the other usage to this usage
+    fn add(&mut self, other: &TenantUsage) {
+        self.conversions += other.conversions;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.pages += other.pages;
+    }
+}
+
+tokio::task_local! {
+    /// Usage recorded while an engine worker handles a request forwarded from
+    /// the main server, the usage is reported back to the main server instead
+    /// of being recorded by the worker
+    static FORWARDED_USAGE: RefCell<TenantUsage>;
+}
+
+/// Runs the future providing the usage recorded while it ran instead of
+/// recording it, used by engine workers to report usage to the main server
+pub async fn forward_usage<F>(future: F) -> (F::Output, TenantUsage)
+where
+    F: Future,
+{
+    FORWARDED_USAGE
+        .scope(RefCell::default(), async move {
+            let output = future.await;
+            let usage = FORWARDED_USAGE.with(|usage| usage.take());
+            (output, usage)
+        })
+        .await
+}
+
+/// Recorded usage, keyed by month ("YYYY-MM") then tenant name
+type UsageData = BTreeMap<String, BTreeMap<String, TenantUsage>>;
+
+/// Tenant identified from an API key
+pub struct Tenant {
+    /// Name of the tenant
+    pub name: String,
+    /// Tenant configuration
+    config: TenantConfig,
+    /// Semaphore limiting the number of concurrent conversions
+    semaphore: Option<Semaphore>,
+    /// Number of requests that are running or waiting to run
+    in_flight: AtomicUsize,
+}
+
+impl Tenant {
+    /// Priority of conversions from the tenant
+    pub fn priority(&self) -> Priority {
+        self.config.priority.unwrap_or_default()
+    }
+
+    /// Engine used for conversions from the tenant that don't select one
+    pub fn engine(&self) -> Option<&str> {
+        self.config.engine.as_deref()
+    }
+
+    /// Weight of the tenant when taking turns with other tenants
+    pub fn weight(&self) -> u32 {
+        self.config.weight.unwrap_or(1)
+    }
+}
+
+/// Collection of configured tenants along with their usage
+pub struct Tenants {
+    /// Tenants keyed by their API keys, replaced when the config is reloaded
+    by_key: RwLock<HashMap<String, Arc<Tenant>>>,
+    /// Recorded usage
+    usage: Mutex<UsageData>,
+    /// File to persist usage to
+    usage_file: Option<PathBuf>,
+    /// Notified when the usage changes so the usage writer persists it
+    usage_changed: Notify,
+}
+
+/// Errors that can occur when identifying a tenant
+#[derive(Debug, Error)]
+pub enum TenantError {
+    /// Request did not include a known API key
+    #[error("missing or invalid api key")]
+    InvalidApiKey,
+
+    /// Tenant has too many requests running or waiting
+    #[error("too many conversions in progress for tenant")]
+    QueueFull,
+
+    /// Tenant has converted their monthly page quota
+    #[error("monthly page quota exceeded")]
+    QuotaExceeded,
+}
+
+impl HttpError for TenantError {
+    fn status(&self) -> StatusCode {
+        match self {
+            TenantError::InvalidApiKey => StatusCode::UNAUTHORIZED,
+            TenantError::QueueFull | TenantError::QuotaExceeded => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            TenantError::InvalidApiKey => Some("INVALID_API_KEY"),
+            TenantError::QueueFull => Some("TENANT_QUEUE_FULL"),
+            TenantError::QuotaExceeded => Some("TENANT_QUOTA_EXCEEDED"),
+        }
+    }
+}
+
+/// Gets the current month in the "YYYY-MM" format (UTC)
+fn current_month() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|value| value.as_secs())
+        .unwrap_or_default();
+
+    // Civil date from days since the unix epoch (Howard Hinnant's algorithm)
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}")
+}
+
+impl Tenants {
+    /// Creates the tenants from their config, loading any persisted usage
+    pub fn new(
+        tenants: &HashMap<String, TenantConfig>,
+        usage_file: Option<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        let by_key = build_tenants(tenants, &HashMap::new())?;
+
+        let usage = match &usage_file {
+            Some(path) if path.exists() => {
+                let bytes = std::fs::read(path).context("failed to read usage file")?;
+                serde_json::from_slice(&bytes).context("failed to parse usage file")?
+            }
+            _ => UsageData::default(),
+        };
+
+        Ok(Self {
+            by_key: RwLock::new(by_key),
+            usage: Mutex::new(usage),
+            usage_file,
+            usage_changed: Notify::new(),
+        })
+    }
+
+    /// Whether any tenants are configured, API keys are only
+    /// required when tenants are configured
+    pub fn is_enabled(&self) -> bool {
+        !self.by_key.read().is_empty()
+    }
+
+    /// Number of configured tenants
+    pub fn count(&self) -> usize {
+        let by_key = self.by_key.read();
+        let mut names: Vec<&str> = by_key.values().map(|tenant| tenant.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        names.len()
+    }
+
+    /// Replaces the tenants with the tenants from the provided config. Tenants
+    /// whose config is unchanged keep their limits state, requests already
+    /// admitted continue using the tenant they were admitted as
+    pub fn reload(&self, tenants: &HashMap<String, TenantConfig>) -> anyhow::Result<()> {
+        let by_key = &mut *self.by_key.write();
+        *by_key = build_tenants(tenants, by_key)?;
+        Ok(())
+    }
+
+    /// Weight of the tenant with the provided name, one for unknown tenants
+    pub fn weight(&self, name: &str) -> u32 {
+        self.by_key
+            .read()
+            .values()
+            .find(|tenant| tenant.name == name)
+            .map_or(1, |tenant| tenant.weight())
+    }
+
+    /// Gets the pages converted by the tenant this month
+    fn monthly_pages(&self, tenant: &str) -> u64 {
+        self.usage
+            .lock()
+            .get(&current_month())
+            .and_then(|tenants| tenants.get(tenant))
+            .map(|usage| usage.pages)
+            .unwrap_or_default()
+    }
+
+    /// Records a completed conversion for the tenant
+    pub fn record(&self, tenant: &str, bytes_in: usize, output: &[u8]) {
+        let pages = page_count(output).unwrap_or_default();
+        self.record_conversion(tenant, bytes_in, output.len(), pages);
+    }
+
+    /// Records a completed conversion producing the provided number of
+    /// bytes and pages for the tenant
+    pub fn record_conversion(&self, tenant: &str, bytes_in: usize, bytes_out: usize, pages: u64) {
+        let usage = TenantUsage {
+            conversions: 1,
+            bytes_in: bytes_in as u64,
+            bytes_out: bytes_out as u64,
+            pages,
+        };
+
+        // Usage of forwarded requests is recorded by the main server
+        if FORWARDED_USAGE
+            .try_with(|forwarded| forwarded.borrow_mut().add(&usage))
+            .is_ok()
+        {
+            return;
+        }
+
+        self.record_usage(tenant, &usage);
+    }
+
+    /// Adds usage to the usage of the tenant for the current month, the
+    /// usage is persisted by the usage writer
+    pub fn record_usage(&self, tenant: &str, usage: &TenantUsage) {
+        self.usage
+            .lock()
+            .entry(current_month())
+            .or_default()
+            .entry(tenant.to_string())
+            .or_default()
+            .add(usage);
+
+        self.usage_changed.notify_one();
+    }
+}
+
+/// Spawns the background task persisting the usage to the usage file when
+/// it changes, usage recorded while the file is written is persisted by the
+/// next write
+pub fn spawn_usage_writer(tenants: Arc<Tenants>) {
+    let Some(usage_file) = tenants.usage_file.clone() else {
+        return;
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tenants.usage_changed.notified().await;
+
+            let data = match serde_json::to_vec(&*tenants.usage.lock()) {
+                Ok(value) => value,
+                Err(cause) => {
+                    warn!(%cause, "failed to serialize tenant usage");
+                    continue;
+                }
+            };
+
+            let usage_file = usage_file.clone();
+            let result = tokio::task::spawn_blocking(move || write_usage_file(&usage_file, &data))
+                .await
+                .context("failed to join usage writer")
+                .and_then(|result| result.context("failed to write usage file"));
+
+            if let Err(cause) = result {
+                warn!("failed to persist tenant usage: {cause:#}");
+            }
+        }
+    });
+}
+
+/// Writes the usage to a temporary file that is renamed over the usage file,
+/// so a crash while writing never leaves a truncated usage file
+fn write_usage_file(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut partial_path = OsString::from(path);
+    partial_path.push(".partial");
+
+    std::fs::write(&partial_path, data)?;
+    std::fs::rename(&partial_path, path)
+}
+
+/// Creates the tenants from their config keyed by their API keys, tenants
+/// from the existing tenants with the same name and config are reused
+fn build_tenants(
+    tenants: &HashMap<String, TenantConfig>,
+    existing: &HashMap<String, Arc<Tenant>>,
+) -> anyhow::Result<HashMap<String, Arc<Tenant>>> {
+    let mut by_key = HashMap::new();
+
+    for (name, config) in tenants {
+        if config.weight == Some(0) {
+            anyhow::bail!("weight for tenant \"{name}\" must be at least 1");
+        }
+
+        let tenant = existing
+            .values()
+            .find(|tenant| tenant.name == *name && tenant.config == *config)
+            .cloned()
+            .unwrap_or_else(|| {
+                Arc::new(Tenant {
+                    name: name.clone(),
+                    config: config.clone(),
+                    semaphore: config.max_concurrent.map(Semaphore::new),
+                    in_flight: AtomicUsize::new(0),
+                })
+            });
+
+        for api_key in &config.api_keys {
+            if by_key.insert(api_key.clone(), tenant.clone()).is_some() {
+                anyhow::bail!("api key for tenant \"{name}\" is used by another tenant");
+            }
+        }
+    }
+
+    Ok(by_key)
+}
+
+/// Decrements the in flight counter for a tenant when dropped
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Middleware identifying the tenant from the API key header and applying
+/// the tenant limits, the tenant is provided to handlers as an extension.
+/// Requests are allowed through without a tenant when none are configured
+pub async fn require_tenant(
+    State(tenants): State<Arc<Tenants>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, DynHttpError> {
+    if !tenants.is_enabled() {
+        return Ok(next.run(request).await);
+    }
+
+    let tenant = request
+        .headers()
+        .get(API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| tenants.by_key.read().get(value).cloned())
+        .ok_or(TenantError::InvalidApiKey)?;
+
+    if let Some(quota) = tenant.config.monthly_page_quota {
+        if tenants.monthly_pages(&tenant.name) >= quota {
+            return Err(TenantError::QuotaExceeded.into());
+        }
+    }
+
+    // Requests allowed to be running or waiting at once
+    let max_in_flight = match (tenant.config.max_concurrent, tenant.config.max_queued) {
+        (Some(max_concurrent), Some(max_queued)) => Some(max_concurrent + max_queued),
+        (None, Some(max_queued)) => Some(max_queued),
+        _ => None,
+    };
+
+    let in_flight = tenant.in_flight.fetch_add(1, Ordering::SeqCst);
+    let _guard = InFlightGuard(&tenant.in_flight);
+
+    if max_in_flight.is_some_and(|max_in_flight| in_flight >= max_in_flight) {
+        return Err(TenantError::QueueFull.into());
+    }
+
+    // Wait for a concurrency slot
+    let _permit = match &tenant.semaphore {
+        Some(semaphore) => Some(
+            semaphore
+                .acquire()
+                .await
+                .context("tenant semaphore closed")?,
+        ),
+        None => None,
+    };
+
+    // Conversions from the tenant wait for office with the tenant priority
+    // and take turns with conversions from other tenants
+    let office = request.extensions().get::<Converter>().map(|office| {
+        office
+            .with_priority(tenant.priority())
+            .with_fair_share(&tenant.name, tenant.weight())
+    });
+
+    if let Some(office) = office {
+        request.extensions_mut().insert(office);
+    }
+
+    request.extensions_mut().insert(tenant.clone());
+
+    Ok(next.run(request).await)
+}
+
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    /// Month to report usage for ("YYYY-MM"), defaults to the current month
+    month: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct UsageResponse {
+    /// Month the usage is for
+    month: String,
+    /// Usage for each tenant
+    tenants: BTreeMap<String, TenantUsage>,
+}
+
+/// GET /admin/usage
+///
+/// Reports the usage of each tenant for a month
+pub async fn usage(
+    Extension(tenants): Extension<Arc<Tenants>>,
+    Query(UsageQuery { month }): Query<UsageQuery>,
+) -> Json<UsageResponse> {
+    let month = month.unwrap_or_else(current_month);
+    let usage = tenants
+        .usage
+        .lock()
+        .get(&month)
+        .cloned()
+        .unwrap_or_default();
+
+    Json(UsageResponse {
+        month,
+        tenants: usage,
+    })
+}
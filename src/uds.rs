@@ -0,0 +1,68 @@
+use axum::Router;
+use std::path::Path;
+
+/// Serves the app from a Unix domain socket at the provided path, any
+/// stale socket left behind by a previous run is removed before binding
+#[cfg(unix)]
+pub async fn serve_unix(path: &Path, app: Router) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::os::unix::fs::FileTypeExt;
+    use tokio::net::UnixListener;
+    use tracing::debug;
+
+    // Only remove existing sockets, other files are left for binding to fail
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            std::fs::remove_file(path).context("failed to remove stale unix socket")?;
+        }
+    }
+
+    let listener = UnixListener::bind(path).context("failed to bind unix socket")?;
+
+    debug!("server started on: unix://{}", path.display());
+
+    serve_unix_listener(listener, app).await
+}
+
+/// Serves the app from a bound Unix domain socket listener
+#[cfg(unix)]
+pub async fn serve_unix_listener(
+    listener: tokio::net::UnixListener,
+    app: Router,
+) -> anyhow::Result<()> {
+    use hyper::server::conn::http1;
+    use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+    use tracing::{debug, warn};
+
+    crate::systemd::notify_ready();
+
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(value) => value,
+            Err(err) => {
+                warn!(%err, "failed to accept unix socket connection");
+                continue;
+            }
+        };
+
+        let service = TowerToHyperService::new(app.clone());
+
+        tokio::spawn(async move {
+            let result = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                // Upgrades are required for the WebSocket endpoint
+                .with_upgrades()
+                .await;
+
+            if let Err(err) = result {
+                debug!(%err, "unix socket connection failed");
+            }
+        });
+    }
+}
+
+/// Unix domain sockets are only supported on unix platforms
+#[cfg(not(unix))]
+pub async fn serve_unix(_path: &Path, _app: Router) -> anyhow::Result<()> {
+    anyhow::bail!("unix sockets are only supported on unix platforms")
+}
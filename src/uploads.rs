@@ -0,0 +1,493 @@
+use crate::{
+    base_path::BasePath,
+    error::{DynHttpError, HttpError},
+    jobs::{now_secs, JobMetadata, JobStore},
+    priority::{request_priority, RequestPriority},
+    tenant::Tenant,
+    ws::MaxFileSize,
+};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{Path, Query},
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+    Extension, Json,
+};
+use bytes::Bytes;
+use futures_util::StreamExt;
+use office_convert_core::TempFile;
+use parking_lot::Mutex;
+use rand::{distributions::Alphanumeric, Rng};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use tracing::debug;
+
+/// Header providing the total size of an upload in bytes
+const UPLOAD_LENGTH_HEADER: &str = "upload-length";
+
+/// Header providing the number of bytes of an upload that have been received
+const UPLOAD_OFFSET_HEADER: &str = "upload-offset";
+
+/// Content type of the chunks appended to an upload
+const CHUNK_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Time between runs of the expired upload collector
+const COLLECT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Errors that can occur when handling uploads
+#[derive(Debug, Error)]
+pub enum UploadError {
+    /// Upload does not exist or has expired
+    #[error("upload not found")]
+    NotFound,
+
+    /// Required upload header was missing or invalid
+    #[error("missing or invalid {0} header")]
+    InvalidHeader(&'static str),
+
+    /// Upload length is larger than the maximum file size
+    #[error("upload length exceeds the maximum size of {0} bytes")]
+    TooLarge(u64),
+
+    /// Chunk was not sent with the chunk content type
+    #[error("upload chunks must use the {CHUNK_CONTENT_TYPE} content type")]
+    InvalidContentType,
+
+    /// Chunk offset does not match the number of bytes received
+    #[error("upload offset does not match the current offset of {0}")]
+    OffsetMismatch(u64),
+
+    /// Chunk extends beyond the length of the upload
+    #[error("upload chunk extends beyond the upload length of {0} bytes")]
+    ExceedsLength(u64),
+
+    /// Upload is being written to or converted by another request
+    #[error("upload is in use by another request")]
+    InUse,
+
+    /// Conversion was requested before every byte was received
+    #[error("upload is incomplete ({offset} of {length} bytes received)")]
+    Incomplete { offset: u64, length: u64 },
+
+    /// Failed to read the chunk from the request body
+    #[error("failed to read upload chunk: {0}")]
+    Body(axum::Error),
+
+    /// Failed to store the upload
+    #[error(transparent)]
+    Storage(#[from] anyhow::Error),
+}
+
+impl HttpError for UploadError {
+    fn status(&self) -> StatusCode {
+        match self {
+            UploadError::NotFound => StatusCode::NOT_FOUND,
+            UploadError::InvalidHeader(_) | UploadError::Body(_) => StatusCode::BAD_REQUEST,
+            UploadError::TooLarge(_) | UploadError::ExceedsLength(_) => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            UploadError::InvalidContentType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            UploadError::OffsetMismatch(_) | UploadError::Incomplete { .. } => StatusCode::CONFLICT,
+            UploadError::InUse => StatusCode::LOCKED,
+            UploadError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            UploadError::NotFound => Some("UPLOAD_NOT_FOUND"),
+            UploadError::InvalidHeader(_) => Some("INVALID_UPLOAD_HEADER"),
+            UploadError::TooLarge(_) => Some("FILE_TOO_LARGE"),
+            UploadError::InvalidContentType => Some("INVALID_UPLOAD_CONTENT_TYPE"),
+            UploadError::OffsetMismatch(_) => Some("UPLOAD_OFFSET_MISMATCH"),
+            UploadError::ExceedsLength(_) => Some("UPLOAD_EXCEEDS_LENGTH"),
+            UploadError::InUse => Some("UPLOAD_IN_USE"),
+            UploadError::Incomplete { .. } => Some("UPLOAD_INCOMPLETE"),
+            UploadError::Body(_) | UploadError::Storage(_) => None,
+        }
+    }
+}
+
+/// Upload tracked by the store, the received bytes are written to a temp file
+struct Upload {
+    /// Unique ID of the upload
+    id: String,
+    /// Name of the tenant that created the upload
+    tenant: Option<String>,
+    /// Total size of the upload in bytes
+    length: u64,
+    /// File the received bytes are written to
+    file: TempFile,
+    /// Number of bytes received
+    offset: AtomicU64,
+    /// Unix timestamp in seconds of when the upload was last written to
+    updated_at: AtomicU64,
+    /// Held while a chunk is written or the upload is converted
+    lock: tokio::sync::Mutex<()>,
+}
+
+/// Details about an upload
+#[derive(Debug, Serialize)]
+pub struct UploadDetails {
+    /// Unique ID of the upload
+    pub id: String,
+    /// Number of bytes received
+    pub offset: u64,
+    /// Total size of the upload in bytes
+    pub length: u64,
+    /// Unix timestamp in seconds of when the upload expires unless more bytes are received
+    pub expires_at: u64,
+}
+
+/// Store for resumable uploads, files are received in chunks that can be
+/// retried from the last received byte when an upload is interrupted
+pub struct UploadStore {
+    /// Uploads tracked by the store
+    uploads: Mutex<HashMap<String, Arc<Upload>>>,
+    /// Directory to write uploads to
+    temp_dir: PathBuf,
+    /// Whether to overwrite uploads before deleting them
+    secure_delete: bool,
+    /// Time to keep uploads that haven't been written to for
+    ttl: Duration,
+}
+
+impl UploadStore {
+    /// Creates a new store writing uploads to the temp directory
+    pub fn new(temp_dir: PathBuf, secure_delete: bool, ttl: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            uploads: Default::default(),
+            temp_dir,
+            secure_delete,
+            ttl,
+        })
+    }
+
+    /// Creates the details for an upload
+    fn details(&self, upload: &Upload) -> UploadDetails {
+        UploadDetails {
+            id: upload.id.clone(),
+            offset: upload.offset.load(Ordering::SeqCst),
+            length: upload.length,
+            expires_at: upload.updated_at.load(Ordering::SeqCst) + self.ttl.as_secs(),
+        }
+    }
+
+    /// Creates a new empty upload
+    async fn create(&self, tenant: Option<String>, length: u64) -> anyhow::Result<Arc<Upload>> {
+        let id = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(|value| value as char)
+            .collect::<String>();
+
+        let file = TempFile::new(&self.temp_dir, "upload");
+        tokio::fs::File::create(&file.path)
+            .await
+            .context("failed to create upload file")?;
+
+        let upload = Arc::new(Upload {
+            id: id.clone(),
+            tenant,
+            length,
+            file,
+            offset: AtomicU64::new(0),
+            updated_at: AtomicU64::new(now_secs()),
+            lock: Default::default(),
+        });
+
+        self.uploads.lock().insert(id, upload.clone());
+
+        Ok(upload)
+    }
+
+    /// Gets an upload, uploads are only accessible by the tenant that created them
+    fn get(&self, id: &str, tenant: Option<&str>) -> Result<Arc<Upload>, UploadError> {
+        self.uploads
+            .lock()
+            .get(id)
+            .filter(|upload| upload.tenant.as_deref() == tenant)
+            .cloned()
+            .ok_or(UploadError::NotFound)
+    }
+
+    /// Stops tracking the upload and removes its file
+    async fn remove(&self, upload: &Upload) {
+        self.uploads.lock().remove(&upload.id);
+
+        let path = upload.file.path.clone();
+        let secure = self.secure_delete;
+        _ = tokio::task::spawn_blocking(move || TempFile { path }.remove(secure)).await;
+    }
+
+    /// Removes uploads that haven't been written to within the TTL
+    async fn collect_expired(&self) {
+        let expires_before = now_secs().saturating_sub(self.ttl.as_secs());
+
+        let expired: Vec<Arc<Upload>> = self
+            .uploads
+            .lock()
+            .values()
+            .filter(|upload| upload.updated_at.load(Ordering::SeqCst) < expires_before)
+            .cloned()
+            .collect();
+
+        for upload in expired {
+            // Uploads in use are collected once they are no longer in use
+            let Ok(_guard) = upload.lock.try_lock() else {
+                continue;
+            };
+
+            debug!(id = %upload.id, "removing expired upload");
+            self.remove(&upload).await;
+        }
+    }
+}
+
+/// Spawns a background task that periodically removes expired uploads
+pub fn spawn_upload_collector(store: Arc<UploadStore>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(COLLECT_INTERVAL);
+
+        loop {
+            interval.tick().await;
+            store.collect_expired().await;
+        }
+    });
+}
+
+/// Reads a header containing a number of bytes
+fn header_u64(headers: &HeaderMap, name: &'static str) -> Result<u64, UploadError> {
+    headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or(UploadError::InvalidHeader(name))
+}
+
+/// Creates a response with the upload offset and length headers
+fn upload_response(status: StatusCode, details: UploadDetails) -> Response<Body> {
+    let mut response = (status, Json(&details)).into_response();
+    let headers = response.headers_mut();
+
+    headers.insert(UPLOAD_OFFSET_HEADER, HeaderValue::from(details.offset));
+    headers.insert(UPLOAD_LENGTH_HEADER, HeaderValue::from(details.length));
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+
+    response
+}
+
+/// Gets the name of the tenant making the request
+fn tenant_name(tenant: &Option<Extension<Arc<Tenant>>>) -> Option<&str> {
+    tenant
+        .as_ref()
+        .map(|Extension(tenant)| tenant.name.as_str())
+}
+
+/// POST /uploads
+///
+/// Creates a resumable upload for a file with the size provided in
+/// the "Upload-Length" header
+pub async fn create_upload(
+    Extension(store): Extension<Arc<UploadStore>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    Extension(base_path): Extension<BasePath>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, DynHttpError> {
+    let length = header_u64(&headers, UPLOAD_LENGTH_HEADER)?;
+    if length > max_file_size {
+        return Err(UploadError::TooLarge(max_file_size).into());
+    }
+
+    let tenant = tenant_name(&tenant).map(str::to_string);
+    let upload = store.create(tenant, length).await?;
+
+    let mut response = upload_response(StatusCode::CREATED, store.details(&upload));
+    let location = HeaderValue::try_from(base_path.url(&format!("/uploads/{}", upload.id)))
+        .context("invalid upload location")?;
+    response.headers_mut().insert(header::LOCATION, location);
+
+    Ok(response)
+}
+
+/// GET /uploads/:id
+///
+/// Provides the details about an upload, clients resuming an upload use
+/// the offset to find where to continue from
+pub async fn get_upload(
+    Extension(store): Extension<Arc<UploadStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<Response<Body>, DynHttpError> {
+    let upload = store.get(&id, tenant_name(&tenant))?;
+    Ok(upload_response(StatusCode::OK, store.details(&upload)))
+}
+
+/// PATCH /uploads/:id
+///
+/// Appends the request body to the upload at the offset provided in the
+/// "Upload-Offset" header, bytes received before the request is interrupted
+/// are kept so the upload can be resumed
+pub async fn append_upload(
+    Extension(store): Extension<Arc<UploadStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response<Body>, DynHttpError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok());
+    if content_type != Some(CHUNK_CONTENT_TYPE) {
+        return Err(UploadError::InvalidContentType.into());
+    }
+
+    let offset = header_u64(&headers, UPLOAD_OFFSET_HEADER)?;
+    let upload = store.get(&id, tenant_name(&tenant))?;
+    let _guard = upload.lock.try_lock().map_err(|_| UploadError::InUse)?;
+
+    let current_offset = upload.offset.load(Ordering::SeqCst);
+    if offset != current_offset {
+        return Err(UploadError::OffsetMismatch(current_offset).into());
+    }
+
+    // Upload file is missing when it was removed as an orphaned temp file
+    let mut file = match tokio::fs::OpenOptions::new()
+        .append(true)
+        .open(&upload.file.path)
+        .await
+    {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            store.remove(&upload).await;
+            return Err(UploadError::NotFound.into());
+        }
+        Err(err) => return Err(UploadError::Storage(err.into()).into()),
+    };
+
+    let mut stream = body.into_data_stream();
+    let mut result = Ok(());
+
+    while let Some(chunk) = stream.next().await {
+        let chunk: Bytes = match chunk {
+            Ok(value) => value,
+            Err(err) => {
+                result = Err(UploadError::Body(err));
+                break;
+            }
+        };
+
+        // Bytes past the end of the upload are not written
+        let remaining = upload.length - upload.offset.load(Ordering::SeqCst);
+        let exceeds_length = chunk.len() as u64 > remaining;
+        let chunk = chunk.slice(..chunk.len().min(remaining as usize));
+
+        if let Err(err) = file.write_all(&chunk).await {
+            result = Err(UploadError::Storage(
+                anyhow::Error::new(err).context("failed to write upload chunk"),
+            ));
+            break;
+        }
+
+        upload
+            .offset
+            .fetch_add(chunk.len() as u64, Ordering::SeqCst);
+        upload.updated_at.store(now_secs(), Ordering::SeqCst);
+
+        if exceeds_length {
+            result = Err(UploadError::ExceedsLength(upload.length));
+            break;
+        }
+    }
+
+    file.flush().await.context("failed to flush upload file")?;
+    result?;
+
+    let details = store.details(&upload);
+    let mut response = upload_response(StatusCode::NO_CONTENT, details);
+    *response.body_mut() = Body::empty();
+
+    Ok(response)
+}
+
+/// DELETE /uploads/:id
+///
+/// Cancels an upload removing the received bytes
+pub async fn delete_upload(
+    Extension(store): Extension<Arc<UploadStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, DynHttpError> {
+    let upload = store.get(&id, tenant_name(&tenant))?;
+    let _guard = upload.lock.try_lock().map_err(|_| UploadError::InUse)?;
+
+    store.remove(&upload).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize)]
+pub struct ConvertUploadQuery {
+    /// Priority of the job when waiting for office
+    priority: Option<RequestPriority>,
+}
+
+/// POST /uploads/:id/convert
+///
+/// Queues a completed upload for conversion as a job responding with
+/// the job details, the upload is removed once the job is queued
+pub async fn convert_upload(
+    Extension(store): Extension<Arc<UploadStore>>,
+    Extension(jobs): Extension<Arc<JobStore>>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    Path(id): Path<String>,
+    Query(ConvertUploadQuery { priority }): Query<ConvertUploadQuery>,
+) -> Result<(StatusCode, Json<JobMetadata>), DynHttpError> {
+    let upload = store.get(&id, tenant_name(&tenant))?;
+    let _guard = upload.lock.try_lock().map_err(|_| UploadError::InUse)?;
+
+    let offset = upload.offset.load(Ordering::SeqCst);
+    if offset < upload.length {
+        return Err(UploadError::Incomplete {
+            offset,
+            length: upload.length,
+        }
+        .into());
+    }
+
+    let input = match tokio::fs::read(&upload.file.path).await {
+        Ok(value) => value,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            store.remove(&upload).await;
+            return Err(UploadError::NotFound.into());
+        }
+        Err(err) => {
+            return Err(UploadError::Storage(
+                anyhow::Error::new(err).context("failed to read upload file"),
+            )
+            .into())
+        }
+    };
+
+    let priority = request_priority(tenant.as_deref().map(Arc::as_ref), priority);
+    let tenant = upload.tenant.clone();
+    let (metadata, _) = jobs
+        .submit(Bytes::from(input), tenant, None, priority, None, false)
+        .await?;
+
+    store.remove(&upload).await;
+
+    Ok((StatusCode::ACCEPTED, Json(metadata)))
+}
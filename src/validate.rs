@@ -0,0 +1,191 @@
+use crate::{
+    archive::read_part,
+    compression::decompress_file,
+    detect::{check_import_filter, detect_input_format},
+    error::DynHttpError,
+    metadata::office_warnings,
+    priority::{request_priority, RequestPriority},
+    tenant::Tenant,
+    ws::MaxFileSize,
+    xml::{find_tag, get_attribute},
+};
+use anyhow::Context;
+use axum::{Extension, Json};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use bytes::Bytes;
+use office_convert_core::{page_count, Converter, DocumentKind, OfficeDetails};
+use serde::Serialize;
+use std::{io::Cursor, sync::Arc};
+use zip::ZipArchive;
+
+/// Request to validate a file
+#[derive(TryFromMultipart)]
+pub struct ValidateRequest {
+    /// The file to validate
+    #[form_data(limit = "unlimited")]
+    file: FieldData<Bytes>,
+
+    /// Priority of the validation when waiting for office
+    priority: Option<RequestPriority>,
+}
+
+/// Outcome of validating a file
+#[derive(Debug, Serialize)]
+pub struct ValidationResponse {
+    /// Whether office was able to load the file
+    loadable: bool,
+    /// Reason the file could not be loaded, [None] when loadable
+    error: Option<String>,
+    /// Name of the office filter type used to import the detected format,
+    /// [None] when office doesn't support the format
+    filter: Option<String>,
+    /// Mime type of the detected format
+    mime: Option<String>,
+    /// Kind of document office loaded the file as
+    document_type: Option<DocumentKind>,
+    /// Number of pages estimated from the document statistics stored in the
+    /// file, [None] when the file doesn't store them
+    estimated_pages: Option<u64>,
+    /// Warnings reported by office while loading the file
+    warnings: Vec<String>,
+    /// Whether the file was loaded read-only as it is protected with a
+    /// password to modify
+    read_only: bool,
+}
+
+/// POST /validate
+///
+/// Checks whether the provided file can be converted by loading it into
+/// office without exporting it, files that can't be loaded are reported
+/// in the response rather than as an error
+pub async fn validate(
+    Extension(office): Extension<Converter>,
+    Extension(details): Extension<Arc<OfficeDetails>>,
+    Extension(MaxFileSize(max_file_size)): Extension<MaxFileSize>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+    TypedMultipart(ValidateRequest { file, priority }): TypedMultipart<ValidateRequest>,
+) -> Result<Json<ValidationResponse>, DynHttpError> {
+    let file = decompress_file(file, max_file_size).await?;
+    let office = office.with_priority(request_priority(
+        tenant.as_deref().map(Arc::as_ref),
+        priority,
+    ));
+
+    let mime = detect_input_format(
+        &file.contents,
+        file.metadata.file_name.as_deref(),
+        file.metadata.content_type.as_deref(),
+    );
+
+    let filter = details
+        .filter_types
+        .as_ref()
+        .zip(mime.as_deref())
+        .and_then(|(filter_types, mime)| filter_types.get_by_mime(mime))
+        .map(str::to_string);
+
+    // Files without an import filter can't be loaded so office isn't used
+    if let Err(cause) = check_import_filter(&details, mime.as_deref()) {
+        return Ok(Json(ValidationResponse {
+            loadable: false,
+            error: Some(cause.to_string()),
+            filter,
+            mime,
+            document_type: None,
+            estimated_pages: None,
+            warnings: Vec::new(),
+            read_only: false,
+        }));
+    }
+
+    let estimated_pages = {
+        let contents = file.contents.clone();
+        let mime = mime.clone();
+
+        tokio::task::spawn_blocking(move || estimate_pages(&contents, mime.as_deref()?))
+            .await
+            .context("failed to estimate pages")?
+    };
+
+    let validation = office.validate(file.contents).await?;
+
+    Ok(Json(ValidationResponse {
+        loadable: validation.is_loadable(),
+        error: validation.error,
+        filter,
+        mime,
+        document_type: validation.document_type,
+        // Statistics of files office can't load aren't trusted
+        estimated_pages: estimated_pages.filter(|_| validation.document_type.is_some()),
+        warnings: office_warnings(validation.warnings),
+        read_only: validation.read_only,
+    }))
+}
+
+/// Estimates the number of pages of a file from the document statistics
+/// stored by the application that saved it, slides are counted for
+/// presentations. Spreadsheets are paginated while printing so their
+/// pages can't be estimated
+fn estimate_pages(bytes: &[u8], mime: &str) -> Option<u64> {
+    match mime {
+        "application/pdf" => page_count(bytes).ok(),
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+            element_value(&read_zip_part(bytes, "docProps/app.xml")?, "Pages")
+        }
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation" => {
+            element_value(&read_zip_part(bytes, "docProps/app.xml")?, "Slides")
+        }
+        "application/vnd.oasis.opendocument.text" => {
+            statistic_page_count(&read_zip_part(bytes, "meta.xml")?)
+        }
+        "application/vnd.oasis.opendocument.presentation"
+        | "application/vnd.oasis.opendocument.graphics" => {
+            count_draw_pages(&read_zip_part(bytes, "content.xml")?)
+        }
+        "application/vnd.oasis.opendocument.text-flat-xml" => {
+            statistic_page_count(std::str::from_utf8(bytes).ok()?)
+        }
+        "application/vnd.oasis.opendocument.presentation-flat-xml"
+        | "application/vnd.oasis.opendocument.graphics-flat-xml" => {
+            count_draw_pages(std::str::from_utf8(bytes).ok()?)
+        }
+        _ => None,
+    }
+}
+
+/// Reads a part of a ZIP based document as a string, parts that are too
+/// large are not read
+fn read_zip_part(bytes: &[u8], name: &str) -> Option<String> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).ok()?;
+    read_part(&mut archive, name).ok()
+}
+
+/// Parses the number contained in the first element with the provided name
+/// (i.e "<Pages>12</Pages>")
+fn element_value(xml: &str, name: &str) -> Option<u64> {
+    let tag = find_tag(xml, name, 0)?;
+    let content = &xml[tag.end..];
+    let end = content.find('<')?;
+    content[..end].trim().parse().ok()
+}
+
+/// Page count from the OpenDocument statistics in the document metadata
+fn statistic_page_count(xml: &str) -> Option<u64> {
+    let tag = find_tag(xml, "meta:document-statistic", 0)?;
+    get_attribute(&tag.attributes, "meta:page-count")?
+        .parse()
+        .ok()
+}
+
+/// Counts the pages (slides) of an OpenDocument presentation or drawing
+fn count_draw_pages(xml: &str) -> Option<u64> {
+    let mut count = 0;
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, "draw:page", position) {
+        count += 1;
+        position = tag.end;
+    }
+
+    (count > 0).then_some(count)
+}
@@ -0,0 +1,238 @@
+use crate::{
+    archive::{open_archive, rewrite_parts, ArchiveError},
+    detect::{detect_archive, known_extension},
+    error::HttpError,
+    xml::find_tag,
+};
+use axum::http::StatusCode;
+use bytes::Bytes;
+use office_convert_core::{CommentsMode, FormFieldsMode, WriterExportOptions};
+use serde::Deserialize;
+use std::io::{Read, Seek};
+use thiserror::Error;
+use zip::ZipArchive;
+
+/// Word elements removed along with their content when accepting changes,
+/// deleted and moved content and the previous properties of changed formatting
+const DOCX_REMOVED_ELEMENTS: &[&str] = &[
+    "w:del",
+    "w:moveFrom",
+    "w:rPrChange",
+    "w:pPrChange",
+    "w:sectPrChange",
+    "w:tblPrChange",
+    "w:tblPrExChange",
+    "w:trPrChange",
+    "w:tcPrChange",
+    "w:tblGridChange",
+    "w:numberingChange",
+    "w:cellIns",
+    "w:cellDel",
+    "w:moveFromRangeStart",
+    "w:moveFromRangeEnd",
+    "w:moveToRangeStart",
+    "w:moveToRangeEnd",
+];
+
+/// Word elements replaced by their content when accepting changes
+const DOCX_UNWRAPPED_ELEMENTS: &[&str] = &["w:ins", "w:moveTo"];
+
+/// OpenDocument elements removed when accepting changes, the body of the
+/// document already contains the inserted content and the deleted content
+/// is only stored in the list of tracked changes
+const ODT_REMOVED_ELEMENTS: &[&str] = &[
+    "text:tracked-changes",
+    "text:change-start",
+    "text:change-end",
+    "text:change",
+];
+
+/// Options for text documents provided with a conversion request
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WriterOptions {
+    /// How comments in the document are exported
+    pub comments: Option<CommentsMode>,
+    /// How form fields in the document are exported
+    pub form_fields: Option<FormFieldsMode>,
+    /// How tracked changes in the document are exported
+    pub tracked_changes: Option<TrackedChanges>,
+}
+
+impl WriterOptions {
+    /// Splits the options into the export filter options and the tracked
+    /// changes mode which is applied to the document before converting
+    pub fn split(self) -> (WriterExportOptions, Option<TrackedChanges>) {
+        let export = WriterExportOptions {
+            comments: self.comments,
+            form_fields: self.form_fields,
+        };
+
+        (export, self.tracked_changes)
+    }
+}
+
+/// How tracked changes are included in the exported PDF
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackedChanges {
+    /// Changes are shown as markup (insertions and deletions are highlighted)
+    Markup,
+    /// Changes are accepted and only the final text is shown
+    Accept,
+}
+
+/// Errors that can occur while applying the tracked changes mode to a document
+#[derive(Debug, Error)]
+pub enum TrackedChangesError {
+    /// Failed to rewrite the document archive
+    #[error(transparent)]
+    Archive(#[from] ArchiveError),
+}
+
+impl HttpError for TrackedChangesError {
+    fn status(&self) -> StatusCode {
+        match self {
+            TrackedChangesError::Archive(err) => err.status(),
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            TrackedChangesError::Archive(err) => err.code(),
+        }
+    }
+}
+
+/// Text document formats that tracked changes can be applied to
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TextFormat {
+    /// Word document (.docx)
+    Docx,
+    /// OpenDocument text (.odt)
+    Odt,
+}
+
+impl TextFormat {
+    /// Detects the format of the document archive
+    fn detect<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Option<Self> {
+        match known_extension(detect_archive(archive)?)? {
+            "docx" => Some(TextFormat::Docx),
+            "odt" => Some(TextFormat::Odt),
+            _ => None,
+        }
+    }
+
+    /// Whether the part of the document must be rewritten for the mode
+    fn should_rewrite(&self, name: &str, mode: TrackedChanges) -> bool {
+        match (self, mode) {
+            // Word stores whether changes are shown in the settings
+            (TextFormat::Docx, TrackedChanges::Markup) => name == "word/settings.xml",
+            // Changes can be in the body, headers, footers, notes and styles
+            (TextFormat::Docx, TrackedChanges::Accept) => name
+                .strip_prefix("word/")
+                .is_some_and(|name| !name.contains('/') && name.ends_with(".xml")),
+            // Office shows the changes in OpenDocument files by default
+            (TextFormat::Odt, TrackedChanges::Markup) => false,
+            (TextFormat::Odt, TrackedChanges::Accept) => {
+                name == "content.xml" || name == "styles.xml"
+            }
+        }
+    }
+
+    /// Applies the mode to the XML of a part
+    fn rewrite(&self, xml: &str, mode: TrackedChanges) -> String {
+        match (self, mode) {
+            // Removing the revision view restores the default of showing markup
+            (TextFormat::Docx, TrackedChanges::Markup) => remove_elements(xml, "w:revisionView"),
+            (TextFormat::Docx, TrackedChanges::Accept) => {
+                let xml = DOCX_REMOVED_ELEMENTS
+                    .iter()
+                    .fold(xml.to_string(), |xml, name| remove_elements(&xml, name));
+
+                DOCX_UNWRAPPED_ELEMENTS
+                    .iter()
+                    .fold(xml, |xml, name| unwrap_elements(&xml, name))
+            }
+            (TextFormat::Odt, TrackedChanges::Markup) => xml.to_string(),
+            (TextFormat::Odt, TrackedChanges::Accept) => ODT_REMOVED_ELEMENTS
+                .iter()
+                .fold(xml.to_string(), |xml, name| remove_elements(&xml, name)),
+        }
+    }
+}
+
+/// Applies the tracked changes mode to the provided document before it is
+/// converted. Changes can be applied to Word and OpenDocument text documents,
+/// other files are left unchanged
+pub fn apply_tracked_changes(
+    input: Bytes,
+    mode: Option<TrackedChanges>,
+) -> Result<Bytes, TrackedChangesError> {
+    let mode = match mode {
+        Some(value) => value,
+        None => return Ok(input),
+    };
+
+    // Archives that can't be read are left for office to handle
+    let mut archive = match open_archive(&input) {
+        Some(value) => value,
+        None => return Ok(input),
+    };
+
+    let format = match TextFormat::detect(&mut archive) {
+        Some(value) => value,
+        None => return Ok(input),
+    };
+
+    // Parts without changes are copied as-is
+    let output = rewrite_parts(
+        &mut archive,
+        |name| format.should_rewrite(name, mode),
+        |xml| format.rewrite(xml, mode),
+    )?;
+
+    Ok(output)
+}
+
+/// Removes every element with the provided name along with its content
+fn remove_elements(xml: &str, name: &str) -> String {
+    let close = format!("</{name}>");
+    let mut output = String::with_capacity(xml.len());
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, name, position) {
+        output.push_str(&xml[position..tag.start]);
+
+        if tag.self_closing {
+            position = tag.end;
+            continue;
+        }
+
+        match xml[tag.end..].find(&close) {
+            Some(end) => position = tag.end + end + close.len(),
+            // Elements that are never closed are left as-is
+            None => {
+                position = tag.start;
+                break;
+            }
+        }
+    }
+
+    output.push_str(&xml[position..]);
+    output
+}
+
+/// Replaces every element with the provided name with its content
+fn unwrap_elements(xml: &str, name: &str) -> String {
+    let mut output = String::with_capacity(xml.len());
+    let mut position = 0;
+
+    while let Some(tag) = find_tag(xml, name, position) {
+        output.push_str(&xml[position..tag.start]);
+        position = tag.end;
+    }
+
+    output.push_str(&xml[position..]);
+    output.replace(&format!("</{name}>"), "")
+}
@@ -0,0 +1,285 @@
+use crate::{
+    config::{Config, SharedConfig},
+    error::{DynHttpError, HttpError},
+    tenant::{Tenant, Tenants},
+};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket},
+        WebSocketUpgrade,
+    },
+    http::StatusCode,
+    response::Response,
+    Extension,
+};
+use bytes::BytesMut;
+use office_convert_core::{ConversionWarning, Converter, OfficeEvent, PdfExportOptions};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// Maximum size in bytes of a file uploaded over the socket or decompressed
+/// from a compressed multipart upload, matches the body limit for multipart uploads
+#[derive(Debug, Clone, Copy)]
+pub struct MaxFileSize(pub u64);
+
+/// Size of the chunks the converted file is sent in
+const RESULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Messages sent by the client as text frames
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Starts a conversion, followed by the file bytes as binary frames
+    Start {
+        /// Total size of the file in bytes
+        size: u64,
+        /// Name of a preset from the server config to use for the export options
+        preset: Option<String>,
+    },
+}
+
+/// Messages sent by the server as text frames
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage<'a> {
+    /// Bytes of the file received so far
+    UploadProgress { received: u64, total: u64 },
+    /// File was received and is being converted
+    Converting,
+    /// Progress of the current office operation (loading or exporting)
+    /// as a percentage
+    ConvertProgress { percent: u8 },
+    /// Problem office recovered from while converting
+    Warning { warning: &'a ConversionWarning },
+    /// Conversion completed, followed by the converted file as binary frames
+    Result { size: u64 },
+    /// All the converted file bytes have been sent
+    Complete,
+    /// Conversion failed
+    Error {
+        status: u16,
+        reason: &'a str,
+        code: Option<&'a str>,
+    },
+}
+
+/// Errors that can occur from messages sent over the socket
+#[derive(Debug, Error)]
+pub enum SocketError {
+    /// Client sent a message that was unexpected or malformed
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+
+    /// Client attempted to upload a file larger than the limit
+    #[error("file is too large, maximum size is {0} bytes")]
+    FileTooLarge(u64),
+}
+
+impl HttpError for SocketError {
+    fn status(&self) -> StatusCode {
+        match self {
+            SocketError::InvalidMessage(_) => StatusCode::BAD_REQUEST,
+            SocketError::FileTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+        }
+    }
+
+    fn code(&self) -> Option<&'static str> {
+        match self {
+            SocketError::InvalidMessage(_) => Some("INVALID_SOCKET_MESSAGE"),
+            SocketError::FileTooLarge(_) => Some("FILE_TOO_LARGE"),
+        }
+    }
+}
+
+/// GET /ws
+///
+/// Converts a file over a WebSocket connection. The client sends a "start"
+/// message followed by the file as binary frames, the server reports the
+/// upload progress then responds with the converted PDF as binary frames
+pub async fn convert_socket(
+    upgrade: WebSocketUpgrade,
+    Extension(office): Extension<Converter>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(tenants): Extension<Arc<Tenants>>,
+    Extension(max_file_size): Extension<MaxFileSize>,
+    tenant: Option<Extension<Arc<Tenant>>>,
+) -> Response {
+    let tenant = tenant.map(|Extension(tenant)| tenant);
+
+    upgrade.on_upgrade(move |mut socket| async move {
+        let config = config.current();
+        let result = handle_socket(
+            &mut socket,
+            &office,
+            &config,
+            &tenants,
+            max_file_size,
+            tenant,
+        )
+        .await;
+
+        if let Err(err) = result {
+            let (status, body) = err.to_raw();
+            _ = send_message(
+                &mut socket,
+                ServerMessage::Error {
+                    status: status.as_u16(),
+                    reason: &body.reason,
+                    code: body.code,
+                },
+            )
+            .await;
+        }
+
+        _ = socket.close().await;
+    })
+}
+
+/// Sends a message to the client as a text frame
+async fn send_message(socket: &mut WebSocket, message: ServerMessage<'_>) -> anyhow::Result<()> {
+    let message = serde_json::to_string(&message)?;
+    socket.send(Message::Text(message)).await?;
+    Ok(())
+}
+
+/// Receives the next message from the client that isn't a ping or pong,
+/// provides [None] when the client has disconnected
+async fn receive_message(socket: &mut WebSocket) -> Option<Message> {
+    while let Some(Ok(message)) = socket.recv().await {
+        match message {
+            Message::Ping(_) | Message::Pong(_) => continue,
+            Message::Close(_) => return None,
+            message => return Some(message),
+        }
+    }
+
+    None
+}
+
+/// Handles the conversion protocol over the socket
+async fn handle_socket(
+    socket: &mut WebSocket,
+    office: &Converter,
+    config: &Config,
+    tenants: &Tenants,
+    MaxFileSize(max_file_size): MaxFileSize,
+    tenant: Option<Arc<Tenant>>,
+) -> Result<(), DynHttpError> {
+    let start = match receive_message(socket).await {
+        Some(Message::Text(message)) => message,
+        Some(_) => {
+            return Err(SocketError::InvalidMessage("expected start message".to_string()).into())
+        }
+        None => return Ok(()),
+    };
+
+    let (size, preset) = match serde_json::from_str(&start) {
+        Ok(ClientMessage::Start { size, preset }) => (size, preset),
+        Err(err) => return Err(SocketError::InvalidMessage(err.to_string()).into()),
+    };
+
+    if size > max_file_size {
+        return Err(SocketError::FileTooLarge(max_file_size).into());
+    }
+
+    let mut export_options = match &preset {
+        Some(name) => config.preset(name)?.clone(),
+        None => PdfExportOptions::default(),
+    };
+
+    // Receive the file bytes
+    let mut file = BytesMut::with_capacity(size as usize);
+
+    while (file.len() as u64) < size {
+        let chunk = match receive_message(socket).await {
+            Some(Message::Binary(chunk)) => chunk,
+            Some(_) => {
+                return Err(SocketError::InvalidMessage("expected file chunk".to_string()).into())
+            }
+            None => {
+                debug!("client disconnected during upload");
+                return Ok(());
+            }
+        };
+
+        if file.len() as u64 + chunk.len() as u64 > size {
+            return Err(SocketError::InvalidMessage(
+                "file is larger than the start size".to_string(),
+            )
+            .into());
+        }
+
+        file.extend_from_slice(&chunk);
+
+        send_message(
+            socket,
+            ServerMessage::UploadProgress {
+                received: file.len() as u64,
+                total: size,
+            },
+        )
+        .await?;
+    }
+
+    send_message(socket, ServerMessage::Converting).await?;
+
+    let file = file.freeze();
+    let input_size = file.len();
+    config.apply_load_options(&mut export_options, &file, None, None);
+
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel();
+    let office = office.with_events(events_tx);
+    let convert = office.convert_with_options(file, export_options);
+    tokio::pin!(convert);
+
+    // Report the progress and warnings from office while converting, the
+    // conversion is cancelled when the client disconnects
+    let converted = loop {
+        tokio::select! {
+            result = &mut convert => break result?,
+            message = receive_message(socket) => {
+                if message.is_none() {
+                    debug!("client disconnected during conversion, cancelling");
+                    return Ok(());
+                }
+            }
+            Some(event) = events_rx.recv() => {
+                let message = match &event {
+                    OfficeEvent::Progress { percent } => {
+                        ServerMessage::ConvertProgress { percent: *percent }
+                    }
+                    OfficeEvent::Warning { warning } => ServerMessage::Warning { warning },
+                    _ => continue,
+                };
+
+                send_message(socket, message).await?;
+            }
+        }
+    };
+
+    if let Some(tenant) = tenant {
+        tenants.record(&tenant.name, input_size, &converted);
+    }
+
+    send_message(
+        socket,
+        ServerMessage::Result {
+            size: converted.len() as u64,
+        },
+    )
+    .await?;
+
+    for chunk in converted.chunks(RESULT_CHUNK_SIZE) {
+        socket
+            .send(Message::Binary(chunk.to_vec()))
+            .await
+            .map_err(anyhow::Error::from)?;
+    }
+
+    send_message(socket, ServerMessage::Complete).await?;
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+//! Minimal helpers for reading and rewriting the tags of XML document parts
+//! without parsing the whole document, the rest of the XML is left untouched
+
+/// Attributes of an XML start tag in order, values are kept in their escaped form
+pub type Attributes = Vec<(String, String)>;
+
+pub fn get_attribute<'a>(attributes: &'a Attributes, name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+pub fn set_attribute(attributes: &mut Attributes, name: &str, value: String) {
+    match attributes.iter_mut().find(|(key, _)| key == name) {
+        Some((_, existing)) => *existing = value,
+        None => attributes.push((name.to_string(), value)),
+    }
+}
+
+pub fn remove_attribute(attributes: &mut Attributes, name: &str) {
+    attributes.retain(|(key, _)| key != name);
+}
+
+/// Start tag of an element within an XML document
+pub struct Tag {
+    /// Position of the start of the tag ('<')
+    pub start: usize,
+    /// Position after the end of the tag ('>')
+    pub end: usize,
+    /// Attributes of the tag
+    pub attributes: Attributes,
+    /// Whether the tag is self closing ("/>")
+    pub self_closing: bool,
+}
+
+/// Finds the next start tag of the element with the provided name
+/// starting from the provided position
+pub fn find_tag(xml: &str, name: &str, mut position: usize) -> Option<Tag> {
+    let open = format!("<{name}");
+
+    loop {
+        let start = position + xml[position..].find(&open)?;
+        let after_name = start + open.len();
+
+        // Ensure the name isn't the prefix of a longer name
+        match xml[after_name..].chars().next() {
+            Some(value) if value.is_whitespace() || value == '/' || value == '>' => {}
+            _ => {
+                position = after_name;
+                continue;
+            }
+        }
+
+        return parse_tag(xml, start, after_name);
+    }
+}
+
+/// Parses the attributes of a start tag
+fn parse_tag(xml: &str, start: usize, mut position: usize) -> Option<Tag> {
+    let bytes = xml.as_bytes();
+    let mut attributes = Attributes::new();
+
+    loop {
+        while bytes.get(position)?.is_ascii_whitespace() {
+            position += 1;
+        }
+
+        match bytes.get(position)? {
+            b'>' => {
+                return Some(Tag {
+                    start,
+                    end: position + 1,
+                    attributes,
+                    self_closing: false,
+                })
+            }
+            b'/' if bytes.get(position + 1)? == &b'>' => {
+                return Some(Tag {
+                    start,
+                    end: position + 2,
+                    attributes,
+                    self_closing: true,
+                })
+            }
+            _ => {}
+        }
+
+        let name_end = position + xml[position..].find('=')?;
+        let name = xml[position..name_end].trim().to_string();
+
+        position = name_end + 1;
+        while bytes.get(position)?.is_ascii_whitespace() {
+            position += 1;
+        }
+
+        let quote = *bytes.get(position)?;
+        if quote != b'"' && quote != b'\'' {
+            return None;
+        }
+
+        let value_start = position + 1;
+        let value_end = value_start + xml[value_start..].find(quote as char)?;
+        attributes.push((name, xml[value_start..value_end].to_string()));
+
+        position = value_end + 1;
+    }
+}
+
+/// Rewrites the attributes of every start tag of the element with the provided name
+pub fn rewrite_tags<F>(xml: &str, name: &str, mut rewrite: F) -> String
+where
+    F: FnMut(&mut Attributes),
+{
+    let mut output = String::with_capacity(xml.len());
+    let mut position = 0;
+
+    while let Some(mut tag) = find_tag(xml, name, position) {
+        output.push_str(&xml[position..tag.start]);
+
+        rewrite(&mut tag.attributes);
+
+        output.push('<');
+        output.push_str(name);
+        for (key, value) in &tag.attributes {
+            // Values are kept escaped but may contain the other quote type
+            let quote = if value.contains('"') { '\'' } else { '"' };
+            output.push_str(&format!(" {key}={quote}{value}{quote}"));
+        }
+        output.push_str(if tag.self_closing { "/>" } else { ">" });
+
+        position = tag.end;
+    }
+
+    output.push_str(&xml[position..]);
+    output
+}
+
+/// Unescapes the predefined entities and character references of an escaped
+/// attribute value
+pub fn unescape(value: &str) -> String {
+    let mut output = String::with_capacity(value.len());
+    let mut remaining = value;
+
+    while let Some(start) = remaining.find('&') {
+        output.push_str(&remaining[..start]);
+        remaining = &remaining[start..];
+
+        let end = match remaining.find(';') {
+            Some(value) => value,
+            None => break,
+        };
+
+        let entity = &remaining[1..end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|decimal| decimal.parse().ok())
+                    .and_then(char::from_u32),
+            },
+        };
+
+        match character {
+            Some(character) => {
+                output.push(character);
+                remaining = &remaining[end + 1..];
+            }
+            // Unknown entities are left as-is
+            None => {
+                output.push('&');
+                remaining = &remaining[1..];
+            }
+        }
+    }
+
+    output.push_str(remaining);
+    output
+}